@@ -96,7 +96,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     println!("\n=== Warehouse Management ===");
-    use stec_shopster::warehouse::WarehouseItem;
+    use stec_shopster::warehouse::{StockPolicy, WarehouseItem};
 
     let laptop_stock = WarehouseItem {
         id: 0,
@@ -105,6 +105,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         reserved: 5,
         created_at: chrono::Utc::now().naive_utc(),
         updated_at: None,
+        stock_policy: StockPolicy::Deny,
+        backordered: 0,
+        location_id: None,
     };
 
     let warehouse_item = warehouse.insert(&laptop_stock).await?;
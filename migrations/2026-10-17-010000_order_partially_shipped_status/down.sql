@@ -0,0 +1,18 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE orders
+    ALTER COLUMN status
+    TYPE text
+    USING status::text;
+
+UPDATE orders SET status = 'ReadyToShip' WHERE status = 'PartiallyShipped';
+
+DROP TYPE dborderstatus;
+
+CREATE TYPE dborderstatus AS ENUM (
+    'New', 'InProgress', 'ReadyToShip', 'Shipping', 'Done', 'Cancelled'
+);
+
+ALTER TABLE orders
+    ALTER COLUMN status
+    TYPE dborderstatus
+    USING status::dborderstatus;
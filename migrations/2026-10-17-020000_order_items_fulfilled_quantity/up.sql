@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE "order_items"
+ADD COLUMN fulfilled_quantity BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE "order_items"
+ADD CONSTRAINT order_items_fulfilled_quantity_check CHECK (fulfilled_quantity >= 0 AND fulfilled_quantity <= quantity);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE return_items;
+DROP TABLE returns;
+DROP TYPE dbreturnstatus;
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TYPE dbreturnstatus AS ENUM (
+    'Requested', 'Approved', 'Received', 'Refunded', 'Rejected'
+);
+
+CREATE TABLE "returns" (
+    id BIGSERIAL PRIMARY KEY,
+    order_id BIGINT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    status dbreturnstatus NOT NULL DEFAULT 'Requested',
+    reason TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP
+);
+
+CREATE INDEX returns_order_id_idx ON returns (order_id);
+
+CREATE TABLE "return_items" (
+    id BIGSERIAL PRIMARY KEY,
+    return_id BIGINT NOT NULL REFERENCES returns(id) ON DELETE CASCADE,
+    order_item_id BIGINT NOT NULL REFERENCES order_items(id) ON DELETE CASCADE,
+    quantity BIGINT NOT NULL CHECK (quantity > 0)
+);
+
+CREATE INDEX return_items_return_id_idx ON return_items (return_id);
@@ -0,0 +1,24 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE orders
+    ALTER COLUMN payment_status DROP DEFAULT;
+
+ALTER TABLE orders
+    ALTER COLUMN payment_status
+    TYPE text
+    USING payment_status::text;
+
+UPDATE orders SET payment_status = 'Paid' WHERE payment_status = 'PartiallyRefunded';
+
+DROP TYPE dbpaymentstatus;
+
+CREATE TYPE dbpaymentstatus AS ENUM (
+    'Pending', 'Paid', 'Failed', 'Refunded'
+);
+
+ALTER TABLE orders
+    ALTER COLUMN payment_status
+    TYPE dbpaymentstatus
+    USING payment_status::dbpaymentstatus;
+
+ALTER TABLE orders
+    ALTER COLUMN payment_status SET DEFAULT 'Pending';
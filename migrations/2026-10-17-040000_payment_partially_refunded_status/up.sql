@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TYPE dbpaymentstatus ADD VALUE 'PartiallyRefunded';
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE TABLE "refunds" (
+    id BIGSERIAL PRIMARY KEY,
+    order_id BIGINT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    currency VARCHAR NOT NULL,
+    reason TEXT NOT NULL,
+    payment_reference VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX refunds_order_id_idx ON refunds (order_id);
+
+CREATE TABLE "refund_items" (
+    id BIGSERIAL PRIMARY KEY,
+    refund_id BIGINT NOT NULL REFERENCES refunds(id) ON DELETE CASCADE,
+    order_item_id BIGINT NOT NULL REFERENCES order_items(id) ON DELETE CASCADE,
+    quantity BIGINT NOT NULL CHECK (quantity > 0)
+);
+
+CREATE INDEX refund_items_refund_id_idx ON refund_items (refund_id);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE "order_status_history" (
+    id BIGSERIAL PRIMARY KEY,
+    order_id BIGINT NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    old_status dborderstatus NOT NULL,
+    new_status dborderstatus NOT NULL,
+    old_payment_status dbpaymentstatus NOT NULL,
+    new_payment_status dbpaymentstatus NOT NULL,
+    actor TEXT,
+    comment TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX order_status_history_order_id_idx ON order_status_history (order_id);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM settings WHERE title = 'order_number_format';
+
+DROP INDEX orders_order_number_idx;
+
+ALTER TABLE "orders"
+DROP COLUMN order_number;
+
+DROP TABLE number_sequences;
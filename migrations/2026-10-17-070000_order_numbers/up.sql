@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE "number_sequences" (
+    name TEXT NOT NULL,
+    period TEXT NOT NULL DEFAULT '',
+    value BIGINT NOT NULL,
+    PRIMARY KEY (name, period)
+);
+
+ALTER TABLE "orders"
+ADD COLUMN order_number TEXT;
+
+CREATE UNIQUE INDEX orders_order_number_idx ON orders (order_number) WHERE order_number IS NOT NULL;
+
+INSERT INTO settings (title, datatype, value) VALUES ('order_number_format', 'String', '{year}-{counter:6}');
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM settings WHERE title = 'credit_note_number_format';
+DELETE FROM settings WHERE title = 'invoice_number_format';
+
+DROP TABLE invoice_lines;
+DROP TABLE invoices;
+DROP FUNCTION prevent_invoice_modification();
+DROP TYPE dbinvoicekind;
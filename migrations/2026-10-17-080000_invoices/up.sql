@@ -0,0 +1,57 @@
+-- Your SQL goes here
+CREATE TYPE dbinvoicekind AS ENUM (
+    'Invoice', 'CreditNote'
+);
+
+CREATE TABLE "invoices" (
+    id BIGSERIAL PRIMARY KEY,
+    order_id BIGINT NOT NULL REFERENCES orders(id),
+    invoice_number TEXT NOT NULL UNIQUE,
+    kind dbinvoicekind NOT NULL,
+    corrected_invoice_id BIGINT REFERENCES invoices(id),
+    refund_id BIGINT UNIQUE REFERENCES refunds(id),
+    billing_address TEXT NOT NULL,
+    delivery_address TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    vat_rate DOUBLE PRECISION NOT NULL,
+    net_total BIGINT NOT NULL,
+    vat_total BIGINT NOT NULL,
+    gross_total BIGINT NOT NULL,
+    issued_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX invoices_order_id_idx ON invoices (order_id);
+CREATE UNIQUE INDEX invoices_one_invoice_per_order_idx ON invoices (order_id) WHERE kind = 'Invoice';
+
+CREATE TABLE "invoice_lines" (
+    id BIGSERIAL PRIMARY KEY,
+    invoice_id BIGINT NOT NULL REFERENCES invoices(id),
+    order_item_id BIGINT REFERENCES order_items(id),
+    article_number TEXT NOT NULL,
+    title TEXT NOT NULL,
+    quantity BIGINT NOT NULL,
+    unit_price BIGINT NOT NULL,
+    net_amount BIGINT NOT NULL,
+    vat_amount BIGINT NOT NULL,
+    gross_amount BIGINT NOT NULL
+);
+
+CREATE INDEX invoice_lines_invoice_id_idx ON invoice_lines (invoice_id);
+
+-- Issued invoices and credit notes are legal documents and must never change.
+CREATE FUNCTION prevent_invoice_modification() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'Invoices are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER invoices_immutable
+    BEFORE UPDATE OR DELETE ON invoices
+    FOR EACH ROW EXECUTE FUNCTION prevent_invoice_modification();
+
+CREATE TRIGGER invoice_lines_immutable
+    BEFORE UPDATE OR DELETE ON invoice_lines
+    FOR EACH ROW EXECUTE FUNCTION prevent_invoice_modification();
+
+INSERT INTO settings (title, datatype, value) VALUES ('invoice_number_format', 'String', 'RE-{year}-{counter:6}');
+INSERT INTO settings (title, datatype, value) VALUES ('credit_note_number_format', 'String', 'GS-{year}-{counter:6}');
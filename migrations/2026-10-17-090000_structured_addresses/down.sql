@@ -0,0 +1,20 @@
+-- This file should undo anything in `up.sql`
+CREATE FUNCTION jsonb_address_to_text(address JSONB) RETURNS TEXT AS $$
+    SELECT concat_ws(', ',
+        NULLIF(address->>'name', ''),
+        NULLIF(address->>'company', ''),
+        NULLIF(trim(concat_ws(' ', address->>'street', address->>'house_number')), ''),
+        NULLIF(trim(concat_ws(' ', address->>'postal_code', address->>'city')), ''),
+        NULLIF(address->>'country', '')
+    );
+$$ LANGUAGE sql IMMUTABLE;
+
+ALTER TABLE invoices
+    ALTER COLUMN billing_address TYPE TEXT USING jsonb_address_to_text(billing_address),
+    ALTER COLUMN delivery_address TYPE TEXT USING jsonb_address_to_text(delivery_address);
+
+ALTER TABLE orders
+    ALTER COLUMN delivery_address TYPE TEXT USING jsonb_address_to_text(delivery_address),
+    ALTER COLUMN billing_address TYPE TEXT USING jsonb_address_to_text(billing_address);
+
+DROP FUNCTION jsonb_address_to_text(JSONB);
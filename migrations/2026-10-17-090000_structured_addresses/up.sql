@@ -0,0 +1,25 @@
+-- Your SQL goes here
+-- Free-text addresses are carried over into the street field so no data is lost;
+-- they have to be completed manually before they can be used for shipping labels.
+CREATE FUNCTION legacy_address_to_jsonb(address TEXT) RETURNS JSONB AS $$
+    SELECT jsonb_build_object(
+        'name', '',
+        'company', NULL,
+        'street', address,
+        'house_number', '',
+        'postal_code', '',
+        'city', '',
+        'country', '',
+        'phone', NULL
+    );
+$$ LANGUAGE sql IMMUTABLE;
+
+ALTER TABLE orders
+    ALTER COLUMN delivery_address TYPE JSONB USING legacy_address_to_jsonb(delivery_address),
+    ALTER COLUMN billing_address TYPE JSONB USING legacy_address_to_jsonb(billing_address);
+
+ALTER TABLE invoices
+    ALTER COLUMN billing_address TYPE JSONB USING legacy_address_to_jsonb(billing_address),
+    ALTER COLUMN delivery_address TYPE JSONB USING legacy_address_to_jsonb(delivery_address);
+
+DROP FUNCTION legacy_address_to_jsonb(TEXT);
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE "customer_addresses" (
+    id BIGSERIAL PRIMARY KEY,
+    customer_id UUID NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    address JSONB NOT NULL,
+    is_default_billing BOOLEAN NOT NULL DEFAULT FALSE,
+    is_default_shipping BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP
+);
+
+CREATE INDEX customer_addresses_customer_id_idx ON customer_addresses (customer_id);
+-- A customer has at most one default billing and one default shipping address.
+CREATE UNIQUE INDEX customer_addresses_default_billing_idx ON customer_addresses (customer_id) WHERE is_default_billing;
+CREATE UNIQUE INDEX customer_addresses_default_shipping_idx ON customer_addresses (customer_id) WHERE is_default_shipping;
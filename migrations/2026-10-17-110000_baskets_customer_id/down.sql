@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX baskets_customer_id_idx;
+ALTER TABLE "baskets" DROP COLUMN customer_id;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE "baskets"
+ADD COLUMN customer_id UUID REFERENCES customers(id) ON DELETE SET NULL;
+
+-- Each customer has at most one persistent basket.
+CREATE UNIQUE INDEX baskets_customer_id_idx ON baskets (customer_id) WHERE customer_id IS NOT NULL;
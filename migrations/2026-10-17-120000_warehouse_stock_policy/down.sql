@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "warehouse"
+DROP COLUMN backordered,
+DROP COLUMN stock_policy;
+
+DROP TYPE dbstockpolicy;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TYPE dbstockpolicy AS ENUM (
+    'Deny', 'Backorder', 'Unlimited'
+);
+
+ALTER TABLE "warehouse"
+ADD COLUMN stock_policy dbstockpolicy NOT NULL DEFAULT 'Deny',
+ADD COLUMN backordered BIGINT NOT NULL DEFAULT 0 CHECK (backordered >= 0);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE stock_movements;
+DROP FUNCTION prevent_stock_movement_modification();
+DROP TYPE dbstockmovementkind;
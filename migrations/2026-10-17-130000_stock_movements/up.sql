@@ -0,0 +1,30 @@
+-- Your SQL goes here
+CREATE TYPE dbstockmovementkind AS ENUM (
+    'GoodsReceipt', 'Sale', 'Return', 'Correction', 'Reservation', 'Release'
+);
+
+-- Order and return ids are kept as plain references, so the ledger survives
+-- the deletion of the documents it points to.
+CREATE TABLE "stock_movements" (
+    id BIGSERIAL PRIMARY KEY,
+    product_id BIGINT NOT NULL,
+    kind dbstockmovementkind NOT NULL,
+    quantity BIGINT NOT NULL,
+    reason TEXT,
+    order_id BIGINT,
+    return_id BIGINT,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX stock_movements_product_id_created_at_idx ON stock_movements (product_id, created_at);
+
+-- The ledger is append-only.
+CREATE FUNCTION prevent_stock_movement_modification() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'Stock movements are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER stock_movements_immutable
+    BEFORE UPDATE OR DELETE ON stock_movements
+    FOR EACH ROW EXECUTE FUNCTION prevent_stock_movement_modification();
@@ -0,0 +1,26 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM settings WHERE title = 'stock_allocation_strategy';
+
+ALTER TABLE "stock_movements" DROP COLUMN location_id;
+
+-- Stock of all locations is merged into the oldest row of each product.
+UPDATE warehouse w
+SET in_stock = totals.in_stock,
+    reserved = totals.reserved,
+    backordered = totals.backordered
+FROM (
+    SELECT min(id) AS id, sum(in_stock) AS in_stock, sum(reserved) AS reserved, sum(backordered) AS backordered
+    FROM warehouse
+    GROUP BY product_id
+) totals
+WHERE w.id = totals.id;
+
+DELETE FROM warehouse w
+WHERE w.id <> (SELECT min(other.id) FROM warehouse other WHERE other.product_id = w.product_id);
+
+DROP INDEX warehouse_product_id_idx;
+ALTER TABLE "warehouse" DROP CONSTRAINT warehouse_location_id_product_id_key;
+ALTER TABLE "warehouse" DROP COLUMN location_id;
+ALTER TABLE "warehouse" ADD CONSTRAINT warehouse_product_id_key UNIQUE (product_id);
+
+DROP TABLE locations;
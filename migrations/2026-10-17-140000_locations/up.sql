@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TABLE "locations" (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    address JSONB,
+    priority INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP
+);
+
+-- Existing stock is kept at a default location.
+INSERT INTO locations (name, priority) VALUES ('Default', 0);
+
+ALTER TABLE "warehouse" ADD COLUMN location_id BIGINT REFERENCES locations(id);
+UPDATE warehouse SET location_id = (SELECT id FROM locations WHERE name = 'Default');
+ALTER TABLE "warehouse" ALTER COLUMN location_id SET NOT NULL;
+
+ALTER TABLE "warehouse" DROP CONSTRAINT warehouse_product_id_key;
+ALTER TABLE "warehouse" ADD CONSTRAINT warehouse_location_id_product_id_key UNIQUE (location_id, product_id);
+CREATE INDEX warehouse_product_id_idx ON warehouse (product_id);
+
+ALTER TABLE "stock_movements" ADD COLUMN location_id BIGINT;
+
+INSERT INTO settings (title, datatype, value) VALUES ('stock_allocation_strategy', 'String', 'priority');
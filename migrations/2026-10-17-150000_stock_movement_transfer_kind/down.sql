@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+-- Changing the column type does not fire the immutability trigger.
+ALTER TABLE stock_movements
+    ALTER COLUMN kind
+    TYPE text
+    USING (CASE WHEN kind = 'Transfer' THEN 'Correction' ELSE kind::text END);
+
+DROP TYPE dbstockmovementkind;
+
+CREATE TYPE dbstockmovementkind AS ENUM (
+    'GoodsReceipt', 'Sale', 'Return', 'Correction', 'Reservation', 'Release'
+);
+
+ALTER TABLE stock_movements
+    ALTER COLUMN kind
+    TYPE dbstockmovementkind
+    USING kind::dbstockmovementkind;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TYPE dbstockmovementkind ADD VALUE 'Transfer';
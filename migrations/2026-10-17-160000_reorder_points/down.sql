@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "stock_alerts";
+DROP TABLE "reorder_points";
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TABLE "reorder_points" (
+    id BIGSERIAL PRIMARY KEY,
+    product_id BIGINT NOT NULL UNIQUE,
+    reorder_point BIGINT NOT NULL CHECK (reorder_point >= 0),
+    reorder_quantity BIGINT NOT NULL CHECK (reorder_quantity > 0),
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP
+);
+
+-- Written in the transaction that lets the available stock of a product drop
+-- to or below its reorder point, so no alert is lost or sent for a rollback.
+CREATE TABLE "stock_alerts" (
+    id BIGSERIAL PRIMARY KEY,
+    product_id BIGINT NOT NULL,
+    available BIGINT NOT NULL,
+    reorder_point BIGINT NOT NULL,
+    reorder_quantity BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    acknowledged_at TIMESTAMP
+);
+
+CREATE INDEX stock_alerts_open_idx ON stock_alerts (created_at) WHERE acknowledged_at IS NULL;
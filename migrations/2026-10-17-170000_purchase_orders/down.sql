@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE stock_movements DROP COLUMN purchase_order_id;
+DROP TABLE purchase_order_items;
+DROP TABLE purchase_orders;
+DROP TYPE dbpurchaseorderstatus;
+DROP TABLE suppliers;
@@ -0,0 +1,40 @@
+-- Your SQL goes here
+CREATE TABLE "suppliers" (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    email TEXT,
+    phone TEXT,
+    address JSONB,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP
+);
+
+CREATE TYPE dbpurchaseorderstatus AS ENUM (
+    'Draft', 'Ordered', 'PartiallyReceived', 'Received'
+);
+
+-- Goods are received at `location_id`, or at the default location if it is NULL.
+CREATE TABLE "purchase_orders" (
+    id BIGSERIAL PRIMARY KEY,
+    supplier_id BIGINT NOT NULL REFERENCES suppliers(id),
+    location_id BIGINT REFERENCES locations(id),
+    status dbpurchaseorderstatus NOT NULL DEFAULT 'Draft',
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    updated_at TIMESTAMP,
+    ordered_at TIMESTAMP,
+    received_at TIMESTAMP
+);
+
+CREATE INDEX purchase_orders_supplier_id_idx ON purchase_orders (supplier_id);
+
+CREATE TABLE "purchase_order_items" (
+    id BIGSERIAL PRIMARY KEY,
+    purchase_order_id BIGINT NOT NULL REFERENCES purchase_orders(id) ON DELETE CASCADE,
+    product_id BIGINT NOT NULL,
+    quantity BIGINT NOT NULL CHECK (quantity > 0),
+    received_quantity BIGINT NOT NULL DEFAULT 0 CHECK (received_quantity >= 0 AND received_quantity <= quantity)
+);
+
+CREATE INDEX purchase_order_items_purchase_order_id_idx ON purchase_order_items (purchase_order_id);
+
+ALTER TABLE stock_movements ADD COLUMN purchase_order_id BIGINT;
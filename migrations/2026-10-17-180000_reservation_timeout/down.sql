@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM settings WHERE title = 'reservation_timeout_minutes';
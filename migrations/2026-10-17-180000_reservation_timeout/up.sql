@@ -0,0 +1,2 @@
+-- Your SQL goes here
+INSERT INTO settings (title, datatype, value) VALUES ('reservation_timeout_minutes', 'Integer', '1440');
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM settings WHERE title = 'basket_reservation_minutes';
+
+DROP INDEX basketproducts_reserved_until_idx;
+
+ALTER TABLE "basketproducts"
+DROP COLUMN reserved_until,
+DROP COLUMN reserved_quantity;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE "basketproducts"
+ADD COLUMN reserved_quantity BIGINT NOT NULL DEFAULT 0 CHECK (reserved_quantity >= 0),
+ADD COLUMN reserved_until TIMESTAMP;
+
+CREATE INDEX basketproducts_reserved_until_idx ON basketproducts (reserved_until) WHERE reserved_quantity > 0;
+
+INSERT INTO settings (title, datatype, value) VALUES ('basket_reservation_minutes', 'Integer', '0');
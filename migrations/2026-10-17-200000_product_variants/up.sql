@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE "product_variants" (
+    product_id BIGINT PRIMARY KEY REFERENCES products(id) ON DELETE CASCADE,
+    parent_id BIGINT NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    options JSONB NOT NULL,
+    price BIGINT CHECK (price >= 0),
+    currency TEXT,
+    weight INTEGER CHECK (weight >= 0),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP,
+    CHECK (product_id <> parent_id),
+    CHECK ((price IS NULL) = (currency IS NULL))
+);
+
+CREATE UNIQUE INDEX product_variants_parent_id_options_idx ON product_variants (parent_id, options);
+
+ALTER TABLE "order_items"
+ADD COLUMN variant_options JSONB NOT NULL DEFAULT '[]';
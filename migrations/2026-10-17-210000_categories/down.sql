@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE product_categories;
+DROP TABLE categories;
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+CREATE TABLE categories (
+    id BIGSERIAL PRIMARY KEY,
+    parent_id BIGINT REFERENCES categories (id) ON DELETE RESTRICT,
+    title TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP,
+    CHECK (parent_id <> id)
+);
+
+CREATE UNIQUE INDEX categories_slug_idx ON categories (slug);
+CREATE INDEX categories_parent_id_idx ON categories (parent_id, position);
+
+CREATE TABLE product_categories (
+    product_id BIGINT NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    category_id BIGINT NOT NULL REFERENCES categories (id) ON DELETE CASCADE,
+    PRIMARY KEY (product_id, category_id)
+);
+
+CREATE INDEX product_categories_category_id_idx ON product_categories (category_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX products_search_german_idx;
+DROP INDEX products_search_english_idx;
+DROP FUNCTION product_search_document(REGCONFIG, TEXT, TEXT, TEXT, TEXT, TEXT);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE FUNCTION product_search_document(config REGCONFIG, article_number TEXT, title TEXT, tags TEXT, short_description TEXT, description TEXT)
+RETURNS TSVECTOR
+LANGUAGE SQL IMMUTABLE PARALLEL SAFE
+AS $$
+    SELECT setweight(to_tsvector('simple', article_number), 'A')
+        || setweight(to_tsvector(config, title), 'A')
+        || setweight(to_tsvector(config, replace(tags, '|', ' ')), 'B')
+        || setweight(to_tsvector(config, short_description), 'C')
+        || setweight(to_tsvector(config, description), 'D')
+$$;
+
+CREATE INDEX products_search_english_idx ON products
+    USING GIN (product_search_document('english', article_number, title, tags, short_description, description));
+CREATE INDEX products_search_german_idx ON products
+    USING GIN (product_search_document('german', article_number, title, tags, short_description, description));
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX products_tags_idx;
+DROP INDEX products_created_at_idx;
+DROP INDEX products_title_idx;
+DROP INDEX products_price_idx;
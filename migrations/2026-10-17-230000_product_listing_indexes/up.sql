@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE INDEX products_price_idx ON products (price, id);
+CREATE INDEX products_title_idx ON products (title, id);
+CREATE INDEX products_created_at_idx ON products (created_at, id);
+CREATE INDEX products_tags_idx ON products USING GIN (string_to_array(tags, '|'));
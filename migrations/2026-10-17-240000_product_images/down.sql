@@ -0,0 +1,22 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE products
+ADD COLUMN title_image TEXT NOT NULL DEFAULT '',
+ADD COLUMN additional_images TEXT NOT NULL DEFAULT '';
+
+-- Variants get a copy of the images of their parent again
+UPDATE products p
+SET title_image = images.urls[1],
+    additional_images = array_to_string(images.urls[2:], '|')
+FROM (
+    SELECT o.id, array_agg(i.url ORDER BY i.position) AS urls
+    FROM (
+        SELECT p.id, COALESCE(v.parent_id, p.id) AS owner_id
+        FROM products p
+        LEFT JOIN product_variants v ON v.product_id = p.id
+    ) o
+    JOIN product_images i ON i.product_id = o.owner_id
+    GROUP BY o.id
+) images
+WHERE images.id = p.id;
+
+DROP TABLE product_images;
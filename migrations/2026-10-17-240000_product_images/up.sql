@@ -0,0 +1,34 @@
+-- Your SQL goes here
+CREATE TABLE product_images (
+    id BIGSERIAL PRIMARY KEY,
+    product_id BIGINT NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    url TEXT NOT NULL CHECK (url <> ''),
+    position INTEGER NOT NULL CHECK (position >= 0),
+    alt_text JSONB NOT NULL DEFAULT '{}',
+    width INTEGER CHECK (width > 0),
+    height INTEGER CHECK (height > 0),
+    mime_type TEXT,
+    checksum TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP,
+    -- Deferred so that reordering can swap positions within a transaction
+    CONSTRAINT product_images_product_id_position_key UNIQUE (product_id, position) DEFERRABLE INITIALLY DEFERRED
+);
+
+-- The title image comes first, followed by the additional images in their
+-- stored order. Variants copied the images of their parent and get none.
+INSERT INTO product_images (product_id, url, position)
+SELECT images.product_id,
+       images.url,
+       (ROW_NUMBER() OVER (PARTITION BY images.product_id ORDER BY images.ordinality) - 1)::INTEGER
+FROM (
+    SELECT p.id AS product_id, i.url, i.ordinality
+    FROM products p,
+         unnest(ARRAY[p.title_image] || string_to_array(p.additional_images, '|')) WITH ORDINALITY AS i (url, ordinality)
+    WHERE NOT EXISTS (SELECT 1 FROM product_variants v WHERE v.product_id = p.id)
+) images
+WHERE images.url <> '';
+
+ALTER TABLE products
+DROP COLUMN title_image,
+DROP COLUMN additional_images;
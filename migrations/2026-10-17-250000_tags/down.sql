@@ -0,0 +1,34 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE products ADD COLUMN tags TEXT NOT NULL DEFAULT '';
+
+-- Variants get a copy of the tags of their parent again
+UPDATE products p
+SET tags = product_tags.titles
+FROM (
+    SELECT o.id, string_agg(t.title, '|' ORDER BY t.title) AS titles
+    FROM (
+        SELECT p.id, COALESCE(v.parent_id, p.id) AS owner_id
+        FROM products p
+        LEFT JOIN product_variants v ON v.product_id = p.id
+    ) o
+    JOIN product_tags pt ON pt.product_id = o.owner_id
+    JOIN tags t ON t.id = pt.tag_id
+    GROUP BY o.id
+) product_tags
+WHERE product_tags.id = p.id;
+
+DROP TRIGGER tags_search_tags ON tags;
+DROP FUNCTION tags_update_search_tags();
+DROP TRIGGER product_tags_search_tags ON product_tags;
+DROP FUNCTION product_tags_update_search_tags();
+DROP FUNCTION product_search_tags(BIGINT);
+ALTER TABLE products DROP COLUMN search_tags;
+
+DROP TABLE product_tags;
+DROP TABLE tags;
+
+CREATE INDEX products_search_english_idx ON products
+    USING GIN (product_search_document('english', article_number, title, tags, short_description, description));
+CREATE INDEX products_search_german_idx ON products
+    USING GIN (product_search_document('german', article_number, title, tags, short_description, description));
+CREATE INDEX products_tags_idx ON products USING GIN (string_to_array(tags, '|'));
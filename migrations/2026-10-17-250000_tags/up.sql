@@ -0,0 +1,86 @@
+-- Your SQL goes here
+CREATE TABLE tags (
+    id SERIAL PRIMARY KEY,
+    title TEXT NOT NULL UNIQUE CHECK (title <> ''),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP
+);
+
+CREATE TABLE product_tags (
+    product_id BIGINT NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    PRIMARY KEY (product_id, tag_id)
+);
+
+CREATE INDEX product_tags_tag_id_idx ON product_tags (tag_id);
+
+-- Variants copied the tags of their parent and get none
+INSERT INTO tags (title)
+SELECT DISTINCT trim(tag)
+FROM products p, unnest(string_to_array(p.tags, '|')) AS tag
+WHERE trim(tag) <> ''
+  AND NOT EXISTS (SELECT 1 FROM product_variants v WHERE v.product_id = p.id);
+
+INSERT INTO product_tags (product_id, tag_id)
+SELECT DISTINCT p.id, t.id
+FROM products p, unnest(string_to_array(p.tags, '|')) AS tag
+JOIN tags t ON t.title = trim(tag)
+WHERE NOT EXISTS (SELECT 1 FROM product_variants v WHERE v.product_id = p.id);
+
+-- Dropping the column drops the search and tag indexes built on it
+ALTER TABLE products DROP COLUMN tags;
+
+-- The tag titles of a product for the full-text search, which can only index
+-- columns of the product itself. Maintained by the triggers below.
+ALTER TABLE products ADD COLUMN search_tags TEXT NOT NULL DEFAULT '';
+
+CREATE FUNCTION product_search_tags(product_id BIGINT)
+RETURNS TEXT
+LANGUAGE SQL STABLE
+AS $$
+    SELECT COALESCE(string_agg(t.title, ' ' ORDER BY t.title), '')
+    FROM product_tags pt
+    JOIN tags t ON t.id = pt.tag_id
+    WHERE pt.product_id = $1
+$$;
+
+CREATE FUNCTION product_tags_update_search_tags()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        UPDATE products SET search_tags = product_search_tags(id) WHERE id = NEW.product_id;
+    END IF;
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        UPDATE products SET search_tags = product_search_tags(id) WHERE id = OLD.product_id;
+    END IF;
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER product_tags_search_tags
+AFTER INSERT OR UPDATE OR DELETE ON product_tags
+FOR EACH ROW EXECUTE FUNCTION product_tags_update_search_tags();
+
+CREATE FUNCTION tags_update_search_tags()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE products SET search_tags = product_search_tags(id)
+    WHERE id IN (SELECT pt.product_id FROM product_tags pt WHERE pt.tag_id = NEW.id);
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER tags_search_tags
+AFTER UPDATE OF title ON tags
+FOR EACH ROW EXECUTE FUNCTION tags_update_search_tags();
+
+UPDATE products SET search_tags = product_search_tags(id);
+
+CREATE INDEX products_search_english_idx ON products
+    USING GIN (product_search_document('english', article_number, title, search_tags, short_description, description));
+CREATE INDEX products_search_german_idx ON products
+    USING GIN (product_search_document('german', article_number, title, search_tags, short_description, description));
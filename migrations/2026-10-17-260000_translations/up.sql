@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- Texts in other languages than the shop's `language` setting. The product and
+-- category rows hold the texts in that default language.
+CREATE TABLE product_translations (
+    product_id BIGINT NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+    language TEXT NOT NULL CHECK (language <> ''),
+    title TEXT,
+    short_description TEXT,
+    description TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP,
+    PRIMARY KEY (product_id, language)
+);
+
+CREATE TABLE category_translations (
+    category_id BIGINT NOT NULL REFERENCES categories (id) ON DELETE CASCADE,
+    language TEXT NOT NULL CHECK (language <> ''),
+    title TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP,
+    PRIMARY KEY (category_id, language)
+);
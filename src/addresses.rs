@@ -0,0 +1,152 @@
+//! Structured postal addresses.
+//!
+//! Addresses are stored as typed documents instead of free text so shipping
+//! labels and tax rules can rely on the individual fields. Postal codes are
+//! validated against the format used in the address' country.
+
+use std::fmt;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::ShopsterError;
+use crate::postgresql::dbaddress::DbAddress;
+
+/// Postal code formats per ISO 3166-1 alpha-2 country code.
+///
+/// `N` stands for a digit, `A` for a letter and `X` for either; every other
+/// character has to match literally. Countries not listed here are validated
+/// leniently by `is_plausible_postal_code`.
+const POSTAL_CODE_FORMATS: &[(&str, &[&str])] = &[
+    ("AT", &["NNNN"]),
+    ("BE", &["NNNN"]),
+    ("CA", &["ANA NAN", "ANANAN"]),
+    ("CH", &["NNNN"]),
+    ("CZ", &["NNN NN", "NNNNN"]),
+    ("DE", &["NNNNN"]),
+    ("DK", &["NNNN"]),
+    ("ES", &["NNNNN"]),
+    ("FI", &["NNNNN"]),
+    ("FR", &["NNNNN"]),
+    ("GB", &["AN NAA", "ANN NAA", "AAN NAA", "AANN NAA", "ANA NAA", "AANA NAA"]),
+    ("IE", &["XXX XXXX", "XXXXXXX"]),
+    ("IT", &["NNNNN"]),
+    ("LI", &["NNNN"]),
+    ("LU", &["NNNN"]),
+    ("NL", &["NNNN AA", "NNNNAA"]),
+    ("NO", &["NNNN"]),
+    ("PL", &["NN-NNN"]),
+    ("PT", &["NNNN-NNN"]),
+    ("SE", &["NNN NN", "NNNNN"]),
+    ("SK", &["NNN NN", "NNNNN"]),
+    ("US", &["NNNNN", "NNNNN-NNNN"]),
+];
+
+/// A postal address used for delivery and billing.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Address {
+    /// Name of the recipient.
+    pub name: String,
+    pub company: Option<String>,
+    pub street: String,
+    pub house_number: String,
+    pub postal_code: String,
+    pub city: String,
+    /// ISO 3166-1 alpha-2 country code, e.g. `DE`.
+    pub country: String,
+    pub phone: Option<String>,
+}
+
+impl Address {
+    /// Checks that all mandatory fields are present, the country is an
+    /// ISO 3166-1 alpha-2 code and the postal code matches the country's format.
+    pub fn validate(&self) -> Result<(), ShopsterError> {
+        for (field, value) in [("name", &self.name), ("street", &self.street), ("city", &self.city)] {
+            if value.trim().is_empty() {
+                return Err(ShopsterError::InvalidOperationError(format!("Address {} cannot be empty", field)));
+            }
+        }
+
+        if self.country.len() != 2 || !self.country.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Country '{}' is not an ISO 3166-1 alpha-2 code",
+                self.country
+            )));
+        }
+
+        if !is_valid_postal_code(&self.country, &self.postal_code) {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Postal code '{}' is not valid for country {}",
+                self.postal_code, self.country
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Address {
+    /// Formats the address as a multi-line label.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.name)?;
+        if let Some(company) = self.company.as_ref().filter(|c| !c.trim().is_empty()) {
+            writeln!(f, "{}", company)?;
+        }
+        writeln!(f, "{} {}", self.street, self.house_number)?;
+        writeln!(f, "{} {}", self.postal_code, self.city)?;
+        write!(f, "{}", self.country)
+    }
+}
+
+impl From<&DbAddress> for Address {
+    fn from(db_address: &DbAddress) -> Self {
+        Address {
+            name: db_address.name.clone(),
+            company: db_address.company.clone(),
+            street: db_address.street.clone(),
+            house_number: db_address.house_number.clone(),
+            postal_code: db_address.postal_code.clone(),
+            city: db_address.city.clone(),
+            country: db_address.country.clone(),
+            phone: db_address.phone.clone(),
+        }
+    }
+}
+
+impl From<&Address> for DbAddress {
+    fn from(address: &Address) -> Self {
+        DbAddress {
+            name: address.name.clone(),
+            company: address.company.clone(),
+            street: address.street.clone(),
+            house_number: address.house_number.clone(),
+            postal_code: address.postal_code.clone(),
+            city: address.city.clone(),
+            country: address.country.clone(),
+            phone: address.phone.clone(),
+        }
+    }
+}
+
+/// Returns true if `postal_code` matches one of the formats known for `country`.
+pub fn is_valid_postal_code(country: &str, postal_code: &str) -> bool {
+    let postal_code = postal_code.trim().to_ascii_uppercase();
+    match POSTAL_CODE_FORMATS.iter().find(|(code, _)| *code == country) {
+        Some((_, formats)) => formats.iter().any(|format| matches_format(format, &postal_code)),
+        None => is_plausible_postal_code(&postal_code),
+    }
+}
+
+fn matches_format(format: &str, postal_code: &str) -> bool {
+    format.len() == postal_code.len()
+        && format.chars().zip(postal_code.chars()).all(|(f, c)| match f {
+            'N' => c.is_ascii_digit(),
+            'A' => c.is_ascii_alphabetic(),
+            'X' => c.is_ascii_alphanumeric(),
+            literal => literal == c,
+        })
+}
+
+fn is_plausible_postal_code(postal_code: &str) -> bool {
+    !postal_code.is_empty()
+        && postal_code.len() <= 10
+        && postal_code.chars().any(|c| c.is_ascii_alphanumeric())
+        && postal_code.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ' || c == '-')
+}
@@ -1,19 +1,30 @@
 //! Shopping basket management for customers.
 
 use uuid::Uuid;
-use chrono::NaiveDateTime;
-use diesel_async::AsyncConnection;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel_async::{AsyncConnection, AsyncPgConnection};
 
 use crate::aquire_pool;
 use crate::{postgresql::dbbasket::DbBasket, error::ShopsterError};
+use crate::error::StockShortage;
 use crate::postgresql::dbbasket::DbBasketProduct;
+use crate::postgresql::dbproductvariant::DbProductVariant;
+use crate::postgresql::dbsettings::DbSetting;
+use crate::postgresql::dbstockmovement::DbMovementReference;
+use crate::postgresql::dbwarehouse::{DbStockPolicy, DbWarehouse};
+
+const RESERVATION_TTL_SETTING: &str = "basket_reservation_minutes";
 
 /// A product within a shopping basket.
 #[derive(Clone)]
 pub struct BasketProduct {
     pub id: i64,
     pub product_id: i64,
-    pub quantity: i64
+    pub quantity: i64,
+    /// Units held in the warehouse for this line; see `Baskets::reservation_ttl`.
+    pub reserved_quantity: i64,
+    /// When the hold is released by `Baskets::release_expired_reservations`.
+    pub reserved_until: Option<NaiveDateTime>
 }
 
 impl From<&DbBasketProduct> for BasketProduct {
@@ -21,7 +32,9 @@ impl From<&DbBasketProduct> for BasketProduct {
         BasketProduct {
             id: db_basket_product.id,
             product_id: db_basket_product.product_id,
-            quantity: db_basket_product.quantity
+            quantity: db_basket_product.quantity,
+            reserved_quantity: db_basket_product.reserved_quantity,
+            reserved_until: db_basket_product.reserved_until
         }
     }
 }
@@ -29,6 +42,8 @@ impl From<&DbBasketProduct> for BasketProduct {
 /// A shopping basket.
 pub struct Basket {
     pub id: Uuid,
+    /// The customer owning this persistent basket; `None` for guest baskets.
+    pub customer_id: Option<Uuid>,
     pub products: Vec<BasketProduct>,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>
@@ -38,6 +53,7 @@ impl From<&DbBasket> for Basket {
     fn from(db_basket: &DbBasket) -> Self {
         Basket {
             id: db_basket.id,
+            customer_id: db_basket.customer_id,
             products: Vec::new(),
             created_at: db_basket.created_at,
             updated_at: db_basket.updated_at
@@ -51,6 +67,7 @@ impl From<&Basket> for DbBasket {
             id: basket.id,
             created_at: basket.created_at,
             updated_at: basket.updated_at,
+            customer_id: basket.customer_id,
         }
     }
 }
@@ -92,8 +109,31 @@ impl Baskets {
         Ok(basket)
     }
 
+    /// Returns the persistent basket of a customer, if they have one.
+    pub async fn get_for_customer(&self, customer_id: Uuid) -> Result<Option<Basket>, ShopsterError> {
+        match DbBasket::find_for_customer(self.tenant_id, customer_id).await? {
+            Some(db_basket) => {
+                let mut basket = Basket::from(&db_basket);
+                basket.products = self.get_products_from_basket(basket.id).await?;
+                Ok(Some(basket))
+            },
+            None => Ok(None),
+        }
+    }
+
     pub async fn add_basket(&self) -> Result<Uuid, ShopsterError> {
-        let db_basket = DbBasket::create(self.tenant_id).await?;
+        let db_basket = DbBasket::create(self.tenant_id, None).await?;
+        Ok(db_basket.id)
+    }
+
+    /// Creates the persistent basket of a customer. Each customer can have only one.
+    pub async fn add_basket_for_customer(&self, customer_id: Uuid) -> Result<Uuid, ShopsterError> {
+        if DbBasket::find_for_customer(self.tenant_id, customer_id).await?.is_some() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Customer already has a basket".to_string(),
+            ));
+        }
+        let db_basket = DbBasket::create(self.tenant_id, Some(customer_id)).await?;
         Ok(db_basket.id)
     }
 
@@ -103,53 +143,222 @@ impl Baskets {
         Ok(deleted_baskets > 0)
     }
 
+    /// Puts a product into a basket, replacing the quantity if it is already
+    /// there. While basket reservations are enabled, the quantity is held in
+    /// the warehouse (see `reservation_ttl`); for a product with the `Deny`
+    /// stock policy this fails with `ShopsterError::InsufficientStockError` if
+    /// not enough stock is available. Products with variants cannot be added
+    /// themselves, only their variants.
     pub async fn add_product_to_basket(&self, basket_id: Uuid, product_id: i64, quantity: i64) -> Result<i64, ShopsterError> {
         if quantity <= 0 {
             return Err(ShopsterError::InvalidOperationError(
                 "Quantity must be positive".to_string(),
             ));
         }
-        let items = DbBasketProduct::get_basket_items(self.tenant_id, basket_id).await?;
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
-        if let Some(mut item) = items.into_iter().find(|x| x.product_id == product_id) {
-            item.quantity = quantity;
-            let updated_item = DbBasketProduct::update_basket_item(self.tenant_id, item.id, item).await?;
-            Ok(updated_item.id)
-        } else {
-            let basket_product = DbBasketProduct { id: 0, product_id, quantity, basket_id };
-            let new_item = DbBasketProduct::create_basket_item(self.tenant_id, basket_product).await?;
-            Ok(new_item.id)
-        }
+        conn.transaction(async |conn| {
+            if DbProductVariant::has_variants_conn(conn, product_id).await? {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Product {} has variants, one of them must be chosen",
+                    product_id
+                )));
+            }
+            DbBasket::find_for_update_conn(conn, basket_id).await?;
+            let items = DbBasketProduct::get_basket_items_for_update_conn(conn, basket_id).await?;
+
+            let item = if let Some(mut item) = items.into_iter().find(|x| x.product_id == product_id) {
+                item.quantity = quantity;
+                DbBasketProduct::update_basket_item_conn(conn, item.id, item).await?
+            } else {
+                let basket_product = DbBasketProduct { id: 0, product_id, quantity, basket_id, reserved_quantity: 0, reserved_until: None };
+                DbBasketProduct::create_basket_item_conn(conn, basket_product).await?
+            };
+            let ttl = Self::reservation_ttl_conn(conn).await?;
+            let item = Self::hold_conn(conn, item, ttl, Utc::now().naive_utc()).await?;
+            Ok(item.id)
+        }).await
     }
 
+    /// Changes the quantity of a basket line and adjusts its stock hold like
+    /// `add_product_to_basket`.
     pub async fn update_product_quantity(&self, basket_id: Uuid, basket_product_id: i64, quantity: i64) -> Result<BasketProduct, ShopsterError> {
         if quantity <= 0 {
             return Err(ShopsterError::InvalidOperationError(
                 "Quantity must be positive".to_string(),
             ));
         }
-        let basket_product = DbBasketProduct::find_basket_item(self.tenant_id, basket_product_id).await?;
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
-        if basket_product.basket_id != basket_id {
-            return Err(ShopsterError::InvalidOperationError("Produkt gehört nicht zu diesem Warenkorb".to_string()));
-        }
+        conn.transaction(async |conn| {
+            let basket_product = DbBasketProduct::find_basket_item_for_update_conn(conn, basket_product_id).await?;
+
+            if basket_product.basket_id != basket_id {
+                return Err(ShopsterError::InvalidOperationError("Produkt gehört nicht zu diesem Warenkorb".to_string()));
+            }
 
-        let mut updated_product = basket_product;
-        updated_product.quantity = quantity;
+            let mut updated_product = basket_product;
+            updated_product.quantity = quantity;
 
-        let result = DbBasketProduct::update_basket_item(self.tenant_id, basket_product_id, updated_product).await?;
-        Ok(BasketProduct::from(&result))
+            let updated_product = DbBasketProduct::update_basket_item_conn(conn, basket_product_id, updated_product).await?;
+            let ttl = Self::reservation_ttl_conn(conn).await?;
+            let result = Self::hold_conn(conn, updated_product, ttl, Utc::now().naive_utc()).await?;
+            Ok(BasketProduct::from(&result))
+        }).await
     }
 
+    /// Removes a line from a basket and releases its stock hold.
     pub async fn remove_product_from_basket(&self, basket_id: Uuid, basket_product_id: i64) -> Result<bool, ShopsterError> {
-        let basket_product = DbBasketProduct::find_basket_item(self.tenant_id, basket_product_id).await?;
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let basket_product = DbBasketProduct::find_basket_item_for_update_conn(conn, basket_product_id).await?;
+
+            if basket_product.basket_id != basket_id {
+                return Err(ShopsterError::InvalidOperationError("Produkt gehört nicht zu diesem Warenkorb".to_string()));
+            }
+
+            Self::release_hold_conn(conn, &basket_product).await?;
+            let result = DbBasketProduct::delete_basket_item_conn(conn, basket_product_id).await?;
+            Ok(result > 0)
+        }).await
+    }
+
+    /// Returns for how long basket lines hold their stock, from the
+    /// `basket_reservation_minutes` setting. `None` (a value of `0`, the
+    /// default) means stock is only reserved once an order is placed.
+    pub async fn reservation_ttl(&self) -> Result<Option<Duration>, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        Self::reservation_ttl_conn(&mut conn).await
+    }
+
+    /// Enables basket reservations with the given lifetime in whole minutes
+    /// (at least one), or disables them with `None`. Existing holds keep their
+    /// expiry.
+    pub async fn set_reservation_ttl(&self, ttl: Option<Duration>) -> Result<Option<Duration>, ShopsterError> {
+        let minutes = match ttl {
+            Some(ttl) if ttl.num_minutes() < 1 => {
+                return Err(ShopsterError::InvalidOperationError(
+                    "Basket reservation time must be at least one minute".to_string(),
+                ));
+            },
+            Some(ttl) => ttl.num_minutes(),
+            None => 0,
+        };
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
-        if basket_product.basket_id != basket_id {
-            return Err(ShopsterError::InvalidOperationError("Produkt gehört nicht zu diesem Warenkorb".to_string()));
+        match DbSetting::find_by_title_conn(&mut conn, RESERVATION_TTL_SETTING).await? {
+            Some(mut setting) => {
+                setting.value = minutes.to_string();
+                DbSetting::update(self.tenant_id, setting.id, setting).await?;
+            }
+            None => {
+                let setting = DbSetting {
+                    id: 0,
+                    title: RESERVATION_TTL_SETTING.to_string(),
+                    datatype: "Integer".to_string(),
+                    value: minutes.to_string(),
+                };
+                DbSetting::create(self.tenant_id, setting).await?;
+            }
+        }
+        Ok(ttl.map(|_| Duration::minutes(minutes)))
+    }
+
+    /// Releases the stock held by basket lines whose hold expired before `now`.
+    /// The lines stay in their baskets. Returns the number of released lines.
+    ///
+    /// Each line is released in its own transaction, and lines locked by a
+    /// concurrent transaction are skipped (`FOR UPDATE SKIP LOCKED`), so the
+    /// sweep can run on several instances at once.
+    pub async fn release_expired_reservations(&self, now: NaiveDateTime) -> Result<usize, ShopsterError> {
+        let basket_product_ids = DbBasketProduct::get_expired_hold_ids(self.tenant_id, now).await?;
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let mut released = 0;
+        for basket_product_id in basket_product_ids {
+            let was_released = conn.transaction(async |conn| {
+                let Some(basket_product) = DbBasketProduct::lock_expired_hold_conn(conn, basket_product_id, now).await? else {
+                    return Ok::<bool, ShopsterError>(false);
+                };
+                Self::release_hold_conn(conn, &basket_product).await?;
+                DbBasketProduct::set_hold_conn(conn, basket_product_id, 0, None).await?;
+                Ok(true)
+            }).await?;
+            if was_released {
+                released += 1;
+            }
+        }
+        Ok(released)
+    }
+
+    async fn reservation_ttl_conn(conn: &mut AsyncPgConnection) -> Result<Option<Duration>, ShopsterError> {
+        let minutes = match DbSetting::find_by_title_conn(conn, RESERVATION_TTL_SETTING).await? {
+            Some(setting) => setting.value.trim().parse::<i64>().map_err(|_| ShopsterError::InvalidOperationError(format!(
+                "Invalid {} setting: {}",
+                RESERVATION_TTL_SETTING, setting.value
+            )))?,
+            None => 0,
+        };
+        Ok((minutes > 0).then(|| Duration::minutes(minutes)))
+    }
+
+    /// Adjusts the warehouse hold of a locked basket line to its quantity, or
+    /// releases it if basket reservations are disabled. A new or changed hold
+    /// expires `ttl` after `now`.
+    ///
+    /// `Deny` products (and products without a warehouse entry) must have the
+    /// full quantity available. Holds on other products are capped at the
+    /// available stock, so that a basket never creates backorders.
+    async fn hold_conn(conn: &mut AsyncPgConnection, basket_product: DbBasketProduct, ttl: Option<Duration>, now: NaiveDateTime) -> Result<DbBasketProduct, ShopsterError> {
+        let held = basket_product.reserved_quantity;
+        let requested = if ttl.is_some() { basket_product.quantity } else { 0 };
+        if requested == 0 && held == 0 {
+            return Ok(basket_product);
         }
 
-        let result = DbBasketProduct::delete_basket_item(self.tenant_id, basket_product_id).await?;
-        Ok(result > 0)
+        let target = if requested == 0 {
+            0
+        } else {
+            let stock = DbWarehouse::lock_for_products_conn(conn, &[basket_product.product_id]).await?;
+            let available = stock.iter().map(|item| (item.in_stock - item.reserved).max(0)).sum::<i64>() + held;
+            match stock.iter().map(|item| item.stock_policy).next() {
+                Some(DbStockPolicy::Deny) | None if requested > available => {
+                    return Err(ShopsterError::InsufficientStockError(vec![StockShortage {
+                        basket_product_id: basket_product.id,
+                        product_id: basket_product.product_id,
+                        requested,
+                        available,
+                    }]));
+                },
+                Some(DbStockPolicy::Deny) | None => requested,
+                Some(_) => requested.min(available),
+            }
+        };
+
+        if target != held {
+            DbWarehouse::apply_reserved_delta_conn(conn, basket_product.product_id, target - held, None, &DbMovementReference::basket(basket_product.basket_id)).await?;
+        }
+        let reserved_until = if target > 0 { ttl.map(|ttl| now + ttl) } else { None };
+        DbBasketProduct::set_hold_conn(conn, basket_product.id, target, reserved_until).await
+    }
+
+    /// Gives the stock held by a locked basket line back to the warehouse. The
+    /// line itself is left unchanged.
+    pub(crate) async fn release_hold_conn(conn: &mut AsyncPgConnection, basket_product: &DbBasketProduct) -> Result<(), ShopsterError> {
+        if basket_product.reserved_quantity > 0 {
+            DbWarehouse::apply_reserved_delta_conn(conn, basket_product.product_id, -basket_product.reserved_quantity, None, &DbMovementReference::basket(basket_product.basket_id)).await?;
+        }
+        Ok(())
     }
 
     pub async fn get_products_from_basket(&self, basket_id: Uuid) -> Result<Vec<BasketProduct>, ShopsterError> {
@@ -177,10 +386,19 @@ impl Baskets {
         Ok(result)
     }
 
+    /// Removes all lines from a basket and releases their stock holds.
     pub async fn clear_basket(&self, basket_id: Uuid) -> Result<bool, ShopsterError> {
-        DbBasket::find(self.tenant_id, basket_id).await?;
-        let result = DbBasketProduct::delete_all_basket_items(self.tenant_id, basket_id).await?;
-        Ok(result > 0)
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            DbBasket::find_for_update_conn(conn, basket_id).await?;
+            for basket_product in DbBasketProduct::get_basket_items_for_update_conn(conn, basket_id).await? {
+                Self::release_hold_conn(conn, &basket_product).await?;
+            }
+            let result = DbBasketProduct::delete_all_basket_items_conn(conn, basket_id).await?;
+            Ok(result > 0)
+        }).await
     }
 
     pub async fn calculate_basket_total(&self, basket_id: Uuid) -> Result<(i64, String), ShopsterError> {
@@ -227,40 +445,82 @@ impl Baskets {
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
         conn.transaction(async |conn| {
-            let source_products = DbBasketProduct::get_basket_items_conn(conn, source_basket_id).await?;
-            let target_products = DbBasketProduct::get_basket_items_conn(conn, target_basket_id).await?;
-
-            for source_product in source_products {
-                let existing_product = target_products.iter()
-                    .find(|p| p.product_id == source_product.product_id);
-
-                match existing_product {
-                    Some(product) => {
-                        let new_quantity = product.quantity + source_product.quantity;
-                        let updated = DbBasketProduct {
-                            id: product.id,
-                            product_id: product.product_id,
-                            quantity: new_quantity,
-                            basket_id: target_basket_id,
-                        };
-                        DbBasketProduct::update_basket_item_conn(conn, product.id, updated).await?;
-                    },
-                    None => {
-                        let new_item = DbBasketProduct {
-                            id: 0,
-                            product_id: source_product.product_id,
-                            quantity: source_product.quantity,
-                            basket_id: target_basket_id,
-                        };
-                        DbBasketProduct::create_basket_item_conn(conn, new_item).await?;
-                    }
+            Self::merge_baskets_conn(conn, source_basket_id, target_basket_id).await
+        }).await
+    }
+
+    /// Folds a guest basket into the customer's persistent basket on login.
+    ///
+    /// If the customer already has a basket, the guest basket is merged into it
+    /// with `merge_baskets` semantics (quantities of the same product are added
+    /// up) and deleted. Otherwise the guest basket becomes the customer's basket.
+    /// Returns the id of the customer's basket.
+    pub async fn merge_guest_basket(&self, guest_basket_id: Uuid, customer_id: Uuid) -> Result<Uuid, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let guest_basket = DbBasket::find_conn(conn, guest_basket_id).await?;
+            if guest_basket.customer_id.is_some_and(|owner| owner != customer_id) {
+                return Err(ShopsterError::InvalidOperationError(
+                    "Basket belongs to another customer".to_string(),
+                ));
+            }
+
+            match DbBasket::find_for_customer_for_update_conn(conn, customer_id).await? {
+                Some(customer_basket) if customer_basket.id != guest_basket_id => {
+                    Self::merge_baskets_conn(conn, guest_basket_id, customer_basket.id).await?;
+                    Ok(customer_basket.id)
+                },
+                Some(customer_basket) => Ok(customer_basket.id),
+                None => {
+                    let db_basket = DbBasket::update_customer_conn(conn, guest_basket_id, Some(customer_id)).await?;
+                    Ok(db_basket.id)
                 }
             }
+        }).await
+    }
 
-            DbBasketProduct::delete_all_basket_items_conn(conn, source_basket_id).await?;
-            DbBasket::delete_conn(conn, source_basket_id).await?;
+    /// Moves the lines of one basket into another. Stock holds move with their
+    /// lines; a merged line keeps the later expiry of the two.
+    async fn merge_baskets_conn(conn: &mut AsyncPgConnection, source_basket_id: Uuid, target_basket_id: Uuid) -> Result<(), ShopsterError> {
+        let source_products = DbBasketProduct::get_basket_items_for_update_conn(conn, source_basket_id).await?;
+        let target_products = DbBasketProduct::get_basket_items_for_update_conn(conn, target_basket_id).await?;
+
+        for source_product in source_products {
+            let existing_product = target_products.iter()
+                .find(|p| p.product_id == source_product.product_id);
+
+            match existing_product {
+                Some(product) => {
+                    let new_quantity = product.quantity + source_product.quantity;
+                    let updated = DbBasketProduct {
+                        id: product.id,
+                        product_id: product.product_id,
+                        quantity: new_quantity,
+                        basket_id: target_basket_id,
+                        reserved_quantity: product.reserved_quantity + source_product.reserved_quantity,
+                        reserved_until: product.reserved_until.max(source_product.reserved_until),
+                    };
+                    DbBasketProduct::update_basket_item_conn(conn, product.id, updated).await?;
+                },
+                None => {
+                    let new_item = DbBasketProduct {
+                        id: 0,
+                        product_id: source_product.product_id,
+                        quantity: source_product.quantity,
+                        basket_id: target_basket_id,
+                        reserved_quantity: source_product.reserved_quantity,
+                        reserved_until: source_product.reserved_until,
+                    };
+                    DbBasketProduct::create_basket_item_conn(conn, new_item).await?;
+                }
+            }
+        }
 
-            Ok(())
-        }).await
+        DbBasketProduct::delete_all_basket_items_conn(conn, source_basket_id).await?;
+        DbBasket::delete_conn(conn, source_basket_id).await?;
+
+        Ok(())
     }
 }
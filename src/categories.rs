@@ -0,0 +1,287 @@
+//! The category tree of the product catalog.
+
+use chrono::{NaiveDateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::aquire_pool;
+use crate::error::ShopsterError;
+use crate::postgresql::dbcategory::DbCategory;
+use crate::postgresql::dbproduct::DbProduct;
+use crate::postgresql::dbproductvariant::DbProductVariant;
+use crate::postgresql::dbtranslation::{default_language, default_language_conn, fallback_languages, DbCategoryTranslation};
+use crate::products::{load_products, validate_language, Product};
+
+/// A node in the category tree. Root categories have no `parent_id`;
+/// siblings are ordered by `position`. The title is in the shop's default
+/// language; see `CategoryTranslation` for other languages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Category {
+    pub id: i64,
+    pub parent_id: Option<i64>,
+    pub title: String,
+    /// Unique URL-friendly name, e.g. `mens-shirts`.
+    pub slug: String,
+    pub position: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbCategory> for Category {
+    fn from(db_category: &DbCategory) -> Self {
+        Category {
+            id: db_category.id,
+            parent_id: db_category.parent_id,
+            title: db_category.title.clone(),
+            slug: db_category.slug.clone(),
+            position: db_category.position,
+            created_at: db_category.created_at,
+            updated_at: db_category.updated_at,
+        }
+    }
+}
+
+impl From<&Category> for DbCategory {
+    fn from(category: &Category) -> Self {
+        DbCategory {
+            id: category.id,
+            parent_id: category.parent_id,
+            title: category.title.clone(),
+            slug: category.slug.clone(),
+            position: category.position,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Some(Utc::now().naive_utc()),
+        }
+    }
+}
+
+/// The title of a category in a language other than the shop's default
+/// language. Slugs are the same in every language.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CategoryTranslation {
+    pub category_id: i64,
+    /// E.g. `de` or `de-CH`.
+    pub language: String,
+    pub title: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbCategoryTranslation> for CategoryTranslation {
+    fn from(db_translation: &DbCategoryTranslation) -> Self {
+        CategoryTranslation {
+            category_id: db_translation.category_id,
+            language: db_translation.language.clone(),
+            title: db_translation.title.clone(),
+            created_at: db_translation.created_at,
+            updated_at: db_translation.updated_at,
+        }
+    }
+}
+
+impl From<&CategoryTranslation> for DbCategoryTranslation {
+    fn from(translation: &CategoryTranslation) -> Self {
+        DbCategoryTranslation {
+            category_id: translation.category_id,
+            language: translation.language.clone(),
+            title: translation.title.clone(),
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }
+    }
+}
+
+/// Handler for the category tree and product assignments.
+pub struct Categories {
+    tenant_id: Uuid,
+}
+
+impl Categories {
+    pub fn new(tenant_id: Uuid) -> Self {
+        Categories { tenant_id }
+    }
+
+    /// Returns all categories, roots first, siblings ordered by position.
+    pub async fn get_all(&self) -> Result<Vec<Category>, ShopsterError> {
+        let db_categories = DbCategory::get_all(self.tenant_id).await?;
+        Ok(db_categories.iter().map(Category::from).collect())
+    }
+
+    /// Returns all categories like `get_all`, with their titles in `locale`,
+    /// falling back to the base language of a regional locale and then to
+    /// the default language.
+    pub async fn get_all_localized(&self, locale: &str) -> Result<Vec<Category>, ShopsterError> {
+        validate_language(locale)?;
+        let categories = self.get_all().await?;
+        self.localize(categories, locale).await
+    }
+
+    pub async fn get(&self, category_id: i64) -> Result<Category, ShopsterError> {
+        let db_category = DbCategory::find(self.tenant_id, category_id).await?;
+        Ok(Category::from(&db_category))
+    }
+
+    /// Returns a category with its title in `locale`; see
+    /// `get_all_localized`.
+    pub async fn get_localized(&self, category_id: i64, locale: &str) -> Result<Category, ShopsterError> {
+        validate_language(locale)?;
+        let category = self.get(category_id).await?;
+        let mut categories = self.localize(vec![category], locale).await?;
+        Ok(categories.remove(0))
+    }
+
+    pub async fn get_by_slug(&self, slug: &str) -> Result<Option<Category>, ShopsterError> {
+        let db_category = DbCategory::find_by_slug(self.tenant_id, slug).await?;
+        Ok(db_category.as_ref().map(Category::from))
+    }
+
+    /// Returns the direct children of a category, or the root categories for
+    /// `None`.
+    pub async fn get_children(&self, parent_id: Option<i64>) -> Result<Vec<Category>, ShopsterError> {
+        let db_categories = DbCategory::get_children(self.tenant_id, parent_id).await?;
+        Ok(db_categories.iter().map(Category::from).collect())
+    }
+
+    /// Returns the breadcrumb trail of a category: its ancestors from the root
+    /// down, followed by the category itself.
+    pub async fn get_breadcrumbs(&self, category_id: i64) -> Result<Vec<Category>, ShopsterError> {
+        let path = DbCategory::get_path(self.tenant_id, category_id).await?;
+        if path.is_empty() {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Category {} does not exist", category_id
+            )));
+        }
+        Ok(path.iter().map(Category::from).collect())
+    }
+
+    /// Returns the breadcrumb trail of a category with the titles in
+    /// `locale`; see `get_breadcrumbs` and `get_all_localized`.
+    pub async fn get_breadcrumbs_localized(&self, category_id: i64, locale: &str) -> Result<Vec<Category>, ShopsterError> {
+        validate_language(locale)?;
+        let breadcrumbs = self.get_breadcrumbs(category_id).await?;
+        self.localize(breadcrumbs, locale).await
+    }
+
+    pub async fn insert(&self, category: &Category) -> Result<Category, ShopsterError> {
+        Self::validate(category)?;
+        let created_category = DbCategory::create(self.tenant_id, DbCategory::from(category)).await?;
+        Ok(Category::from(&created_category))
+    }
+
+    /// Updates a category, possibly moving it to another parent. A category
+    /// cannot be moved below itself or one of its descendants.
+    pub async fn update(&self, category: &Category) -> Result<Category, ShopsterError> {
+        Self::validate(category)?;
+        let updated_category = DbCategory::update(self.tenant_id, category.id, DbCategory::from(category)).await?;
+        Ok(Category::from(&updated_category))
+    }
+
+    /// Removes a category together with its product assignments. Fails while
+    /// it still has subcategories.
+    pub async fn remove(&self, category_id: i64) -> Result<bool, ShopsterError> {
+        let result = DbCategory::delete(self.tenant_id, category_id).await?;
+        Ok(result > 0)
+    }
+
+    /// Assigns a product to a category. Variants are categorised through their
+    /// parent product and cannot be assigned themselves.
+    pub async fn add_product(&self, category_id: i64, product_id: i64) -> Result<(), ShopsterError> {
+        if DbProductVariant::is_variant(self.tenant_id, product_id).await? {
+            return Err(ShopsterError::InvalidOperationError(
+                "Variants cannot be assigned to categories".to_string(),
+            ));
+        }
+        DbCategory::add_product(self.tenant_id, category_id, product_id).await
+    }
+
+    pub async fn remove_product(&self, category_id: i64, product_id: i64) -> Result<bool, ShopsterError> {
+        let result = DbCategory::remove_product(self.tenant_id, category_id, product_id).await?;
+        Ok(result > 0)
+    }
+
+    /// Returns the categories a product is assigned to.
+    pub async fn get_for_product(&self, product_id: i64) -> Result<Vec<Category>, ShopsterError> {
+        let db_categories = DbCategory::get_for_product(self.tenant_id, product_id).await?;
+        Ok(db_categories.iter().map(Category::from).collect())
+    }
+
+    /// Returns the products assigned to a category. With `include_descendants`
+    /// products in any of its subcategories are included, each only once.
+    pub async fn get_products(&self, category_id: i64, include_descendants: bool) -> Result<Vec<Product>, ShopsterError> {
+        let db_products: Vec<DbProduct> = DbCategory::get_products(self.tenant_id, category_id, include_descendants).await?;
+        load_products(self.tenant_id, &db_products).await
+    }
+
+    /// Returns the translations of a category ordered by language.
+    pub async fn get_translations(&self, category_id: i64) -> Result<Vec<CategoryTranslation>, ShopsterError> {
+        let db_translations = DbCategoryTranslation::get_for_category(self.tenant_id, category_id).await?;
+        Ok(db_translations.iter().map(CategoryTranslation::from).collect())
+    }
+
+    /// Creates or replaces the translation of a category into
+    /// `translation.language`. The title in the default language is that of
+    /// the category itself.
+    pub async fn set_translation(&self, translation: &CategoryTranslation) -> Result<CategoryTranslation, ShopsterError> {
+        validate_language(&translation.language)?;
+        if translation.title.trim().is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Category title cannot be empty".to_string(),
+            ));
+        }
+        if default_language(self.tenant_id).await?.as_ref() == Some(&translation.language) {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "The title in the default language '{}' is set on the category itself",
+                translation.language
+            )));
+        }
+        DbCategory::find(self.tenant_id, translation.category_id).await?;
+
+        let db_translation = DbCategoryTranslation::upsert(self.tenant_id, DbCategoryTranslation::from(translation)).await?;
+        Ok(CategoryTranslation::from(&db_translation))
+    }
+
+    pub async fn remove_translation(&self, category_id: i64, language: &str) -> Result<bool, ShopsterError> {
+        let result = DbCategoryTranslation::delete(self.tenant_id, category_id, language).await?;
+        Ok(result > 0)
+    }
+
+    /// Replaces the titles of the categories by their translations for
+    /// `locale`.
+    async fn localize(&self, mut categories: Vec<Category>, locale: &str) -> Result<Vec<Category>, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let default_language = default_language_conn(&mut conn).await?;
+        let languages = fallback_languages(locale, default_language.as_deref());
+        let category_ids: Vec<i64> = categories.iter().map(|category| category.id).collect();
+        let translations = DbCategoryTranslation::get_for_categories_conn(&mut conn, &category_ids, &languages).await?;
+        for category in &mut categories {
+            let translation = translations.get(&category.id).and_then(|db_translations| {
+                languages.iter().find_map(|language| {
+                    db_translations.iter().find(|translation| &translation.language == language)
+                })
+            });
+            if let Some(translation) = translation {
+                category.title = translation.title.clone();
+            }
+        }
+        Ok(categories)
+    }
+
+    fn validate(category: &Category) -> Result<(), ShopsterError> {
+        if category.title.trim().is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Category title cannot be empty".to_string(),
+            ));
+        }
+        let valid_slug = !category.slug.is_empty()
+            && category.slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !valid_slug {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Invalid category slug '{}': only lowercase letters, digits and '-' are allowed",
+                category.slug
+            )));
+        }
+        Ok(())
+    }
+}
@@ -6,8 +6,12 @@ use std::convert::TryFrom;
 use stec_tenet::encryption_modes::EncryptionModes;
 use uuid::Uuid;
 use chrono::{NaiveDateTime, Utc};
+use diesel_async::AsyncConnection;
+use crate::aquire_pool;
+use crate::addresses::Address;
 use crate::error::ShopsterError;
-use crate::postgresql::dbcustomer::{DbCustomer, DbCustomerMessage, DbProfileMessage};
+use crate::postgresql::dbaddress::DbAddress;
+use crate::postgresql::dbcustomer::{DbCustomer, DbCustomerAddress, DbCustomerMessage, DbProfileMessage};
 
 
 /// A customer in the shop system.
@@ -88,6 +92,32 @@ impl From<&CustomerProfile> for DbProfileMessage {
     }
 }
 
+/// An entry in a customer's address book.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomerAddress {
+    pub id: i64,
+    pub customer_id: Uuid,
+    pub address: Address,
+    pub is_default_billing: bool,
+    pub is_default_shipping: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbCustomerAddress> for CustomerAddress {
+    fn from(db_address: &DbCustomerAddress) -> Self {
+        CustomerAddress {
+            id: db_address.id,
+            customer_id: db_address.customer_id,
+            address: Address::from(&db_address.address),
+            is_default_billing: db_address.is_default_billing,
+            is_default_shipping: db_address.is_default_shipping,
+            created_at: db_address.created_at,
+            updated_at: db_address.updated_at,
+        }
+    }
+}
+
 
 /// Handler for customer management operations.
 pub struct Customers {
@@ -229,6 +259,81 @@ impl Customers {
         Ok(customers)
     }
 
+    /// Returns the customer's address book, oldest entry first.
+    pub async fn get_addresses(&self, customer_id: Uuid) -> Result<Vec<CustomerAddress>, ShopsterError> {
+        let db_addresses = DbCustomerAddress::get_for_customer(self.tenant_id, customer_id).await?;
+        Ok(db_addresses.iter().map(CustomerAddress::from).collect())
+    }
+
+    pub async fn get_address(&self, customer_id: Uuid, address_id: i64) -> Result<Option<CustomerAddress>, ShopsterError> {
+        let db_address = DbCustomerAddress::find_for_customer(self.tenant_id, customer_id, address_id).await?;
+        Ok(db_address.as_ref().map(CustomerAddress::from))
+    }
+
+    /// Adds a validated address to the customer's address book.
+    ///
+    /// The address becomes the default billing and/or shipping address if the
+    /// customer has none yet.
+    pub async fn add_address(&self, customer_id: Uuid, address: &Address) -> Result<CustomerAddress, ShopsterError> {
+        address.validate()?;
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_address = conn.transaction(async |conn| {
+            let existing = DbCustomerAddress::get_for_customer_conn(conn, customer_id).await?;
+
+            DbCustomerAddress::create_conn(conn, DbCustomerAddress {
+                id: 0,
+                customer_id,
+                address: DbAddress::from(address),
+                is_default_billing: !existing.iter().any(|a| a.is_default_billing),
+                is_default_shipping: !existing.iter().any(|a| a.is_default_shipping),
+                created_at: Utc::now().naive_utc(),
+                updated_at: None,
+            }).await
+        }).await?;
+
+        Ok(CustomerAddress::from(&db_address))
+    }
+
+    /// Replaces an address in the customer's address book. Orders already
+    /// placed keep the address they were created with.
+    pub async fn update_address(&self, customer_id: Uuid, address_id: i64, address: &Address) -> Result<CustomerAddress, ShopsterError> {
+        address.validate()?;
+
+        let db_address = DbCustomerAddress::update_address(self.tenant_id, customer_id, address_id, DbAddress::from(address)).await?
+            .ok_or_else(|| ShopsterError::InvalidOperationError(format!("Address {} not found", address_id)))?;
+        Ok(CustomerAddress::from(&db_address))
+    }
+
+    /// Removes an address from the customer's address book. Removing a default
+    /// address leaves the customer without that default.
+    pub async fn remove_address(&self, customer_id: Uuid, address_id: i64) -> Result<bool, ShopsterError> {
+        let result = DbCustomerAddress::delete(self.tenant_id, customer_id, address_id).await?;
+        Ok(result > 0)
+    }
+
+    pub async fn set_default_billing_address(&self, customer_id: Uuid, address_id: i64) -> Result<CustomerAddress, ShopsterError> {
+        let db_address = DbCustomerAddress::set_default_billing(self.tenant_id, customer_id, address_id).await?;
+        Ok(CustomerAddress::from(&db_address))
+    }
+
+    pub async fn set_default_shipping_address(&self, customer_id: Uuid, address_id: i64) -> Result<CustomerAddress, ShopsterError> {
+        let db_address = DbCustomerAddress::set_default_shipping(self.tenant_id, customer_id, address_id).await?;
+        Ok(CustomerAddress::from(&db_address))
+    }
+
+    pub async fn get_default_billing_address(&self, customer_id: Uuid) -> Result<Option<CustomerAddress>, ShopsterError> {
+        let addresses = self.get_addresses(customer_id).await?;
+        Ok(addresses.into_iter().find(|a| a.is_default_billing))
+    }
+
+    pub async fn get_default_shipping_address(&self, customer_id: Uuid) -> Result<Option<CustomerAddress>, ShopsterError> {
+        let addresses = self.get_addresses(customer_id).await?;
+        Ok(addresses.into_iter().find(|a| a.is_default_shipping))
+    }
+
 }
 
 fn is_valid_email(email: &str) -> bool {
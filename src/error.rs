@@ -6,6 +6,15 @@
 use stec_tenet::TenetError;
 use thiserror::Error;
 
+/// A basket line whose quantity exceeds the stock available for checkout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StockShortage {
+    pub basket_product_id: i64,
+    pub product_id: i64,
+    pub requested: i64,
+    pub available: i64,
+}
+
 /// Error types that can occur during Shopster operations.
 ///
 /// All Shopster operations return `Result<T, ShopsterError>`. This enum covers
@@ -45,6 +54,10 @@ pub enum ShopsterError {
     #[error("Invalid Operation")]
     InvalidOperationError(String),
 
+    /// Checkout was rejected because the listed basket lines exceed the available stock.
+    #[error("Insufficient stock for {} basket line(s)", .0.len())]
+    InsufficientStockError(Vec<StockShortage>),
+
     /// Authentication failed (invalid credentials, expired session, etc.).
     #[error("Authentication Error")]
     AuthenticationError(String),
@@ -0,0 +1,490 @@
+//! Invoice and credit note documents issued from order snapshots.
+
+use uuid::Uuid;
+use chrono::{NaiveDateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+
+use crate::aquire_pool;
+use crate::addresses::Address;
+use crate::error::ShopsterError;
+use crate::numbering;
+use crate::orders::{OrderStatus, Orders};
+use crate::postgresql::dbaddress::DbAddress;
+use crate::postgresql::dbinvoice::{DbInvoice, DbInvoiceKind, DbInvoiceLine};
+use crate::postgresql::dbrefund::{DbRefund, DbRefundItem};
+use crate::postgresql::dbsettings::DbSetting;
+
+const INVOICE_SEQUENCE: &str = "invoices";
+const INVOICE_NUMBER_FORMAT_SETTING: &str = "invoice_number_format";
+const DEFAULT_INVOICE_NUMBER_FORMAT: &str = "RE-{year}-{counter:6}";
+
+const CREDIT_NOTE_SEQUENCE: &str = "credit_notes";
+const CREDIT_NOTE_NUMBER_FORMAT_SETTING: &str = "credit_note_number_format";
+const DEFAULT_CREDIT_NOTE_NUMBER_FORMAT: &str = "GS-{year}-{counter:6}";
+
+const VAT_SETTING: &str = "vat";
+
+/// Whether a document bills an order or corrects an earlier invoice.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InvoiceKind {
+    Invoice,
+    CreditNote,
+}
+
+impl From<DbInvoiceKind> for InvoiceKind {
+    fn from(kind: DbInvoiceKind) -> Self {
+        match kind {
+            DbInvoiceKind::Invoice => InvoiceKind::Invoice,
+            DbInvoiceKind::CreditNote => InvoiceKind::CreditNote,
+        }
+    }
+}
+
+impl From<InvoiceKind> for DbInvoiceKind {
+    fn from(kind: InvoiceKind) -> Self {
+        match kind {
+            InvoiceKind::Invoice => DbInvoiceKind::Invoice,
+            InvoiceKind::CreditNote => DbInvoiceKind::CreditNote,
+        }
+    }
+}
+
+/// A line of an invoice. All amounts are in cents; `unit_price` and
+/// `gross_amount` include VAT. Credit note lines carry negative amounts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InvoiceLine {
+    pub id: i64,
+    pub order_item_id: Option<i64>,
+    pub article_number: String,
+    pub title: String,
+    pub quantity: i64,
+    pub unit_price: i64,
+    pub net_amount: i64,
+    pub vat_amount: i64,
+    pub gross_amount: i64,
+}
+
+impl From<&DbInvoiceLine> for InvoiceLine {
+    fn from(db_line: &DbInvoiceLine) -> Self {
+        InvoiceLine {
+            id: db_line.id,
+            order_item_id: db_line.order_item_id,
+            article_number: db_line.article_number.clone(),
+            title: db_line.title.clone(),
+            quantity: db_line.quantity,
+            unit_price: db_line.unit_price,
+            net_amount: db_line.net_amount,
+            vat_amount: db_line.vat_amount,
+            gross_amount: db_line.gross_amount,
+        }
+    }
+}
+
+/// An issued, immutable invoice or credit note.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Invoice {
+    pub id: i64,
+    pub order_id: i64,
+    pub invoice_number: String,
+    pub kind: InvoiceKind,
+    /// For credit notes, the invoice being corrected.
+    pub corrected_invoice_id: Option<i64>,
+    /// For credit notes, the refund the credit note documents.
+    pub refund_id: Option<i64>,
+    pub billing_address: Address,
+    pub delivery_address: Address,
+    pub currency: String,
+    /// VAT rate in percent, taken from the `vat` setting at issue time.
+    pub vat_rate: f64,
+    pub net_total: i64,
+    pub vat_total: i64,
+    pub gross_total: i64,
+    pub lines: Vec<InvoiceLine>,
+    pub issued_at: NaiveDateTime,
+}
+
+impl From<&DbInvoice> for Invoice {
+    fn from(db_invoice: &DbInvoice) -> Self {
+        Invoice {
+            id: db_invoice.id,
+            order_id: db_invoice.order_id,
+            invoice_number: db_invoice.invoice_number.clone(),
+            kind: db_invoice.kind.into(),
+            corrected_invoice_id: db_invoice.corrected_invoice_id,
+            refund_id: db_invoice.refund_id,
+            billing_address: Address::from(&db_invoice.billing_address),
+            delivery_address: Address::from(&db_invoice.delivery_address),
+            currency: db_invoice.currency.clone(),
+            vat_rate: db_invoice.vat_rate,
+            net_total: db_invoice.net_total,
+            vat_total: db_invoice.vat_total,
+            gross_total: db_invoice.gross_total,
+            lines: Vec::new(),
+            issued_at: db_invoice.issued_at,
+        }
+    }
+}
+
+impl Invoice {
+    /// Serializes the invoice, including its lines and totals, as JSON.
+    pub fn to_json(&self) -> Result<String, ShopsterError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Renders the invoice as plain text.
+    pub fn render_text(&self) -> String {
+        let mut text = String::new();
+        text.push_str(&format!("{} {}\n", self.title(), self.invoice_number));
+        text.push_str(&format!("Date: {}\n", self.issued_at.format("%Y-%m-%d")));
+        text.push_str(&format!("Billing address:\n{}\n", self.billing_address));
+        text.push_str(&format!("Delivery address:\n{}\n", self.delivery_address));
+        text.push('\n');
+
+        for line in &self.lines {
+            text.push_str(&format!(
+                "{:>4} x {} {} @ {} = {}\n",
+                line.quantity,
+                line.article_number,
+                line.title,
+                format_amount(line.unit_price, &self.currency),
+                format_amount(line.gross_amount, &self.currency),
+            ));
+        }
+
+        text.push('\n');
+        text.push_str(&format!("Net: {}\n", format_amount(self.net_total, &self.currency)));
+        text.push_str(&format!("VAT {}%: {}\n", self.vat_rate, format_amount(self.vat_total, &self.currency)));
+        text.push_str(&format!("Total: {}\n", format_amount(self.gross_total, &self.currency)));
+        text
+    }
+
+    /// Renders the invoice as a self-contained HTML fragment.
+    pub fn render_html(&self) -> String {
+        let mut html = String::new();
+        html.push_str("<div class=\"invoice\">\n");
+        html.push_str(&format!("<h1>{} {}</h1>\n", self.title(), escape_html(&self.invoice_number)));
+        html.push_str(&format!("<p>Date: {}</p>\n", self.issued_at.format("%Y-%m-%d")));
+        html.push_str(&format!("<p>Billing address:<br>{}</p>\n", address_html(&self.billing_address)));
+        html.push_str(&format!("<p>Delivery address:<br>{}</p>\n", address_html(&self.delivery_address)));
+
+        html.push_str("<table>\n<tr><th>Quantity</th><th>Article</th><th>Title</th><th>Unit price</th><th>Total</th></tr>\n");
+        for line in &self.lines {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                line.quantity,
+                escape_html(&line.article_number),
+                escape_html(&line.title),
+                escape_html(&format_amount(line.unit_price, &self.currency)),
+                escape_html(&format_amount(line.gross_amount, &self.currency)),
+            ));
+        }
+        html.push_str("</table>\n");
+
+        html.push_str(&format!("<p>Net: {}</p>\n", escape_html(&format_amount(self.net_total, &self.currency))));
+        html.push_str(&format!("<p>VAT {}%: {}</p>\n", self.vat_rate, escape_html(&format_amount(self.vat_total, &self.currency))));
+        html.push_str(&format!("<p><strong>Total: {}</strong></p>\n", escape_html(&format_amount(self.gross_total, &self.currency))));
+        html.push_str("</div>\n");
+        html
+    }
+
+    fn title(&self) -> &'static str {
+        match self.kind {
+            InvoiceKind::Invoice => "Invoice",
+            InvoiceKind::CreditNote => "Credit note",
+        }
+    }
+}
+
+/// Formats an amount in cents, e.g. `-1234` as `-12.34 EUR`.
+fn format_amount(cents: i64, currency: &str) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    format!("{}{}.{:02} {}", sign, cents / 100, cents % 100, currency)
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Escapes an address and puts each of its lines on a line of its own.
+fn address_html(address: &Address) -> String {
+    escape_html(&address.to_string()).replace('\n', "<br>")
+}
+
+/// Splits a gross amount (VAT included) into its net and VAT parts.
+fn split_gross(gross: i64, vat_rate: f64) -> (i64, i64) {
+    let net = (gross as f64 * 100.0 / (100.0 + vat_rate)).round() as i64;
+    (net, gross - net)
+}
+
+fn make_line(order_item_id: Option<i64>, article_number: String, title: String, quantity: i64, unit_price: i64, vat_rate: f64) -> DbInvoiceLine {
+    let gross_amount = unit_price * quantity;
+    let (net_amount, vat_amount) = split_gross(gross_amount, vat_rate);
+    DbInvoiceLine {
+        id: 0,
+        invoice_id: 0,
+        order_item_id,
+        article_number,
+        title,
+        quantity,
+        unit_price,
+        net_amount,
+        vat_amount,
+        gross_amount,
+    }
+}
+
+/// Handler for invoices and credit notes.
+///
+/// Documents are built from the frozen `OrderItemSnapshot` prices, which are
+/// treated as gross prices including VAT. Once issued they cannot be changed:
+/// corrections are made by issuing a credit note for a refund. Invoices and
+/// credit notes are numbered from their own gap-free sequences, formatted by
+/// the `invoice_number_format` and `credit_note_number_format` settings.
+pub struct Invoices {
+    tenant_id: Uuid
+}
+
+impl Invoices {
+    pub fn new(tenant_id: Uuid) -> Self {
+        Invoices { tenant_id }
+    }
+
+    pub async fn get(&self, invoice_id: i64) -> Result<Invoice, ShopsterError> {
+        let db_invoice = DbInvoice::find(self.tenant_id, invoice_id).await?;
+        let db_lines = DbInvoiceLine::get_for_invoice(self.tenant_id, db_invoice.id).await?;
+
+        let mut invoice = Invoice::from(&db_invoice);
+        invoice.lines = db_lines.iter().map(InvoiceLine::from).collect();
+        Ok(invoice)
+    }
+
+    pub async fn get_by_number(&self, invoice_number: &str) -> Result<Option<Invoice>, ShopsterError> {
+        let Some(db_invoice) = DbInvoice::find_by_number(self.tenant_id, invoice_number).await? else {
+            return Ok(None);
+        };
+        let db_lines = DbInvoiceLine::get_for_invoice(self.tenant_id, db_invoice.id).await?;
+
+        let mut invoice = Invoice::from(&db_invoice);
+        invoice.lines = db_lines.iter().map(InvoiceLine::from).collect();
+        Ok(Some(invoice))
+    }
+
+    /// Returns the invoice and all credit notes of an order, in issue order.
+    pub async fn get_for_order(&self, order_id: i64) -> Result<Vec<Invoice>, ShopsterError> {
+        let db_invoices = DbInvoice::get_for_order(self.tenant_id, order_id).await?;
+        let mut invoices = Vec::new();
+
+        for db_invoice in db_invoices {
+            let db_lines = DbInvoiceLine::get_for_invoice(self.tenant_id, db_invoice.id).await?;
+            let mut invoice = Invoice::from(&db_invoice);
+            invoice.lines = db_lines.iter().map(InvoiceLine::from).collect();
+            invoices.push(invoice);
+        }
+
+        Ok(invoices)
+    }
+
+    /// Issues the invoice for an order. Every order can be invoiced once;
+    /// cancelled orders cannot be invoiced.
+    pub async fn issue(&self, order_id: i64) -> Result<Invoice, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let order = Orders::get_by_id_conn(conn, order_id).await?;
+
+            if order.status == OrderStatus::Cancelled {
+                return Err(ShopsterError::InvalidOperationError(
+                    "Cannot invoice a cancelled order".to_string(),
+                ));
+            }
+            let currency = match order.items.first() {
+                Some(item) => item.price.currency.clone(),
+                None => return Err(ShopsterError::InvalidOperationError(
+                    "Cannot invoice an order without items".to_string(),
+                )),
+            };
+            if order.items.iter().any(|item| item.price.currency != currency) {
+                return Err(ShopsterError::InvalidOperationError(
+                    "Cannot invoice an order with mixed currencies".to_string(),
+                ));
+            }
+
+            let existing = DbInvoice::get_for_order_conn(conn, order.id).await?;
+            if existing.iter().any(|invoice| invoice.kind == DbInvoiceKind::Invoice) {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Order {} has already been invoiced",
+                    order.id
+                )));
+            }
+
+            let vat_rate = Self::vat_rate_conn(conn).await?;
+            let db_lines: Vec<DbInvoiceLine> = order.items.iter()
+                .map(|item| make_line(
+                    Some(item.id),
+                    item.article_number.clone(),
+                    item.title.clone(),
+                    item.quantity,
+                    item.price.amount,
+                    vat_rate,
+                ))
+                .collect();
+
+            let issued_at = Utc::now().naive_utc();
+            let invoice_number = numbering::next_number_conn(
+                conn,
+                INVOICE_SEQUENCE,
+                INVOICE_NUMBER_FORMAT_SETTING,
+                DEFAULT_INVOICE_NUMBER_FORMAT,
+                issued_at.date(),
+            ).await?;
+
+            Self::create_conn(conn, DbInvoice {
+                id: 0,
+                order_id: order.id,
+                invoice_number,
+                kind: DbInvoiceKind::Invoice,
+                corrected_invoice_id: None,
+                refund_id: None,
+                billing_address: DbAddress::from(&order.billing_address),
+                delivery_address: DbAddress::from(&order.delivery_address),
+                currency,
+                vat_rate,
+                net_total: 0,
+                vat_total: 0,
+                gross_total: 0,
+                issued_at,
+            }, db_lines).await
+        }).await
+    }
+
+    /// Issues a credit note documenting a refund against the order's invoice.
+    ///
+    /// Refunded lines are credited at their snapshot price. If the refunded
+    /// amount differs from the value of those lines (e.g. goodwill refunds), an
+    /// adjustment line makes up the difference. The credit note uses the VAT
+    /// rate of the invoice it corrects.
+    pub async fn issue_credit_note(&self, refund_id: i64) -> Result<Invoice, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let refund = DbRefund::find_conn(conn, refund_id).await?;
+            let order = Orders::get_by_id_conn(conn, refund.order_id).await?;
+
+            let existing = DbInvoice::get_for_order_conn(conn, order.id).await?;
+            let invoice = existing.iter()
+                .find(|invoice| invoice.kind == DbInvoiceKind::Invoice)
+                .ok_or_else(|| ShopsterError::InvalidOperationError(format!(
+                    "Order {} has not been invoiced",
+                    order.id
+                )))?;
+            if existing.iter().any(|invoice| invoice.refund_id == Some(refund.id)) {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "A credit note for refund {} has already been issued",
+                    refund.id
+                )));
+            }
+
+            let mut db_lines = Vec::new();
+            for refund_item in DbRefundItem::get_for_refund_conn(conn, refund.id).await? {
+                let order_item = order.items.iter()
+                    .find(|item| item.id == refund_item.order_item_id)
+                    .ok_or_else(|| ShopsterError::InvalidOperationError(format!(
+                        "Order item {} no longer exists",
+                        refund_item.order_item_id
+                    )))?;
+                db_lines.push(make_line(
+                    Some(order_item.id),
+                    order_item.article_number.clone(),
+                    order_item.title.clone(),
+                    refund_item.quantity,
+                    -order_item.price.amount,
+                    invoice.vat_rate,
+                ));
+            }
+
+            let credited: i64 = db_lines.iter().map(|line| line.gross_amount).sum();
+            let difference = -refund.amount - credited;
+            if difference != 0 {
+                db_lines.push(make_line(
+                    None,
+                    String::new(),
+                    format!("Refund: {}", refund.reason),
+                    1,
+                    difference,
+                    invoice.vat_rate,
+                ));
+            }
+
+            let issued_at = Utc::now().naive_utc();
+            let invoice_number = numbering::next_number_conn(
+                conn,
+                CREDIT_NOTE_SEQUENCE,
+                CREDIT_NOTE_NUMBER_FORMAT_SETTING,
+                DEFAULT_CREDIT_NOTE_NUMBER_FORMAT,
+                issued_at.date(),
+            ).await?;
+
+            Self::create_conn(conn, DbInvoice {
+                id: 0,
+                order_id: order.id,
+                invoice_number,
+                kind: DbInvoiceKind::CreditNote,
+                corrected_invoice_id: Some(invoice.id),
+                refund_id: Some(refund.id),
+                billing_address: invoice.billing_address.clone(),
+                delivery_address: invoice.delivery_address.clone(),
+                currency: refund.currency.clone(),
+                vat_rate: invoice.vat_rate,
+                net_total: 0,
+                vat_total: 0,
+                gross_total: 0,
+                issued_at,
+            }, db_lines).await
+        }).await
+    }
+
+    /// Stores a document with totals summed from its lines.
+    async fn create_conn(conn: &mut AsyncPgConnection, mut db_invoice: DbInvoice, db_lines: Vec<DbInvoiceLine>) -> Result<Invoice, ShopsterError> {
+        db_invoice.net_total = db_lines.iter().map(|line| line.net_amount).sum();
+        db_invoice.vat_total = db_lines.iter().map(|line| line.vat_amount).sum();
+        db_invoice.gross_total = db_lines.iter().map(|line| line.gross_amount).sum();
+
+        let created_invoice = DbInvoice::create_conn(conn, db_invoice).await?;
+        let db_lines = db_lines.into_iter()
+            .map(|line| DbInvoiceLine { invoice_id: created_invoice.id, ..line })
+            .collect();
+        let created_lines = DbInvoiceLine::create_for_invoice_conn(conn, db_lines).await?;
+
+        let mut reply = Invoice::from(&created_invoice);
+        reply.lines = created_lines.iter().map(InvoiceLine::from).collect();
+        Ok(reply)
+    }
+
+    async fn vat_rate_conn(conn: &mut AsyncPgConnection) -> Result<f64, ShopsterError> {
+        let setting = DbSetting::find_by_title_conn(conn, VAT_SETTING).await?
+            .ok_or_else(|| ShopsterError::InvalidOperationError(
+                "The vat setting is missing".to_string(),
+            ))?;
+        let vat_rate: f64 = setting.value.trim().parse().map_err(|_| ShopsterError::InvalidOperationError(format!(
+            "Invalid vat setting: {}",
+            setting.value
+        )))?;
+        if !vat_rate.is_finite() || vat_rate < 0.0 {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Invalid vat setting: {}",
+                setting.value
+            )));
+        }
+        Ok(vat_rate)
+    }
+}
@@ -7,7 +7,7 @@
 //! ## Features
 //!
 //! - **Multi-tenant Support**: Built-in tenant isolation for managing multiple shops
-//! - **E-commerce Models**: Customers, Products, Shopping Baskets, Orders, Warehouse inventory
+//! - **E-commerce Models**: Customers, Products, Categories, Tags, Shopping Baskets, Orders, Shipments, Returns, Invoices, Warehouse inventory across locations
 //! - **Type Safety**: Leverages Rust's type system for compile-time guarantees
 //! - **PostgreSQL Backend**: Uses Diesel ORM for type-safe database interactions
 //! - **Connection Pooling**: Efficient async connection management with bb8
@@ -43,15 +43,26 @@
 extern crate diesel;
 #[macro_use] extern crate diesel_migrations;
 
+mod numbering;
 mod postgresql;
 mod schema;
 pub mod error;
+pub mod addresses;
 pub mod baskets;
 pub mod customers;
 pub mod products;
+pub mod categories;
+pub mod tags;
 pub mod orders;
 pub mod settings;
+pub mod shipments;
+pub mod returns;
+pub mod invoices;
 pub mod warehouse;
+pub mod locations;
+pub mod suppliers;
+pub mod purchase_orders;
+pub use addresses::Address;
 pub use orders::OrderStatus;
 pub use orders::PaymentStatus;
 
@@ -59,6 +70,8 @@ pub use orders::PaymentStatus;
 pub use postgresql::dborder::DbOrderStatus;
 #[doc(hidden)]
 pub use postgresql::dborder::DbPaymentStatus;
+#[doc(hidden)]
+pub use postgresql::dbinvoice::DbInvoiceKind;
 
 use diesel::PgConnection;
 use diesel::prelude::*;
@@ -81,9 +94,17 @@ use uuid::Uuid;
 use baskets::Baskets;
 use customers::Customers;
 use products::Products;
+use categories::Categories;
+use tags::Tags;
 use orders::Orders;
 use settings::Settings;
+use shipments::Shipments;
+use returns::Returns;
+use invoices::Invoices;
 use warehouse::Warehouse;
+use locations::Locations;
+use suppliers::Suppliers;
+use purchase_orders::PurchaseOrders;
 
 
 pub(crate) type DbPool = Pool<AsyncPgConnection>;
@@ -252,11 +273,36 @@ impl Shopster {
         Ok(Products::new(tenant_id))
     }
 
+    /// Gets a `Categories` handler for the category tree of the catalog.
+    pub fn categories(&self, tenant_id: Uuid) -> Result<Categories, ShopsterError> {
+        Ok(Categories::new(tenant_id))
+    }
+
+    /// Gets a `Tags` handler for renaming, merging and removing product tags.
+    pub fn tags(&self, tenant_id: Uuid) -> Result<Tags, ShopsterError> {
+        Ok(Tags::new(tenant_id))
+    }
+
     /// Gets an `Orders` handler for order management and processing.
     pub fn orders(&self, tenant_id: Uuid) -> Result<Orders, ShopsterError> {
         Ok(Orders::new(tenant_id))
     }
 
+    /// Gets a `Shipments` handler for tracking parcels sent out for orders.
+    pub fn shipments(&self, tenant_id: Uuid) -> Result<Shipments, ShopsterError> {
+        Ok(Shipments::new(tenant_id))
+    }
+
+    /// Gets a `Returns` handler for customer returns and restocking.
+    pub fn returns(&self, tenant_id: Uuid) -> Result<Returns, ShopsterError> {
+        Ok(Returns::new(tenant_id))
+    }
+
+    /// Gets an `Invoices` handler for issuing invoices and credit notes.
+    pub fn invoices(&self, tenant_id: Uuid) -> Result<Invoices, ShopsterError> {
+        Ok(Invoices::new(tenant_id))
+    }
+
     /// Gets a `Settings` handler for shop configuration.
     pub fn settings(&self, tenant_id: Uuid) -> Result<Settings, ShopsterError> {
         Ok(Settings::new(tenant_id))
@@ -266,4 +312,19 @@ impl Shopster {
     pub fn warehouse(&self, tenant_id: Uuid) -> Result<Warehouse, ShopsterError> {
         Ok(Warehouse::new(tenant_id))
     }
+
+    /// Gets a `Locations` handler for the places stock is kept at.
+    pub fn locations(&self, tenant_id: Uuid) -> Result<Locations, ShopsterError> {
+        Ok(Locations::new(tenant_id))
+    }
+
+    /// Gets a `Suppliers` handler for the companies goods are purchased from.
+    pub fn suppliers(&self, tenant_id: Uuid) -> Result<Suppliers, ShopsterError> {
+        Ok(Suppliers::new(tenant_id))
+    }
+
+    /// Gets a `PurchaseOrders` handler for ordering and receiving goods.
+    pub fn purchase_orders(&self, tenant_id: Uuid) -> Result<PurchaseOrders, ShopsterError> {
+        Ok(PurchaseOrders::new(tenant_id))
+    }
 }
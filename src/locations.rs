@@ -0,0 +1,144 @@
+//! Warehouse locations.
+//!
+//! A tenant can keep stock at several locations. Reservations and shipments
+//! take stock from the locations in the order given by the tenant's
+//! allocation strategy.
+
+use std::fmt;
+use chrono::{NaiveDateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::addresses::Address;
+use crate::error::ShopsterError;
+use crate::postgresql::dbaddress::DbAddress;
+use crate::postgresql::dblocation::DbLocation;
+use crate::postgresql::dbsettings::DbSetting;
+
+const ALLOCATION_STRATEGY_SETTING: &str = "stock_allocation_strategy";
+
+/// A place where stock is kept, e.g. a warehouse or a store.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub id: i64,
+    pub name: String,
+    /// Needed for the `Nearest` allocation strategy.
+    pub address: Option<Address>,
+    /// Locations with a lower value are allocated first. The location with
+    /// the lowest value is the default location.
+    pub priority: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbLocation> for Location {
+    fn from(db_location: &DbLocation) -> Self {
+        Location {
+            id: db_location.id,
+            name: db_location.name.clone(),
+            address: db_location.address.as_ref().map(Address::from),
+            priority: db_location.priority,
+            created_at: db_location.created_at,
+            updated_at: db_location.updated_at,
+        }
+    }
+}
+
+impl From<&Location> for DbLocation {
+    fn from(location: &Location) -> Self {
+        DbLocation {
+            id: location.id,
+            name: location.name.clone(),
+            address: location.address.as_ref().map(DbAddress::from),
+            priority: location.priority,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Some(Utc::now().naive_utc()),
+        }
+    }
+}
+
+/// The order in which stock is taken from the locations of a tenant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AllocationStrategy {
+    /// By location priority.
+    Priority,
+    /// Locations closest to the delivery address first, judged by country and
+    /// postal code. Falls back to priority where no address is known.
+    Nearest,
+}
+
+impl fmt::Display for AllocationStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AllocationStrategy::Priority => write!(f, "priority"),
+            AllocationStrategy::Nearest => write!(f, "nearest"),
+        }
+    }
+}
+
+/// Handler for warehouse locations.
+pub struct Locations {
+    tenant_id: Uuid,
+}
+
+impl Locations {
+    pub fn new(tenant_id: Uuid) -> Self {
+        Locations { tenant_id }
+    }
+
+    /// Returns all locations by priority.
+    pub async fn get_all(&self) -> Result<Vec<Location>, ShopsterError> {
+        let db_locations = DbLocation::get_all(self.tenant_id).await?;
+        Ok(db_locations.iter().map(Location::from).collect())
+    }
+
+    pub async fn get(&self, location_id: i64) -> Result<Location, ShopsterError> {
+        let db_location = DbLocation::find(self.tenant_id, location_id).await?;
+        Ok(Location::from(&db_location))
+    }
+
+    pub async fn insert(&self, location: &Location) -> Result<Location, ShopsterError> {
+        Self::validate(location)?;
+        let created_location = DbLocation::create(self.tenant_id, DbLocation::from(location)).await?;
+        Ok(Location::from(&created_location))
+    }
+
+    pub async fn update(&self, location: &Location) -> Result<Location, ShopsterError> {
+        Self::validate(location)?;
+        let updated_location = DbLocation::update(self.tenant_id, location.id, DbLocation::from(location)).await?;
+        Ok(Location::from(&updated_location))
+    }
+
+    /// Removes a location. Fails while stock is still kept there.
+    pub async fn remove(&self, location_id: i64) -> Result<bool, ShopsterError> {
+        let result = DbLocation::delete(self.tenant_id, location_id).await?;
+        Ok(result > 0)
+    }
+
+    pub async fn allocation_strategy(&self) -> Result<AllocationStrategy, ShopsterError> {
+        let setting = DbSetting::find_by_title(self.tenant_id, ALLOCATION_STRATEGY_SETTING.to_string()).await?;
+        match setting.value.as_str() {
+            "nearest" => Ok(AllocationStrategy::Nearest),
+            _ => Ok(AllocationStrategy::Priority),
+        }
+    }
+
+    pub async fn set_allocation_strategy(&self, strategy: AllocationStrategy) -> Result<AllocationStrategy, ShopsterError> {
+        let mut setting = DbSetting::find_by_title(self.tenant_id, ALLOCATION_STRATEGY_SETTING.to_string()).await?;
+        setting.value = strategy.to_string();
+        DbSetting::update(self.tenant_id, setting.id, setting).await?;
+        Ok(strategy)
+    }
+
+    fn validate(location: &Location) -> Result<(), ShopsterError> {
+        if location.name.trim().is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Location name cannot be empty".to_string(),
+            ));
+        }
+        if let Some(address) = &location.address {
+            address.validate()?;
+        }
+        Ok(())
+    }
+}
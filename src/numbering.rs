@@ -0,0 +1,78 @@
+//! Gap-free document numbers built from a template stored in `settings`.
+
+use chrono::{Datelike, NaiveDate};
+use diesel_async::AsyncPgConnection;
+
+use crate::error::ShopsterError;
+use crate::postgresql::dbsequence::DbNumberSequence;
+use crate::postgresql::dbsettings::DbSetting;
+
+/// Renders a number template.
+///
+/// Supported placeholders are `{year}` and `{counter}`; `{counter:N}` pads the
+/// counter with zeros to at least `N` digits. Every template must contain a
+/// counter placeholder, otherwise the numbers would not be unique.
+pub(crate) fn format_number(template: &str, date: NaiveDate, counter: i64) -> Result<String, ShopsterError> {
+    let mut formatted = String::new();
+    let mut has_counter = false;
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        formatted.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| ShopsterError::InvalidOperationError(format!(
+            "Unterminated placeholder in number format '{}'",
+            template
+        )))? + start;
+
+        let placeholder = &rest[start + 1..end];
+        match placeholder.split_once(':') {
+            None if placeholder == "year" => formatted.push_str(&date.year().to_string()),
+            None if placeholder == "counter" => {
+                formatted.push_str(&counter.to_string());
+                has_counter = true;
+            }
+            Some(("counter", width)) => {
+                let width: usize = width.parse().map_err(|_| ShopsterError::InvalidOperationError(format!(
+                    "Invalid counter width in number format '{}'",
+                    template
+                )))?;
+                formatted.push_str(&format!("{:0width$}", counter, width = width));
+                has_counter = true;
+            }
+            _ => return Err(ShopsterError::InvalidOperationError(format!(
+                "Unknown placeholder '{{{}}}' in number format '{}'",
+                placeholder, template
+            ))),
+        }
+        rest = &rest[end + 1..];
+    }
+    formatted.push_str(rest);
+
+    if !has_counter {
+        return Err(ShopsterError::InvalidOperationError(format!(
+            "Number format '{}' must contain a {{counter}} placeholder",
+            template
+        )));
+    }
+    Ok(formatted)
+}
+
+/// Draws the next number of `sequence_name` and renders it with the template
+/// stored in the setting `format_setting`, falling back to `default_format`.
+///
+/// Templates containing `{year}` restart their counter every year. Must run in
+/// the transaction that stores the number, so that a rollback leaves no gap.
+pub(crate) async fn next_number_conn(conn: &mut AsyncPgConnection, sequence_name: &str, format_setting: &str, default_format: &str, date: NaiveDate) -> Result<String, ShopsterError> {
+    let template = DbSetting::find_by_title_conn(conn, format_setting).await?
+        .map(|setting| setting.value)
+        .unwrap_or_else(|| default_format.to_string());
+
+    let period = if template.contains("{year}") {
+        date.year().to_string()
+    } else {
+        String::new()
+    };
+
+    let counter = DbNumberSequence::next_value_conn(conn, sequence_name, &period).await?;
+    format_number(&template, date, counter)
+}
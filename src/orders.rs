@@ -1,19 +1,38 @@
 //! Order management and processing.
 
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use uuid::Uuid;
-use chrono::{NaiveDateTime, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
 
-use diesel_async::AsyncConnection;
+use diesel_async::{AsyncConnection, AsyncPgConnection};
 
 use crate::aquire_pool;
-use crate::error::ShopsterError;
+use crate::addresses::Address;
+use crate::error::{ShopsterError, StockShortage};
 use crate::baskets::Baskets;
+use crate::customers::Customers;
+use crate::products::{load_products_conn, VariantOption};
+use crate::numbering;
+use crate::postgresql::dbaddress::DbAddress;
+use crate::postgresql::dbbasket::{DbBasket, DbBasketProduct};
 use crate::postgresql::dborder::DbOrder;
 use crate::postgresql::dborder::DbOrderItem;
 use crate::postgresql::dborder::DbOrderStatus;
+use crate::postgresql::dborder::DbOrderStatusHistory;
 use crate::postgresql::dborder::DbPaymentStatus;
-use crate::postgresql::dbwarehouse::DbWarehouse;
+use crate::postgresql::dbproduct::DbProduct;
+use crate::postgresql::dbproductvariant::{DbProductVariant, DbVariantOption, DbVariantOptions};
+use crate::postgresql::dbrefund::{DbRefund, DbRefundItem};
+use crate::postgresql::dbsettings::DbSetting;
+use crate::postgresql::dbstockmovement::DbMovementReference;
+use crate::postgresql::dbwarehouse::{DbStockPolicy, DbWarehouse};
+
+const ORDER_NUMBER_SEQUENCE: &str = "orders";
+const ORDER_NUMBER_FORMAT_SETTING: &str = "order_number_format";
+const DEFAULT_ORDER_NUMBER_FORMAT: &str = "{year}-{counter:6}";
+const RESERVATION_TIMEOUT_SETTING: &str = "reservation_timeout_minutes";
+const DEFAULT_RESERVATION_TIMEOUT_MINUTES: i64 = 24 * 60;
 
 /// The lifecycle status of an order.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -24,6 +43,7 @@ pub enum OrderStatus {
     Shipping,
     Done,
     Cancelled,
+    PartiallyShipped,
 }
 
 impl fmt::Display for OrderStatus {
@@ -41,6 +61,7 @@ impl From<DbOrderStatus> for OrderStatus {
             DbOrderStatus::Shipping => OrderStatus::Shipping,
             DbOrderStatus::Done => OrderStatus::Done,
             DbOrderStatus::Cancelled => OrderStatus::Cancelled,
+            DbOrderStatus::PartiallyShipped => OrderStatus::PartiallyShipped,
         }
     }
 }
@@ -54,6 +75,7 @@ impl From<OrderStatus> for DbOrderStatus {
             OrderStatus::Shipping => DbOrderStatus::Shipping,
             OrderStatus::Done => DbOrderStatus::Done,
             OrderStatus::Cancelled => DbOrderStatus::Cancelled,
+            OrderStatus::PartiallyShipped => DbOrderStatus::PartiallyShipped,
         }
     }
 }
@@ -65,6 +87,8 @@ pub enum PaymentStatus {
     Paid,
     Failed,
     Refunded,
+    /// Derived from the refund ledger when some, but not all, of the captured total was refunded.
+    PartiallyRefunded,
 }
 
 impl fmt::Display for PaymentStatus {
@@ -80,6 +104,7 @@ impl From<DbPaymentStatus> for PaymentStatus {
             DbPaymentStatus::Paid => PaymentStatus::Paid,
             DbPaymentStatus::Failed => PaymentStatus::Failed,
             DbPaymentStatus::Refunded => PaymentStatus::Refunded,
+            DbPaymentStatus::PartiallyRefunded => PaymentStatus::PartiallyRefunded,
         }
     }
 }
@@ -91,6 +116,7 @@ impl From<PaymentStatus> for DbPaymentStatus {
             PaymentStatus::Paid => DbPaymentStatus::Paid,
             PaymentStatus::Failed => DbPaymentStatus::Failed,
             PaymentStatus::Refunded => DbPaymentStatus::Refunded,
+            PaymentStatus::PartiallyRefunded => DbPaymentStatus::PartiallyRefunded,
         }
     }
 }
@@ -118,6 +144,16 @@ pub struct OrderItemSnapshot {
     pub additional_images: Vec<String>,
     pub price: OrderItemPrice,
     pub weight: i64,
+    /// Units of this line that have already left the warehouse in a shipment.
+    pub fulfilled_quantity: i64,
+    /// The options of the ordered variant; empty for products without variants.
+    pub variant_options: Vec<VariantOption>,
+}
+
+impl OrderItemSnapshot {
+    pub fn unfulfilled_quantity(&self) -> i64 {
+        self.quantity - self.fulfilled_quantity
+    }
 }
 
 impl From<&DbOrderItem> for OrderItemSnapshot {
@@ -152,6 +188,8 @@ impl From<&DbOrderItem> for OrderItemSnapshot {
                 currency: db_item.currency.clone(),
             },
             weight: db_item.weight as i64,
+            fulfilled_quantity: db_item.fulfilled_quantity,
+            variant_options: db_item.variant_options.0.iter().map(VariantOption::from).collect(),
         }
     }
 }
@@ -175,6 +213,8 @@ impl From<&OrderItemSnapshot> for DbOrderItem {
             currency: item.price.currency.clone(),
             weight: item.weight as i32,
             created_at: Utc::now().naive_utc(),
+            fulfilled_quantity: item.fulfilled_quantity,
+            variant_options: DbVariantOptions::new(item.variant_options.iter().map(DbVariantOption::from).collect()),
         }
     }
 }
@@ -184,13 +224,16 @@ pub struct Order {
     pub id: i64,
     pub customer_id: Option<Uuid>,
     pub status: OrderStatus,
-    pub delivery_address: String,
-    pub billing_address: String,
+    pub delivery_address: Address,
+    pub billing_address: Address,
     pub items: Vec<OrderItemSnapshot>,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
     pub payment_reference: Option<String>,
     pub payment_status: PaymentStatus,
+    /// Human-readable, gap-free number assigned by `Orders::insert`; `None` for
+    /// orders created before numbering was introduced.
+    pub order_number: Option<String>,
 }
 
 impl From<&Order> for DbOrder {
@@ -199,12 +242,90 @@ impl From<&Order> for DbOrder {
             id: order.id,
             customer_id: order.customer_id,
             status: order.status.into(),
-            delivery_address: order.delivery_address.clone(),
-            billing_address: order.billing_address.clone(),
+            delivery_address: DbAddress::from(&order.delivery_address),
+            billing_address: DbAddress::from(&order.billing_address),
             created_at: Utc::now().naive_utc(),
             updated_at: Some(Utc::now().naive_utc()),
             payment_reference: order.payment_reference.clone(),
             payment_status: order.payment_status.into(),
+            order_number: order.order_number.clone(),
+        }
+    }
+}
+
+/// An entry in an order's audit trail, recorded whenever its fulfillment or
+/// payment status changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderStatusChange {
+    pub id: i64,
+    pub order_id: i64,
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub old_payment_status: PaymentStatus,
+    pub new_payment_status: PaymentStatus,
+    pub actor: Option<String>,
+    pub comment: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<&DbOrderStatusHistory> for OrderStatusChange {
+    fn from(entry: &DbOrderStatusHistory) -> Self {
+        OrderStatusChange {
+            id: entry.id,
+            order_id: entry.order_id,
+            old_status: entry.old_status.into(),
+            new_status: entry.new_status.into(),
+            old_payment_status: entry.old_payment_status.into(),
+            new_payment_status: entry.new_payment_status.into(),
+            actor: entry.actor.clone(),
+            comment: entry.comment.clone(),
+            created_at: entry.created_at,
+        }
+    }
+}
+
+/// An order line covered by a refund, referencing an `OrderItemSnapshot` by id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefundLine {
+    pub id: i64,
+    pub order_item_id: i64,
+    pub quantity: i64,
+}
+
+impl From<&DbRefundItem> for RefundLine {
+    fn from(db_item: &DbRefundItem) -> Self {
+        RefundLine {
+            id: db_item.id,
+            order_item_id: db_item.order_item_id,
+            quantity: db_item.quantity,
+        }
+    }
+}
+
+/// A single entry in an order's refund ledger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Refund {
+    pub id: i64,
+    pub order_id: i64,
+    pub amount: i64,
+    pub currency: String,
+    pub reason: String,
+    pub payment_reference: Option<String>,
+    pub lines: Vec<RefundLine>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<&DbRefund> for Refund {
+    fn from(db_refund: &DbRefund) -> Self {
+        Refund {
+            id: db_refund.id,
+            order_id: db_refund.order_id,
+            amount: db_refund.amount,
+            currency: db_refund.currency.clone(),
+            reason: db_refund.reason.clone(),
+            payment_reference: db_refund.payment_reference.clone(),
+            lines: Vec::new(),
+            created_at: db_refund.created_at,
         }
     }
 }
@@ -219,8 +340,10 @@ impl Orders {
         Orders { tenant_id }
     }
 
+    /// Statuses in which the unfulfilled quantity of every line is held as a
+    /// warehouse reservation.
     fn is_reserving_status(status: OrderStatus) -> bool {
-        matches!(status, OrderStatus::New | OrderStatus::InProgress | OrderStatus::ReadyToShip)
+        matches!(status, OrderStatus::New | OrderStatus::InProgress | OrderStatus::ReadyToShip | OrderStatus::PartiallyShipped)
     }
 
     fn is_valid_transition(from: OrderStatus, to: OrderStatus) -> bool {
@@ -229,10 +352,13 @@ impl Orders {
             (OrderStatus::New, OrderStatus::InProgress)
                 | (OrderStatus::InProgress, OrderStatus::ReadyToShip)
                 | (OrderStatus::ReadyToShip, OrderStatus::Shipping)
+                | (OrderStatus::ReadyToShip, OrderStatus::PartiallyShipped)
+                | (OrderStatus::PartiallyShipped, OrderStatus::Shipping)
                 | (OrderStatus::Shipping, OrderStatus::Done)
                 | (OrderStatus::New, OrderStatus::Cancelled)
                 | (OrderStatus::InProgress, OrderStatus::Cancelled)
                 | (OrderStatus::ReadyToShip, OrderStatus::Cancelled)
+                | (OrderStatus::PartiallyShipped, OrderStatus::Cancelled)
                 | (OrderStatus::Shipping, OrderStatus::Cancelled)
         )
     }
@@ -249,13 +375,14 @@ impl Orders {
                 id: db_order.id,
                 customer_id: db_order.customer_id,
                 status: db_order.status.into(),
-                delivery_address: db_order.delivery_address,
-                billing_address: db_order.billing_address,
+                delivery_address: Address::from(&db_order.delivery_address),
+                billing_address: Address::from(&db_order.billing_address),
                 items,
                 created_at: db_order.created_at,
                 updated_at: db_order.updated_at,
                 payment_reference: db_order.payment_reference,
                 payment_status: db_order.payment_status.into(),
+                order_number: db_order.order_number,
             });
         }
 
@@ -271,13 +398,14 @@ impl Orders {
             id: db_order.id,
             customer_id: db_order.customer_id,
             status: db_order.status.into(),
-            delivery_address: db_order.delivery_address,
-            billing_address: db_order.billing_address,
+            delivery_address: Address::from(&db_order.delivery_address),
+            billing_address: Address::from(&db_order.billing_address),
             items,
             created_at: db_order.created_at,
             updated_at: db_order.updated_at,
             payment_reference: db_order.payment_reference,
             payment_status: db_order.payment_status.into(),
+            order_number: db_order.order_number,
         })
     }
 
@@ -293,13 +421,14 @@ impl Orders {
                 id: db_order.id,
                 customer_id: db_order.customer_id,
                 status: db_order.status.into(),
-                delivery_address: db_order.delivery_address,
-                billing_address: db_order.billing_address,
+                delivery_address: Address::from(&db_order.delivery_address),
+                billing_address: Address::from(&db_order.billing_address),
                 items,
                 created_at: db_order.created_at,
                 updated_at: db_order.updated_at,
                 payment_reference: db_order.payment_reference,
                 payment_status: db_order.payment_status.into(),
+                order_number: db_order.order_number,
             });
         }
 
@@ -318,13 +447,14 @@ impl Orders {
                 id: db_order.id,
                 customer_id: db_order.customer_id,
                 status: db_order.status.into(),
-                delivery_address: db_order.delivery_address,
-                billing_address: db_order.billing_address,
+                delivery_address: Address::from(&db_order.delivery_address),
+                billing_address: Address::from(&db_order.billing_address),
                 items,
                 created_at: db_order.created_at,
                 updated_at: db_order.updated_at,
                 payment_reference: db_order.payment_reference,
                 payment_status: db_order.payment_status.into(),
+                order_number: db_order.order_number,
             });
         }
 
@@ -342,68 +472,122 @@ impl Orders {
             id: db_order.id,
             customer_id: db_order.customer_id,
             status: db_order.status.into(),
-            delivery_address: db_order.delivery_address,
-            billing_address: db_order.billing_address,
+            delivery_address: Address::from(&db_order.delivery_address),
+            billing_address: Address::from(&db_order.billing_address),
+            items,
+            created_at: db_order.created_at,
+            updated_at: db_order.updated_at,
+            payment_reference: db_order.payment_reference,
+            payment_status: db_order.payment_status.into(),
+            order_number: db_order.order_number,
+        }))
+    }
+
+    pub async fn get_by_order_number(&self, order_number: &str) -> Result<Option<Order>, ShopsterError> {
+        let Some(db_order) = DbOrder::find_by_order_number(self.tenant_id, order_number).await? else {
+            return Ok(None);
+        };
+        let db_items = DbOrderItem::get_for_order(self.tenant_id, db_order.id).await?;
+        let items = db_items.iter().map(OrderItemSnapshot::from).collect();
+
+        Ok(Some(Order {
+            id: db_order.id,
+            customer_id: db_order.customer_id,
+            status: db_order.status.into(),
+            delivery_address: Address::from(&db_order.delivery_address),
+            billing_address: Address::from(&db_order.billing_address),
             items,
             created_at: db_order.created_at,
             updated_at: db_order.updated_at,
             payment_reference: db_order.payment_reference,
             payment_status: db_order.payment_status.into(),
+            order_number: db_order.order_number,
         }))
     }
 
+    /// Inserts a new order and assigns the next order number.
+    ///
+    /// The number is rendered from the `order_number_format` setting (default
+    /// `{year}-{counter:6}`, e.g. `2026-000123`) and drawn inside the insert
+    /// transaction, so numbers stay gap-free even when the insert fails. Any
+    /// `order_number` on the given order is ignored.
+    ///
+    /// Both addresses are validated with `Address::validate`, including the
+    /// country-specific postal code format.
     pub async fn insert(&self, order: &Order) -> Result<Order, ShopsterError> {
-        if order.delivery_address.trim().is_empty() {
-            return Err(ShopsterError::InvalidOperationError(
-                "Delivery address cannot be empty".to_string(),
-            ));
-        }
-        if order.billing_address.trim().is_empty() {
-            return Err(ShopsterError::InvalidOperationError(
-                "Billing address cannot be empty".to_string(),
-            ));
-        }
+        validate_address("Delivery", &order.delivery_address)?;
+        validate_address("Billing", &order.billing_address)?;
 
         let pool = aquire_pool(self.tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
+        conn.transaction(async |conn| Self::insert_conn(conn, order).await).await
+    }
+
+    /// Inserts an order on an existing connection; see `insert`. Addresses are
+    /// expected to be validated by the caller.
+    async fn insert_conn(conn: &mut AsyncPgConnection, order: &Order) -> Result<Order, ShopsterError> {
         let db_order = DbOrder::from(order);
         let mut db_items_input: Vec<DbOrderItem> = order.items.iter().map(DbOrderItem::from).collect();
         let is_reserving = Self::is_reserving_status(order.status);
 
-        conn.transaction(async |conn| {
-            let created_order = DbOrder::create_conn(conn, db_order).await?;
+        let order_number = numbering::next_number_conn(
+            conn,
+            ORDER_NUMBER_SEQUENCE,
+            ORDER_NUMBER_FORMAT_SETTING,
+            DEFAULT_ORDER_NUMBER_FORMAT,
+            Utc::now().date_naive(),
+        ).await?;
+        let db_order = DbOrder { order_number: Some(order_number), ..db_order };
+        let created_order = DbOrder::create_conn(conn, db_order).await?;
 
-            for db_item in &mut db_items_input {
-                db_item.order_id = created_order.id;
-            }
-            let created_items = DbOrderItem::create_for_order_conn(conn, db_items_input).await?;
-            let items: Vec<OrderItemSnapshot> = created_items.iter().map(OrderItemSnapshot::from).collect();
+        for db_item in &mut db_items_input {
+            db_item.order_id = created_order.id;
+        }
+        let created_items = DbOrderItem::create_for_order_conn(conn, db_items_input).await?;
+        let items: Vec<OrderItemSnapshot> = created_items.iter().map(OrderItemSnapshot::from).collect();
 
-            if is_reserving {
-                for item in &items {
-                    DbWarehouse::apply_reserved_delta_conn(conn, item.product_id, item.quantity).await?;
-                }
+        if is_reserving {
+            for item in &items {
+                DbWarehouse::apply_reserved_delta_conn(conn, item.product_id, item.quantity, Some(&created_order.delivery_address), &DbMovementReference::order(created_order.id)).await?;
             }
+        }
 
-            Ok(Order {
-                id: created_order.id,
-                customer_id: created_order.customer_id,
-                status: created_order.status.into(),
-                delivery_address: created_order.delivery_address,
-                billing_address: created_order.billing_address,
-                items,
-                created_at: created_order.created_at,
-                updated_at: created_order.updated_at,
-                payment_reference: created_order.payment_reference,
-                payment_status: created_order.payment_status.into(),
-            })
-        }).await
+        Ok(Order {
+            id: created_order.id,
+            customer_id: created_order.customer_id,
+            status: created_order.status.into(),
+            delivery_address: Address::from(&created_order.delivery_address),
+            billing_address: Address::from(&created_order.billing_address),
+            items,
+            created_at: created_order.created_at,
+            updated_at: created_order.updated_at,
+            payment_reference: created_order.payment_reference,
+            payment_status: created_order.payment_status.into(),
+            order_number: created_order.order_number,
+        })
     }
 
     pub async fn update(&self, order: &Order) -> Result<Order, ShopsterError> {
-        let existing_order = DbOrder::find(self.tenant_id, order.id).await?;
-        let existing_items = DbOrderItem::get_for_order(self.tenant_id, order.id).await?;
+        self.update_with_note(order, None, None).await
+    }
+
+    /// Like `update`, but records who made the change and why in the order's
+    /// status history.
+    pub async fn update_with_note(&self, order: &Order, actor: Option<&str>, comment: Option<&str>) -> Result<Order, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| Self::update_conn(conn, order, actor, comment).await).await
+    }
+
+    /// Applies an order update on an existing connection, validating the status
+    /// transition and moving warehouse reservations accordingly.
+    ///
+    /// Used by `update` as well as by other subsystems (e.g. `Shipments`) that
+    /// need to drive the order lifecycle inside their own transaction.
+    pub(crate) async fn update_conn(conn: &mut AsyncPgConnection, order: &Order, actor: Option<&str>, comment: Option<&str>) -> Result<Order, ShopsterError> {
+        let existing_order = DbOrder::find_for_update_conn(conn, order.id).await?;
         let previous_status: OrderStatus = existing_order.status.into();
         let next_status: OrderStatus = order.status;
 
@@ -414,41 +598,161 @@ impl Orders {
             )));
         }
 
-        let pool = aquire_pool(self.tenant_id).await?;
-        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        let updated_order = DbOrder::update_conn(conn, order.id, DbOrder::from(order), actor, comment).await?;
 
-        let db_order = DbOrder::from(order);
-        let order_id = order.id;
+        let db_items = DbOrderItem::get_for_order_conn(conn, updated_order.id).await?;
+        let mut items: Vec<OrderItemSnapshot> = db_items.iter().map(OrderItemSnapshot::from).collect();
+        if next_status == OrderStatus::Shipping && previous_status != next_status {
+            Self::ship_open_quantities_conn(conn, &updated_order, &items).await?;
+            let db_items = DbOrderItem::get_for_order_conn(conn, updated_order.id).await?;
+            items = db_items.iter().map(OrderItemSnapshot::from).collect();
+        }
+
+        Self::move_reservations_conn(conn, &updated_order, &items, previous_status, next_status).await?;
+
+        Ok(Order {
+            id: updated_order.id,
+            customer_id: updated_order.customer_id,
+            status: updated_order.status.into(),
+            delivery_address: Address::from(&updated_order.delivery_address),
+            billing_address: Address::from(&updated_order.billing_address),
+            items,
+            created_at: updated_order.created_at,
+            updated_at: updated_order.updated_at,
+            payment_reference: updated_order.payment_reference,
+            payment_status: updated_order.payment_status.into(),
+            order_number: updated_order.order_number,
+        })
+    }
+
+    /// Ships the unfulfilled quantity of every line when an order is moved to
+    /// `Shipping` without recording shipments for it, the same way
+    /// `Shipments::insert` ships a parcel: the units are fulfilled, and their
+    /// reservation and stock leave the warehouse.
+    async fn ship_open_quantities_conn(conn: &mut AsyncPgConnection, order: &DbOrder, items: &[OrderItemSnapshot]) -> Result<(), ShopsterError> {
+        for item in items.iter().filter(|item| item.unfulfilled_quantity() > 0) {
+            DbOrderItem::apply_fulfilled_delta_conn(conn, item.id, item.unfulfilled_quantity()).await?;
+            DbWarehouse::apply_shipped_quantity_conn(conn, item.product_id, item.unfulfilled_quantity(), Some(&order.delivery_address), &DbMovementReference::order(order.id)).await?;
+        }
+        Ok(())
+    }
+
+    /// Reserves or releases the unfulfilled quantity of every line when a status
+    /// change enters or leaves a reserving status.
+    async fn move_reservations_conn(conn: &mut AsyncPgConnection, order: &DbOrder, items: &[OrderItemSnapshot], previous_status: OrderStatus, next_status: OrderStatus) -> Result<(), ShopsterError> {
         let previous_reserving = Self::is_reserving_status(previous_status);
         let next_reserving = Self::is_reserving_status(next_status);
-        let previous_snapshots: Vec<OrderItemSnapshot> = existing_items.iter().map(OrderItemSnapshot::from).collect();
+        if previous_reserving == next_reserving {
+            return Ok(());
+        }
 
-        conn.transaction(async |conn| {
-            let updated_order = DbOrder::update_conn(conn, order_id, db_order).await?;
+        let delta = if next_reserving { 1i64 } else { -1i64 };
+        for item in items.iter().filter(|item| item.unfulfilled_quantity() > 0) {
+            DbWarehouse::apply_reserved_delta_conn(conn, item.product_id, item.unfulfilled_quantity() * delta, Some(&order.delivery_address), &DbMovementReference::order(order.id)).await?;
+        }
+        Ok(())
+    }
 
-            let db_items = DbOrderItem::get_for_order_conn(conn, updated_order.id).await?;
-            let items: Vec<OrderItemSnapshot> = db_items.iter().map(OrderItemSnapshot::from).collect();
+    /// Returns how long a `New` order may wait for its payment before
+    /// `expire_stale_reservations` cancels it, from the
+    /// `reservation_timeout_minutes` setting (default 24 hours).
+    pub async fn reservation_timeout(&self) -> Result<Duration, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
-            if previous_reserving != next_reserving {
-                let delta = if next_reserving { 1i64 } else { -1i64 };
-                for item in &previous_snapshots {
-                    DbWarehouse::apply_reserved_delta_conn(conn, item.product_id, item.quantity * delta).await?;
-                }
+        let minutes = match DbSetting::find_by_title_conn(&mut conn, RESERVATION_TIMEOUT_SETTING).await? {
+            Some(setting) => setting.value.trim().parse::<i64>().map_err(|_| ShopsterError::InvalidOperationError(format!(
+                "Invalid {} setting: {}",
+                RESERVATION_TIMEOUT_SETTING, setting.value
+            )))?,
+            None => DEFAULT_RESERVATION_TIMEOUT_MINUTES,
+        };
+        Ok(Duration::minutes(minutes))
+    }
+
+    /// Stores the reservation timeout in whole minutes. It must be at least one
+    /// minute.
+    pub async fn set_reservation_timeout(&self, timeout: Duration) -> Result<Duration, ShopsterError> {
+        let minutes = timeout.num_minutes();
+        if minutes < 1 {
+            return Err(ShopsterError::InvalidOperationError(
+                "Reservation timeout must be at least one minute".to_string(),
+            ));
+        }
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        match DbSetting::find_by_title_conn(&mut conn, RESERVATION_TIMEOUT_SETTING).await? {
+            Some(mut setting) => {
+                setting.value = minutes.to_string();
+                DbSetting::update(self.tenant_id, setting.id, setting).await?;
             }
+            None => {
+                let setting = DbSetting {
+                    id: 0,
+                    title: RESERVATION_TIMEOUT_SETTING.to_string(),
+                    datatype: "Integer".to_string(),
+                    value: minutes.to_string(),
+                };
+                DbSetting::create(self.tenant_id, setting).await?;
+            }
+        }
+        Ok(Duration::minutes(minutes))
+    }
 
-            Ok(Order {
-                id: updated_order.id,
-                customer_id: updated_order.customer_id,
-                status: updated_order.status.into(),
-                delivery_address: updated_order.delivery_address,
-                billing_address: updated_order.billing_address,
-                items,
-                created_at: updated_order.created_at,
-                updated_at: updated_order.updated_at,
-                payment_reference: updated_order.payment_reference,
-                payment_status: updated_order.payment_status.into(),
-            })
-        }).await
+    /// Cancels every `New` order whose payment is still `Pending` and that was
+    /// created longer than the reservation timeout before `now`, releasing its
+    /// warehouse reservation. Returns the cancelled orders.
+    ///
+    /// Each order is cancelled in its own transaction, and orders locked by a
+    /// concurrent transaction are skipped (`FOR UPDATE SKIP LOCKED`), so the
+    /// sweep can run on several instances at once without cancelling an order
+    /// twice or racing an incoming payment.
+    pub async fn expire_stale_reservations(&self, now: NaiveDateTime) -> Result<Vec<Order>, ShopsterError> {
+        let created_before = now - self.reservation_timeout().await?;
+        let order_ids = DbOrder::get_unpaid_ids_since(self.tenant_id, created_before).await?;
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let mut expired = Vec::new();
+        for order_id in order_ids {
+            let order = conn.transaction(async |conn| {
+                let Some(existing_order) = DbOrder::lock_if_unpaid_since_conn(conn, order_id, created_before).await? else {
+                    return Ok(None);
+                };
+                let updated_order = DbOrder::update_status_conn(conn, order_id, DbOrderStatus::Cancelled, None, Some("Reservation expired")).await?;
+                let db_items = DbOrderItem::get_for_order_conn(conn, order_id).await?;
+                let items: Vec<OrderItemSnapshot> = db_items.iter().map(OrderItemSnapshot::from).collect();
+                Self::move_reservations_conn(conn, &updated_order, &items, existing_order.status.into(), OrderStatus::Cancelled).await?;
+                Self::get_by_id_conn(conn, order_id).await.map(Some)
+            }).await?;
+            expired.extend(order);
+        }
+        Ok(expired)
+    }
+
+    /// Loads an order together with its items on an existing connection. The
+    /// order row is locked for the rest of the transaction.
+    pub(crate) async fn get_by_id_conn(conn: &mut AsyncPgConnection, order_id: i64) -> Result<Order, ShopsterError> {
+        let db_order = DbOrder::find_for_update_conn(conn, order_id).await?;
+        let db_items = DbOrderItem::get_for_order_conn(conn, db_order.id).await?;
+        let items = db_items.iter().map(OrderItemSnapshot::from).collect();
+
+        Ok(Order {
+            id: db_order.id,
+            customer_id: db_order.customer_id,
+            status: db_order.status.into(),
+            delivery_address: Address::from(&db_order.delivery_address),
+            billing_address: Address::from(&db_order.billing_address),
+            items,
+            created_at: db_order.created_at,
+            updated_at: db_order.updated_at,
+            payment_reference: db_order.payment_reference,
+            payment_status: db_order.payment_status.into(),
+            order_number: db_order.order_number,
+        })
     }
 
     pub async fn remove(&self, order_id: i64) -> Result<bool, ShopsterError> {
@@ -459,8 +763,8 @@ impl Orders {
         if Self::is_reserving_status(existing_status) {
             let pool = aquire_pool(self.tenant_id).await?;
             let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
-            for item in &existing_items {
-                DbWarehouse::apply_reserved_delta_conn(&mut conn, item.product_id, -(item.quantity)).await?;
+            for item in existing_items.iter().filter(|item| item.quantity > item.fulfilled_quantity) {
+                DbWarehouse::apply_reserved_delta_conn(&mut conn, item.product_id, -(item.quantity - item.fulfilled_quantity), Some(&existing_order.delivery_address), &DbMovementReference::order(order_id)).await?;
             }
         }
 
@@ -468,51 +772,157 @@ impl Orders {
         Ok(result > 0)
     }
 
-    pub async fn create_from_basket(&self, basket_id: Uuid, delivery_address: String, billing_address: String, payment_reference: Option<String>) -> Result<Order, ShopsterError> {
-        let baskets = Baskets::new(self.tenant_id);
-        let basket_items = baskets.get_products_with_details(basket_id).await?;
+    /// Checks out a basket: creates an order from its items, reserves their stock
+    /// and empties the basket in a single transaction.
+    ///
+    /// The warehouse rows of all products in the basket are locked first. If any
+    /// line asks for more than the available stock (`in_stock - reserved`) of a
+    /// product with the `Deny` stock policy, the checkout fails with
+    /// `ShopsterError::InsufficientStockError` listing every such line, and
+    /// nothing is changed. Lines of `Backorder` products beyond the available
+    /// stock are backordered instead. Guest baskets are deleted afterwards; a
+    /// customer's persistent basket is kept, but emptied. Orders created from a
+    /// customer's basket are assigned to that customer.
+    pub async fn create_from_basket(&self, basket_id: Uuid, delivery_address: Address, billing_address: Address, payment_reference: Option<String>) -> Result<Order, ShopsterError> {
+        self.checkout(basket_id, None, delivery_address, billing_address, payment_reference).await
+    }
+
+    /// Like `create_from_basket`, but takes the delivery and billing address from
+    /// the customer's address book.
+    ///
+    /// Both addresses must belong to `customer_id`. They are copied into the
+    /// order, so later changes to the address book do not alter placed orders.
+    /// The order is assigned to the customer.
+    pub async fn create_from_basket_with_address_ids(&self, basket_id: Uuid, customer_id: Uuid, delivery_address_id: i64, billing_address_id: i64, payment_reference: Option<String>) -> Result<Order, ShopsterError> {
+        let customers = Customers::new(self.tenant_id);
+        let delivery_address = customers.get_address(customer_id, delivery_address_id).await?
+            .ok_or_else(|| ShopsterError::InvalidOperationError(format!("Address {} not found", delivery_address_id)))?;
+        let billing_address = customers.get_address(customer_id, billing_address_id).await?
+            .ok_or_else(|| ShopsterError::InvalidOperationError(format!("Address {} not found", billing_address_id)))?;
 
-        let mut items = Vec::new();
-        for basket_item in basket_items {
-            let product = basket_item.product;
-            let price = product.price.ok_or_else(|| {
-                ShopsterError::InvalidOperationError("Product price missing".to_string())
-            })?;
+        self.checkout(basket_id, Some(customer_id), delivery_address.address, billing_address.address, payment_reference).await
+    }
 
-            items.push(OrderItemSnapshot {
-                id: 0,
-                product_id: product.id,
-                quantity: basket_item.quantity,
-                article_number: product.article_number,
-                gtin: product.gtin,
-                title: product.title,
-                short_description: product.short_description,
-                description: product.description,
-                tags: product.tags,
-                title_image: product.image_url,
-                additional_images: product.additional_images,
-                price: OrderItemPrice {
-                    amount: price.amount,
-                    currency: price.currency,
+    /// The order belongs to the basket's customer; a `customer_id` may only be
+    /// given for guest baskets or baskets of that same customer.
+    async fn checkout(&self, basket_id: Uuid, customer_id: Option<Uuid>, delivery_address: Address, billing_address: Address, payment_reference: Option<String>) -> Result<Order, ShopsterError> {
+        validate_address("Delivery", &delivery_address)?;
+        validate_address("Billing", &billing_address)?;
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let db_basket = DbBasket::find_for_update_conn(conn, basket_id).await?;
+            let customer_id = match (db_basket.customer_id, customer_id) {
+                (Some(owner), Some(customer_id)) if owner != customer_id => {
+                    return Err(ShopsterError::InvalidOperationError(
+                        "Basket belongs to another customer".to_string(),
+                    ));
                 },
-                weight: product.weight,
-            });
-        }
+                (owner, customer_id) => customer_id.or(owner),
+            };
 
-        let order = Order {
-            id: 0,
-            customer_id: None,
-            status: OrderStatus::New,
-            delivery_address,
-            billing_address,
-            items,
-            created_at: Utc::now().naive_utc(),
-            updated_at: None,
-            payment_reference,
-            payment_status: PaymentStatus::Pending,
-        };
+            let basket_items = DbBasketProduct::get_basket_items_for_update_conn(conn, basket_id).await?;
+            if basket_items.is_empty() {
+                return Err(ShopsterError::InvalidOperationError("Basket is empty".to_string()));
+            }
+
+            // Stock held by the basket itself is reserved for the order instead
+            for basket_item in &basket_items {
+                Baskets::release_hold_conn(conn, basket_item).await?;
+            }
+
+            let mut product_ids: Vec<i64> = basket_items.iter().map(|item| item.product_id).collect();
+            product_ids.sort_unstable();
+            product_ids.dedup();
+
+            // Only products with the `Deny` policy can run short; a product without
+            // a warehouse row has no stock at all. Stock counts across all locations.
+            let stock = DbWarehouse::lock_for_products_conn(conn, &product_ids).await?;
+            let unconstrained: HashSet<i64> = stock.iter()
+                .filter(|item| item.stock_policy != DbStockPolicy::Deny)
+                .map(|item| item.product_id)
+                .collect();
+            let mut available: HashMap<i64, i64> = HashMap::new();
+            for item in &stock {
+                *available.entry(item.product_id).or_insert(0) += (item.in_stock - item.reserved).max(0);
+            }
 
-        self.insert(&order).await
+            let mut shortages = Vec::new();
+            for basket_item in basket_items.iter().filter(|item| !unconstrained.contains(&item.product_id)) {
+                let remaining = available.entry(basket_item.product_id).or_insert(0);
+                if basket_item.quantity > *remaining {
+                    shortages.push(StockShortage {
+                        basket_product_id: basket_item.id,
+                        product_id: basket_item.product_id,
+                        requested: basket_item.quantity,
+                        available: (*remaining).max(0),
+                    });
+                } else {
+                    *remaining -= basket_item.quantity;
+                }
+            }
+            if !shortages.is_empty() {
+                return Err(ShopsterError::InsufficientStockError(shortages));
+            }
+
+            let mut items = Vec::new();
+            for basket_item in &basket_items {
+                let db_product = DbProduct::find_conn(conn, basket_item.product_id).await?;
+                let product = load_products_conn(conn, &[db_product]).await?.remove(0);
+                let price = product.price.ok_or_else(|| {
+                    ShopsterError::InvalidOperationError("Product price missing".to_string())
+                })?;
+                let variant_options = match DbProductVariant::find_conn(conn, basket_item.product_id).await? {
+                    Some(variant) => variant.options.0.iter().map(VariantOption::from).collect(),
+                    None => Vec::new(),
+                };
+
+                items.push(OrderItemSnapshot {
+                    id: 0,
+                    product_id: product.id,
+                    quantity: basket_item.quantity,
+                    article_number: product.article_number,
+                    gtin: product.gtin,
+                    title: product.title,
+                    short_description: product.short_description,
+                    description: product.description,
+                    tags: product.tags,
+                    title_image: product.image_url,
+                    additional_images: product.additional_images,
+                    price: OrderItemPrice {
+                        amount: price.amount,
+                        currency: price.currency,
+                    },
+                    weight: product.weight,
+                    fulfilled_quantity: 0,
+                    variant_options,
+                });
+            }
+
+            // New orders are reserving, so inserting reserves the stock checked above
+            let order = Self::insert_conn(conn, &Order {
+                id: 0,
+                customer_id,
+                status: OrderStatus::New,
+                delivery_address,
+                billing_address,
+                items,
+                created_at: Utc::now().naive_utc(),
+                updated_at: None,
+                payment_reference,
+                payment_status: PaymentStatus::Pending,
+                order_number: None,
+            }).await?;
+
+            DbBasketProduct::delete_all_basket_items_conn(conn, basket_id).await?;
+            if db_basket.customer_id.is_none() {
+                DbBasket::delete_conn(conn, basket_id).await?;
+            }
+
+            Ok(order)
+        }).await
     }
 
     /// Updates an order's payment status independently of its fulfillment status.
@@ -521,7 +931,13 @@ impl Orders {
     /// (e.g. a Stripe webhook confirming or refunding a payment) are orthogonal to
     /// the order's shipping lifecycle.
     pub async fn update_payment_status(&self, order_id: i64, payment_status: PaymentStatus) -> Result<Order, ShopsterError> {
-        let updated_order = DbOrder::update_payment_status(self.tenant_id, order_id, payment_status.into()).await?;
+        self.update_payment_status_with_note(order_id, payment_status, None, None).await
+    }
+
+    /// Like `update_payment_status`, but records who made the change and why in
+    /// the order's status history.
+    pub async fn update_payment_status_with_note(&self, order_id: i64, payment_status: PaymentStatus, actor: Option<&str>, comment: Option<&str>) -> Result<Order, ShopsterError> {
+        let updated_order = DbOrder::update_payment_status(self.tenant_id, order_id, payment_status.into(), actor, comment).await?;
         let db_items = DbOrderItem::get_for_order(self.tenant_id, updated_order.id).await?;
         let items = db_items.iter().map(OrderItemSnapshot::from).collect();
 
@@ -529,13 +945,158 @@ impl Orders {
             id: updated_order.id,
             customer_id: updated_order.customer_id,
             status: updated_order.status.into(),
-            delivery_address: updated_order.delivery_address,
-            billing_address: updated_order.billing_address,
+            delivery_address: Address::from(&updated_order.delivery_address),
+            billing_address: Address::from(&updated_order.billing_address),
             items,
             created_at: updated_order.created_at,
             updated_at: updated_order.updated_at,
             payment_reference: updated_order.payment_reference,
             payment_status: updated_order.payment_status.into(),
+            order_number: updated_order.order_number,
         })
     }
+
+    /// Books a partial or full refund against a paid order.
+    ///
+    /// `lines` names the order lines (and quantities) the refund covers; it may be
+    /// empty for goodwill refunds not tied to specific goods. The refunded
+    /// quantity per line may not exceed the ordered quantity, and the sum of all
+    /// refunds may not exceed the captured total. The order's payment status is
+    /// derived from the ledger afterwards: `Refunded` once the captured total is
+    /// fully refunded, `PartiallyRefunded` otherwise.
+    pub async fn refund(&self, order_id: i64, lines: &[RefundLine], amount: i64, reason: String, payment_reference: Option<String>) -> Result<Refund, ShopsterError> {
+        if amount <= 0 {
+            return Err(ShopsterError::InvalidOperationError(
+                "Refund amount must be positive".to_string(),
+            ));
+        }
+        if lines.iter().any(|line| line.quantity <= 0) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Quantity must be positive".to_string(),
+            ));
+        }
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let order = Self::get_by_id_conn(conn, order_id).await?;
+
+            if !matches!(order.payment_status, PaymentStatus::Paid | PaymentStatus::PartiallyRefunded) {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Cannot refund an order with payment status {}",
+                    order.payment_status
+                )));
+            }
+
+            let currency = match order.items.first() {
+                Some(item) => item.price.currency.clone(),
+                None => return Err(ShopsterError::InvalidOperationError(
+                    "Cannot refund an order without items".to_string(),
+                )),
+            };
+            if order.items.iter().any(|item| item.price.currency != currency) {
+                return Err(ShopsterError::InvalidOperationError(
+                    "Cannot refund an order with mixed currencies".to_string(),
+                ));
+            }
+
+            let mut refunded_quantities: HashMap<i64, i64> = HashMap::new();
+            for item in DbRefundItem::get_for_order_conn(conn, order.id).await? {
+                *refunded_quantities.entry(item.order_item_id).or_insert(0) += item.quantity;
+            }
+            for line in lines {
+                let order_item = order.items.iter()
+                    .find(|order_item| order_item.id == line.order_item_id)
+                    .ok_or_else(|| ShopsterError::InvalidOperationError(format!(
+                        "Order item {} does not belong to order {}",
+                        line.order_item_id, order.id
+                    )))?;
+
+                let total = refunded_quantities.entry(line.order_item_id).or_insert(0);
+                *total += line.quantity;
+                if *total > order_item.quantity {
+                    return Err(ShopsterError::InvalidOperationError(format!(
+                        "Refunded quantity for order item {} exceeds ordered quantity",
+                        line.order_item_id
+                    )));
+                }
+            }
+
+            let captured_total: i64 = order.items.iter()
+                .map(|item| item.price.amount * item.quantity)
+                .sum();
+            let refunded_total: i64 = DbRefund::get_for_order_conn(conn, order.id).await?
+                .iter()
+                .map(|refund| refund.amount)
+                .sum();
+            if refunded_total + amount > captured_total {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Refund of {} exceeds the remaining captured amount of {}",
+                    amount, captured_total - refunded_total
+                )));
+            }
+
+            let created_refund = DbRefund::create_conn(conn, DbRefund {
+                id: 0,
+                order_id: order.id,
+                amount,
+                currency,
+                reason,
+                payment_reference,
+                created_at: Utc::now().naive_utc(),
+            }).await?;
+
+            let db_items: Vec<DbRefundItem> = lines.iter()
+                .map(|line| DbRefundItem {
+                    id: 0,
+                    refund_id: created_refund.id,
+                    order_item_id: line.order_item_id,
+                    quantity: line.quantity,
+                })
+                .collect();
+            let created_items = DbRefundItem::create_for_refund_conn(conn, db_items).await?;
+
+            let payment_status = if refunded_total + amount == captured_total {
+                PaymentStatus::Refunded
+            } else {
+                PaymentStatus::PartiallyRefunded
+            };
+            DbOrder::update_payment_status_conn(conn, order.id, payment_status.into(), None, Some(&created_refund.reason)).await?;
+
+            let mut reply = Refund::from(&created_refund);
+            reply.lines = created_items.iter().map(RefundLine::from).collect();
+            Ok(reply)
+        }).await
+    }
+
+    /// Returns the status history of an order, oldest entry first.
+    pub async fn get_history(&self, order_id: i64) -> Result<Vec<OrderStatusChange>, ShopsterError> {
+        let history = DbOrderStatusHistory::get_for_order(self.tenant_id, order_id).await?;
+        Ok(history.iter().map(OrderStatusChange::from).collect())
+    }
+
+    /// Returns the refund ledger of an order, oldest entry first.
+    pub async fn get_refunds(&self, order_id: i64) -> Result<Vec<Refund>, ShopsterError> {
+        let db_refunds = DbRefund::get_for_order(self.tenant_id, order_id).await?;
+        let mut refunds = Vec::new();
+
+        for db_refund in db_refunds {
+            let db_items = DbRefundItem::get_for_refund(self.tenant_id, db_refund.id).await?;
+            let mut refund = Refund::from(&db_refund);
+            refund.lines = db_items.iter().map(RefundLine::from).collect();
+            refunds.push(refund);
+        }
+
+        Ok(refunds)
+    }
+}
+
+fn validate_address(kind: &str, address: &Address) -> Result<(), ShopsterError> {
+    address.validate().map_err(|e| match e {
+        ShopsterError::InvalidOperationError(message) => {
+            ShopsterError::InvalidOperationError(format!("{} address: {}", kind, message))
+        }
+        other => other,
+    })
 }
@@ -0,0 +1,41 @@
+use serde::{Serialize, Deserialize};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Jsonb;
+use std::io::Write;
+
+
+/// Postal address stored as a JSONB document.
+#[derive(Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq, Clone, Default)]
+#[diesel(sql_type = Jsonb)]
+pub struct DbAddress {
+    pub name: String,
+    pub company: Option<String>,
+    pub street: String,
+    pub house_number: String,
+    pub postal_code: String,
+    pub city: String,
+    pub country: String,
+    pub phone: Option<String>
+}
+
+impl ToSql<Jsonb, Pg> for DbAddress {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        // JSONB binary format: version byte followed by the JSON text
+        out.write_all(&[1])?;
+        serde_json::to_writer(out, self)?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Jsonb, Pg> for DbAddress {
+    fn from_sql(value: PgValue) -> deserialize::Result<Self> {
+        let bytes = value.as_bytes();
+        match bytes.split_first() {
+            Some((1, json)) => Ok(serde_json::from_slice(json)?),
+            _ => Err("Unsupported JSONB encoding version".into()),
+        }
+    }
+}
@@ -19,7 +19,9 @@ pub struct DbBasketProduct {
     pub id: i64,
     pub product_id: i64,
     pub quantity: i64,
-    pub basket_id: Uuid
+    pub basket_id: Uuid,
+    pub reserved_quantity: i64,
+    pub reserved_until: Option<NaiveDateTime>
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
@@ -27,7 +29,9 @@ pub struct DbBasketProduct {
 pub struct InsertableDbBasketProduct {
     pub product_id: i64,
     pub quantity: i64,
-    pub basket_id: Uuid
+    pub basket_id: Uuid,
+    pub reserved_quantity: i64,
+    pub reserved_until: Option<NaiveDateTime>
 }
 
 impl From<&DbBasketProduct> for InsertableDbBasketProduct {
@@ -35,7 +39,9 @@ impl From<&DbBasketProduct> for InsertableDbBasketProduct {
         InsertableDbBasketProduct {
             product_id: basket_product.product_id,
             quantity: basket_product.quantity,
-            basket_id: basket_product.basket_id
+            basket_id: basket_product.basket_id,
+            reserved_quantity: basket_product.reserved_quantity,
+            reserved_until: basket_product.reserved_until
         }
     }
 }
@@ -51,6 +57,16 @@ impl DbBasketProduct {
         Ok(basket_product)
     }
 
+    /// Loads a basket item with `FOR UPDATE`, so changes to its stock hold are
+    /// serialized by the surrounding transaction.
+    pub async fn find_basket_item_for_update_conn(conn: &mut AsyncPgConnection, basket_product_id: i64) -> Result<DbBasketProduct, ShopsterError> {
+        let basket_product = basketproducts::table
+            .filter(basketproducts::id.eq(basket_product_id))
+            .for_update()
+            .first(conn).await?;
+        Ok(basket_product)
+    }
+
     pub async fn get_basket_items(tenant_id: Uuid, basket_id: Uuid) -> Result<Vec<DbBasketProduct>, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
@@ -123,6 +139,64 @@ impl DbBasketProduct {
         Ok(basket_products)
     }
 
+    /// Loads all items of a basket with `FOR UPDATE`, in product id order.
+    pub async fn get_basket_items_for_update_conn(conn: &mut AsyncPgConnection, basket_id: Uuid) -> Result<Vec<DbBasketProduct>, ShopsterError> {
+        let basket_products = basketproducts::table
+            .filter(basketproducts::basket_id.eq(basket_id))
+            .order((basketproducts::product_id.asc(), basketproducts::id.asc()))
+            .for_update()
+            .get_results(conn).await?;
+        Ok(basket_products)
+    }
+
+    /// Returns the ids of basket items whose stock hold expired before `now`.
+    pub async fn get_expired_hold_ids(tenant_id: Uuid, now: NaiveDateTime) -> Result<Vec<i64>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let ids = basketproducts::table
+            .filter(basketproducts::reserved_quantity.gt(0))
+            .filter(basketproducts::reserved_until.lt(now))
+            .order((basketproducts::reserved_until.asc(), basketproducts::id.asc()))
+            .select(basketproducts::id)
+            .load(&mut conn).await?;
+        Ok(ids)
+    }
+
+    /// Locks a basket item whose stock hold expired before `now`. Returns
+    /// `None` if the hold has been changed meanwhile or another transaction
+    /// holds the lock (`SKIP LOCKED`).
+    pub async fn lock_expired_hold_conn(conn: &mut AsyncPgConnection, basket_product_id: i64, now: NaiveDateTime) -> Result<Option<DbBasketProduct>, ShopsterError> {
+        let basket_product = basketproducts::table
+            .filter(basketproducts::id.eq(basket_product_id))
+            .filter(basketproducts::reserved_quantity.gt(0))
+            .filter(basketproducts::reserved_until.lt(now))
+            .for_update()
+            .skip_locked()
+            .first(conn).await
+            .optional()?;
+        Ok(basket_product)
+    }
+
+    pub async fn set_hold_conn(conn: &mut AsyncPgConnection, basket_product_id: i64, reserved_quantity: i64, reserved_until: Option<NaiveDateTime>) -> Result<Self, ShopsterError> {
+        let db_basket_product = diesel::update(basketproducts::table)
+            .filter(basketproducts::id.eq(basket_product_id))
+            .set((
+                basketproducts::reserved_quantity.eq(reserved_quantity),
+                basketproducts::reserved_until.eq(reserved_until),
+            ))
+            .get_result(conn).await?;
+        Ok(db_basket_product)
+    }
+
+    pub async fn delete_basket_item_conn(conn: &mut AsyncPgConnection, basket_product_id: i64) -> Result<usize, ShopsterError> {
+        let res = diesel::delete(
+            basketproducts::table
+                .filter(basketproducts::id.eq(basket_product_id))
+        ).execute(conn).await?;
+        Ok(res)
+    }
+
     pub async fn create_basket_item_conn(conn: &mut AsyncPgConnection, basket_product: DbBasketProduct) -> Result<Self, ShopsterError> {
         let insertable = InsertableDbBasketProduct::from(&basket_product);
         let db_basket_product = diesel::insert_into(basketproducts::table)
@@ -146,21 +220,24 @@ impl DbBasketProduct {
 pub struct DbBasket {
     pub id: Uuid,
     pub created_at: NaiveDateTime,
-    pub updated_at: Option<NaiveDateTime>
+    pub updated_at: Option<NaiveDateTime>,
+    pub customer_id: Option<Uuid>
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
 #[diesel(table_name = baskets)]
 pub struct InsertableDbBasket {
     pub created_at: NaiveDateTime,
-    pub updated_at: Option<NaiveDateTime>
+    pub updated_at: Option<NaiveDateTime>,
+    pub customer_id: Option<Uuid>
 }
 
 impl From<&DbBasket> for InsertableDbBasket {
     fn from(basket: &DbBasket) -> Self {
         InsertableDbBasket {
             created_at: basket.created_at,
-            updated_at: basket.updated_at
+            updated_at: basket.updated_at,
+            customer_id: basket.customer_id
         }
     }
 }
@@ -186,13 +263,49 @@ impl DbBasket {
         Ok(basket)
     }
 
-    pub async fn create(tenant_id: Uuid) -> Result<Self, ShopsterError> {
+    pub async fn find_conn(conn: &mut AsyncPgConnection, basket_id: Uuid) -> Result<Self, ShopsterError> {
+        let basket = baskets::table
+            .filter(baskets::id.eq(basket_id))
+            .first(conn).await?;
+        Ok(basket)
+    }
+
+    pub async fn find_for_update_conn(conn: &mut AsyncPgConnection, basket_id: Uuid) -> Result<Self, ShopsterError> {
+        let basket = baskets::table
+            .filter(baskets::id.eq(basket_id))
+            .for_update()
+            .first(conn).await?;
+        Ok(basket)
+    }
+
+    pub async fn find_for_customer(tenant_id: Uuid, customer_id: Uuid) -> Result<Option<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let basket = baskets::table
+            .filter(baskets::customer_id.eq(customer_id))
+            .first(&mut conn).await
+            .optional()?;
+        Ok(basket)
+    }
+
+    pub async fn find_for_customer_for_update_conn(conn: &mut AsyncPgConnection, customer_id: Uuid) -> Result<Option<Self>, ShopsterError> {
+        let basket = baskets::table
+            .filter(baskets::customer_id.eq(customer_id))
+            .for_update()
+            .first(conn).await
+            .optional()?;
+        Ok(basket)
+    }
+
+    pub async fn create(tenant_id: Uuid, customer_id: Option<Uuid>) -> Result<Self, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
         let insertable = InsertableDbBasket {
             created_at: Utc::now().naive_utc(),
-            updated_at: Some(Utc::now().naive_utc())
+            updated_at: Some(Utc::now().naive_utc()),
+            customer_id
         };
         let basket = diesel::insert_into(baskets::table)
             .values(insertable)
@@ -200,6 +313,17 @@ impl DbBasket {
         Ok(basket)
     }
 
+    pub async fn update_customer_conn(conn: &mut AsyncPgConnection, basket_id: Uuid, customer_id: Option<Uuid>) -> Result<Self, ShopsterError> {
+        let basket = diesel::update(baskets::table)
+            .filter(baskets::id.eq(basket_id))
+            .set((
+                baskets::customer_id.eq(customer_id),
+                baskets::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(conn).await?;
+        Ok(basket)
+    }
+
     pub async fn delete(tenant_id: Uuid, basket_id: Uuid) -> Result<usize, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
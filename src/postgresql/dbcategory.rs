@@ -0,0 +1,284 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
+use crate::postgresql::dbproduct::DbProduct;
+
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, QueryableByName)]
+#[diesel(table_name = categories)]
+pub struct DbCategory {
+    pub id: i64,
+    pub parent_id: Option<i64>,
+    pub title: String,
+    pub slug: String,
+    pub position: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = categories)]
+pub struct InsertableDbCategory {
+    pub parent_id: Option<i64>,
+    pub title: String,
+    pub slug: String,
+    pub position: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbCategory> for InsertableDbCategory {
+    fn from(category: &DbCategory) -> Self {
+        InsertableDbCategory {
+            parent_id: category.parent_id,
+            title: category.title.clone(),
+            slug: category.slug.clone(),
+            position: category.position,
+            created_at: category.created_at,
+            updated_at: category.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, QueryableByName)]
+struct DbCategoryId {
+    #[diesel(sql_type = BigInt)]
+    id: i64,
+}
+
+impl DbCategory {
+    /// Returns all categories ordered by parent and position.
+    pub async fn get_all(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_categories = categories::table
+            .order((categories::parent_id.asc().nulls_first(), categories::position.asc(), categories::id.asc()))
+            .load(&mut conn).await?;
+        Ok(db_categories)
+    }
+
+    pub async fn find(tenant_id: Uuid, category_id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_category = categories::table
+            .filter(categories::id.eq(category_id))
+            .first(&mut conn).await?;
+        Ok(db_category)
+    }
+
+    pub async fn find_by_slug(tenant_id: Uuid, slug: &str) -> Result<Option<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_category = categories::table
+            .filter(categories::slug.eq(slug))
+            .first(&mut conn).await
+            .optional()?;
+        Ok(db_category)
+    }
+
+    /// Returns the direct children of a category, or the root categories for
+    /// `None`, ordered by position.
+    pub async fn get_children(tenant_id: Uuid, parent_id: Option<i64>) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let query = categories::table.into_boxed();
+        let query = match parent_id {
+            Some(parent_id) => query.filter(categories::parent_id.eq(parent_id)),
+            None => query.filter(categories::parent_id.is_null()),
+        };
+        let db_categories = query
+            .order((categories::position.asc(), categories::id.asc()))
+            .load(&mut conn).await?;
+        Ok(db_categories)
+    }
+
+    /// Returns the path from the root down to the category, the category
+    /// itself being the last element.
+    pub async fn get_path(tenant_id: Uuid, category_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        Self::get_path_conn(&mut conn, category_id).await
+    }
+
+    pub async fn get_path_conn(conn: &mut AsyncPgConnection, category_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let path = diesel::sql_query(
+            "WITH RECURSIVE path AS ( \
+                SELECT c.*, 0 AS depth FROM categories c WHERE c.id = $1 \
+                UNION ALL \
+                SELECT c.*, p.depth + 1 FROM categories c JOIN path p ON c.id = p.parent_id \
+             ) \
+             SELECT id, parent_id, title, slug, position, created_at, updated_at \
+             FROM path \
+             ORDER BY depth DESC",
+        )
+        .bind::<BigInt, _>(category_id)
+        .load(conn).await?;
+        Ok(path)
+    }
+
+    /// Returns the ids of a category and all of its descendants.
+    pub async fn get_descendant_ids_conn(conn: &mut AsyncPgConnection, category_id: i64) -> Result<Vec<i64>, ShopsterError> {
+        let ids: Vec<DbCategoryId> = diesel::sql_query(
+            "WITH RECURSIVE tree AS ( \
+                SELECT id FROM categories WHERE id = $1 \
+                UNION ALL \
+                SELECT c.id FROM categories c JOIN tree t ON c.parent_id = t.id \
+             ) \
+             SELECT id FROM tree",
+        )
+        .bind::<BigInt, _>(category_id)
+        .load(conn).await?;
+        Ok(ids.into_iter().map(|row| row.id).collect())
+    }
+
+    pub async fn create(tenant_id: Uuid, category: DbCategory) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let insertable = InsertableDbCategory::from(&category);
+        let db_category = diesel::insert_into(categories::table)
+            .values(insertable)
+            .get_result(&mut conn).await?;
+        Ok(db_category)
+    }
+
+    /// Updates a category. Moving it below one of its own descendants is
+    /// rejected; moves are serialized so that concurrent moves cannot form a
+    /// cycle either.
+    pub async fn update(tenant_id: Uuid, category_id: i64, category: DbCategory) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            if let Some(parent_id) = category.parent_id {
+                diesel::sql_query("LOCK TABLE categories IN SHARE ROW EXCLUSIVE MODE")
+                    .execute(conn).await?;
+                let path = Self::get_path_conn(conn, parent_id).await?;
+                if path.is_empty() {
+                    return Err(ShopsterError::InvalidOperationError(format!(
+                        "Parent category {} does not exist", parent_id
+                    )));
+                }
+                if path.iter().any(|ancestor| ancestor.id == category_id) {
+                    return Err(ShopsterError::InvalidOperationError(
+                        "A category cannot be moved below itself".to_string(),
+                    ));
+                }
+            }
+
+            let db_category = diesel::update(categories::table)
+                .filter(categories::id.eq(category_id))
+                .set((
+                    categories::parent_id.eq(category.parent_id),
+                    categories::title.eq(category.title),
+                    categories::slug.eq(category.slug),
+                    categories::position.eq(category.position),
+                    categories::updated_at.eq(Some(Utc::now().naive_utc())),
+                ))
+                .get_result(conn).await?;
+            Ok(db_category)
+        }).await
+    }
+
+    /// Deletes a category and its product assignments. Fails while it still
+    /// has child categories.
+    pub async fn delete(tenant_id: Uuid, category_id: i64) -> Result<usize, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let has_children: bool = diesel::select(diesel::dsl::exists(
+            categories::table.filter(categories::parent_id.eq(category_id)),
+        ))
+        .get_result(&mut conn).await?;
+        if has_children {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Category {} still has subcategories", category_id
+            )));
+        }
+
+        let res = diesel::delete(categories::table.filter(categories::id.eq(category_id)))
+            .execute(&mut conn).await?;
+        Ok(res)
+    }
+
+    /// Assigns a product to a category. Assigning it again has no effect.
+    pub async fn add_product(tenant_id: Uuid, category_id: i64, product_id: i64) -> Result<(), ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        diesel::insert_into(product_categories::table)
+            .values((
+                product_categories::product_id.eq(product_id),
+                product_categories::category_id.eq(category_id),
+            ))
+            .on_conflict_do_nothing()
+            .execute(&mut conn).await?;
+        Ok(())
+    }
+
+    pub async fn remove_product(tenant_id: Uuid, category_id: i64, product_id: i64) -> Result<usize, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let res = diesel::delete(product_categories::table
+            .filter(product_categories::category_id.eq(category_id))
+            .filter(product_categories::product_id.eq(product_id)))
+            .execute(&mut conn).await?;
+        Ok(res)
+    }
+
+    /// Returns the categories a product is assigned to.
+    pub async fn get_for_product(tenant_id: Uuid, product_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_categories = categories::table
+            .inner_join(product_categories::table)
+            .filter(product_categories::product_id.eq(product_id))
+            .select(categories::all_columns)
+            .order((categories::position.asc(), categories::id.asc()))
+            .load(&mut conn).await?;
+        Ok(db_categories)
+    }
+
+    /// Returns the products assigned to a category, and with
+    /// `include_descendants` also those assigned to any category below it.
+    pub async fn get_products(tenant_id: Uuid, category_id: i64, include_descendants: bool) -> Result<Vec<DbProduct>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let category_ids = if include_descendants {
+            Self::get_descendant_ids_conn(&mut conn, category_id).await?
+        } else {
+            vec![category_id]
+        };
+
+        let db_products = products::table
+            .filter(products::id.eq_any(
+                product_categories::table
+                    .filter(product_categories::category_id.eq_any(category_ids))
+                    .select(product_categories::product_id),
+            ))
+            .order(products::id.asc())
+            .select(DbProduct::as_select())
+            .load(&mut conn).await?;
+        Ok(db_products)
+    }
+}
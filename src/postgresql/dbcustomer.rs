@@ -6,10 +6,11 @@ use diesel::{
     Insertable
 };
 use diesel::prelude::*;
-use diesel_async::RunQueryDsl;
+use diesel_async::{RunQueryDsl, AsyncConnection, AsyncPgConnection};
 use uuid::Uuid;
 use crate::ShopsterError;
 use crate::schema::*;
+use crate::postgresql::dbaddress::DbAddress;
 use crate::aquire_pool;
 use argon2::Config;
 
@@ -211,3 +212,157 @@ impl DbCustomer {
         Ok(customers)
     }
 }
+
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = customer_addresses)]
+pub struct DbCustomerAddress {
+    pub id: i64,
+    pub customer_id: Uuid,
+    pub address: DbAddress,
+    pub is_default_billing: bool,
+    pub is_default_shipping: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = customer_addresses)]
+pub struct InsertableDbCustomerAddress {
+    pub customer_id: Uuid,
+    pub address: DbAddress,
+    pub is_default_billing: bool,
+    pub is_default_shipping: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbCustomerAddress> for InsertableDbCustomerAddress {
+    fn from(address: &DbCustomerAddress) -> Self {
+        InsertableDbCustomerAddress {
+            customer_id: address.customer_id,
+            address: address.address.clone(),
+            is_default_billing: address.is_default_billing,
+            is_default_shipping: address.is_default_shipping,
+            created_at: address.created_at,
+            updated_at: address.updated_at,
+        }
+    }
+}
+
+impl DbCustomerAddress {
+    pub async fn get_for_customer(tenant_id: Uuid, customer_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::get_for_customer_conn(&mut conn, customer_id).await
+    }
+
+    pub async fn get_for_customer_conn(conn: &mut AsyncPgConnection, customer_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
+        let addresses = customer_addresses::table
+            .filter(customer_addresses::customer_id.eq(customer_id))
+            .order(customer_addresses::id.asc())
+            .load(conn).await?;
+        Ok(addresses)
+    }
+
+    /// Looks up an address, but only if it belongs to the given customer.
+    pub async fn find_for_customer(tenant_id: Uuid, customer_id: Uuid, id: i64) -> Result<Option<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::find_for_customer_conn(&mut conn, customer_id, id).await
+    }
+
+    pub async fn find_for_customer_conn(conn: &mut AsyncPgConnection, customer_id: Uuid, id: i64) -> Result<Option<Self>, ShopsterError> {
+        let address = customer_addresses::table
+            .filter(customer_addresses::id.eq(id))
+            .filter(customer_addresses::customer_id.eq(customer_id))
+            .first(conn).await
+            .optional()?;
+        Ok(address)
+    }
+
+    pub async fn create_conn(conn: &mut AsyncPgConnection, address: DbCustomerAddress) -> Result<Self, ShopsterError> {
+        let insertable = InsertableDbCustomerAddress::from(&address);
+        let db_address = diesel::insert_into(customer_addresses::table)
+            .values(insertable)
+            .get_result(conn).await?;
+        Ok(db_address)
+    }
+
+    pub async fn update_address(tenant_id: Uuid, customer_id: Uuid, id: i64, address: DbAddress) -> Result<Option<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_address = diesel::update(customer_addresses::table)
+            .filter(customer_addresses::id.eq(id))
+            .filter(customer_addresses::customer_id.eq(customer_id))
+            .set((
+                customer_addresses::address.eq(address),
+                customer_addresses::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(&mut conn).await
+            .optional()?;
+        Ok(db_address)
+    }
+
+    /// Makes the address the customer's only default billing address.
+    pub async fn set_default_billing(tenant_id: Uuid, customer_id: Uuid, id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            diesel::update(customer_addresses::table)
+                .filter(customer_addresses::customer_id.eq(customer_id))
+                .filter(customer_addresses::is_default_billing.eq(true))
+                .set(customer_addresses::is_default_billing.eq(false))
+                .execute(conn).await?;
+
+            let db_address = diesel::update(customer_addresses::table)
+                .filter(customer_addresses::id.eq(id))
+                .filter(customer_addresses::customer_id.eq(customer_id))
+                .set(customer_addresses::is_default_billing.eq(true))
+                .get_result(conn).await
+                .optional()?;
+
+            // Failing here rolls back the cleared default when the address does not belong to the customer
+            db_address.ok_or_else(|| ShopsterError::InvalidOperationError(format!("Address {} not found", id)))
+        }).await
+    }
+
+    /// Makes the address the customer's only default shipping address.
+    pub async fn set_default_shipping(tenant_id: Uuid, customer_id: Uuid, id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            diesel::update(customer_addresses::table)
+                .filter(customer_addresses::customer_id.eq(customer_id))
+                .filter(customer_addresses::is_default_shipping.eq(true))
+                .set(customer_addresses::is_default_shipping.eq(false))
+                .execute(conn).await?;
+
+            let db_address = diesel::update(customer_addresses::table)
+                .filter(customer_addresses::id.eq(id))
+                .filter(customer_addresses::customer_id.eq(customer_id))
+                .set(customer_addresses::is_default_shipping.eq(true))
+                .get_result(conn).await
+                .optional()?;
+
+            // Failing here rolls back the cleared default when the address does not belong to the customer
+            db_address.ok_or_else(|| ShopsterError::InvalidOperationError(format!("Address {} not found", id)))
+        }).await
+    }
+
+    pub async fn delete(tenant_id: Uuid, customer_id: Uuid, id: i64) -> Result<usize, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let res = diesel::delete(
+            customer_addresses::table
+                .filter(customer_addresses::id.eq(id))
+                .filter(customer_addresses::customer_id.eq(customer_id))
+            )
+            .execute(&mut conn).await?;
+        Ok(res)
+    }
+}
@@ -1,25 +1,290 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Jsonb;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use uuid::Uuid;
 
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
-pub struct DbImage {
-    pub id: i32,
-    pub path: String,
+
+/// The alternative texts of an image by language code, stored as a JSONB
+/// object.
+#[derive(Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq, Clone, Default)]
+#[diesel(sql_type = Jsonb)]
+pub struct DbAltText(pub BTreeMap<String, String>);
+
+impl ToSql<Jsonb, Pg> for DbAltText {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        // JSONB binary format: version byte followed by the JSON text
+        out.write_all(&[1])?;
+        serde_json::to_writer(out, &self.0)?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Jsonb, Pg> for DbAltText {
+    fn from_sql(value: PgValue) -> deserialize::Result<Self> {
+        let bytes = value.as_bytes();
+        match bytes.split_first() {
+            Some((1, json)) => Ok(DbAltText(serde_json::from_slice(json)?)),
+            _ => Err("Unsupported JSONB encoding version".into()),
+        }
+    }
+}
+
+
+/// An image of a product. The images of a product are ordered by `position`,
+/// counting from `0` without gaps; the first one is its title image.
+#[derive(Debug, Clone, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = product_images)]
+pub struct DbProductImage {
+    pub id: i64,
+    pub product_id: i64,
+    pub url: String,
+    pub position: i32,
+    pub alt_text: DbAltText,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub mime_type: Option<String>,
+    pub checksum: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = product_images)]
+pub struct InsertableDbProductImage {
+    pub product_id: i64,
+    pub url: String,
+    pub position: i32,
+    pub alt_text: DbAltText,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub mime_type: Option<String>,
+    pub checksum: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
 
-
-impl From<&String> for DbImage {
-    fn from(data: &String) -> Self {
-        serde_json::from_str(data).expect("Unable to deserialize DbImage")
+impl From<&DbProductImage> for InsertableDbProductImage {
+    fn from(image: &DbProductImage) -> Self {
+        InsertableDbProductImage {
+            product_id: image.product_id,
+            url: image.url.clone(),
+            position: image.position,
+            alt_text: image.alt_text.clone(),
+            width: image.width,
+            height: image.height,
+            mime_type: image.mime_type.clone(),
+            checksum: image.checksum.clone(),
+            created_at: image.created_at,
+            updated_at: image.updated_at,
+        }
     }
 }
 
-impl From<&DbImage> for String {
-    fn from(data: &DbImage) -> Self {
-        serde_json::to_string(data).expect("Unable to serialize DbImage")
+impl DbProductImage {
+    /// Returns the images of a product in order.
+    pub async fn get_for_product_conn(conn: &mut AsyncPgConnection, product_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let images = product_images::table
+            .filter(product_images::product_id.eq(product_id))
+            .order(product_images::position.asc())
+            .load(conn).await?;
+        Ok(images)
+    }
+
+    /// Returns the ordered images of each of the given products. Variants have
+    /// no images of their own and get those of their parent.
+    pub async fn get_for_products(tenant_id: Uuid, product_ids: &[i64]) -> Result<HashMap<i64, Vec<Self>>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        Self::get_for_products_conn(&mut conn, product_ids).await
+    }
+
+    pub async fn get_for_products_conn(conn: &mut AsyncPgConnection, product_ids: &[i64]) -> Result<HashMap<i64, Vec<Self>>, ShopsterError> {
+        let parents: HashMap<i64, i64> = product_variants::table
+            .filter(product_variants::product_id.eq_any(product_ids))
+            .select((product_variants::product_id, product_variants::parent_id))
+            .load::<(i64, i64)>(conn).await?
+            .into_iter()
+            .collect();
+        let owner_id = |product_id: i64| parents.get(&product_id).copied().unwrap_or(product_id);
+
+        let images: Vec<Self> = product_images::table
+            .filter(product_images::product_id.eq_any(product_ids.iter().map(|id| owner_id(*id)).collect::<Vec<_>>()))
+            .order((product_images::product_id.asc(), product_images::position.asc()))
+            .load(conn).await?;
+        let mut by_owner: HashMap<i64, Vec<Self>> = HashMap::new();
+        for image in images {
+            by_owner.entry(image.product_id).or_default().push(image);
+        }
+
+        Ok(product_ids.iter()
+            .map(|id| (*id, by_owner.get(&owner_id(*id)).cloned().unwrap_or_default()))
+            .collect())
+    }
+
+    /// Appends an image to the images of its product, ignoring its position.
+    /// Must run in a transaction.
+    pub async fn create_conn(conn: &mut AsyncPgConnection, image: DbProductImage) -> Result<Self, ShopsterError> {
+        Self::lock_product_conn(conn, image.product_id).await?;
+        let position = Self::get_for_product_conn(conn, image.product_id).await?.len() as i32;
+
+        let insertable = InsertableDbProductImage::from(&DbProductImage { position, ..image });
+        let db_image = diesel::insert_into(product_images::table)
+            .values(insertable)
+            .get_result(conn).await?;
+        Ok(db_image)
+    }
+
+    /// Updates the URL and metadata of an image. Its product and position are
+    /// kept.
+    pub async fn update(tenant_id: Uuid, image_id: i64, image: DbProductImage) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_image = diesel::update(product_images::table)
+            .filter(product_images::id.eq(image_id))
+            .set((
+                product_images::url.eq(image.url),
+                product_images::alt_text.eq(image.alt_text),
+                product_images::width.eq(image.width),
+                product_images::height.eq(image.height),
+                product_images::mime_type.eq(image.mime_type),
+                product_images::checksum.eq(image.checksum),
+                product_images::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(&mut conn).await?;
+        Ok(db_image)
+    }
+
+    /// Deletes an image and moves the images after it up by one. Must run in a
+    /// transaction.
+    pub async fn delete_conn(conn: &mut AsyncPgConnection, image_id: i64) -> Result<usize, ShopsterError> {
+        let product_id: Option<i64> = product_images::table
+            .filter(product_images::id.eq(image_id))
+            .select(product_images::product_id)
+            .first(conn).await
+            .optional()?;
+        let product_id = match product_id {
+            Some(product_id) => product_id,
+            None => return Ok(0),
+        };
+        Self::lock_product_conn(conn, product_id).await?;
+
+        let image: Option<Self> = diesel::delete(product_images::table.filter(product_images::id.eq(image_id)))
+            .get_result(conn).await
+            .optional()?;
+        let image = match image {
+            Some(image) => image,
+            None => return Ok(0),
+        };
+        diesel::update(product_images::table)
+            .filter(product_images::product_id.eq(image.product_id))
+            .filter(product_images::position.gt(image.position))
+            .set(product_images::position.eq(product_images::position - 1))
+            .execute(conn).await?;
+        Ok(1)
+    }
+
+    /// Puts the images of a product into the given order. `image_ids` must
+    /// contain every image of the product exactly once. Must run in a
+    /// transaction.
+    pub async fn reorder_conn(conn: &mut AsyncPgConnection, product_id: i64, image_ids: &[i64]) -> Result<Vec<Self>, ShopsterError> {
+        Self::lock_product_conn(conn, product_id).await?;
+        let images = Self::get_for_product_conn(conn, product_id).await?;
+
+        let mut current: Vec<i64> = images.iter().map(|image| image.id).collect();
+        let mut requested = image_ids.to_vec();
+        current.sort_unstable();
+        requested.sort_unstable();
+        if current != requested {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "The new order must list each image of product {} exactly once", product_id
+            )));
+        }
+
+        for (position, image_id) in image_ids.iter().enumerate() {
+            diesel::update(product_images::table)
+                .filter(product_images::id.eq(image_id))
+                .set(product_images::position.eq(position as i32))
+                .execute(conn).await?;
+        }
+        Self::get_for_product_conn(conn, product_id).await
+    }
+
+    /// Makes the images of a product match the given ordered URLs. Images
+    /// whose URL is still listed keep their metadata and are moved to their
+    /// new position, the others are deleted, and new URLs are added without
+    /// metadata. Must run in a transaction.
+    pub async fn set_urls_conn(conn: &mut AsyncPgConnection, product_id: i64, urls: &[String]) -> Result<Vec<Self>, ShopsterError> {
+        Self::lock_product_conn(conn, product_id).await?;
+        let mut remaining: Vec<Option<Self>> = Self::get_for_product_conn(conn, product_id).await?
+            .into_iter()
+            .map(Some)
+            .collect();
+
+        for (position, url) in urls.iter().enumerate() {
+            let position = position as i32;
+            let existing = remaining.iter_mut()
+                .find(|image| image.as_ref().is_some_and(|image| &image.url == url))
+                .and_then(Option::take);
+            match existing {
+                Some(image) if image.position == position => {},
+                Some(image) => {
+                    diesel::update(product_images::table)
+                        .filter(product_images::id.eq(image.id))
+                        .set(product_images::position.eq(position))
+                        .execute(conn).await?;
+                },
+                None => {
+                    diesel::insert_into(product_images::table)
+                        .values(InsertableDbProductImage {
+                            product_id,
+                            url: url.clone(),
+                            position,
+                            alt_text: DbAltText::default(),
+                            width: None,
+                            height: None,
+                            mime_type: None,
+                            checksum: None,
+                            created_at: Utc::now().naive_utc(),
+                            updated_at: None,
+                        })
+                        .execute(conn).await?;
+                },
+            }
+        }
+
+        let removed: Vec<i64> = remaining.into_iter().flatten().map(|image| image.id).collect();
+        diesel::delete(product_images::table.filter(product_images::id.eq_any(removed)))
+            .execute(conn).await?;
+        Self::get_for_product_conn(conn, product_id).await
+    }
+
+    /// Locks the product row so that concurrent changes to its images are
+    /// serialized.
+    async fn lock_product_conn(conn: &mut AsyncPgConnection, product_id: i64) -> Result<(), ShopsterError> {
+        products::table
+            .filter(products::id.eq(product_id))
+            .select(products::id)
+            .for_update()
+            .first::<i64>(conn).await?;
+        Ok(())
     }
 }
@@ -0,0 +1,253 @@
+use chrono::NaiveDateTime;
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use std::fmt;
+use std::io::Write;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::postgresql::dbaddress::DbAddress;
+use crate::aquire_pool;
+
+#[derive(Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq, PartialOrd, Copy, Clone)]
+#[diesel(sql_type = crate::schema::sql_types::DbInvoiceKind)]
+pub enum DbInvoiceKind {
+    Invoice,
+    CreditNote
+}
+
+impl fmt::Display for DbInvoiceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl ToSql<crate::schema::sql_types::DbInvoiceKind, Pg> for DbInvoiceKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            DbInvoiceKind::Invoice => out.write_all(b"Invoice")?,
+            DbInvoiceKind::CreditNote => out.write_all(b"CreditNote")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::DbInvoiceKind, Pg> for DbInvoiceKind {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"Invoice" => Ok(DbInvoiceKind::Invoice),
+            b"CreditNote" => Ok(DbInvoiceKind::CreditNote),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl From<&DbInvoiceKind> for i32 {
+    fn from(kind: &DbInvoiceKind) -> Self {
+        match kind {
+            DbInvoiceKind::Invoice => 0,
+            DbInvoiceKind::CreditNote => 1,
+        }
+    }
+}
+
+impl TryFrom<i32> for DbInvoiceKind {
+    type Error = String;
+
+    fn try_from(kind: i32) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(DbInvoiceKind::Invoice),
+            1 => Ok(DbInvoiceKind::CreditNote),
+            _ => Err(format!("Unknown invoice kind: {}", kind))
+        }
+    }
+}
+
+
+/// An issued invoice or credit note. Rows are protected by a database trigger
+/// against updates and deletes, so there is deliberately no `AsChangeset`.
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = invoices)]
+pub struct DbInvoice {
+    pub id: i64,
+    pub order_id: i64,
+    pub invoice_number: String,
+    pub kind: DbInvoiceKind,
+    pub corrected_invoice_id: Option<i64>,
+    pub refund_id: Option<i64>,
+    pub billing_address: DbAddress,
+    pub delivery_address: DbAddress,
+    pub currency: String,
+    pub vat_rate: f64,
+    pub net_total: i64,
+    pub vat_total: i64,
+    pub gross_total: i64,
+    pub issued_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = invoices)]
+pub struct InsertableDbInvoice {
+    pub order_id: i64,
+    pub invoice_number: String,
+    pub kind: DbInvoiceKind,
+    pub corrected_invoice_id: Option<i64>,
+    pub refund_id: Option<i64>,
+    pub billing_address: DbAddress,
+    pub delivery_address: DbAddress,
+    pub currency: String,
+    pub vat_rate: f64,
+    pub net_total: i64,
+    pub vat_total: i64,
+    pub gross_total: i64,
+    pub issued_at: NaiveDateTime,
+}
+
+impl From<&DbInvoice> for InsertableDbInvoice {
+    fn from(invoice: &DbInvoice) -> Self {
+        InsertableDbInvoice {
+            order_id: invoice.order_id,
+            invoice_number: invoice.invoice_number.clone(),
+            kind: invoice.kind,
+            corrected_invoice_id: invoice.corrected_invoice_id,
+            refund_id: invoice.refund_id,
+            billing_address: invoice.billing_address.clone(),
+            delivery_address: invoice.delivery_address.clone(),
+            currency: invoice.currency.clone(),
+            vat_rate: invoice.vat_rate,
+            net_total: invoice.net_total,
+            vat_total: invoice.vat_total,
+            gross_total: invoice.gross_total,
+            issued_at: invoice.issued_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = invoice_lines)]
+pub struct DbInvoiceLine {
+    pub id: i64,
+    pub invoice_id: i64,
+    pub order_item_id: Option<i64>,
+    pub article_number: String,
+    pub title: String,
+    pub quantity: i64,
+    pub unit_price: i64,
+    pub net_amount: i64,
+    pub vat_amount: i64,
+    pub gross_amount: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = invoice_lines)]
+pub struct InsertableDbInvoiceLine {
+    pub invoice_id: i64,
+    pub order_item_id: Option<i64>,
+    pub article_number: String,
+    pub title: String,
+    pub quantity: i64,
+    pub unit_price: i64,
+    pub net_amount: i64,
+    pub vat_amount: i64,
+    pub gross_amount: i64,
+}
+
+impl From<&DbInvoiceLine> for InsertableDbInvoiceLine {
+    fn from(line: &DbInvoiceLine) -> Self {
+        InsertableDbInvoiceLine {
+            invoice_id: line.invoice_id,
+            order_item_id: line.order_item_id,
+            article_number: line.article_number.clone(),
+            title: line.title.clone(),
+            quantity: line.quantity,
+            unit_price: line.unit_price,
+            net_amount: line.net_amount,
+            vat_amount: line.vat_amount,
+            gross_amount: line.gross_amount,
+        }
+    }
+}
+
+
+impl DbInvoiceLine {
+    pub async fn get_for_invoice(tenant_id: Uuid, invoice_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let lines = invoice_lines::table
+            .filter(invoice_lines::invoice_id.eq(invoice_id))
+            .order(invoice_lines::id.asc())
+            .get_results(&mut conn).await?;
+        Ok(lines)
+    }
+
+    pub async fn create_for_invoice_conn(conn: &mut AsyncPgConnection, lines: Vec<DbInvoiceLine>) -> Result<Vec<Self>, ShopsterError> {
+        if lines.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let insertables: Vec<InsertableDbInvoiceLine> = lines.iter().map(InsertableDbInvoiceLine::from).collect();
+        let db_lines = diesel::insert_into(invoice_lines::table)
+            .values(insertables)
+            .get_results(conn).await?;
+        Ok(db_lines)
+    }
+}
+
+
+impl DbInvoice {
+    pub async fn find(tenant_id: Uuid, id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let invoice = invoices::table
+            .filter(invoices::id.eq(id))
+            .first(&mut conn).await?;
+        Ok(invoice)
+    }
+
+    pub async fn find_by_number(tenant_id: Uuid, invoice_number: &str) -> Result<Option<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let invoice = invoices::table
+            .filter(invoices::invoice_number.eq(invoice_number))
+            .first(&mut conn).await
+            .optional()?;
+        Ok(invoice)
+    }
+
+    pub async fn get_for_order(tenant_id: Uuid, order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::get_for_order_conn(&mut conn, order_id).await
+    }
+
+    pub async fn get_for_order_conn(conn: &mut AsyncPgConnection, order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let db_invoices = invoices::table
+            .filter(invoices::order_id.eq(order_id))
+            .order(invoices::id.asc())
+            .load(conn).await?;
+        Ok(db_invoices)
+    }
+
+    pub async fn create_conn(conn: &mut AsyncPgConnection, invoice: DbInvoice) -> Result<Self, ShopsterError> {
+        let insertable = InsertableDbInvoice::from(&invoice);
+        let db_invoice = diesel::insert_into(invoices::table)
+            .values(insertable)
+            .get_result(conn).await?;
+        Ok(db_invoice)
+    }
+}
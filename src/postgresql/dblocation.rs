@@ -0,0 +1,171 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::postgresql::dbaddress::DbAddress;
+use crate::postgresql::dbsettings::DbSetting;
+use crate::aquire_pool;
+
+const ALLOCATION_STRATEGY_SETTING: &str = "stock_allocation_strategy";
+
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = locations)]
+pub struct DbLocation {
+    pub id: i64,
+    pub name: String,
+    pub address: Option<DbAddress>,
+    pub priority: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = locations)]
+pub struct InsertableDbLocation {
+    pub name: String,
+    pub address: Option<DbAddress>,
+    pub priority: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbLocation> for InsertableDbLocation {
+    fn from(location: &DbLocation) -> Self {
+        InsertableDbLocation {
+            name: location.name.clone(),
+            address: location.address.clone(),
+            priority: location.priority,
+            created_at: location.created_at,
+            updated_at: location.updated_at,
+        }
+    }
+}
+
+impl DbLocation {
+    /// Returns all locations by priority (lowest value first).
+    pub async fn get_all(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::get_all_conn(&mut conn).await
+    }
+
+    pub async fn get_all_conn(conn: &mut AsyncPgConnection) -> Result<Vec<Self>, ShopsterError> {
+        let locations = locations::table
+            .order((locations::priority.asc(), locations::id.asc()))
+            .load(conn).await?;
+        Ok(locations)
+    }
+
+    pub async fn find(tenant_id: Uuid, location_id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let location = locations::table
+            .filter(locations::id.eq(location_id))
+            .first(&mut conn).await?;
+        Ok(location)
+    }
+
+    /// The location with the highest priority. Stock that is not assigned to a
+    /// location explicitly is kept here.
+    pub async fn find_default(tenant_id: Uuid) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::find_default_conn(&mut conn).await
+    }
+
+    pub async fn find_default_conn(conn: &mut AsyncPgConnection) -> Result<Self, ShopsterError> {
+        let location = locations::table
+            .order((locations::priority.asc(), locations::id.asc()))
+            .first(conn).await
+            .optional()?;
+        location.ok_or_else(|| ShopsterError::InvalidOperationError("No stock location configured".to_string()))
+    }
+
+    pub async fn create(tenant_id: Uuid, location: DbLocation) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let insertable = InsertableDbLocation::from(&location);
+        let db_location = diesel::insert_into(locations::table)
+            .values(insertable)
+            .get_result(&mut conn).await?;
+        Ok(db_location)
+    }
+
+    pub async fn update(tenant_id: Uuid, location_id: i64, location: DbLocation) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_location = diesel::update(locations::table)
+            .filter(locations::id.eq(location_id))
+            .set((
+                locations::name.eq(location.name),
+                locations::address.eq(location.address),
+                locations::priority.eq(location.priority),
+                locations::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(&mut conn).await?;
+        Ok(db_location)
+    }
+
+    /// Deletes a location. Fails while the location still has warehouse rows.
+    pub async fn delete(tenant_id: Uuid, location_id: i64) -> Result<usize, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let has_stock: bool = diesel::select(diesel::dsl::exists(
+            warehouse::table.filter(warehouse::location_id.eq(location_id)),
+        ))
+        .get_result(&mut conn).await?;
+        if has_stock {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Location {} still holds stock", location_id
+            )));
+        }
+
+        let res = diesel::delete(locations::table.filter(locations::id.eq(location_id)))
+            .execute(&mut conn).await?;
+        Ok(res)
+    }
+
+    /// Returns the ids of all locations in the order stock should be taken
+    /// from, according to the `stock_allocation_strategy` setting.
+    ///
+    /// With `priority` (the default) locations are ordered by priority. With
+    /// `nearest`, locations in the destination's country come first, then those
+    /// sharing the longest postal code prefix with it; ties are broken by
+    /// priority. Without a destination, `nearest` falls back to `priority`.
+    pub async fn allocation_order_conn(conn: &mut AsyncPgConnection, destination: Option<&DbAddress>) -> Result<Vec<i64>, ShopsterError> {
+        let strategy = DbSetting::find_by_title_conn(conn, ALLOCATION_STRATEGY_SETTING).await?
+            .map(|setting| setting.value);
+        let mut locations = Self::get_all_conn(conn).await?;
+
+        if let (Some("nearest"), Some(destination)) = (strategy.as_deref(), destination) {
+            // `sort_by_key` is stable, so the priority order breaks ties
+            locations.sort_by_key(|location| match &location.address {
+                Some(address) => (
+                    !address.country.eq_ignore_ascii_case(&destination.country),
+                    std::cmp::Reverse(common_prefix_len(&address.postal_code, &destination.postal_code)),
+                ),
+                None => (true, std::cmp::Reverse(0)),
+            });
+        }
+
+        Ok(locations.iter().map(|location| location.id).collect())
+    }
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(x, y)| x.eq_ignore_ascii_case(y)).count()
+}
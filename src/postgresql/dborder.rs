@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use serde::{Serialize, Deserialize};
 use diesel::{
     self,
@@ -10,7 +10,7 @@ use diesel::deserialize::{self, FromSql, FromSqlRow};
 use diesel::expression::AsExpression;
 use diesel::pg::{Pg, PgValue};
 use diesel::serialize::{self, IsNull, Output, ToSql};
-use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use diesel_async::{RunQueryDsl, AsyncConnection, AsyncPgConnection};
 use std::fmt;
 use std::io::Write;
 use std::convert::TryFrom;
@@ -18,6 +18,8 @@ use uuid::Uuid;
 
 use crate::ShopsterError;
 use crate::schema::*;
+use crate::postgresql::dbaddress::DbAddress;
+use crate::postgresql::dbproductvariant::DbVariantOptions;
 use crate::aquire_pool;
 
 #[derive(Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq, PartialOrd, Copy, Clone)]
@@ -28,7 +30,8 @@ pub enum DbOrderStatus {
     ReadyToShip,
     Shipping,
     Done,
-    Cancelled
+    Cancelled,
+    PartiallyShipped
 }
 
 impl fmt::Display for DbOrderStatus {
@@ -46,6 +49,7 @@ impl ToSql<crate::schema::sql_types::DbOrderStatus, Pg> for DbOrderStatus {
             DbOrderStatus::Shipping => out.write_all(b"Shipping")?,
             DbOrderStatus::Done => out.write_all(b"Done")?,
             DbOrderStatus::Cancelled => out.write_all(b"Cancelled")?,
+            DbOrderStatus::PartiallyShipped => out.write_all(b"PartiallyShipped")?,
         }
         Ok(IsNull::No)
     }
@@ -60,6 +64,7 @@ impl FromSql<crate::schema::sql_types::DbOrderStatus, Pg> for DbOrderStatus {
             b"Shipping" => Ok(DbOrderStatus::Shipping),
             b"Done" => Ok(DbOrderStatus::Done),
             b"Cancelled" => Ok(DbOrderStatus::Cancelled),
+            b"PartiallyShipped" => Ok(DbOrderStatus::PartiallyShipped),
             _ => Err("Unrecognized enum variant".into()),
         }
     }
@@ -74,6 +79,7 @@ impl From<&DbOrderStatus> for i32 {
             DbOrderStatus::Shipping => 3,
             DbOrderStatus::Done => 4,
             DbOrderStatus::Cancelled => 5,
+            DbOrderStatus::PartiallyShipped => 6,
         }
     }
 }
@@ -89,6 +95,7 @@ impl TryFrom<i32> for DbOrderStatus {
             3 => Ok(DbOrderStatus::Shipping),
             4 => Ok(DbOrderStatus::Done),
             5 => Ok(DbOrderStatus::Cancelled),
+            6 => Ok(DbOrderStatus::PartiallyShipped),
             _ => Err(format!("Unknown order status: {}", status))
         }
     }
@@ -100,7 +107,8 @@ pub enum DbPaymentStatus {
     Pending,
     Paid,
     Failed,
-    Refunded
+    Refunded,
+    PartiallyRefunded
 }
 
 impl fmt::Display for DbPaymentStatus {
@@ -116,6 +124,7 @@ impl ToSql<crate::schema::sql_types::DbPaymentStatus, Pg> for DbPaymentStatus {
             DbPaymentStatus::Paid => out.write_all(b"Paid")?,
             DbPaymentStatus::Failed => out.write_all(b"Failed")?,
             DbPaymentStatus::Refunded => out.write_all(b"Refunded")?,
+            DbPaymentStatus::PartiallyRefunded => out.write_all(b"PartiallyRefunded")?,
         }
         Ok(IsNull::No)
     }
@@ -128,6 +137,7 @@ impl FromSql<crate::schema::sql_types::DbPaymentStatus, Pg> for DbPaymentStatus
             b"Paid" => Ok(DbPaymentStatus::Paid),
             b"Failed" => Ok(DbPaymentStatus::Failed),
             b"Refunded" => Ok(DbPaymentStatus::Refunded),
+            b"PartiallyRefunded" => Ok(DbPaymentStatus::PartiallyRefunded),
             _ => Err("Unrecognized enum variant".into()),
         }
     }
@@ -140,6 +150,7 @@ impl From<&DbPaymentStatus> for i32 {
             DbPaymentStatus::Paid => 1,
             DbPaymentStatus::Failed => 2,
             DbPaymentStatus::Refunded => 3,
+            DbPaymentStatus::PartiallyRefunded => 4,
         }
     }
 }
@@ -153,24 +164,26 @@ impl TryFrom<i32> for DbPaymentStatus {
             1 => Ok(DbPaymentStatus::Paid),
             2 => Ok(DbPaymentStatus::Failed),
             3 => Ok(DbPaymentStatus::Refunded),
+            4 => Ok(DbPaymentStatus::PartiallyRefunded),
             _ => Err(format!("Unknown payment status: {}", status))
         }
     }
 }
 
 
-#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable)]
 #[diesel(table_name = orders)]
 pub struct DbOrder {
     pub id: i64,
     pub customer_id: Option<Uuid>,
     pub status: DbOrderStatus,
-    pub delivery_address: String,
-    pub billing_address: String,
+    pub delivery_address: DbAddress,
+    pub billing_address: DbAddress,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
     pub payment_reference: Option<String>,
     pub payment_status: DbPaymentStatus,
+    pub order_number: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
@@ -178,12 +191,13 @@ pub struct DbOrder {
 pub struct InsertableDbOrder {
     pub customer_id: Option<Uuid>,
     pub status: DbOrderStatus,
-    pub delivery_address: String,
-    pub billing_address: String,
+    pub delivery_address: DbAddress,
+    pub billing_address: DbAddress,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
     pub payment_reference: Option<String>,
     pub payment_status: DbPaymentStatus,
+    pub order_number: Option<String>,
 }
 
 impl From<&DbOrder> for InsertableDbOrder {
@@ -197,6 +211,35 @@ impl From<&DbOrder> for InsertableDbOrder {
             updated_at: order.updated_at,
             payment_reference: order.payment_reference.clone(),
             payment_status: order.payment_status,
+            order_number: order.order_number.clone(),
+        }
+    }
+}
+
+/// The columns `update` may change. The order number and the creation time
+/// are set once on insert and never change.
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = orders)]
+struct DbOrderChange {
+    customer_id: Option<Uuid>,
+    status: DbOrderStatus,
+    delivery_address: DbAddress,
+    billing_address: DbAddress,
+    updated_at: Option<NaiveDateTime>,
+    payment_reference: Option<String>,
+    payment_status: DbPaymentStatus,
+}
+
+impl From<DbOrder> for DbOrderChange {
+    fn from(order: DbOrder) -> Self {
+        DbOrderChange {
+            customer_id: order.customer_id,
+            status: order.status,
+            delivery_address: order.delivery_address,
+            billing_address: order.billing_address,
+            updated_at: order.updated_at,
+            payment_reference: order.payment_reference,
+            payment_status: order.payment_status,
         }
     }
 }
@@ -220,8 +263,12 @@ pub struct DbOrderItem {
     pub currency: String,
     pub weight: i32,
     pub created_at: NaiveDateTime,
+    pub fulfilled_quantity: i64,
+    pub variant_options: DbVariantOptions,
 }
 
+/// Insert payload for order items. `fulfilled_quantity` is deliberately absent:
+/// new lines always start unfulfilled and only shipments may advance it.
 #[derive(Debug, Serialize, Deserialize, Insertable)]
 #[diesel(table_name = order_items)]
 pub struct InsertableDbOrderItem {
@@ -240,6 +287,7 @@ pub struct InsertableDbOrderItem {
     pub currency: String,
     pub weight: i32,
     pub created_at: NaiveDateTime,
+    pub variant_options: DbVariantOptions,
 }
 
 impl From<&DbOrderItem> for InsertableDbOrderItem {
@@ -260,6 +308,7 @@ impl From<&DbOrderItem> for InsertableDbOrderItem {
             currency: item.currency.clone(),
             weight: item.weight,
             created_at: item.created_at,
+            variant_options: item.variant_options.clone(),
         }
     }
 }
@@ -303,9 +352,92 @@ impl DbOrderItem {
             .get_results(conn).await?;
         Ok(db_items)
     }
+
+    /// Atomically adds `delta` to the fulfilled quantity of an order item. The
+    /// guard keeps the fulfilled quantity between zero and the ordered quantity.
+    pub async fn apply_fulfilled_delta_conn(conn: &mut AsyncPgConnection, id: i64, delta: i64) -> Result<Self, ShopsterError> {
+        let result = diesel::update(order_items::table)
+            .filter(order_items::id.eq(id))
+            .filter((order_items::fulfilled_quantity + delta).ge(0i64))
+            .filter((order_items::fulfilled_quantity + delta).le(order_items::quantity))
+            .set(order_items::fulfilled_quantity.eq(order_items::fulfilled_quantity + delta))
+            .get_result::<DbOrderItem>(conn).await;
+
+        match result {
+            Ok(updated) => Ok(updated),
+            Err(diesel::result::Error::NotFound) => Err(ShopsterError::InvalidOperationError(
+                "Fulfilled quantity must stay between zero and the ordered quantity".to_string(),
+            )),
+            Err(e) => Err(ShopsterError::DatabaseError(e)),
+        }
+    }
+}
+
+
+
+/// One row of the order audit trail, written whenever an update changes the
+/// fulfillment or payment status of an order.
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = order_status_history)]
+pub struct DbOrderStatusHistory {
+    pub id: i64,
+    pub order_id: i64,
+    pub old_status: DbOrderStatus,
+    pub new_status: DbOrderStatus,
+    pub old_payment_status: DbPaymentStatus,
+    pub new_payment_status: DbPaymentStatus,
+    pub actor: Option<String>,
+    pub comment: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = order_status_history)]
+pub struct InsertableDbOrderStatusHistory {
+    pub order_id: i64,
+    pub old_status: DbOrderStatus,
+    pub new_status: DbOrderStatus,
+    pub old_payment_status: DbPaymentStatus,
+    pub new_payment_status: DbPaymentStatus,
+    pub actor: Option<String>,
+    pub comment: Option<String>,
+    pub created_at: NaiveDateTime,
 }
 
+impl DbOrderStatusHistory {
+    pub async fn get_for_order(tenant_id: Uuid, order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let history = order_status_history::table
+            .filter(order_status_history::order_id.eq(order_id))
+            .order(order_status_history::id.asc())
+            .load(&mut conn).await?;
+        Ok(history)
+    }
+
+    /// Records the transition from `previous` to `current` if either status changed.
+    pub async fn record_conn(conn: &mut AsyncPgConnection, previous: &DbOrder, current: &DbOrder, actor: Option<&str>, comment: Option<&str>) -> Result<(), ShopsterError> {
+        if previous.status == current.status && previous.payment_status == current.payment_status {
+            return Ok(());
+        }
 
+        let entry = InsertableDbOrderStatusHistory {
+            order_id: current.id,
+            old_status: previous.status,
+            new_status: current.status,
+            old_payment_status: previous.payment_status,
+            new_payment_status: current.payment_status,
+            actor: actor.map(String::from),
+            comment: comment.map(String::from),
+            created_at: Utc::now().naive_utc(),
+        };
+        diesel::insert_into(order_status_history::table)
+            .values(entry)
+            .execute(conn).await?;
+        Ok(())
+    }
+}
 
 impl DbOrder {
 
@@ -319,6 +451,32 @@ impl DbOrder {
         Ok(order)
     }
 
+    /// Loads the order row with `FOR UPDATE`, so concurrent status changes on
+    /// the same order are serialized by the surrounding transaction.
+    pub async fn find_for_update_conn(conn: &mut AsyncPgConnection, id: i64) -> Result<Self, ShopsterError> {
+        let order = orders::table
+            .filter(orders::id.eq(id))
+            .for_update()
+            .first(conn).await?;
+        Ok(order)
+    }
+
+    /// Locks a `New` order that is still awaiting payment and was created
+    /// before `created_before`. Returns `None` if the order no longer
+    /// qualifies or another transaction holds its lock (`SKIP LOCKED`).
+    pub async fn lock_if_unpaid_since_conn(conn: &mut AsyncPgConnection, id: i64, created_before: NaiveDateTime) -> Result<Option<Self>, ShopsterError> {
+        let order = orders::table
+            .filter(orders::id.eq(id))
+            .filter(orders::status.eq(DbOrderStatus::New))
+            .filter(orders::payment_status.eq(DbPaymentStatus::Pending))
+            .filter(orders::created_at.lt(created_before))
+            .for_update()
+            .skip_locked()
+            .first(conn).await
+            .optional()?;
+        Ok(order)
+    }
+
     pub async fn get_all(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
@@ -327,6 +485,22 @@ impl DbOrder {
         Ok(orders)
     }
 
+    /// Returns the ids of `New` orders still awaiting payment that were
+    /// created before `created_before`, oldest first.
+    pub async fn get_unpaid_ids_since(tenant_id: Uuid, created_before: NaiveDateTime) -> Result<Vec<i64>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let ids = orders::table
+            .filter(orders::status.eq(DbOrderStatus::New))
+            .filter(orders::payment_status.eq(DbPaymentStatus::Pending))
+            .filter(orders::created_at.lt(created_before))
+            .order((orders::created_at.asc(), orders::id.asc()))
+            .select(orders::id)
+            .load(&mut conn).await?;
+        Ok(ids)
+    }
+
     pub async fn get_by_customer_id(tenant_id: Uuid, customer_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
@@ -348,6 +522,17 @@ impl DbOrder {
         Ok(order)
     }
 
+    pub async fn find_by_order_number(tenant_id: Uuid, order_number: &str) -> Result<Option<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let order = orders::table
+            .filter(orders::order_number.eq(order_number))
+            .first(&mut conn).await
+            .optional()?;
+        Ok(order)
+    }
+
     pub async fn get_without_customer_id(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
@@ -377,33 +562,56 @@ impl DbOrder {
         Ok(db_order)
     }
 
-    pub async fn update(tenant_id: Uuid, id: i64, order: DbOrder) -> Result<Self, ShopsterError> {
+    pub async fn update(tenant_id: Uuid, id: i64, order: DbOrder, actor: Option<&str>, comment: Option<&str>) -> Result<Self, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
+        conn.transaction(async |conn| Self::update_conn(conn, id, order, actor, comment).await).await
+    }
+
+    /// Updates the order and appends to `order_status_history` if the fulfillment
+    /// or payment status changed. Must run inside a transaction.
+    pub async fn update_conn(conn: &mut AsyncPgConnection, id: i64, order: DbOrder, actor: Option<&str>, comment: Option<&str>) -> Result<Self, ShopsterError> {
+        let previous = Self::find_for_update_conn(conn, id).await?;
         let db_order = diesel::update(orders::table)
             .filter(orders::id.eq(id))
-            .set(order)
-            .get_result(&mut conn).await?;
+            .set(DbOrderChange::from(order))
+            .get_result(conn).await?;
+        DbOrderStatusHistory::record_conn(conn, &previous, &db_order, actor, comment).await?;
         Ok(db_order)
     }
 
-    pub async fn update_conn(conn: &mut AsyncPgConnection, id: i64, order: DbOrder) -> Result<Self, ShopsterError> {
+    /// Updates only the fulfillment status and appends to `order_status_history`
+    /// if it changed. Must run inside a transaction.
+    pub async fn update_status_conn(conn: &mut AsyncPgConnection, id: i64, status: DbOrderStatus, actor: Option<&str>, comment: Option<&str>) -> Result<Self, ShopsterError> {
+        let previous = Self::find_for_update_conn(conn, id).await?;
         let db_order = diesel::update(orders::table)
             .filter(orders::id.eq(id))
-            .set(order)
+            .set((
+                orders::status.eq(status),
+                orders::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
             .get_result(conn).await?;
+        DbOrderStatusHistory::record_conn(conn, &previous, &db_order, actor, comment).await?;
         Ok(db_order)
     }
 
-    pub async fn update_payment_status(tenant_id: Uuid, id: i64, payment_status: DbPaymentStatus) -> Result<Self, ShopsterError> {
+    pub async fn update_payment_status(tenant_id: Uuid, id: i64, payment_status: DbPaymentStatus, actor: Option<&str>, comment: Option<&str>) -> Result<Self, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
+        conn.transaction(async |conn| Self::update_payment_status_conn(conn, id, payment_status, actor, comment).await).await
+    }
+
+    /// Updates the payment status and appends to `order_status_history` if it
+    /// changed. Must run inside a transaction.
+    pub async fn update_payment_status_conn(conn: &mut AsyncPgConnection, id: i64, payment_status: DbPaymentStatus, actor: Option<&str>, comment: Option<&str>) -> Result<Self, ShopsterError> {
+        let previous = Self::find_for_update_conn(conn, id).await?;
         let db_order = diesel::update(orders::table)
             .filter(orders::id.eq(id))
             .set(orders::payment_status.eq(payment_status))
-            .get_result(&mut conn).await?;
+            .get_result(conn).await?;
+        DbOrderStatusHistory::record_conn(conn, &previous, &db_order, actor, comment).await?;
         Ok(db_order)
     }
 
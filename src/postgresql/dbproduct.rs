@@ -6,17 +6,25 @@ use diesel::{
     Insertable
 };
 use diesel::prelude::*;
-use diesel_async::RunQueryDsl;
+use diesel::sql_types::{Array, BigInt, Float4, Integer, Text};
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
 use uuid::Uuid;
 use crate::ShopsterError;
 use crate::schema::*;
 use crate::aquire_pool;
+use crate::postgresql::dbsettings::DbSetting;
+use crate::postgresql::dbwarehouse::DbStockPolicy;
 
 
+const LANGUAGE_SETTING: &str = "language";
 
 
-#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+/// A row of `products`. The row does not hold `search_tags`, which the
+/// database keeps up to date for the search, so queries select the columns
+/// with `DbProduct::as_select()`.
+#[derive(Debug, Clone, Serialize, Deserialize, Identifiable, PartialEq, Queryable, QueryableByName, Selectable, Insertable)]
 #[diesel(table_name = products)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct DbProduct {
     pub id: i64,
     pub article_number: String,
@@ -24,9 +32,6 @@ pub struct DbProduct {
     pub title: String,
     pub short_description: String,
     pub description: String,
-    pub tags: String,
-    pub title_image: String,
-    pub additional_images: String,
     pub price: i64,
     pub currency: String,
     pub weight: i32,
@@ -42,9 +47,6 @@ pub struct InsertableDbProduct {
     pub title: String,
     pub short_description: String,
     pub description: String,
-    pub tags: String,
-    pub title_image: String,
-    pub additional_images: String,
     pub price: i64,
     pub currency: String,
     pub weight: i32,
@@ -62,9 +64,6 @@ impl From<&DbProduct> for InsertableDbProduct {
             description: product.description.clone(),
             price: product.price,
             currency: product.currency.clone(),
-            tags: product.tags.clone(),
-            title_image: product.title_image.clone(),
-            additional_images: product.additional_images.clone(),
             weight: product.weight,
             created_at: product.created_at,
             updated_at: product.updated_at
@@ -72,8 +71,155 @@ impl From<&DbProduct> for InsertableDbProduct {
     }
 }
 
+/// The columns `update` may change. The creation time is set once on insert,
+/// since listings sort, filter and page by it.
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = products)]
+struct DbProductChange {
+    article_number: String,
+    gtin: String,
+    title: String,
+    short_description: String,
+    description: String,
+    price: i64,
+    currency: String,
+    weight: i32,
+    updated_at: Option<NaiveDateTime>,
+}
+
+impl From<DbProduct> for DbProductChange {
+    fn from(product: DbProduct) -> Self {
+        DbProductChange {
+            article_number: product.article_number,
+            gtin: product.gtin,
+            title: product.title,
+            short_description: product.short_description,
+            description: product.description,
+            price: product.price,
+            currency: product.currency,
+            weight: product.weight,
+            updated_at: product.updated_at,
+        }
+    }
+}
+
 
 
+/// The order of a product listing. Ties are broken by id, in the same
+/// direction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DbProductSort {
+    PriceAscending,
+    PriceDescending,
+    Title,
+    Newest,
+}
+
+/// The sort keys of the last product of a page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DbProductCursor {
+    pub id: i64,
+    pub price: i64,
+    pub title: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<&DbProduct> for DbProductCursor {
+    fn from(product: &DbProduct) -> Self {
+        DbProductCursor {
+            id: product.id,
+            price: product.price,
+            title: product.title.clone(),
+            created_at: product.created_at,
+        }
+    }
+}
+
+/// Filters, order and page of a product listing.
+#[derive(Debug, Clone)]
+pub struct DbProductQuery {
+    pub tag: Option<String>,
+    pub min_price: Option<i64>,
+    pub max_price: Option<i64>,
+    pub currency: Option<String>,
+    pub in_stock_only: bool,
+    pub created_after: Option<NaiveDateTime>,
+    pub sort: DbProductSort,
+    pub after: Option<DbProductCursor>,
+    pub limit: i64,
+}
+
+/// A product matching a full-text search, with its relevance.
+#[derive(Debug, QueryableByName)]
+pub struct DbProductSearchHit {
+    #[diesel(embed)]
+    pub product: DbProduct,
+    #[diesel(sql_type = Float4)]
+    pub rank: f32,
+}
+
+#[derive(Debug, QueryableByName)]
+struct DbSearchSummary {
+    #[diesel(sql_type = BigInt)]
+    total: i64,
+    #[diesel(sql_type = BigInt)]
+    available: i64,
+}
+
+#[derive(Debug, PartialEq, QueryableByName)]
+pub struct DbTagCount {
+    #[diesel(sql_type = Text)]
+    pub tag: String,
+    #[diesel(sql_type = BigInt)]
+    pub count: i64,
+}
+
+/// The number of matches in a price range. Bucket `0` lies below the first
+/// bound, bucket `i` between bound `i - 1` and bound `i`.
+#[derive(Debug, PartialEq, QueryableByName)]
+pub struct DbPriceBucket {
+    #[diesel(sql_type = Integer)]
+    pub bucket: i32,
+    #[diesel(sql_type = BigInt)]
+    pub count: i64,
+}
+
+/// One page of search hits together with the facets over all matches.
+#[derive(Debug)]
+pub struct DbProductSearch {
+    pub hits: Vec<DbProductSearchHit>,
+    pub total: i64,
+    /// Matches that can be ordered right now.
+    pub available: i64,
+    pub tags: Vec<DbTagCount>,
+    pub price_buckets: Vec<DbPriceBucket>,
+}
+
+/// Returns the text search configuration for the shop's `language` setting.
+/// Only German and English have an index; everything else is searched in
+/// English.
+fn search_config(language: Option<&str>) -> &'static str {
+    match language {
+        Some("de") => "german",
+        _ => "english",
+    }
+}
+
+/// The products matching the search terms in `$1`, excluding variants, with
+/// their rank. `config` must be one of the configurations returned by
+/// `search_config`, since the indexes are built for exactly those.
+fn search_matches(config: &str) -> String {
+    format!(
+        "WITH matches AS ( \
+            SELECT p.*, \
+                   ts_rank_cd(product_search_document('{config}', p.article_number, p.title, p.search_tags, p.short_description, p.description), q) AS rank \
+            FROM products p, websearch_to_tsquery('{config}', $1) q \
+            WHERE product_search_document('{config}', p.article_number, p.title, p.search_tags, p.short_description, p.description) @@ q \
+              AND NOT EXISTS (SELECT 1 FROM product_variants v WHERE v.product_id = p.id) \
+         ) "
+    )
+}
+
 impl DbProduct {
 
     pub async fn find(tenant_id: Uuid, id: i64) -> Result<Self, ShopsterError> {
@@ -82,15 +228,31 @@ impl DbProduct {
 
         let product = products::table
             .filter(products::id.eq(id))
+            .select(DbProduct::as_select())
             .first(&mut conn).await?;
         Ok(product)
     }
 
+    pub async fn find_conn(conn: &mut AsyncPgConnection, id: i64) -> Result<Self, ShopsterError> {
+        let product = products::table
+            .filter(products::id.eq(id))
+            .select(DbProduct::as_select())
+            .first(conn).await?;
+        Ok(product)
+    }
+
+    /// Returns all products except variants, which are listed with their
+    /// parent via `DbProductVariant::get_for_parent`.
     pub async fn get_all(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
-        let products = products::table.load(&mut conn).await?;
+        let products = products::table
+            .filter(diesel::dsl::not(diesel::dsl::exists(
+                product_variants::table.filter(product_variants::product_id.eq(products::id)),
+            )))
+            .select(DbProduct::as_select())
+            .load(&mut conn).await?;
         Ok(products)
     }
 
@@ -101,21 +263,41 @@ impl DbProduct {
         let insertable = InsertableDbProduct::from(&product);
         let db_product = diesel::insert_into(products::table)
             .values(insertable)
+            .returning(DbProduct::as_returning())
             .get_result(&mut conn).await?;
         Ok(db_product)
     }
 
+    pub async fn create_conn(conn: &mut AsyncPgConnection, product: DbProduct) -> Result<Self, ShopsterError> {
+        let insertable = InsertableDbProduct::from(&product);
+        let db_product = diesel::insert_into(products::table)
+            .values(insertable)
+            .returning(DbProduct::as_returning())
+            .get_result(conn).await?;
+        Ok(db_product)
+    }
+
     pub async fn update(tenant_id: Uuid, id: i64, product: DbProduct) -> Result<Self, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
         let db_product = diesel::update(products::table)
             .filter(products::id.eq(id))
-            .set(product)
+            .set(DbProductChange::from(product))
+            .returning(DbProduct::as_returning())
             .get_result(&mut conn).await?;
         Ok(db_product)
     }
 
+    pub async fn update_conn(conn: &mut AsyncPgConnection, id: i64, product: DbProduct) -> Result<Self, ShopsterError> {
+        let db_product = diesel::update(products::table)
+            .filter(products::id.eq(id))
+            .set(DbProductChange::from(product))
+            .returning(DbProduct::as_returning())
+            .get_result(conn).await?;
+        Ok(db_product)
+    }
+
     pub async fn delete(tenant_id: Uuid, id: i64) -> Result<usize, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
@@ -127,4 +309,159 @@ impl DbProduct {
             .execute(&mut conn).await?;
         Ok(res)
     }
+
+    pub async fn delete_conn(conn: &mut AsyncPgConnection, id: i64) -> Result<usize, ShopsterError> {
+        let res = diesel::delete(
+                products::table
+                    .filter(products::id.eq(id))
+            )
+            .execute(conn).await?;
+        Ok(res)
+    }
+
+    /// Runs a full-text search over article number, title, tags and
+    /// descriptions in the language of the shop. Returns the hits at `offset`,
+    /// best first, and counts all matches by tag, price bucket (see
+    /// `DbPriceBucket`) and availability. A match is available if it or one of
+    /// its variants has free stock or may be ordered without stock.
+    pub async fn search(tenant_id: Uuid, query: &str, limit: i64, offset: i64, price_bounds: &[i64]) -> Result<DbProductSearch, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let language = DbSetting::find_by_title_conn(&mut conn, LANGUAGE_SETTING).await?
+            .map(|setting| setting.value);
+        let matches = search_matches(search_config(language.as_deref()));
+
+        let hits = diesel::sql_query(format!(
+            "{matches} SELECT * FROM matches ORDER BY rank DESC, id ASC LIMIT $2 OFFSET $3"
+        ))
+        .bind::<Text, _>(query)
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load(&mut conn).await?;
+
+        let summary: DbSearchSummary = diesel::sql_query(format!(
+            "{matches} \
+             SELECT COUNT(*) AS total, \
+                    COUNT(*) FILTER (WHERE EXISTS ( \
+                        SELECT 1 FROM warehouse w \
+                        WHERE (w.product_id = m.id \
+                               OR w.product_id IN (SELECT v.product_id FROM product_variants v WHERE v.parent_id = m.id)) \
+                          AND (w.in_stock - w.reserved > 0 OR w.stock_policy <> 'Deny') \
+                    )) AS available \
+             FROM matches m"
+        ))
+        .bind::<Text, _>(query)
+        .get_result(&mut conn).await?;
+
+        let tags = diesel::sql_query(format!(
+            "{matches} \
+             SELECT t.title AS tag, COUNT(*) AS count \
+             FROM matches m \
+             JOIN product_tags pt ON pt.product_id = m.id \
+             JOIN tags t ON t.id = pt.tag_id \
+             GROUP BY t.title \
+             ORDER BY count DESC, tag ASC"
+        ))
+        .bind::<Text, _>(query)
+        .load(&mut conn).await?;
+
+        let price_buckets = diesel::sql_query(format!(
+            "{matches} \
+             SELECT width_bucket(m.price, $2) AS bucket, COUNT(*) AS count \
+             FROM matches m \
+             GROUP BY bucket \
+             ORDER BY bucket ASC"
+        ))
+        .bind::<Text, _>(query)
+        .bind::<Array<BigInt>, _>(price_bounds)
+        .load(&mut conn).await?;
+
+        Ok(DbProductSearch {
+            hits,
+            total: summary.total,
+            available: summary.available,
+            tags,
+            price_buckets,
+        })
+    }
+
+    /// Returns up to `limit` products except variants that match the filters,
+    /// in the requested order and starting after the cursor. A product is in
+    /// stock if it or one of its variants has free stock at any location or
+    /// a stock policy other than `Deny`, as in the search's availability facet.
+    pub async fn query(tenant_id: Uuid, query: &DbProductQuery) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let mut db_query = products::table
+            .filter(diesel::dsl::not(diesel::dsl::exists(
+                product_variants::table.filter(product_variants::product_id.eq(products::id)),
+            )))
+            .select(DbProduct::as_select())
+            .into_boxed();
+
+        if let Some(tag) = &query.tag {
+            db_query = db_query.filter(diesel::dsl::exists(
+                product_tags::table
+                    .inner_join(tags::table)
+                    .filter(product_tags::product_id.eq(products::id))
+                    .filter(tags::title.eq(tag.clone())),
+            ));
+        }
+        if let Some(min_price) = query.min_price {
+            db_query = db_query.filter(products::price.ge(min_price));
+        }
+        if let Some(max_price) = query.max_price {
+            db_query = db_query.filter(products::price.le(max_price));
+        }
+        if let Some(currency) = &query.currency {
+            db_query = db_query.filter(products::currency.eq(currency.clone()));
+        }
+        if query.in_stock_only {
+            db_query = db_query.filter(diesel::dsl::exists(
+                warehouse::table
+                    .filter(warehouse::product_id.eq(products::id).or(warehouse::product_id.eq_any(
+                        product_variants::table
+                            .filter(product_variants::parent_id.eq(products::id))
+                            .select(product_variants::product_id),
+                    )))
+                    .filter(warehouse::in_stock.gt(warehouse::reserved).or(warehouse::stock_policy.ne(DbStockPolicy::Deny))),
+            ));
+        }
+        if let Some(created_after) = query.created_after {
+            db_query = db_query.filter(products::created_at.gt(created_after));
+        }
+
+        db_query = match (query.sort, &query.after) {
+            (DbProductSort::PriceAscending, Some(after)) => db_query.filter(
+                products::price.gt(after.price)
+                    .or(products::price.eq(after.price).and(products::id.gt(after.id))),
+            ),
+            (DbProductSort::PriceDescending, Some(after)) => db_query.filter(
+                products::price.lt(after.price)
+                    .or(products::price.eq(after.price).and(products::id.lt(after.id))),
+            ),
+            (DbProductSort::Title, Some(after)) => db_query.filter(
+                products::title.gt(after.title.clone())
+                    .or(products::title.eq(after.title.clone()).and(products::id.gt(after.id))),
+            ),
+            (DbProductSort::Newest, Some(after)) => db_query.filter(
+                products::created_at.lt(after.created_at)
+                    .or(products::created_at.eq(after.created_at).and(products::id.lt(after.id))),
+            ),
+            (_, None) => db_query,
+        };
+        db_query = match query.sort {
+            DbProductSort::PriceAscending => db_query.order((products::price.asc(), products::id.asc())),
+            DbProductSort::PriceDescending => db_query.order((products::price.desc(), products::id.desc())),
+            DbProductSort::Title => db_query.order((products::title.asc(), products::id.asc())),
+            DbProductSort::Newest => db_query.order((products::created_at.desc(), products::id.desc())),
+        };
+
+        let products = db_query
+            .limit(query.limit)
+            .load(&mut conn).await?;
+        Ok(products)
+    }
 }
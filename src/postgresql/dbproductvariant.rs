@@ -0,0 +1,158 @@
+use chrono::NaiveDateTime;
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Jsonb;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use std::io::Write;
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
+
+
+/// A single option of a variant, e.g. `Size` = `M`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct DbVariantOption {
+    pub name: String,
+    pub value: String,
+}
+
+/// The options of a variant stored as a JSONB array, sorted by name so that
+/// equal option sets compare equal in the database.
+#[derive(Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq, Clone, Default)]
+#[diesel(sql_type = Jsonb)]
+pub struct DbVariantOptions(pub Vec<DbVariantOption>);
+
+impl DbVariantOptions {
+    pub fn new(mut options: Vec<DbVariantOption>) -> Self {
+        options.sort();
+        DbVariantOptions(options)
+    }
+}
+
+impl ToSql<Jsonb, Pg> for DbVariantOptions {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        // JSONB binary format: version byte followed by the JSON text
+        out.write_all(&[1])?;
+        serde_json::to_writer(out, &self.0)?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Jsonb, Pg> for DbVariantOptions {
+    fn from_sql(value: PgValue) -> deserialize::Result<Self> {
+        let bytes = value.as_bytes();
+        match bytes.split_first() {
+            Some((1, json)) => Ok(DbVariantOptions(serde_json::from_slice(json)?)),
+            _ => Err("Unsupported JSONB encoding version".into()),
+        }
+    }
+}
+
+
+/// Links the product row of a variant to its parent product. Price and weight
+/// are overrides; `None` means the parent's value is used.
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = product_variants, primary_key(product_id), treat_none_as_null = true)]
+pub struct DbProductVariant {
+    pub product_id: i64,
+    pub parent_id: i64,
+    pub options: DbVariantOptions,
+    pub price: Option<i64>,
+    pub currency: Option<String>,
+    pub weight: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl DbProductVariant {
+    pub async fn find(tenant_id: Uuid, product_id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let variant = product_variants::table
+            .filter(product_variants::product_id.eq(product_id))
+            .first(&mut conn).await?;
+        Ok(variant)
+    }
+
+    /// Returns the variant link of a product, or `None` if it is not a variant.
+    pub async fn find_conn(conn: &mut AsyncPgConnection, product_id: i64) -> Result<Option<Self>, ShopsterError> {
+        let variant = product_variants::table
+            .filter(product_variants::product_id.eq(product_id))
+            .first(conn).await
+            .optional()?;
+        Ok(variant)
+    }
+
+    pub async fn find_by_options(tenant_id: Uuid, parent_id: i64, options: &DbVariantOptions) -> Result<Option<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let variant = product_variants::table
+            .filter(product_variants::parent_id.eq(parent_id))
+            .filter(product_variants::options.eq(options))
+            .first(&mut conn).await
+            .optional()?;
+        Ok(variant)
+    }
+
+    pub async fn get_for_parent(tenant_id: Uuid, parent_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        Self::get_for_parent_conn(&mut conn, parent_id).await
+    }
+
+    pub async fn get_for_parent_conn(conn: &mut AsyncPgConnection, parent_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let variants = product_variants::table
+            .filter(product_variants::parent_id.eq(parent_id))
+            .order(product_variants::product_id.asc())
+            .load(conn).await?;
+        Ok(variants)
+    }
+
+    pub async fn is_variant(tenant_id: Uuid, product_id: i64) -> Result<bool, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let is_variant = diesel::select(diesel::dsl::exists(
+            product_variants::table.filter(product_variants::product_id.eq(product_id)),
+        ))
+        .get_result(&mut conn).await?;
+        Ok(is_variant)
+    }
+
+    pub async fn has_variants_conn(conn: &mut AsyncPgConnection, product_id: i64) -> Result<bool, ShopsterError> {
+        let has_variants = diesel::select(diesel::dsl::exists(
+            product_variants::table.filter(product_variants::parent_id.eq(product_id)),
+        ))
+        .get_result(conn).await?;
+        Ok(has_variants)
+    }
+
+    pub async fn create_conn(conn: &mut AsyncPgConnection, variant: DbProductVariant) -> Result<Self, ShopsterError> {
+        let db_variant = diesel::insert_into(product_variants::table)
+            .values(variant)
+            .get_result(conn).await?;
+        Ok(db_variant)
+    }
+
+    pub async fn update_conn(conn: &mut AsyncPgConnection, product_id: i64, variant: DbProductVariant) -> Result<Self, ShopsterError> {
+        let db_variant = diesel::update(product_variants::table)
+            .filter(product_variants::product_id.eq(product_id))
+            .set(variant)
+            .get_result(conn).await?;
+        Ok(db_variant)
+    }
+}
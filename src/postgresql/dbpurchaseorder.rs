@@ -0,0 +1,296 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use std::fmt;
+use std::io::Write;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
+
+#[derive(Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq, PartialOrd, Copy, Clone)]
+#[diesel(sql_type = crate::schema::sql_types::DbPurchaseOrderStatus)]
+pub enum DbPurchaseOrderStatus {
+    Draft,
+    Ordered,
+    PartiallyReceived,
+    Received
+}
+
+impl fmt::Display for DbPurchaseOrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl ToSql<crate::schema::sql_types::DbPurchaseOrderStatus, Pg> for DbPurchaseOrderStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            DbPurchaseOrderStatus::Draft => out.write_all(b"Draft")?,
+            DbPurchaseOrderStatus::Ordered => out.write_all(b"Ordered")?,
+            DbPurchaseOrderStatus::PartiallyReceived => out.write_all(b"PartiallyReceived")?,
+            DbPurchaseOrderStatus::Received => out.write_all(b"Received")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::DbPurchaseOrderStatus, Pg> for DbPurchaseOrderStatus {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"Draft" => Ok(DbPurchaseOrderStatus::Draft),
+            b"Ordered" => Ok(DbPurchaseOrderStatus::Ordered),
+            b"PartiallyReceived" => Ok(DbPurchaseOrderStatus::PartiallyReceived),
+            b"Received" => Ok(DbPurchaseOrderStatus::Received),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl From<&DbPurchaseOrderStatus> for i32 {
+    fn from(status: &DbPurchaseOrderStatus) -> Self {
+        match status {
+            DbPurchaseOrderStatus::Draft => 0,
+            DbPurchaseOrderStatus::Ordered => 1,
+            DbPurchaseOrderStatus::PartiallyReceived => 2,
+            DbPurchaseOrderStatus::Received => 3,
+        }
+    }
+}
+
+impl TryFrom<i32> for DbPurchaseOrderStatus {
+    type Error = String;
+
+    fn try_from(status: i32) -> Result<Self, Self::Error> {
+        match status {
+            0 => Ok(DbPurchaseOrderStatus::Draft),
+            1 => Ok(DbPurchaseOrderStatus::Ordered),
+            2 => Ok(DbPurchaseOrderStatus::PartiallyReceived),
+            3 => Ok(DbPurchaseOrderStatus::Received),
+            _ => Err(format!("Unknown purchase order status: {}", status))
+        }
+    }
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = purchase_orders)]
+pub struct DbPurchaseOrder {
+    pub id: i64,
+    pub supplier_id: i64,
+    pub location_id: Option<i64>,
+    pub status: DbPurchaseOrderStatus,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    pub ordered_at: Option<NaiveDateTime>,
+    pub received_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = purchase_orders)]
+pub struct InsertableDbPurchaseOrder {
+    pub supplier_id: i64,
+    pub location_id: Option<i64>,
+    pub status: DbPurchaseOrderStatus,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    pub ordered_at: Option<NaiveDateTime>,
+    pub received_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbPurchaseOrder> for InsertableDbPurchaseOrder {
+    fn from(purchase_order: &DbPurchaseOrder) -> Self {
+        InsertableDbPurchaseOrder {
+            supplier_id: purchase_order.supplier_id,
+            location_id: purchase_order.location_id,
+            status: purchase_order.status,
+            created_at: purchase_order.created_at,
+            updated_at: purchase_order.updated_at,
+            ordered_at: purchase_order.ordered_at,
+            received_at: purchase_order.received_at,
+        }
+    }
+}
+
+/// Timestamps left at `None` are not changed.
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = purchase_orders)]
+struct DbPurchaseOrderStatusChange {
+    status: DbPurchaseOrderStatus,
+    updated_at: Option<NaiveDateTime>,
+    ordered_at: Option<NaiveDateTime>,
+    received_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = purchase_order_items)]
+pub struct DbPurchaseOrderItem {
+    pub id: i64,
+    pub purchase_order_id: i64,
+    pub product_id: i64,
+    pub quantity: i64,
+    pub received_quantity: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = purchase_order_items)]
+pub struct InsertableDbPurchaseOrderItem {
+    pub purchase_order_id: i64,
+    pub product_id: i64,
+    pub quantity: i64,
+    pub received_quantity: i64,
+}
+
+impl From<&DbPurchaseOrderItem> for InsertableDbPurchaseOrderItem {
+    fn from(item: &DbPurchaseOrderItem) -> Self {
+        InsertableDbPurchaseOrderItem {
+            purchase_order_id: item.purchase_order_id,
+            product_id: item.product_id,
+            quantity: item.quantity,
+            received_quantity: item.received_quantity,
+        }
+    }
+}
+
+
+impl DbPurchaseOrderItem {
+    pub async fn get_for_purchase_order(tenant_id: Uuid, purchase_order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::get_for_purchase_order_conn(&mut conn, purchase_order_id).await
+    }
+
+    pub async fn get_for_purchase_order_conn(conn: &mut AsyncPgConnection, purchase_order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let items = purchase_order_items::table
+            .filter(purchase_order_items::purchase_order_id.eq(purchase_order_id))
+            .order(purchase_order_items::id.asc())
+            .get_results(conn).await?;
+        Ok(items)
+    }
+
+    pub async fn create_for_purchase_order_conn(conn: &mut AsyncPgConnection, items: Vec<DbPurchaseOrderItem>) -> Result<Vec<Self>, ShopsterError> {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let insertables: Vec<InsertableDbPurchaseOrderItem> = items.iter().map(InsertableDbPurchaseOrderItem::from).collect();
+        let db_items = diesel::insert_into(purchase_order_items::table)
+            .values(insertables)
+            .get_results(conn).await?;
+        Ok(db_items)
+    }
+
+    pub async fn delete_for_purchase_order_conn(conn: &mut AsyncPgConnection, purchase_order_id: i64) -> Result<usize, ShopsterError> {
+        let res = diesel::delete(
+            purchase_order_items::table
+                .filter(purchase_order_items::purchase_order_id.eq(purchase_order_id)),
+        )
+        .execute(conn).await?;
+        Ok(res)
+    }
+
+    pub async fn apply_received_delta_conn(conn: &mut AsyncPgConnection, id: i64, delta: i64) -> Result<Self, ShopsterError> {
+        let item = diesel::update(purchase_order_items::table)
+            .filter(purchase_order_items::id.eq(id))
+            .set(purchase_order_items::received_quantity.eq(purchase_order_items::received_quantity + delta))
+            .get_result(conn).await?;
+        Ok(item)
+    }
+}
+
+
+impl DbPurchaseOrder {
+    pub async fn find(tenant_id: Uuid, id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let purchase_order = purchase_orders::table
+            .filter(purchase_orders::id.eq(id))
+            .first(&mut conn).await?;
+        Ok(purchase_order)
+    }
+
+    pub async fn find_for_update_conn(conn: &mut AsyncPgConnection, id: i64) -> Result<Self, ShopsterError> {
+        let purchase_order = purchase_orders::table
+            .filter(purchase_orders::id.eq(id))
+            .for_update()
+            .first(conn).await?;
+        Ok(purchase_order)
+    }
+
+    pub async fn get_all(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let purchase_orders = purchase_orders::table
+            .order(purchase_orders::id.asc())
+            .load(&mut conn).await?;
+        Ok(purchase_orders)
+    }
+
+    pub async fn get_for_supplier(tenant_id: Uuid, supplier_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let purchase_orders = purchase_orders::table
+            .filter(purchase_orders::supplier_id.eq(supplier_id))
+            .order(purchase_orders::id.asc())
+            .load(&mut conn).await?;
+        Ok(purchase_orders)
+    }
+
+    pub async fn create_conn(conn: &mut AsyncPgConnection, purchase_order: DbPurchaseOrder) -> Result<Self, ShopsterError> {
+        let insertable = InsertableDbPurchaseOrder::from(&purchase_order);
+        let created = diesel::insert_into(purchase_orders::table)
+            .values(insertable)
+            .get_result(conn).await?;
+        Ok(created)
+    }
+
+    pub async fn update_conn(conn: &mut AsyncPgConnection, id: i64, supplier_id: i64, location_id: Option<i64>) -> Result<Self, ShopsterError> {
+        let purchase_order = diesel::update(purchase_orders::table)
+            .filter(purchase_orders::id.eq(id))
+            .set((
+                purchase_orders::supplier_id.eq(supplier_id),
+                purchase_orders::location_id.eq(location_id),
+                purchase_orders::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(conn).await?;
+        Ok(purchase_order)
+    }
+
+    /// Sets the status and stamps `ordered_at` or `received_at` when the
+    /// purchase order is placed or completely received.
+    pub async fn update_status_conn(conn: &mut AsyncPgConnection, id: i64, status: DbPurchaseOrderStatus) -> Result<Self, ShopsterError> {
+        let now = Utc::now().naive_utc();
+        let change = DbPurchaseOrderStatusChange {
+            status,
+            updated_at: Some(now),
+            ordered_at: (status == DbPurchaseOrderStatus::Ordered).then_some(now),
+            received_at: (status == DbPurchaseOrderStatus::Received).then_some(now),
+        };
+        let purchase_order = diesel::update(purchase_orders::table)
+            .filter(purchase_orders::id.eq(id))
+            .set(change)
+            .get_result(conn).await?;
+        Ok(purchase_order)
+    }
+
+    pub async fn delete_conn(conn: &mut AsyncPgConnection, id: i64) -> Result<usize, ShopsterError> {
+        let res = diesel::delete(purchase_orders::table.filter(purchase_orders::id.eq(id)))
+            .execute(conn).await?;
+        Ok(res)
+    }
+}
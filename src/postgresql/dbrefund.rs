@@ -0,0 +1,149 @@
+use chrono::NaiveDateTime;
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
+
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = refunds)]
+pub struct DbRefund {
+    pub id: i64,
+    pub order_id: i64,
+    pub amount: i64,
+    pub currency: String,
+    pub reason: String,
+    pub payment_reference: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = refunds)]
+pub struct InsertableDbRefund {
+    pub order_id: i64,
+    pub amount: i64,
+    pub currency: String,
+    pub reason: String,
+    pub payment_reference: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<&DbRefund> for InsertableDbRefund {
+    fn from(refund: &DbRefund) -> Self {
+        InsertableDbRefund {
+            order_id: refund.order_id,
+            amount: refund.amount,
+            currency: refund.currency.clone(),
+            reason: refund.reason.clone(),
+            payment_reference: refund.payment_reference.clone(),
+            created_at: refund.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = refund_items)]
+pub struct DbRefundItem {
+    pub id: i64,
+    pub refund_id: i64,
+    pub order_item_id: i64,
+    pub quantity: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = refund_items)]
+pub struct InsertableDbRefundItem {
+    pub refund_id: i64,
+    pub order_item_id: i64,
+    pub quantity: i64,
+}
+
+impl From<&DbRefundItem> for InsertableDbRefundItem {
+    fn from(item: &DbRefundItem) -> Self {
+        InsertableDbRefundItem {
+            refund_id: item.refund_id,
+            order_item_id: item.order_item_id,
+            quantity: item.quantity,
+        }
+    }
+}
+
+
+impl DbRefundItem {
+    pub async fn get_for_refund(tenant_id: Uuid, refund_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::get_for_refund_conn(&mut conn, refund_id).await
+    }
+
+    pub async fn get_for_refund_conn(conn: &mut AsyncPgConnection, refund_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let items = refund_items::table
+            .filter(refund_items::refund_id.eq(refund_id))
+            .order(refund_items::id.asc())
+            .get_results(conn).await?;
+        Ok(items)
+    }
+
+    /// Returns the items of every refund booked against the given order.
+    pub async fn get_for_order_conn(conn: &mut AsyncPgConnection, order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let items = refund_items::table
+            .inner_join(refunds::table)
+            .filter(refunds::order_id.eq(order_id))
+            .select(refund_items::all_columns)
+            .get_results(conn).await?;
+        Ok(items)
+    }
+
+    pub async fn create_for_refund_conn(conn: &mut AsyncPgConnection, items: Vec<DbRefundItem>) -> Result<Vec<Self>, ShopsterError> {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let insertables: Vec<InsertableDbRefundItem> = items.iter().map(InsertableDbRefundItem::from).collect();
+        let db_items = diesel::insert_into(refund_items::table)
+            .values(insertables)
+            .get_results(conn).await?;
+        Ok(db_items)
+    }
+}
+
+
+impl DbRefund {
+    pub async fn find_conn(conn: &mut AsyncPgConnection, id: i64) -> Result<Self, ShopsterError> {
+        let db_refund = refunds::table
+            .filter(refunds::id.eq(id))
+            .first(conn).await?;
+        Ok(db_refund)
+    }
+
+    pub async fn get_for_order(tenant_id: Uuid, order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::get_for_order_conn(&mut conn, order_id).await
+    }
+
+    pub async fn get_for_order_conn(conn: &mut AsyncPgConnection, order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let db_refunds = refunds::table
+            .filter(refunds::order_id.eq(order_id))
+            .order(refunds::id.asc())
+            .load(conn).await?;
+        Ok(db_refunds)
+    }
+
+    pub async fn create_conn(conn: &mut AsyncPgConnection, refund: DbRefund) -> Result<Self, ShopsterError> {
+        let insertable = InsertableDbRefund::from(&refund);
+        let db_refund = diesel::insert_into(refunds::table)
+            .values(insertable)
+            .get_result(conn).await?;
+        Ok(db_refund)
+    }
+}
@@ -0,0 +1,208 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use log::info;
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
+
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = reorder_points)]
+pub struct DbReorderPoint {
+    pub id: i64,
+    pub product_id: i64,
+    pub reorder_point: i64,
+    pub reorder_quantity: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = reorder_points)]
+pub struct InsertableDbReorderPoint {
+    pub product_id: i64,
+    pub reorder_point: i64,
+    pub reorder_quantity: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+/// A product whose available stock is at or below its reorder point.
+#[derive(Debug, PartialEq, QueryableByName)]
+pub struct DbLowStock {
+    #[diesel(sql_type = BigInt)]
+    pub product_id: i64,
+    #[diesel(sql_type = BigInt)]
+    pub available: i64,
+    #[diesel(sql_type = BigInt)]
+    pub reorder_point: i64,
+    #[diesel(sql_type = BigInt)]
+    pub reorder_quantity: i64,
+}
+
+impl DbReorderPoint {
+    pub async fn find_by_product_id(tenant_id: Uuid, product_id: i64) -> Result<Option<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let reorder_point = reorder_points::table
+            .filter(reorder_points::product_id.eq(product_id))
+            .first(&mut conn).await
+            .optional()?;
+        Ok(reorder_point)
+    }
+
+    /// Sets the reorder point of a product, replacing an existing one.
+    pub async fn upsert(tenant_id: Uuid, product_id: i64, reorder_point: i64, reorder_quantity: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let now = Utc::now().naive_utc();
+        let insertable = InsertableDbReorderPoint {
+            product_id,
+            reorder_point,
+            reorder_quantity,
+            created_at: now,
+            updated_at: Some(now),
+        };
+        let db_reorder_point = diesel::insert_into(reorder_points::table)
+            .values(insertable)
+            .on_conflict(reorder_points::product_id)
+            .do_update()
+            .set((
+                reorder_points::reorder_point.eq(reorder_point),
+                reorder_points::reorder_quantity.eq(reorder_quantity),
+                reorder_points::updated_at.eq(Some(now)),
+            ))
+            .get_result(&mut conn).await?;
+        Ok(db_reorder_point)
+    }
+
+    pub async fn delete_by_product_id(tenant_id: Uuid, product_id: i64) -> Result<usize, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let res = diesel::delete(
+            reorder_points::table
+                .filter(reorder_points::product_id.eq(product_id)),
+        )
+        .execute(&mut conn).await?;
+        Ok(res)
+    }
+
+    /// Returns all products whose free stock (`in_stock - reserved`, summed over
+    /// all locations) is at or below their reorder point, lowest stock first.
+    /// Products without any warehouse row count as having no stock.
+    pub async fn get_low_stock(tenant_id: Uuid) -> Result<Vec<DbLowStock>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let low_stock = diesel::sql_query(
+            "SELECT r.product_id, \
+                    COALESCE(SUM(w.in_stock - w.reserved), 0)::BIGINT AS available, \
+                    r.reorder_point, \
+                    r.reorder_quantity \
+             FROM reorder_points r \
+             LEFT JOIN warehouse w ON w.product_id = r.product_id \
+             GROUP BY r.product_id, r.reorder_point, r.reorder_quantity \
+             HAVING COALESCE(SUM(w.in_stock - w.reserved), 0) <= r.reorder_point \
+             ORDER BY available ASC, r.product_id ASC",
+        )
+        .load(&mut conn).await?;
+        Ok(low_stock)
+    }
+
+    /// Appends a stock alert if a change moved the free stock of a product from
+    /// above its reorder point to or below it. Must run in the transaction that
+    /// made the change.
+    pub async fn alert_if_crossed_conn(conn: &mut AsyncPgConnection, product_id: i64, available_before: i64, available_after: i64) -> Result<Option<DbStockAlert>, ShopsterError> {
+        if available_after >= available_before {
+            return Ok(None);
+        }
+
+        let reorder_point: Option<Self> = reorder_points::table
+            .filter(reorder_points::product_id.eq(product_id))
+            .first(conn).await
+            .optional()?;
+        let reorder_point = match reorder_point {
+            Some(reorder_point) if available_before > reorder_point.reorder_point && available_after <= reorder_point.reorder_point => reorder_point,
+            _ => return Ok(None),
+        };
+
+        info!(
+            "Product {} dropped to {} available units (reorder point {})",
+            product_id, available_after, reorder_point.reorder_point
+        );
+        let insertable = InsertableDbStockAlert {
+            product_id,
+            available: available_after,
+            reorder_point: reorder_point.reorder_point,
+            reorder_quantity: reorder_point.reorder_quantity,
+            created_at: Utc::now().naive_utc(),
+            acknowledged_at: None,
+        };
+        let alert = diesel::insert_into(stock_alerts::table)
+            .values(insertable)
+            .get_result(conn).await?;
+        Ok(Some(alert))
+    }
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = stock_alerts)]
+pub struct DbStockAlert {
+    pub id: i64,
+    pub product_id: i64,
+    pub available: i64,
+    pub reorder_point: i64,
+    pub reorder_quantity: i64,
+    pub created_at: NaiveDateTime,
+    pub acknowledged_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = stock_alerts)]
+pub struct InsertableDbStockAlert {
+    pub product_id: i64,
+    pub available: i64,
+    pub reorder_point: i64,
+    pub reorder_quantity: i64,
+    pub created_at: NaiveDateTime,
+    pub acknowledged_at: Option<NaiveDateTime>,
+}
+
+impl DbStockAlert {
+    /// Returns the alerts that have not been acknowledged yet, oldest first.
+    pub async fn get_open(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let alerts = stock_alerts::table
+            .filter(stock_alerts::acknowledged_at.is_null())
+            .order((stock_alerts::created_at.asc(), stock_alerts::id.asc()))
+            .load(&mut conn).await?;
+        Ok(alerts)
+    }
+
+    pub async fn acknowledge(tenant_id: Uuid, alert_id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let alert = diesel::update(stock_alerts::table)
+            .filter(stock_alerts::id.eq(alert_id))
+            .set(stock_alerts::acknowledged_at.eq(Some(Utc::now().naive_utc())))
+            .get_result(&mut conn).await?;
+        Ok(alert)
+    }
+}
@@ -0,0 +1,252 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use std::fmt;
+use std::io::Write;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
+
+#[derive(Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq, PartialOrd, Copy, Clone)]
+#[diesel(sql_type = crate::schema::sql_types::DbReturnStatus)]
+pub enum DbReturnStatus {
+    Requested,
+    Approved,
+    Received,
+    Refunded,
+    Rejected
+}
+
+impl fmt::Display for DbReturnStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl ToSql<crate::schema::sql_types::DbReturnStatus, Pg> for DbReturnStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            DbReturnStatus::Requested => out.write_all(b"Requested")?,
+            DbReturnStatus::Approved => out.write_all(b"Approved")?,
+            DbReturnStatus::Received => out.write_all(b"Received")?,
+            DbReturnStatus::Refunded => out.write_all(b"Refunded")?,
+            DbReturnStatus::Rejected => out.write_all(b"Rejected")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::DbReturnStatus, Pg> for DbReturnStatus {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"Requested" => Ok(DbReturnStatus::Requested),
+            b"Approved" => Ok(DbReturnStatus::Approved),
+            b"Received" => Ok(DbReturnStatus::Received),
+            b"Refunded" => Ok(DbReturnStatus::Refunded),
+            b"Rejected" => Ok(DbReturnStatus::Rejected),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl From<&DbReturnStatus> for i32 {
+    fn from(status: &DbReturnStatus) -> Self {
+        match status {
+            DbReturnStatus::Requested => 0,
+            DbReturnStatus::Approved => 1,
+            DbReturnStatus::Received => 2,
+            DbReturnStatus::Refunded => 3,
+            DbReturnStatus::Rejected => 4,
+        }
+    }
+}
+
+impl TryFrom<i32> for DbReturnStatus {
+    type Error = String;
+
+    fn try_from(status: i32) -> Result<Self, Self::Error> {
+        match status {
+            0 => Ok(DbReturnStatus::Requested),
+            1 => Ok(DbReturnStatus::Approved),
+            2 => Ok(DbReturnStatus::Received),
+            3 => Ok(DbReturnStatus::Refunded),
+            4 => Ok(DbReturnStatus::Rejected),
+            _ => Err(format!("Unknown return status: {}", status))
+        }
+    }
+}
+
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = returns)]
+pub struct DbReturn {
+    pub id: i64,
+    pub order_id: i64,
+    pub status: DbReturnStatus,
+    pub reason: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = returns)]
+pub struct InsertableDbReturn {
+    pub order_id: i64,
+    pub status: DbReturnStatus,
+    pub reason: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbReturn> for InsertableDbReturn {
+    fn from(db_return: &DbReturn) -> Self {
+        InsertableDbReturn {
+            order_id: db_return.order_id,
+            status: db_return.status,
+            reason: db_return.reason.clone(),
+            created_at: db_return.created_at,
+            updated_at: db_return.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = return_items)]
+pub struct DbReturnItem {
+    pub id: i64,
+    pub return_id: i64,
+    pub order_item_id: i64,
+    pub quantity: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = return_items)]
+pub struct InsertableDbReturnItem {
+    pub return_id: i64,
+    pub order_item_id: i64,
+    pub quantity: i64,
+}
+
+impl From<&DbReturnItem> for InsertableDbReturnItem {
+    fn from(item: &DbReturnItem) -> Self {
+        InsertableDbReturnItem {
+            return_id: item.return_id,
+            order_item_id: item.order_item_id,
+            quantity: item.quantity,
+        }
+    }
+}
+
+
+impl DbReturnItem {
+    pub async fn get_for_return(tenant_id: Uuid, return_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::get_for_return_conn(&mut conn, return_id).await
+    }
+
+    pub async fn get_for_return_conn(conn: &mut AsyncPgConnection, return_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let items = return_items::table
+            .filter(return_items::return_id.eq(return_id))
+            .order(return_items::id.asc())
+            .get_results(conn).await?;
+        Ok(items)
+    }
+
+    /// Returns the items of every non-rejected return opened against the given order.
+    pub async fn get_open_for_order_conn(conn: &mut AsyncPgConnection, order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let items = return_items::table
+            .inner_join(returns::table)
+            .filter(returns::order_id.eq(order_id))
+            .filter(returns::status.ne(DbReturnStatus::Rejected))
+            .select(return_items::all_columns)
+            .get_results(conn).await?;
+        Ok(items)
+    }
+
+    pub async fn create_for_return_conn(conn: &mut AsyncPgConnection, items: Vec<DbReturnItem>) -> Result<Vec<Self>, ShopsterError> {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let insertables: Vec<InsertableDbReturnItem> = items.iter().map(InsertableDbReturnItem::from).collect();
+        let db_items = diesel::insert_into(return_items::table)
+            .values(insertables)
+            .get_results(conn).await?;
+        Ok(db_items)
+    }
+}
+
+
+impl DbReturn {
+    pub async fn find(tenant_id: Uuid, id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_return = returns::table
+            .filter(returns::id.eq(id))
+            .first(&mut conn).await?;
+        Ok(db_return)
+    }
+
+    pub async fn find_for_update_conn(conn: &mut AsyncPgConnection, id: i64) -> Result<Self, ShopsterError> {
+        let db_return = returns::table
+            .filter(returns::id.eq(id))
+            .for_update()
+            .first(conn).await?;
+        Ok(db_return)
+    }
+
+    pub async fn get_all(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_returns = returns::table
+            .order(returns::id.asc())
+            .load(&mut conn).await?;
+        Ok(db_returns)
+    }
+
+    pub async fn get_for_order(tenant_id: Uuid, order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_returns = returns::table
+            .filter(returns::order_id.eq(order_id))
+            .order(returns::id.asc())
+            .load(&mut conn).await?;
+        Ok(db_returns)
+    }
+
+    pub async fn create_conn(conn: &mut AsyncPgConnection, db_return: DbReturn) -> Result<Self, ShopsterError> {
+        let insertable = InsertableDbReturn::from(&db_return);
+        let created = diesel::insert_into(returns::table)
+            .values(insertable)
+            .get_result(conn).await?;
+        Ok(created)
+    }
+
+    pub async fn update_status_conn(conn: &mut AsyncPgConnection, id: i64, status: DbReturnStatus) -> Result<Self, ShopsterError> {
+        let db_return = diesel::update(returns::table)
+            .filter(returns::id.eq(id))
+            .set((
+                returns::status.eq(status),
+                returns::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(conn).await?;
+        Ok(db_return)
+    }
+}
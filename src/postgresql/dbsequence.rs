@@ -0,0 +1,45 @@
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+
+use crate::ShopsterError;
+use crate::schema::*;
+
+
+/// A named counter used to hand out gap-free document numbers (order numbers,
+/// invoice numbers, ...). `period` partitions a sequence, e.g. by year, so that
+/// numbering can restart; it is empty for sequences that never reset.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[diesel(table_name = number_sequences)]
+pub struct DbNumberSequence {
+    pub name: String,
+    pub period: String,
+    pub value: i64,
+}
+
+impl DbNumberSequence {
+    /// Increments the sequence and returns the new value, starting at 1.
+    ///
+    /// The row stays locked until the surrounding transaction ends, so
+    /// concurrent callers are serialized and a rollback releases the number
+    /// again instead of leaving a gap.
+    pub async fn next_value_conn(conn: &mut AsyncPgConnection, name: &str, period: &str) -> Result<i64, ShopsterError> {
+        let value = diesel::insert_into(number_sequences::table)
+            .values(DbNumberSequence {
+                name: name.to_string(),
+                period: period.to_string(),
+                value: 1,
+            })
+            .on_conflict((number_sequences::name, number_sequences::period))
+            .do_update()
+            .set(number_sequences::value.eq(number_sequences::value + 1))
+            .returning(number_sequences::value)
+            .get_result(conn).await?;
+        Ok(value)
+    }
+}
@@ -10,7 +10,7 @@ use diesel::{
     Queryable,
     Insertable, Identifiable, AsChangeset
 };
-use diesel_async::RunQueryDsl;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
 
 #[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = settings)]
@@ -40,6 +40,14 @@ impl DbSetting {
         Ok(setting)
     }
 
+    pub async fn find_by_title_conn(conn: &mut AsyncPgConnection, title: &str) -> Result<Option<Self>, ShopsterError> {
+        let setting = settings::table
+            .filter(settings::title.eq(title))
+            .first(conn).await
+            .optional()?;
+        Ok(setting)
+    }
+
     pub async fn get_all(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
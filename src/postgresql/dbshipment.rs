@@ -0,0 +1,178 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
+
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = shipments)]
+pub struct DbShipment {
+    pub id: i64,
+    pub order_id: i64,
+    pub carrier: String,
+    pub tracking_code: String,
+    pub shipped_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = shipments)]
+pub struct InsertableDbShipment {
+    pub order_id: i64,
+    pub carrier: String,
+    pub tracking_code: String,
+    pub shipped_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbShipment> for InsertableDbShipment {
+    fn from(shipment: &DbShipment) -> Self {
+        InsertableDbShipment {
+            order_id: shipment.order_id,
+            carrier: shipment.carrier.clone(),
+            tracking_code: shipment.tracking_code.clone(),
+            shipped_at: shipment.shipped_at,
+            delivered_at: shipment.delivered_at,
+            created_at: shipment.created_at,
+            updated_at: shipment.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = shipment_items)]
+pub struct DbShipmentItem {
+    pub id: i64,
+    pub shipment_id: i64,
+    pub order_item_id: i64,
+    pub quantity: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = shipment_items)]
+pub struct InsertableDbShipmentItem {
+    pub shipment_id: i64,
+    pub order_item_id: i64,
+    pub quantity: i64,
+}
+
+impl From<&DbShipmentItem> for InsertableDbShipmentItem {
+    fn from(item: &DbShipmentItem) -> Self {
+        InsertableDbShipmentItem {
+            shipment_id: item.shipment_id,
+            order_item_id: item.order_item_id,
+            quantity: item.quantity,
+        }
+    }
+}
+
+
+impl DbShipmentItem {
+    pub async fn get_for_shipment(tenant_id: Uuid, shipment_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::get_for_shipment_conn(&mut conn, shipment_id).await
+    }
+
+    pub async fn get_for_shipment_conn(conn: &mut AsyncPgConnection, shipment_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let items = shipment_items::table
+            .filter(shipment_items::shipment_id.eq(shipment_id))
+            .order(shipment_items::id.asc())
+            .get_results(conn).await?;
+        Ok(items)
+    }
+
+    pub async fn create_for_shipment_conn(conn: &mut AsyncPgConnection, items: Vec<DbShipmentItem>) -> Result<Vec<Self>, ShopsterError> {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let insertables: Vec<InsertableDbShipmentItem> = items.iter().map(InsertableDbShipmentItem::from).collect();
+        let db_items = diesel::insert_into(shipment_items::table)
+            .values(insertables)
+            .get_results(conn).await?;
+        Ok(db_items)
+    }
+}
+
+
+impl DbShipment {
+    pub async fn find(tenant_id: Uuid, id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let shipment = shipments::table
+            .filter(shipments::id.eq(id))
+            .first(&mut conn).await?;
+        Ok(shipment)
+    }
+
+    pub async fn find_conn(conn: &mut AsyncPgConnection, id: i64) -> Result<Self, ShopsterError> {
+        let shipment = shipments::table
+            .filter(shipments::id.eq(id))
+            .first(conn).await?;
+        Ok(shipment)
+    }
+
+    pub async fn get_for_order(tenant_id: Uuid, order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+        Self::get_for_order_conn(&mut conn, order_id).await
+    }
+
+    pub async fn get_for_order_conn(conn: &mut AsyncPgConnection, order_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let shipments = shipments::table
+            .filter(shipments::order_id.eq(order_id))
+            .order(shipments::id.asc())
+            .load(conn).await?;
+        Ok(shipments)
+    }
+
+    pub async fn create_conn(conn: &mut AsyncPgConnection, shipment: DbShipment) -> Result<Self, ShopsterError> {
+        let insertable = InsertableDbShipment::from(&shipment);
+        let db_shipment = diesel::insert_into(shipments::table)
+            .values(insertable)
+            .get_result(conn).await?;
+        Ok(db_shipment)
+    }
+
+    pub async fn update_tracking(tenant_id: Uuid, id: i64, carrier: &str, tracking_code: &str) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_shipment = diesel::update(shipments::table)
+            .filter(shipments::id.eq(id))
+            .set((
+                shipments::carrier.eq(carrier),
+                shipments::tracking_code.eq(tracking_code),
+                shipments::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(&mut conn).await?;
+        Ok(db_shipment)
+    }
+
+    pub async fn mark_delivered_conn(conn: &mut AsyncPgConnection, id: i64, delivered_at: NaiveDateTime) -> Result<Self, ShopsterError> {
+        let db_shipment = diesel::update(shipments::table)
+            .filter(shipments::id.eq(id))
+            .set((
+                shipments::delivered_at.eq(Some(delivered_at)),
+                shipments::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(conn).await?;
+        Ok(db_shipment)
+    }
+}
@@ -0,0 +1,204 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use std::fmt;
+use std::io::Write;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
+
+#[derive(Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq, PartialOrd, Copy, Clone)]
+#[diesel(sql_type = crate::schema::sql_types::DbStockMovementKind)]
+pub enum DbStockMovementKind {
+    GoodsReceipt,
+    Sale,
+    Return,
+    Correction,
+    Reservation,
+    Release,
+    Transfer
+}
+
+impl fmt::Display for DbStockMovementKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl ToSql<crate::schema::sql_types::DbStockMovementKind, Pg> for DbStockMovementKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            DbStockMovementKind::GoodsReceipt => out.write_all(b"GoodsReceipt")?,
+            DbStockMovementKind::Sale => out.write_all(b"Sale")?,
+            DbStockMovementKind::Return => out.write_all(b"Return")?,
+            DbStockMovementKind::Correction => out.write_all(b"Correction")?,
+            DbStockMovementKind::Reservation => out.write_all(b"Reservation")?,
+            DbStockMovementKind::Release => out.write_all(b"Release")?,
+            DbStockMovementKind::Transfer => out.write_all(b"Transfer")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::DbStockMovementKind, Pg> for DbStockMovementKind {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"GoodsReceipt" => Ok(DbStockMovementKind::GoodsReceipt),
+            b"Sale" => Ok(DbStockMovementKind::Sale),
+            b"Return" => Ok(DbStockMovementKind::Return),
+            b"Correction" => Ok(DbStockMovementKind::Correction),
+            b"Reservation" => Ok(DbStockMovementKind::Reservation),
+            b"Release" => Ok(DbStockMovementKind::Release),
+            b"Transfer" => Ok(DbStockMovementKind::Transfer),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl From<&DbStockMovementKind> for i32 {
+    fn from(kind: &DbStockMovementKind) -> Self {
+        match kind {
+            DbStockMovementKind::GoodsReceipt => 0,
+            DbStockMovementKind::Sale => 1,
+            DbStockMovementKind::Return => 2,
+            DbStockMovementKind::Correction => 3,
+            DbStockMovementKind::Reservation => 4,
+            DbStockMovementKind::Release => 5,
+            DbStockMovementKind::Transfer => 6,
+        }
+    }
+}
+
+impl TryFrom<i32> for DbStockMovementKind {
+    type Error = String;
+
+    fn try_from(kind: i32) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(DbStockMovementKind::GoodsReceipt),
+            1 => Ok(DbStockMovementKind::Sale),
+            2 => Ok(DbStockMovementKind::Return),
+            3 => Ok(DbStockMovementKind::Correction),
+            4 => Ok(DbStockMovementKind::Reservation),
+            5 => Ok(DbStockMovementKind::Release),
+            6 => Ok(DbStockMovementKind::Transfer),
+            _ => Err(format!("Unknown stock movement kind: {}", kind))
+        }
+    }
+}
+
+
+/// What caused a warehouse change. Stored with the movement it produces.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DbMovementReference {
+    pub reason: Option<String>,
+    pub order_id: Option<i64>,
+    pub return_id: Option<i64>,
+    pub purchase_order_id: Option<i64>,
+}
+
+impl DbMovementReference {
+    pub fn order(order_id: i64) -> Self {
+        DbMovementReference { order_id: Some(order_id), ..Default::default() }
+    }
+
+    pub fn return_request(return_id: i64, order_id: i64) -> Self {
+        DbMovementReference { order_id: Some(order_id), return_id: Some(return_id), ..Default::default() }
+    }
+
+    pub fn purchase_order(purchase_order_id: i64) -> Self {
+        DbMovementReference { purchase_order_id: Some(purchase_order_id), ..Default::default() }
+    }
+
+    /// A stock hold of a basket, which only lives until checkout.
+    pub fn basket(basket_id: Uuid) -> Self {
+        DbMovementReference { reason: Some(format!("Basket {}", basket_id)), ..Default::default() }
+    }
+
+    pub fn reason(reason: Option<&str>) -> Self {
+        DbMovementReference { reason: reason.map(str::to_string), ..Default::default() }
+    }
+}
+
+
+/// An entry of the append-only stock ledger. Rows are protected by a database
+/// trigger against updates and deletes, so there is deliberately no `AsChangeset`.
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = stock_movements)]
+pub struct DbStockMovement {
+    pub id: i64,
+    pub product_id: i64,
+    pub kind: DbStockMovementKind,
+    pub quantity: i64,
+    pub reason: Option<String>,
+    pub order_id: Option<i64>,
+    pub return_id: Option<i64>,
+    pub created_at: NaiveDateTime,
+    pub location_id: Option<i64>,
+    pub purchase_order_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = stock_movements)]
+pub struct InsertableDbStockMovement {
+    pub product_id: i64,
+    pub kind: DbStockMovementKind,
+    pub quantity: i64,
+    pub reason: Option<String>,
+    pub order_id: Option<i64>,
+    pub return_id: Option<i64>,
+    pub created_at: NaiveDateTime,
+    pub location_id: Option<i64>,
+    pub purchase_order_id: Option<i64>,
+}
+
+impl DbStockMovement {
+    /// Returns the movements of a product with `from <= created_at < to`, oldest first.
+    pub async fn get_for_product(tenant_id: Uuid, product_id: i64, from: NaiveDateTime, to: NaiveDateTime) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let movements = stock_movements::table
+            .filter(stock_movements::product_id.eq(product_id))
+            .filter(stock_movements::created_at.ge(from))
+            .filter(stock_movements::created_at.lt(to))
+            .order((stock_movements::created_at.asc(), stock_movements::id.asc()))
+            .load(&mut conn).await?;
+        Ok(movements)
+    }
+
+    /// Appends a movement at a location to the ledger. Zero quantities are not recorded.
+    pub async fn record_conn(conn: &mut AsyncPgConnection, product_id: i64, location_id: i64, kind: DbStockMovementKind, quantity: i64, reference: &DbMovementReference) -> Result<(), ShopsterError> {
+        if quantity == 0 {
+            return Ok(());
+        }
+
+        let insertable = InsertableDbStockMovement {
+            product_id,
+            kind,
+            quantity,
+            reason: reference.reason.clone(),
+            order_id: reference.order_id,
+            return_id: reference.return_id,
+            created_at: Utc::now().naive_utc(),
+            location_id: Some(location_id),
+            purchase_order_id: reference.purchase_order_id,
+        };
+        diesel::insert_into(stock_movements::table)
+            .values(insertable)
+            .execute(conn).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::postgresql::dbaddress::DbAddress;
+use crate::aquire_pool;
+
+
+#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = suppliers)]
+pub struct DbSupplier {
+    pub id: i64,
+    pub name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<DbAddress>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = suppliers)]
+pub struct InsertableDbSupplier {
+    pub name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<DbAddress>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbSupplier> for InsertableDbSupplier {
+    fn from(supplier: &DbSupplier) -> Self {
+        InsertableDbSupplier {
+            name: supplier.name.clone(),
+            email: supplier.email.clone(),
+            phone: supplier.phone.clone(),
+            address: supplier.address.clone(),
+            created_at: supplier.created_at,
+            updated_at: supplier.updated_at,
+        }
+    }
+}
+
+impl DbSupplier {
+    pub async fn get_all(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_suppliers = suppliers::table
+            .order(suppliers::name.asc())
+            .load(&mut conn).await?;
+        Ok(db_suppliers)
+    }
+
+    pub async fn find(tenant_id: Uuid, supplier_id: i64) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_supplier = suppliers::table
+            .filter(suppliers::id.eq(supplier_id))
+            .first(&mut conn).await?;
+        Ok(db_supplier)
+    }
+
+    pub async fn create(tenant_id: Uuid, supplier: DbSupplier) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let insertable = InsertableDbSupplier::from(&supplier);
+        let db_supplier = diesel::insert_into(suppliers::table)
+            .values(insertable)
+            .get_result(&mut conn).await?;
+        Ok(db_supplier)
+    }
+
+    pub async fn update(tenant_id: Uuid, supplier_id: i64, supplier: DbSupplier) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_supplier = diesel::update(suppliers::table)
+            .filter(suppliers::id.eq(supplier_id))
+            .set((
+                suppliers::name.eq(supplier.name),
+                suppliers::email.eq(supplier.email),
+                suppliers::phone.eq(supplier.phone),
+                suppliers::address.eq(supplier.address),
+                suppliers::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(&mut conn).await?;
+        Ok(db_supplier)
+    }
+
+    /// Deletes a supplier. Fails while purchase orders still reference it.
+    pub async fn delete(tenant_id: Uuid, supplier_id: i64) -> Result<usize, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let has_purchase_orders: bool = diesel::select(diesel::dsl::exists(
+            purchase_orders::table.filter(purchase_orders::supplier_id.eq(supplier_id)),
+        ))
+        .get_result(&mut conn).await?;
+        if has_purchase_orders {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Supplier {} still has purchase orders", supplier_id
+            )));
+        }
+
+        let res = diesel::delete(suppliers::table.filter(suppliers::id.eq(supplier_id)))
+            .execute(&mut conn).await?;
+        Ok(res)
+    }
+}
@@ -1,8 +1,24 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+use std::collections::HashMap;
+use uuid::Uuid;
 
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
+use crate::postgresql::dbproduct::DbProduct;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+
+/// A tag that products are labelled with. Titles are unique.
+#[derive(Debug, Clone, Serialize, Deserialize, Identifiable, PartialEq, Queryable)]
+#[diesel(table_name = tags)]
 pub struct DbTag {
     pub id: i32,
     pub title: String,
@@ -10,16 +26,227 @@ pub struct DbTag {
     pub updated_at: Option<NaiveDateTime>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = tags)]
+pub struct InsertableDbTag {
+    pub title: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
 
-
-impl From<&String> for DbTag {
-    fn from(data: &String) -> Self {
-        serde_json::from_str(data).expect("Unable to deserialize DbTag")
+impl From<&DbTag> for InsertableDbTag {
+    fn from(tag: &DbTag) -> Self {
+        InsertableDbTag {
+            title: tag.title.clone(),
+            created_at: tag.created_at,
+            updated_at: tag.updated_at,
+        }
     }
 }
 
-impl From<&DbTag> for String {
-    fn from(data: &DbTag) -> Self {
-        serde_json::to_string(data).expect("Unable to serialize DbTag")
+impl DbTag {
+    /// Returns all tags ordered by title.
+    pub async fn get_all(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_tags = tags::table
+            .order(tags::title.asc())
+            .load(&mut conn).await?;
+        Ok(db_tags)
+    }
+
+    pub async fn find(tenant_id: Uuid, tag_id: i32) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_tag = tags::table
+            .filter(tags::id.eq(tag_id))
+            .first(&mut conn).await?;
+        Ok(db_tag)
+    }
+
+    pub async fn find_by_title(tenant_id: Uuid, title: &str) -> Result<Option<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_tag = tags::table
+            .filter(tags::title.eq(title))
+            .first(&mut conn).await
+            .optional()?;
+        Ok(db_tag)
+    }
+
+    pub async fn create(tenant_id: Uuid, tag: DbTag) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let insertable = InsertableDbTag::from(&tag);
+        let db_tag = diesel::insert_into(tags::table)
+            .values(insertable)
+            .get_result(&mut conn).await?;
+        Ok(db_tag)
+    }
+
+    /// Changes the title of a tag for all of its products. Fails if another
+    /// tag already has the title; see `merge`.
+    pub async fn rename(tenant_id: Uuid, tag_id: i32, title: &str) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let existing: Option<Self> = tags::table
+                .filter(tags::title.eq(title))
+                .filter(tags::id.ne(tag_id))
+                .first(conn).await
+                .optional()?;
+            if let Some(existing) = existing {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Tag '{}' already exists as tag {}", title, existing.id
+                )));
+            }
+
+            let db_tag = diesel::update(tags::table)
+                .filter(tags::id.eq(tag_id))
+                .set((
+                    tags::title.eq(title),
+                    tags::updated_at.eq(Some(Utc::now().naive_utc())),
+                ))
+                .get_result(conn).await?;
+            Ok(db_tag)
+        }).await
+    }
+
+    /// Moves all products of the tag `source_id` to the tag `target_id` and
+    /// deletes the source tag. Returns the target tag.
+    pub async fn merge(tenant_id: Uuid, source_id: i32, target_id: i32) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let locked: Vec<i32> = tags::table
+                .filter(tags::id.eq_any([source_id, target_id]))
+                .select(tags::id)
+                .order(tags::id.asc())
+                .for_update()
+                .load(conn).await?;
+            if locked.len() != 2 {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Tags {} and {} must be two existing tags", source_id, target_id
+                )));
+            }
+
+            diesel::insert_into(product_tags::table)
+                .values(
+                    product_tags::table
+                        .filter(product_tags::tag_id.eq(source_id))
+                        .select((product_tags::product_id, target_id.into_sql::<diesel::sql_types::Integer>())),
+                )
+                .into_columns((product_tags::product_id, product_tags::tag_id))
+                .on_conflict_do_nothing()
+                .execute(conn).await?;
+            diesel::delete(tags::table.filter(tags::id.eq(source_id)))
+                .execute(conn).await?;
+
+            let db_tag = diesel::update(tags::table)
+                .filter(tags::id.eq(target_id))
+                .set(tags::updated_at.eq(Some(Utc::now().naive_utc())))
+                .get_result(conn).await?;
+            Ok(db_tag)
+        }).await
+    }
+
+    /// Deletes a tag and removes it from all products.
+    pub async fn delete(tenant_id: Uuid, tag_id: i32) -> Result<usize, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let res = diesel::delete(tags::table.filter(tags::id.eq(tag_id)))
+            .execute(&mut conn).await?;
+        Ok(res)
+    }
+
+    /// Returns the products carrying a tag.
+    pub async fn get_products(tenant_id: Uuid, tag_id: i32) -> Result<Vec<DbProduct>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_products = products::table
+            .filter(products::id.eq_any(
+                product_tags::table
+                    .filter(product_tags::tag_id.eq(tag_id))
+                    .select(product_tags::product_id),
+            ))
+            .order(products::id.asc())
+            .select(DbProduct::as_select())
+            .load(&mut conn).await?;
+        Ok(db_products)
+    }
+
+    /// Returns the tags of each of the given products ordered by title.
+    /// Variants have no tags of their own and get those of their parent.
+    pub async fn get_for_products(tenant_id: Uuid, product_ids: &[i64]) -> Result<HashMap<i64, Vec<Self>>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        Self::get_for_products_conn(&mut conn, product_ids).await
+    }
+
+    pub async fn get_for_products_conn(conn: &mut AsyncPgConnection, product_ids: &[i64]) -> Result<HashMap<i64, Vec<Self>>, ShopsterError> {
+        let parents: HashMap<i64, i64> = product_variants::table
+            .filter(product_variants::product_id.eq_any(product_ids))
+            .select((product_variants::product_id, product_variants::parent_id))
+            .load::<(i64, i64)>(conn).await?
+            .into_iter()
+            .collect();
+        let owner_id = |product_id: i64| parents.get(&product_id).copied().unwrap_or(product_id);
+
+        let rows: Vec<(i64, Self)> = product_tags::table
+            .inner_join(tags::table)
+            .filter(product_tags::product_id.eq_any(product_ids.iter().map(|id| owner_id(*id)).collect::<Vec<_>>()))
+            .select((product_tags::product_id, tags::all_columns))
+            .order((product_tags::product_id.asc(), tags::title.asc()))
+            .load(conn).await?;
+        let mut by_owner: HashMap<i64, Vec<Self>> = HashMap::new();
+        for (product_id, tag) in rows {
+            by_owner.entry(product_id).or_default().push(tag);
+        }
+
+        Ok(product_ids.iter()
+            .map(|id| (*id, by_owner.get(&owner_id(*id)).cloned().unwrap_or_default()))
+            .collect())
+    }
+
+    /// Makes the tags of a product exactly the given titles, creating tags
+    /// that do not exist yet. Must run in a transaction.
+    pub async fn set_for_product_conn(conn: &mut AsyncPgConnection, product_id: i64, titles: &[String]) -> Result<Vec<Self>, ShopsterError> {
+        let now = Utc::now().naive_utc();
+        let new_tags: Vec<InsertableDbTag> = titles.iter()
+            .map(|title| InsertableDbTag { title: title.clone(), created_at: now, updated_at: None })
+            .collect();
+        diesel::insert_into(tags::table)
+            .values(new_tags)
+            .on_conflict(tags::title)
+            .do_nothing()
+            .execute(conn).await?;
+        let tag_ids: Vec<i32> = tags::table
+            .filter(tags::title.eq_any(titles))
+            .select(tags::id)
+            .load(conn).await?;
+
+        diesel::delete(product_tags::table
+            .filter(product_tags::product_id.eq(product_id))
+            .filter(product_tags::tag_id.ne_all(&tag_ids)))
+            .execute(conn).await?;
+        let links: Vec<_> = tag_ids.iter()
+            .map(|tag_id| (product_tags::product_id.eq(product_id), product_tags::tag_id.eq(*tag_id)))
+            .collect();
+        diesel::insert_into(product_tags::table)
+            .values(links)
+            .on_conflict_do_nothing()
+            .execute(conn).await?;
+
+        let mut db_tags = Self::get_for_products_conn(conn, &[product_id]).await?;
+        Ok(db_tags.remove(&product_id).unwrap_or_default())
     }
 }
@@ -0,0 +1,201 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::{Serialize, Deserialize};
+use diesel::{
+    self,
+    Queryable,
+    Insertable,
+};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::ShopsterError;
+use crate::schema::*;
+use crate::aquire_pool;
+use crate::postgresql::dbsettings::DbSetting;
+
+
+const LANGUAGE_SETTING: &str = "language";
+
+
+/// Returns the shop's default language from the `language` setting. The
+/// product and category rows hold the texts in this language.
+pub async fn default_language(tenant_id: Uuid) -> Result<Option<String>, ShopsterError> {
+    let pool = aquire_pool(tenant_id).await?;
+    let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+    default_language_conn(&mut conn).await
+}
+
+pub async fn default_language_conn(conn: &mut AsyncPgConnection) -> Result<Option<String>, ShopsterError> {
+    let setting = DbSetting::find_by_title_conn(conn, LANGUAGE_SETTING).await?;
+    Ok(setting.map(|setting| setting.value))
+}
+
+/// Returns the languages whose translations apply to `locale`, most specific
+/// first: the locale itself and, for a regional locale such as `de-CH`, its
+/// language. The default language is left out, since its texts are those of
+/// the rows themselves.
+pub fn fallback_languages(locale: &str, default_language: Option<&str>) -> Vec<String> {
+    let mut languages = vec![locale.to_string()];
+    if let Some((language, _)) = locale.split_once('-') {
+        languages.push(language.to_string());
+    }
+    languages.retain(|language| Some(language.as_str()) != default_language);
+    languages
+}
+
+
+/// The texts of a product in one language. `None` falls back to the next
+/// language, and finally to the product row.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[diesel(table_name = product_translations)]
+pub struct DbProductTranslation {
+    pub product_id: i64,
+    pub language: String,
+    pub title: Option<String>,
+    pub short_description: Option<String>,
+    pub description: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl DbProductTranslation {
+    /// Returns all translations of a product ordered by language.
+    pub async fn get_for_product(tenant_id: Uuid, product_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let translations = product_translations::table
+            .filter(product_translations::product_id.eq(product_id))
+            .order(product_translations::language.asc())
+            .load(&mut conn).await?;
+        Ok(translations)
+    }
+
+    /// Returns the translations into the given languages of each of the given
+    /// products. Variants have no translations of their own and get those of
+    /// their parent.
+    pub async fn get_for_products_conn(conn: &mut AsyncPgConnection, product_ids: &[i64], languages: &[String]) -> Result<HashMap<i64, Vec<Self>>, ShopsterError> {
+        let parents: HashMap<i64, i64> = product_variants::table
+            .filter(product_variants::product_id.eq_any(product_ids))
+            .select((product_variants::product_id, product_variants::parent_id))
+            .load::<(i64, i64)>(conn).await?
+            .into_iter()
+            .collect();
+        let owner_id = |product_id: i64| parents.get(&product_id).copied().unwrap_or(product_id);
+
+        let translations: Vec<Self> = product_translations::table
+            .filter(product_translations::product_id.eq_any(product_ids.iter().map(|id| owner_id(*id)).collect::<Vec<_>>()))
+            .filter(product_translations::language.eq_any(languages))
+            .load(conn).await?;
+        let mut by_owner: HashMap<i64, Vec<Self>> = HashMap::new();
+        for translation in translations {
+            by_owner.entry(translation.product_id).or_default().push(translation);
+        }
+
+        Ok(product_ids.iter()
+            .map(|id| (*id, by_owner.get(&owner_id(*id)).cloned().unwrap_or_default()))
+            .collect())
+    }
+
+    /// Creates or replaces the translation of a product into a language.
+    pub async fn upsert(tenant_id: Uuid, translation: DbProductTranslation) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_translation = diesel::insert_into(product_translations::table)
+            .values(&translation)
+            .on_conflict((product_translations::product_id, product_translations::language))
+            .do_update()
+            .set((
+                product_translations::title.eq(excluded(product_translations::title)),
+                product_translations::short_description.eq(excluded(product_translations::short_description)),
+                product_translations::description.eq(excluded(product_translations::description)),
+                product_translations::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(&mut conn).await?;
+        Ok(db_translation)
+    }
+
+    pub async fn delete(tenant_id: Uuid, product_id: i64, language: &str) -> Result<usize, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let res = diesel::delete(product_translations::table
+            .filter(product_translations::product_id.eq(product_id))
+            .filter(product_translations::language.eq(language)))
+            .execute(&mut conn).await?;
+        Ok(res)
+    }
+}
+
+
+/// The title of a category in one language.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Queryable, Insertable)]
+#[diesel(table_name = category_translations)]
+pub struct DbCategoryTranslation {
+    pub category_id: i64,
+    pub language: String,
+    pub title: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl DbCategoryTranslation {
+    /// Returns all translations of a category ordered by language.
+    pub async fn get_for_category(tenant_id: Uuid, category_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let translations = category_translations::table
+            .filter(category_translations::category_id.eq(category_id))
+            .order(category_translations::language.asc())
+            .load(&mut conn).await?;
+        Ok(translations)
+    }
+
+    /// Returns the translations into the given languages of each of the given
+    /// categories.
+    pub async fn get_for_categories_conn(conn: &mut AsyncPgConnection, category_ids: &[i64], languages: &[String]) -> Result<HashMap<i64, Vec<Self>>, ShopsterError> {
+        let translations: Vec<Self> = category_translations::table
+            .filter(category_translations::category_id.eq_any(category_ids))
+            .filter(category_translations::language.eq_any(languages))
+            .load(conn).await?;
+        let mut by_category: HashMap<i64, Vec<Self>> = HashMap::new();
+        for translation in translations {
+            by_category.entry(translation.category_id).or_default().push(translation);
+        }
+        Ok(by_category)
+    }
+
+    /// Creates or replaces the translation of a category into a language.
+    pub async fn upsert(tenant_id: Uuid, translation: DbCategoryTranslation) -> Result<Self, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_translation = diesel::insert_into(category_translations::table)
+            .values(&translation)
+            .on_conflict((category_translations::category_id, category_translations::language))
+            .do_update()
+            .set((
+                category_translations::title.eq(excluded(category_translations::title)),
+                category_translations::updated_at.eq(Some(Utc::now().naive_utc())),
+            ))
+            .get_result(&mut conn).await?;
+        Ok(db_translation)
+    }
+
+    pub async fn delete(tenant_id: Uuid, category_id: i64, language: &str) -> Result<usize, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let res = diesel::delete(category_translations::table
+            .filter(category_translations::category_id.eq(category_id))
+            .filter(category_translations::language.eq(language)))
+            .execute(&mut conn).await?;
+        Ok(res)
+    }
+}
@@ -6,14 +6,84 @@ use diesel::{
     Queryable,
 };
 use diesel::prelude::*;
-use diesel_async::{RunQueryDsl, AsyncPgConnection};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel_async::{AsyncConnection, RunQueryDsl, AsyncPgConnection};
+use std::fmt;
+use std::io::Write;
+use std::convert::TryFrom;
 use uuid::Uuid;
 
 use crate::ShopsterError;
 use crate::schema::*;
+use crate::postgresql::dbaddress::DbAddress;
+use crate::postgresql::dblocation::DbLocation;
+use crate::postgresql::dbreorderpoint::DbReorderPoint;
+use crate::postgresql::dbstockmovement::{DbMovementReference, DbStockMovement, DbStockMovementKind};
 use crate::aquire_pool;
 
-#[derive(Debug, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable, AsChangeset)]
+#[derive(Debug, AsExpression, FromSqlRow, Serialize, Deserialize, PartialEq, PartialOrd, Copy, Clone)]
+#[diesel(sql_type = crate::schema::sql_types::DbStockPolicy)]
+pub enum DbStockPolicy {
+    Deny,
+    Backorder,
+    Unlimited
+}
+
+impl fmt::Display for DbStockPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl ToSql<crate::schema::sql_types::DbStockPolicy, Pg> for DbStockPolicy {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            DbStockPolicy::Deny => out.write_all(b"Deny")?,
+            DbStockPolicy::Backorder => out.write_all(b"Backorder")?,
+            DbStockPolicy::Unlimited => out.write_all(b"Unlimited")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::DbStockPolicy, Pg> for DbStockPolicy {
+    fn from_sql(bytes: PgValue) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"Deny" => Ok(DbStockPolicy::Deny),
+            b"Backorder" => Ok(DbStockPolicy::Backorder),
+            b"Unlimited" => Ok(DbStockPolicy::Unlimited),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl From<&DbStockPolicy> for i32 {
+    fn from(policy: &DbStockPolicy) -> Self {
+        match policy {
+            DbStockPolicy::Deny => 0,
+            DbStockPolicy::Backorder => 1,
+            DbStockPolicy::Unlimited => 2,
+        }
+    }
+}
+
+impl TryFrom<i32> for DbStockPolicy {
+    type Error = String;
+
+    fn try_from(policy: i32) -> Result<Self, Self::Error> {
+        match policy {
+            0 => Ok(DbStockPolicy::Deny),
+            1 => Ok(DbStockPolicy::Backorder),
+            2 => Ok(DbStockPolicy::Unlimited),
+            _ => Err(format!("Unknown stock policy: {}", policy))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Identifiable, PartialEq, Queryable, Insertable)]
 #[diesel(table_name = warehouse)]
 pub struct DbWarehouse {
     pub id: i64,
@@ -22,6 +92,9 @@ pub struct DbWarehouse {
     pub reserved: i64,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub stock_policy: DbStockPolicy,
+    pub backordered: i64,
+    pub location_id: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
@@ -32,6 +105,9 @@ pub struct InsertableDbWarehouse {
     pub reserved: i64,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub stock_policy: DbStockPolicy,
+    pub backordered: i64,
+    pub location_id: i64,
 }
 
 impl From<&DbWarehouse> for InsertableDbWarehouse {
@@ -42,115 +118,480 @@ impl From<&DbWarehouse> for InsertableDbWarehouse {
             reserved: item.reserved,
             created_at: item.created_at,
             updated_at: item.updated_at,
+            stock_policy: item.stock_policy,
+            backordered: item.backordered,
+            location_id: item.location_id,
         }
     }
 }
 
+/// A product has one warehouse row per location it is stocked at. Reservations
+/// and shipments are spread over these rows in the order given by
+/// `DbLocation::allocation_order_conn`; the stock policy is kept the same on
+/// all rows of a product.
 impl DbWarehouse {
-    pub async fn find_by_product_id(tenant_id: Uuid, product_id: i64) -> Result<Self, ShopsterError> {
+    /// Returns the rows of a product at all locations, oldest first. Fails with
+    /// `NotFound` if the product is not stocked anywhere.
+    pub async fn find_by_product_id(tenant_id: Uuid, product_id: i64) -> Result<Vec<Self>, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
-        let item = warehouse::table
+        let items: Vec<Self> = warehouse::table
             .filter(warehouse::product_id.eq(product_id))
-            .first(&mut conn).await?;
-        Ok(item)
+            .order(warehouse::id.asc())
+            .load(&mut conn).await?;
+        if items.is_empty() {
+            return Err(ShopsterError::DatabaseError(diesel::result::Error::NotFound));
+        }
+        Ok(items)
+    }
+
+    pub async fn get_for_location(tenant_id: Uuid, location_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let items = warehouse::table
+            .filter(warehouse::location_id.eq(location_id))
+            .order(warehouse::product_id.asc())
+            .load(&mut conn).await?;
+        Ok(items)
+    }
+
+    /// Locks the warehouse rows of the given products until the end of the
+    /// transaction. Rows are locked in product and location id order to avoid
+    /// deadlocks between concurrent checkouts.
+    pub async fn lock_for_products_conn(conn: &mut AsyncPgConnection, product_ids: &[i64]) -> Result<Vec<Self>, ShopsterError> {
+        let items = warehouse::table
+            .filter(warehouse::product_id.eq_any(product_ids))
+            .order((warehouse::product_id.asc(), warehouse::location_id.asc()))
+            .for_update()
+            .load(conn).await?;
+        Ok(items)
+    }
+
+    /// Orders all rows of the locked product by `DbLocation::allocation_order_conn`.
+    async fn lock_in_allocation_order_conn(conn: &mut AsyncPgConnection, product_id: i64, destination: Option<&DbAddress>) -> Result<Vec<Self>, ShopsterError> {
+        let mut items = Self::lock_for_products_conn(conn, &[product_id]).await?;
+        if items.is_empty() {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Product {} has no warehouse entry", product_id
+            )));
+        }
+
+        let order = DbLocation::allocation_order_conn(conn, destination).await?;
+        items.sort_by_key(|item| order.iter().position(|id| *id == item.location_id).unwrap_or(order.len()));
+        Ok(items)
     }
 
     pub async fn get_all(tenant_id: Uuid) -> Result<Vec<Self>, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
-        let items = warehouse::table.load(&mut conn).await?;
+        let items = warehouse::table
+            .order((warehouse::product_id.asc(), warehouse::id.asc()))
+            .load(&mut conn).await?;
         Ok(items)
     }
 
+    /// Creates the warehouse row of a product at a location. Its initial stock
+    /// is recorded as a correction, an initial reservation as a reservation.
     pub async fn create(tenant_id: Uuid, item: DbWarehouse) -> Result<Self, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
-        let insertable = InsertableDbWarehouse::from(&item);
-        let db_item = diesel::insert_into(warehouse::table)
-            .values(insertable)
-            .get_result(&mut conn).await?;
-        Ok(db_item)
+        conn.transaction(async |conn| {
+            let existing = Self::lock_for_products_conn(conn, &[item.product_id]).await?;
+            let insertable = InsertableDbWarehouse::from(&item);
+            let db_item: DbWarehouse = diesel::insert_into(warehouse::table)
+                .values(insertable)
+                .get_result(conn).await?;
+            Self::set_stock_policy_conn(conn, db_item.product_id, db_item.stock_policy).await?;
+            let available_before = Self::available(&existing);
+            DbReorderPoint::alert_if_crossed_conn(conn, db_item.product_id, available_before, available_before + db_item.in_stock - db_item.reserved).await?;
+
+            let reference = DbMovementReference::default();
+            DbStockMovement::record_conn(conn, db_item.product_id, db_item.location_id, DbStockMovementKind::Correction, db_item.in_stock, &reference).await?;
+            DbStockMovement::record_conn(conn, db_item.product_id, db_item.location_id, DbStockMovementKind::Reservation, db_item.reserved + db_item.backordered, &reference).await?;
+            Ok(db_item)
+        }).await
     }
 
-    pub async fn update_by_product_id(tenant_id: Uuid, product_id: i64, item: DbWarehouse) -> Result<Self, ShopsterError> {
+    /// Overwrites the quantities and the stock policy of the row of a product
+    /// at `location_id`. `None` stands for the product's only row and is
+    /// rejected for products stocked at several locations, since the summed up
+    /// stock cannot be written back to one of them. The differences to the
+    /// previous stock and reservation are recorded as movements, a new stock
+    /// policy is applied to all locations, and new stock is allocated to open
+    /// backorders.
+    pub async fn update_by_product_id(tenant_id: Uuid, product_id: i64, location_id: Option<i64>, item: DbWarehouse, reference: &DbMovementReference) -> Result<Self, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
-        let db_item = diesel::update(warehouse::table)
+        conn.transaction(async |conn| {
+            let rows = Self::lock_for_products_conn(conn, &[product_id]).await?;
+            if location_id.is_none() && rows.len() > 1 {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Product {} is stocked at {} locations, the location to update must be given",
+                    product_id, rows.len()
+                )));
+            }
+            let available_before = Self::available(&rows);
+            let existing = rows.into_iter()
+                .find(|existing| location_id.is_none_or(|location_id| existing.location_id == location_id))
+                .ok_or(diesel::result::Error::NotFound)?;
+            let location_id = existing.location_id;
+
+            let updated: DbWarehouse = diesel::update(warehouse::table)
+                .filter(warehouse::id.eq(existing.id))
+                .set((
+                    warehouse::in_stock.eq(item.in_stock),
+                    warehouse::reserved.eq(item.reserved),
+                    warehouse::backordered.eq(item.backordered),
+                    warehouse::stock_policy.eq(item.stock_policy),
+                    warehouse::updated_at.eq(Some(Utc::now().naive_utc())),
+                ))
+                .get_result(conn).await?;
+            Self::set_stock_policy_conn(conn, product_id, updated.stock_policy).await?;
+
+            let stock_delta = updated.in_stock - existing.in_stock;
+            let reservation_delta = (updated.reserved + updated.backordered) - (existing.reserved + existing.backordered);
+            DbStockMovement::record_conn(conn, product_id, location_id, DbStockMovementKind::Correction, stock_delta, reference).await?;
+            DbStockMovement::record_conn(conn, product_id, location_id, Self::reservation_kind(reservation_delta), reservation_delta, reference).await?;
+
+            let items = Self::allocate_backorders_conn(conn, product_id).await?;
+            DbReorderPoint::alert_if_crossed_conn(conn, product_id, available_before, Self::available(&items)).await?;
+            Ok(items.into_iter().find(|item| item.location_id == location_id).unwrap_or(updated))
+        }).await
+    }
+
+    async fn set_stock_policy_conn(conn: &mut AsyncPgConnection, product_id: i64, stock_policy: DbStockPolicy) -> Result<(), ShopsterError> {
+        diesel::update(warehouse::table)
             .filter(warehouse::product_id.eq(product_id))
-            .set(item)
-            .get_result(&mut conn).await?;
-        Ok(db_item)
+            .filter(warehouse::stock_policy.ne(stock_policy))
+            .set(warehouse::stock_policy.eq(stock_policy))
+            .execute(conn).await?;
+        Ok(())
     }
 
-    pub async fn delete_by_product_id(tenant_id: Uuid, product_id: i64) -> Result<usize, ShopsterError> {
+    /// Adds received goods to `in_stock` at a location (the default location if
+    /// `None`) and allocates them to open backorders.
+    pub async fn receive_stock(tenant_id: Uuid, product_id: i64, location_id: Option<i64>, quantity: i64, reference: &DbMovementReference) -> Result<Vec<Self>, ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
 
-        let res = diesel::delete(
-            warehouse::table
-                .filter(warehouse::product_id.eq(product_id)),
-        )
-        .execute(&mut conn).await?;
-        Ok(res)
+        conn.transaction(async |conn| {
+            Self::apply_in_stock_delta_conn(conn, product_id, location_id, quantity, DbStockMovementKind::GoodsReceipt, reference).await
+        }).await
     }
 
-    pub async fn apply_reserved_delta(tenant_id: Uuid, product_id: i64, delta: i64) -> Result<Self, ShopsterError> {
+    /// Moves unreserved stock of a product from one location to another and
+    /// records a transfer movement at both. Returns the rows of both locations.
+    pub async fn transfer(tenant_id: Uuid, product_id: i64, from_location_id: i64, to_location_id: i64, quantity: i64, reference: &DbMovementReference) -> Result<(Self, Self), ShopsterError> {
         let pool = aquire_pool(tenant_id).await?;
         let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
-        Self::apply_reserved_delta_conn(&mut conn, product_id, delta).await
-    }
 
-    pub async fn apply_reserved_delta_conn(conn: &mut AsyncPgConnection, product_id: i64, delta: i64) -> Result<Self, ShopsterError> {
-        // Atomic UPDATE using SQL arithmetic — avoids the read-modify-write race condition
-        // where two concurrent callers both read the same value and one update is lost.
-        // The WHERE reserved + delta >= 0 guard is evaluated atomically with the SET.
-        let result = diesel::update(warehouse::table)
-            .filter(warehouse::product_id.eq(product_id))
-            .filter((warehouse::reserved + delta).ge(0i64))
-            .set((
-                warehouse::reserved.eq(warehouse::reserved + delta),
-                warehouse::updated_at.eq(Some(Utc::now().naive_utc())),
-            ))
-            .get_result::<DbWarehouse>(conn).await;
-
-        match result {
-            Ok(updated) => Ok(updated),
-            Err(diesel::result::Error::NotFound) => {
-                let exists: bool = diesel::select(diesel::dsl::exists(
-                    warehouse::table.filter(warehouse::product_id.eq(product_id)),
+        conn.transaction(async |conn| {
+            let items = Self::lock_for_products_conn(conn, &[product_id]).await?;
+            let source = items.iter()
+                .find(|item| item.location_id == from_location_id)
+                .ok_or_else(|| ShopsterError::InvalidOperationError(format!(
+                    "Product {} has no stock at location {}", product_id, from_location_id
+                )))?;
+
+            let free = source.in_stock - source.reserved;
+            if quantity > free {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Only {} units of product {} can be transferred from location {}",
+                    free.max(0), product_id, from_location_id
+                )));
+            }
+
+            let now = Utc::now().naive_utc();
+            let source: DbWarehouse = diesel::update(warehouse::table)
+                .filter(warehouse::id.eq(source.id))
+                .set((
+                    warehouse::in_stock.eq(warehouse::in_stock - quantity),
+                    warehouse::updated_at.eq(Some(now)),
                 ))
                 .get_result(conn).await?;
 
-                if exists {
+            let target: DbWarehouse = match items.iter().find(|item| item.location_id == to_location_id) {
+                Some(target) => diesel::update(warehouse::table)
+                    .filter(warehouse::id.eq(target.id))
+                    .set((
+                        warehouse::in_stock.eq(warehouse::in_stock + quantity),
+                        warehouse::updated_at.eq(Some(now)),
+                    ))
+                    .get_result(conn).await?,
+                None => diesel::insert_into(warehouse::table)
+                    .values(InsertableDbWarehouse {
+                        product_id,
+                        in_stock: quantity,
+                        reserved: 0,
+                        created_at: now,
+                        updated_at: Some(now),
+                        stock_policy: source.stock_policy,
+                        backordered: 0,
+                        location_id: to_location_id,
+                    })
+                    .get_result(conn).await?,
+            };
+
+            DbStockMovement::record_conn(conn, product_id, from_location_id, DbStockMovementKind::Transfer, -quantity, reference).await?;
+            DbStockMovement::record_conn(conn, product_id, to_location_id, DbStockMovementKind::Transfer, quantity, reference).await?;
+            Ok((source, target))
+        }).await
+    }
+
+    /// Deletes the rows of a product at all locations. Their remaining stock
+    /// and reservations are written off as movements.
+    pub async fn delete_by_product_id(tenant_id: Uuid, product_id: i64, reference: &DbMovementReference) -> Result<usize, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let existing = Self::lock_for_products_conn(conn, &[product_id]).await?;
+
+            let res = diesel::delete(
+                warehouse::table
+                    .filter(warehouse::product_id.eq(product_id)),
+            )
+            .execute(conn).await?;
+
+            for item in &existing {
+                DbStockMovement::record_conn(conn, product_id, item.location_id, DbStockMovementKind::Correction, -item.in_stock, reference).await?;
+                DbStockMovement::record_conn(conn, product_id, item.location_id, DbStockMovementKind::Release, -(item.reserved + item.backordered), reference).await?;
+            }
+            Ok(res)
+        }).await
+    }
+
+    pub async fn apply_reserved_delta(tenant_id: Uuid, product_id: i64, delta: i64, reference: &DbMovementReference) -> Result<Vec<Self>, ShopsterError> {
+        let pool = aquire_pool(tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| Self::apply_reserved_delta_conn(conn, product_id, delta, None, reference).await).await
+    }
+
+    /// The free stock (`in_stock - reserved`) of the given rows together.
+    fn available(items: &[Self]) -> i64 {
+        items.iter().map(|item| item.in_stock - item.reserved).sum()
+    }
+
+    fn reservation_kind(delta: i64) -> DbStockMovementKind {
+        if delta < 0 { DbStockMovementKind::Release } else { DbStockMovementKind::Reservation }
+    }
+
+    /// Writes the quantities of all rows that differ from `original` and
+    /// returns the rows as stored.
+    async fn save_changed_conn(conn: &mut AsyncPgConnection, items: Vec<Self>, original: &[Self]) -> Result<Vec<Self>, ShopsterError> {
+        let mut saved = Vec::with_capacity(items.len());
+        for (item, before) in items.into_iter().zip(original) {
+            if (item.in_stock, item.reserved, item.backordered) == (before.in_stock, before.reserved, before.backordered) {
+                saved.push(item);
+                continue;
+            }
+            saved.push(diesel::update(warehouse::table)
+                .filter(warehouse::id.eq(item.id))
+                .set((
+                    warehouse::in_stock.eq(item.in_stock),
+                    warehouse::reserved.eq(item.reserved),
+                    warehouse::backordered.eq(item.backordered),
+                    warehouse::updated_at.eq(Some(Utc::now().naive_utc())),
+                ))
+                .get_result(conn).await?);
+        }
+        Ok(saved)
+    }
+
+    /// Adds `delta` units to the reservation of a product (or releases them if
+    /// `delta` is negative), enforcing the product's stock policy. The warehouse
+    /// rows are locked for the rest of the transaction.
+    ///
+    /// Units are reserved from the free stock of each location in allocation
+    /// order for `destination`. With `Deny`, reservations may not exceed the
+    /// free stock of all locations together. With `Backorder`, the remaining
+    /// units are booked as `backordered` at the first location. With
+    /// `Unlimited`, they are reserved there regardless of stock. Releases take
+    /// back backordered units before reserved ones, since those have not been
+    /// allocated any stock yet. Every change is recorded as a reservation or
+    /// release movement at its location.
+    pub async fn apply_reserved_delta_conn(conn: &mut AsyncPgConnection, product_id: i64, delta: i64, destination: Option<&DbAddress>, reference: &DbMovementReference) -> Result<Vec<Self>, ShopsterError> {
+        let mut items = Self::lock_in_allocation_order_conn(conn, product_id, destination).await?;
+        let original = items.clone();
+
+        if delta < 0 {
+            let claimed: i64 = items.iter().map(|item| item.reserved + item.backordered).sum();
+            if claimed + delta < 0 {
+                return Err(ShopsterError::InvalidOperationError(
+                    "Reserved stock cannot be negative".to_string(),
+                ));
+            }
+
+            let mut remaining = -delta;
+            for item in items.iter_mut() {
+                let released = remaining.min(item.backordered);
+                item.backordered -= released;
+                remaining -= released;
+            }
+            for item in items.iter_mut() {
+                let released = remaining.min(item.reserved);
+                item.reserved -= released;
+                remaining -= released;
+            }
+        } else {
+            let stock_policy = items[0].stock_policy;
+            let free: i64 = items.iter().map(|item| (item.in_stock - item.reserved).max(0)).sum();
+            if stock_policy == DbStockPolicy::Deny && delta > free {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Insufficient stock for product {}: {} requested, {} available",
+                    product_id, delta, free
+                )));
+            }
+
+            let mut remaining = delta;
+            for item in items.iter_mut() {
+                let reserved = remaining.min((item.in_stock - item.reserved).max(0));
+                item.reserved += reserved;
+                remaining -= reserved;
+            }
+            match stock_policy {
+                DbStockPolicy::Backorder => items[0].backordered += remaining,
+                DbStockPolicy::Deny | DbStockPolicy::Unlimited => items[0].reserved += remaining,
+            }
+        }
+
+        let items = Self::save_changed_conn(conn, items, &original).await?;
+        for (item, before) in items.iter().zip(&original) {
+            let change = (item.reserved + item.backordered) - (before.reserved + before.backordered);
+            DbStockMovement::record_conn(conn, product_id, item.location_id, Self::reservation_kind(change), change, reference).await?;
+        }
+        DbReorderPoint::alert_if_crossed_conn(conn, product_id, Self::available(&original), Self::available(&items)).await?;
+        Ok(items)
+    }
+
+    /// Moves backordered units of a product into its reservation as far as the
+    /// free stock (`in_stock - reserved`) of its locations allows. Called
+    /// whenever stock arrives. No movement is recorded, since the total
+    /// reservation does not change.
+    pub async fn allocate_backorders_conn(conn: &mut AsyncPgConnection, product_id: i64) -> Result<Vec<Self>, ShopsterError> {
+        let mut items = Self::lock_in_allocation_order_conn(conn, product_id, None).await?;
+        let original = items.clone();
+
+        let mut open: i64 = items.iter().map(|item| item.backordered).sum();
+        let mut allocated = 0;
+        for item in items.iter_mut() {
+            let taken = open.min((item.in_stock - item.reserved).max(0));
+            item.reserved += taken;
+            open -= taken;
+            allocated += taken;
+        }
+        for item in items.iter_mut() {
+            let taken = allocated.min(item.backordered);
+            item.backordered -= taken;
+            allocated -= taken;
+        }
+
+        Self::save_changed_conn(conn, items, &original).await
+    }
+
+    /// Adds `delta` to `in_stock` at a location (the default location if
+    /// `None`), e.g. for goods coming back from a customer return, records it
+    /// as a movement of the given kind and allocates the new stock to open
+    /// backorders. Creates the row if the product is not stocked at the
+    /// location yet. Returns the rows of the product at all locations.
+    pub async fn apply_in_stock_delta_conn(conn: &mut AsyncPgConnection, product_id: i64, location_id: Option<i64>, delta: i64, kind: DbStockMovementKind, reference: &DbMovementReference) -> Result<Vec<Self>, ShopsterError> {
+        let location_id = match location_id {
+            Some(location_id) => location_id,
+            None => DbLocation::find_default_conn(conn).await?.id,
+        };
+
+        let items = Self::lock_for_products_conn(conn, &[product_id]).await?;
+        let now = Utc::now().naive_utc();
+        match items.iter().find(|item| item.location_id == location_id) {
+            Some(item) => {
+                if item.in_stock + delta < 0 {
                     return Err(ShopsterError::InvalidOperationError(
-                        "Reserved stock cannot be negative".to_string(),
+                        "Stock cannot be negative".to_string(),
                     ));
                 }
-
+                diesel::update(warehouse::table)
+                    .filter(warehouse::id.eq(item.id))
+                    .set((
+                        warehouse::in_stock.eq(warehouse::in_stock + delta),
+                        warehouse::updated_at.eq(Some(now)),
+                    ))
+                    .execute(conn).await?;
+            },
+            None => {
                 if delta < 0 {
                     return Err(ShopsterError::InvalidOperationError(
-                        "Reserved stock cannot be negative".to_string(),
+                        "Stock cannot be negative".to_string(),
                     ));
                 }
-
-                let now = Utc::now().naive_utc();
                 let insertable = InsertableDbWarehouse {
                     product_id,
-                    in_stock: 0,
-                    reserved: delta,
+                    in_stock: delta,
+                    reserved: 0,
                     created_at: now,
                     updated_at: Some(now),
+                    stock_policy: items.iter().map(|item| item.stock_policy).next().unwrap_or(DbStockPolicy::Deny),
+                    backordered: 0,
+                    location_id,
                 };
-                Ok(diesel::insert_into(warehouse::table)
+                diesel::insert_into(warehouse::table)
                     .values(insertable)
-                    .get_result(conn).await?)
+                    .execute(conn).await?;
+            }
+        }
+
+        DbStockMovement::record_conn(conn, product_id, location_id, kind, delta, reference).await?;
+        let updated = Self::allocate_backorders_conn(conn, product_id).await?;
+        DbReorderPoint::alert_if_crossed_conn(conn, product_id, Self::available(&items), Self::available(&updated)).await?;
+        Ok(updated)
+    }
+
+    /// Books `quantity` units out of the warehouse for a shipment: the reservation
+    /// held for them is consumed and `in_stock` is decremented by the same amount,
+    /// taking from the locations in allocation order for `destination`.
+    /// Products with the `Unlimited` policy keep their `in_stock` untouched.
+    /// Backordered units cannot be shipped until stock has been allocated to them.
+    /// Each location records the stock that left as a `Sale` (none for
+    /// `Unlimited`) and the consumed reservation as a `Release`.
+    pub async fn apply_shipped_quantity_conn(conn: &mut AsyncPgConnection, product_id: i64, quantity: i64, destination: Option<&DbAddress>, reference: &DbMovementReference) -> Result<Vec<Self>, ShopsterError> {
+        let insufficient = || ShopsterError::InvalidOperationError(format!(
+            "Insufficient stock or reservation to ship {} units of product {}",
+            quantity, product_id
+        ));
+
+        let mut items = Self::lock_in_allocation_order_conn(conn, product_id, destination).await
+            .map_err(|_| insufficient())?;
+        let original = items.clone();
+
+        let shippable = |item: &DbWarehouse| match item.stock_policy {
+            DbStockPolicy::Unlimited => item.reserved,
+            DbStockPolicy::Deny | DbStockPolicy::Backorder => item.reserved.min(item.in_stock),
+        };
+        if items.iter().map(shippable).sum::<i64>() < quantity {
+            return Err(insufficient());
+        }
+
+        let mut remaining = quantity;
+        for item in items.iter_mut() {
+            let shipped = remaining.min(shippable(item).max(0));
+            item.reserved -= shipped;
+            if item.stock_policy != DbStockPolicy::Unlimited {
+                item.in_stock -= shipped;
             }
-            Err(e) => Err(ShopsterError::DatabaseError(e)),
+            remaining -= shipped;
         }
+
+        let items = Self::save_changed_conn(conn, items, &original).await?;
+        for (item, before) in items.iter().zip(&original) {
+            DbStockMovement::record_conn(conn, product_id, item.location_id, DbStockMovementKind::Sale, item.in_stock - before.in_stock, reference).await?;
+            DbStockMovement::record_conn(conn, product_id, item.location_id, DbStockMovementKind::Release, item.reserved - before.reserved, reference).await?;
+        }
+        DbReorderPoint::alert_if_crossed_conn(conn, product_id, Self::available(&original), Self::available(&items)).await?;
+        Ok(items)
     }
 }
@@ -1,12 +1,26 @@
 use diesel::{Connection, sql_query, RunQueryDsl, PgConnection};
 
+pub mod dbaddress;
 pub mod dbbasket;
+pub mod dbcategory;
 pub mod dbcustomer;
 pub mod dbimage;
+pub mod dbinvoice;
+pub mod dblocation;
 pub mod dborder;
 pub mod dbproduct;
+pub mod dbproductvariant;
+pub mod dbpurchaseorder;
+pub mod dbrefund;
+pub mod dbreorderpoint;
+pub mod dbreturn;
+pub mod dbsequence;
 pub mod dbsettings;
+pub mod dbshipment;
+pub mod dbstockmovement;
+pub mod dbsupplier;
 pub mod dbtag;
+pub mod dbtranslation;
 pub mod dbwarehouse;
 
 pub struct DatabaseHelper;
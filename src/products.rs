@@ -1,9 +1,16 @@
 //! Product catalog management.
 
+use crate::aquire_pool;
 use crate::error::ShopsterError;
-use crate::postgresql::dbproduct::DbProduct;
+use crate::postgresql::dbimage::{DbAltText, DbProductImage};
+use crate::postgresql::dbproduct::{DbProduct, DbProductCursor, DbProductQuery, DbProductSearch, DbProductSort};
+use crate::postgresql::dbproductvariant::{DbProductVariant, DbVariantOption, DbVariantOptions};
+use crate::postgresql::dbtag::DbTag;
+use crate::postgresql::dbtranslation::{default_language, default_language_conn, fallback_languages, DbProductTranslation};
 use chrono::{NaiveDateTime, Utc};
+use diesel_async::{AsyncConnection, AsyncPgConnection};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
 use uuid::Uuid;
 
@@ -15,6 +22,17 @@ pub struct Price {
 }
 
 /// A product in the catalog.
+///
+/// `image_url` and `additional_images` list the URLs of the product's images
+/// in order; see `ProductImage` for their metadata. Inserting or updating a
+/// product makes its images match these lists.
+///
+/// `tags` holds the titles of the product's tags, ordered by title; see
+/// `Tags` to manage the tags themselves. Writing a product creates missing
+/// tags and ignores blank and repeated titles.
+///
+/// Title and descriptions are in the shop's default language (the `language`
+/// setting); see `ProductTranslation` for other languages.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Product {
     pub id: i64,
@@ -24,7 +42,9 @@ pub struct Product {
     pub short_description: String,
     pub description: String,
     pub tags: Vec<String>,
+    /// The URL of the first image; empty if the product has none.
     pub image_url: String,
+    /// The URLs of all further images.
     pub additional_images: Vec<String>,
     pub price: Option<Price>,
     pub weight: i64,
@@ -32,10 +52,52 @@ pub struct Product {
     pub updated_at: Option<NaiveDateTime>,
 }
 
-impl From<&DbProduct> for Product {
-    fn from(db_product: &DbProduct) -> Self {
-        let additional_images = db_product.additional_images.split('|').map(String::from).collect();
-        let tags = db_product.tags.split('|').map(String::from).collect();
+impl Product {
+    /// The URLs of all images in order, leaving out an empty `image_url`.
+    fn image_urls(&self) -> Vec<String> {
+        std::iter::once(&self.image_url)
+            .chain(self.additional_images.iter())
+            .filter(|url| !url.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// The trimmed tag titles without blank and repeated ones.
+    fn tag_titles(&self) -> Vec<String> {
+        let mut titles: Vec<String> = Vec::new();
+        for title in self.tags.iter().map(|title| title.trim()) {
+            if !title.is_empty() && !titles.iter().any(|existing| existing == title) {
+                titles.push(title.to_string());
+            }
+        }
+        titles
+    }
+
+    /// Replaces title and descriptions by their translation into the first of
+    /// `languages` that has one.
+    fn localize(&mut self, translations: &[DbProductTranslation], languages: &[String]) {
+        let translated = |field: fn(&DbProductTranslation) -> &Option<String>| {
+            languages.iter()
+                .filter_map(|language| translations.iter().find(|translation| &translation.language == language))
+                .find_map(|translation| field(translation).clone())
+        };
+        if let Some(title) = translated(|translation| &translation.title) {
+            self.title = title;
+        }
+        if let Some(short_description) = translated(|translation| &translation.short_description) {
+            self.short_description = short_description;
+        }
+        if let Some(description) = translated(|translation| &translation.description) {
+            self.description = description;
+        }
+    }
+}
+
+impl From<(&DbProduct, &[DbProductImage], &[DbTag])> for Product {
+    fn from((db_product, db_images, db_tags): (&DbProduct, &[DbProductImage], &[DbTag])) -> Self {
+        let image_url = db_images.first().map(|image| image.url.clone()).unwrap_or_default();
+        let additional_images = db_images.iter().skip(1).map(|image| image.url.clone()).collect();
+        let tags = db_tags.iter().map(|tag| tag.title.clone()).collect();
 
         Product {
             id: db_product.id,
@@ -44,7 +106,7 @@ impl From<&DbProduct> for Product {
             article_number: db_product.article_number.clone(),
             short_description: db_product.short_description.clone(),
             description: db_product.description.clone(),
-            image_url: db_product.title_image.clone(),
+            image_url,
             additional_images,
             tags,
             price: Some(Price {
@@ -77,15 +139,515 @@ impl TryFrom<&Product> for DbProduct {
             description: product.description.clone(),
             price: price.amount,
             currency: price.currency.clone(),
-            tags: product.tags.join("|"),
-            title_image: product.image_url.clone(),
-            additional_images: product.additional_images.join("|"),
             weight: product.weight as i32,
             created_at: Utc::now().naive_utc(),
             updated_at: Some(Utc::now().naive_utc())
         })
     }
 }
+/// Reads the images and tags of the given product rows and combines them into
+/// products.
+pub(crate) async fn load_products(tenant_id: Uuid, db_products: &[DbProduct]) -> Result<Vec<Product>, ShopsterError> {
+    let pool = aquire_pool(tenant_id).await?;
+    let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+    load_products_conn(&mut conn, db_products).await
+}
+
+pub(crate) async fn load_products_conn(conn: &mut AsyncPgConnection, db_products: &[DbProduct]) -> Result<Vec<Product>, ShopsterError> {
+    let product_ids: Vec<i64> = db_products.iter().map(|db_product| db_product.id).collect();
+    let images = DbProductImage::get_for_products_conn(conn, &product_ids).await?;
+    let tags = DbTag::get_for_products_conn(conn, &product_ids).await?;
+    Ok(combine(db_products, &images, &tags))
+}
+
+fn combine(db_products: &[DbProduct], images: &HashMap<i64, Vec<DbProductImage>>, tags: &HashMap<i64, Vec<DbTag>>) -> Vec<Product> {
+    db_products.iter()
+        .map(|db_product| {
+            let db_images = images.get(&db_product.id).map(Vec::as_slice).unwrap_or_default();
+            let db_tags = tags.get(&db_product.id).map(Vec::as_slice).unwrap_or_default();
+            Product::from((db_product, db_images, db_tags))
+        })
+        .collect()
+}
+
+/// An image of a product with its metadata. The images of a product are
+/// ordered by `position`, starting at `0`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ProductImage {
+    pub id: i64,
+    pub product_id: i64,
+    pub url: String,
+    pub position: i32,
+    /// Alternative text by language code, e.g. `de` and `en`.
+    pub alt_text: BTreeMap<String, String>,
+    /// In pixels.
+    pub width: Option<i32>,
+    /// In pixels.
+    pub height: Option<i32>,
+    /// E.g. `image/webp`.
+    pub mime_type: Option<String>,
+    /// A checksum of the image file, e.g. to detect changed uploads.
+    pub checksum: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbProductImage> for ProductImage {
+    fn from(db_image: &DbProductImage) -> Self {
+        ProductImage {
+            id: db_image.id,
+            product_id: db_image.product_id,
+            url: db_image.url.clone(),
+            position: db_image.position,
+            alt_text: db_image.alt_text.0.clone(),
+            width: db_image.width,
+            height: db_image.height,
+            mime_type: db_image.mime_type.clone(),
+            checksum: db_image.checksum.clone(),
+            created_at: db_image.created_at,
+            updated_at: db_image.updated_at,
+        }
+    }
+}
+
+impl From<&ProductImage> for DbProductImage {
+    fn from(image: &ProductImage) -> Self {
+        DbProductImage {
+            id: image.id,
+            product_id: image.product_id,
+            url: image.url.clone(),
+            position: image.position,
+            alt_text: DbAltText(image.alt_text.clone()),
+            width: image.width,
+            height: image.height,
+            mime_type: image.mime_type.clone(),
+            checksum: image.checksum.clone(),
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }
+    }
+}
+
+/// Checks that a language is a tag such as `de` or `de-CH`.
+pub(crate) fn validate_language(language: &str) -> Result<(), ShopsterError> {
+    let mut subtags = language.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    let valid = (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_lowercase())
+        && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()));
+    if !valid {
+        return Err(ShopsterError::InvalidOperationError(format!(
+            "Invalid language '{}': expected a tag such as 'de' or 'de-CH'", language
+        )));
+    }
+    Ok(())
+}
+
+/// The title and descriptions of a product in a language other than the
+/// shop's default language. A text left `None` falls back to the translation
+/// into the base language of a regional locale (e.g. `de` for `de-CH`) and
+/// then to the product's own text.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ProductTranslation {
+    pub product_id: i64,
+    /// E.g. `de` or `de-CH`.
+    pub language: String,
+    pub title: Option<String>,
+    pub short_description: Option<String>,
+    pub description: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbProductTranslation> for ProductTranslation {
+    fn from(db_translation: &DbProductTranslation) -> Self {
+        ProductTranslation {
+            product_id: db_translation.product_id,
+            language: db_translation.language.clone(),
+            title: db_translation.title.clone(),
+            short_description: db_translation.short_description.clone(),
+            description: db_translation.description.clone(),
+            created_at: db_translation.created_at,
+            updated_at: db_translation.updated_at,
+        }
+    }
+}
+
+impl From<&ProductTranslation> for DbProductTranslation {
+    fn from(translation: &ProductTranslation) -> Self {
+        DbProductTranslation {
+            product_id: translation.product_id,
+            language: translation.language.clone(),
+            title: translation.title.clone(),
+            short_description: translation.short_description.clone(),
+            description: translation.description.clone(),
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }
+    }
+}
+
+/// A single option that distinguishes a variant, e.g. `Size` = `M`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VariantOption {
+    pub name: String,
+    pub value: String
+}
+
+impl From<&DbVariantOption> for VariantOption {
+    fn from(option: &DbVariantOption) -> Self {
+        VariantOption {
+            name: option.name.clone(),
+            value: option.value.clone()
+        }
+    }
+}
+
+impl From<&VariantOption> for DbVariantOption {
+    fn from(option: &VariantOption) -> Self {
+        DbVariantOption {
+            name: option.name.clone(),
+            value: option.value.clone()
+        }
+    }
+}
+
+/// A sellable variant of a product, e.g. a shirt in size M and colour red.
+///
+/// Every variant is backed by a product of its own, and `id` is that product's
+/// id: warehouse rows, basket lines and order items refer to the variant by it,
+/// and `Products::get(id)` returns it with the effective price and weight.
+/// Title, descriptions, tags and images are always those of the parent.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProductVariant {
+    pub id: i64,
+    pub parent_id: i64,
+    pub article_number: String,
+    pub gtin: String,
+    /// Sorted by name.
+    pub options: Vec<VariantOption>,
+    /// Overrides the parent's price; `None` uses the parent's.
+    pub price: Option<Price>,
+    /// Overrides the parent's weight; `None` uses the parent's.
+    pub weight: Option<i64>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<(&DbProductVariant, &DbProduct)> for ProductVariant {
+    fn from((db_variant, db_product): (&DbProductVariant, &DbProduct)) -> Self {
+        let price = match (db_variant.price, &db_variant.currency) {
+            (Some(amount), Some(currency)) => Some(Price { amount, currency: currency.clone() }),
+            _ => None,
+        };
+
+        ProductVariant {
+            id: db_product.id,
+            parent_id: db_variant.parent_id,
+            article_number: db_product.article_number.clone(),
+            gtin: db_product.gtin.clone(),
+            options: db_variant.options.0.iter().map(VariantOption::from).collect(),
+            price,
+            weight: db_variant.weight.map(i64::from),
+            created_at: db_variant.created_at,
+            updated_at: db_variant.updated_at
+        }
+    }
+}
+
+impl From<&ProductVariant> for DbProductVariant {
+    fn from(variant: &ProductVariant) -> Self {
+        DbProductVariant {
+            product_id: variant.id,
+            parent_id: variant.parent_id,
+            options: DbVariantOptions::new(variant.options.iter().map(DbVariantOption::from).collect()),
+            price: variant.price.as_ref().map(|price| price.amount),
+            currency: variant.price.as_ref().map(|price| price.currency.clone()),
+            weight: variant.weight.map(|weight| weight as i32),
+            created_at: Utc::now().naive_utc(),
+            updated_at: Some(Utc::now().naive_utc())
+        }
+    }
+}
+
+/// The order of a product listing.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ProductSort {
+    /// Most recently created first.
+    #[default]
+    Newest,
+    PriceAscending,
+    PriceDescending,
+    /// Alphabetically by title.
+    Title,
+}
+
+impl From<ProductSort> for DbProductSort {
+    fn from(sort: ProductSort) -> Self {
+        match sort {
+            ProductSort::Newest => DbProductSort::Newest,
+            ProductSort::PriceAscending => DbProductSort::PriceAscending,
+            ProductSort::PriceDescending => DbProductSort::PriceDescending,
+            ProductSort::Title => DbProductSort::Title,
+        }
+    }
+}
+
+/// The position after the last product of a page. It is only obtained from
+/// `ProductPage::next_cursor`, but can be serialized to hand it to a client
+/// and back.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ProductCursor(DbProductCursor);
+
+/// A filtered and sorted listing of the catalog, read page by page; see
+/// `Products::query`.
+///
+/// ```ignore
+/// let query = ProductQuery::new()
+///     .tag("shoes")
+///     .max_price(10000)
+///     .in_stock_only()
+///     .sort(ProductSort::PriceAscending);
+/// let page = products.query(&query).await?;
+/// let next_page = match page.next_cursor {
+///     Some(cursor) => Some(products.query(&query.after(cursor)).await?),
+///     None => None,
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProductQuery {
+    tag: Option<String>,
+    min_price: Option<i64>,
+    max_price: Option<i64>,
+    currency: Option<String>,
+    in_stock_only: bool,
+    created_after: Option<NaiveDateTime>,
+    sort: ProductSort,
+    after: Option<ProductCursor>,
+    limit: i64,
+}
+
+impl Default for ProductQuery {
+    fn default() -> Self {
+        ProductQuery {
+            tag: None,
+            min_price: None,
+            max_price: None,
+            currency: None,
+            in_stock_only: false,
+            created_after: None,
+            sort: ProductSort::default(),
+            after: None,
+            limit: 50,
+        }
+    }
+}
+
+impl ProductQuery {
+    /// A query for the first 50 products, newest first.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only products carrying this tag.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Only products costing at least this amount, in cents.
+    pub fn min_price(mut self, amount: i64) -> Self {
+        self.min_price = Some(amount);
+        self
+    }
+
+    /// Only products costing at most this amount, in cents.
+    pub fn max_price(mut self, amount: i64) -> Self {
+        self.max_price = Some(amount);
+        self
+    }
+
+    /// Only products priced in this currency.
+    pub fn currency(mut self, currency: &str) -> Self {
+        self.currency = Some(currency.to_string());
+        self
+    }
+
+    /// Only products that are available, themselves or in one of their
+    /// variants: they have free stock at any location, or a stock policy
+    /// other than `Deny`. This matches the availability facet of `search`.
+    pub fn in_stock_only(mut self) -> Self {
+        self.in_stock_only = true;
+        self
+    }
+
+    /// Only products created after this point in time.
+    pub fn created_after(mut self, created_after: NaiveDateTime) -> Self {
+        self.created_after = Some(created_after);
+        self
+    }
+
+    pub fn sort(mut self, sort: ProductSort) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// The maximum number of products per page.
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Continues the listing after the page the cursor was returned with.
+    pub fn after(mut self, cursor: ProductCursor) -> Self {
+        self.after = Some(cursor);
+        self
+    }
+}
+
+impl From<&ProductQuery> for DbProductQuery {
+    fn from(query: &ProductQuery) -> Self {
+        DbProductQuery {
+            tag: query.tag.clone(),
+            min_price: query.min_price,
+            max_price: query.max_price,
+            currency: query.currency.clone(),
+            in_stock_only: query.in_stock_only,
+            created_after: query.created_after,
+            sort: DbProductSort::from(query.sort),
+            after: query.after.as_ref().map(|cursor| cursor.0.clone()),
+            limit: query.limit,
+        }
+    }
+}
+
+/// One page of a product listing.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProductPage {
+    pub products: Vec<Product>,
+    /// Continues the listing with `ProductQuery::after`; `None` on the last
+    /// page.
+    pub next_cursor: Option<ProductCursor>,
+}
+
+/// A full-text search over the catalog; see `Products::search`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProductSearch {
+    /// Search terms in web search syntax, e.g. `"running shoes" -kids`.
+    pub query: String,
+    /// Zero-based page number.
+    pub page: i64,
+    pub per_page: i64,
+    /// Ascending bounds of the price range facets, in cents. `[1000, 5000]`
+    /// yields the ranges below 1000, from 1000 below 5000, and 5000 and above.
+    pub price_bounds: Vec<i64>,
+}
+
+impl ProductSearch {
+    /// A search for the first page of 20 hits with price ranges of 0-10,
+    /// 10-25, 25-50, 50-100 and over 100 currency units.
+    pub fn new(query: &str) -> Self {
+        ProductSearch {
+            query: query.to_string(),
+            page: 0,
+            per_page: 20,
+            price_bounds: vec![1000, 2500, 5000, 10000],
+        }
+    }
+}
+
+/// A product found by a search, with its relevance.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProductSearchHit {
+    pub product: Product,
+    pub rank: f32,
+}
+
+/// The number of matches carrying a tag.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TagFacet {
+    pub tag: String,
+    pub count: i64,
+}
+
+/// The number of matches in a price range.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PriceRangeFacet {
+    /// Inclusive; `None` for the lowest range.
+    pub min: Option<i64>,
+    /// Exclusive; `None` for the highest range.
+    pub max: Option<i64>,
+    pub count: i64,
+}
+
+/// The number of matches that can or cannot be ordered right now. A product
+/// is available if it or one of its variants has free stock at any location,
+/// or has a stock policy other than `Deny`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AvailabilityFacet {
+    pub available: i64,
+    pub unavailable: i64,
+}
+
+/// Facet counts over all matches of a search, not just the returned page.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchFacets {
+    /// Most frequent first.
+    pub tags: Vec<TagFacet>,
+    /// One entry per range, including empty ones, cheapest first.
+    pub price_ranges: Vec<PriceRangeFacet>,
+    pub availability: AvailabilityFacet,
+}
+
+/// One page of search results.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProductSearchResult {
+    /// Best match first.
+    pub hits: Vec<ProductSearchHit>,
+    /// Number of matches over all pages.
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+    pub facets: SearchFacets,
+}
+
+/// Built from the search, the request and the products of the hits in the
+/// same order.
+impl From<(&DbProductSearch, &ProductSearch, Vec<Product>)> for ProductSearchResult {
+    fn from((db_search, search, products): (&DbProductSearch, &ProductSearch, Vec<Product>)) -> Self {
+        let hits = db_search.hits.iter().zip(products)
+            .map(|(hit, product)| ProductSearchHit { product, rank: hit.rank })
+            .collect();
+        let tags = db_search.tags.iter()
+            .map(|tag| TagFacet { tag: tag.tag.clone(), count: tag.count })
+            .collect();
+        let price_ranges = (0..=search.price_bounds.len())
+            .map(|bucket| PriceRangeFacet {
+                min: bucket.checked_sub(1).map(|i| search.price_bounds[i]),
+                max: search.price_bounds.get(bucket).copied(),
+                count: db_search.price_buckets.iter()
+                    .find(|db_bucket| db_bucket.bucket as usize == bucket)
+                    .map_or(0, |db_bucket| db_bucket.count),
+            })
+            .collect();
+
+        ProductSearchResult {
+            hits,
+            total: db_search.total,
+            page: search.page,
+            per_page: search.per_page,
+            facets: SearchFacets {
+                tags,
+                price_ranges,
+                availability: AvailabilityFacet {
+                    available: db_search.available,
+                    unavailable: db_search.total - db_search.available,
+                },
+            },
+        }
+    }
+}
 
 
 /// Handler for product management operations.
@@ -98,16 +660,98 @@ impl Products {
         Products { tenant_id }
     }
 
+    /// Returns all products except variants; see `get_variants`.
     pub async fn get_all(&self) -> Result<Vec<Product>, ShopsterError> {
         let db_products = DbProduct::get_all(self.tenant_id).await?;
-        let products = db_products.iter().map(Product::from).collect();
-        Ok(products)
+        load_products(self.tenant_id, &db_products).await
+    }
+
+    /// Returns all products except variants with their texts in `locale`,
+    /// falling back to the base language of a regional locale and then to
+    /// the default language.
+    pub async fn get_all_localized(&self, locale: &str) -> Result<Vec<Product>, ShopsterError> {
+        validate_language(locale)?;
+        let products = self.get_all().await?;
+        self.localize(products, locale).await
+    }
+
+    /// Returns one page of the products matching the query, except variants.
+    /// Pages are read with a cursor instead of an offset, so that deep pages
+    /// of large catalogs are as cheap as the first and concurrent inserts do
+    /// not shift products between pages.
+    pub async fn query(&self, query: &ProductQuery) -> Result<ProductPage, ShopsterError> {
+        if query.limit <= 0 {
+            return Err(ShopsterError::InvalidOperationError(
+                "A product query needs a limit of at least one".to_string(),
+            ));
+        }
+        if query.min_price.zip(query.max_price).is_some_and(|(min_price, max_price)| min_price > max_price) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Minimum price cannot exceed the maximum price".to_string(),
+            ));
+        }
+
+        // One more than requested tells whether there is another page
+        let mut db_query = DbProductQuery::from(query);
+        db_query.limit = query.limit + 1;
+        let mut db_products = DbProduct::query(self.tenant_id, &db_query).await?;
+
+        let next_cursor = if db_products.len() as i64 > query.limit {
+            db_products.truncate(query.limit as usize);
+            db_products.last().map(|db_product| ProductCursor(DbProductCursor::from(db_product)))
+        } else {
+            None
+        };
+
+        Ok(ProductPage {
+            products: load_products(self.tenant_id, &db_products).await?,
+            next_cursor,
+        })
+    }
+
+    /// Searches article number, title, tags and descriptions of all products
+    /// except variants, using the German or English dictionary according to
+    /// the `language` setting. Returns the requested page of hits, best first,
+    /// with facet counts over all matches.
+    pub async fn search(&self, search: &ProductSearch) -> Result<ProductSearchResult, ShopsterError> {
+        if search.query.trim().is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Search query cannot be empty".to_string(),
+            ));
+        }
+        if search.page < 0 || search.per_page <= 0 {
+            return Err(ShopsterError::InvalidOperationError(
+                "Search page cannot be negative and needs at least one hit per page".to_string(),
+            ));
+        }
+        if search.price_bounds.windows(2).any(|bounds| bounds[0] >= bounds[1]) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Price bounds must be strictly ascending".to_string(),
+            ));
+        }
+
+        let offset = search.page.checked_mul(search.per_page)
+            .ok_or_else(|| ShopsterError::InvalidOperationError("Search page is out of range".to_string()))?;
+        let db_search = DbProduct::search(self.tenant_id, &search.query, search.per_page, offset, &search.price_bounds).await?;
+        let db_products: Vec<DbProduct> = db_search.hits.iter().map(|hit| hit.product.clone()).collect();
+        let products = load_products(self.tenant_id, &db_products).await?;
+        Ok(ProductSearchResult::from((&db_search, search, products)))
     }
 
     pub async fn get(&self, product_id: i64) -> Result<Product, ShopsterError> {
         let db_product = DbProduct::find(self.tenant_id, product_id).await?;
-        let product = Product::from(&db_product);
-        Ok(product)
+        let mut products = load_products(self.tenant_id, &[db_product]).await?;
+        Ok(products.remove(0))
+    }
+
+    /// Returns a product with its texts in `locale`; see `get_all_localized`.
+    /// The result is meant for display. To change the product, update the
+    /// one returned by `get`, or its translations.
+    pub async fn get_localized(&self, product_id: i64, locale: &str) -> Result<Product, ShopsterError> {
+        validate_language(locale)?;
+        let product = self.get(product_id).await?;
+        let mut products = self.localize(vec![product], locale).await?;
+        Ok(products.remove(0))
     }
 
     pub async fn insert(&self, product: &Product) -> Result<Product, ShopsterError> {
@@ -124,10 +768,16 @@ impl Products {
             }
         }
         let db_product = DbProduct::try_from(product)?;
-        let created_product = DbProduct::create(self.tenant_id, db_product).await?;
 
-        let reply = Product::from(&created_product);
-        Ok(reply)
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let created_product = DbProduct::create_conn(conn, db_product).await?;
+            let db_images = DbProductImage::set_urls_conn(conn, created_product.id, &product.image_urls()).await?;
+            let db_tags = DbTag::set_for_product_conn(conn, created_product.id, &product.tag_titles()).await?;
+            Ok(Product::from((&created_product, db_images.as_slice(), db_tags.as_slice())))
+        }).await
     }
 
     pub async fn update(&self, product: &Product) -> Result<Product, ShopsterError> {
@@ -144,14 +794,325 @@ impl Products {
             }
         }
         let db_product = DbProduct::try_from(product)?;
-        let updated_product = DbProduct::update(self.tenant_id, product.id, db_product).await?;
 
-        let reply = Product::from(&updated_product);
-        Ok(reply)
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let updated_product = DbProduct::update_conn(conn, product.id, db_product).await?;
+
+            // Variants follow their parent except for their own overrides
+            for db_variant in DbProductVariant::get_for_parent_conn(conn, updated_product.id).await? {
+                let variant_product = DbProduct::find_conn(conn, db_variant.product_id).await?;
+                let variant_product = Self::variant_product(&updated_product, &variant_product.article_number, &variant_product.gtin, &db_variant, variant_product.created_at);
+                DbProduct::update_conn(conn, db_variant.product_id, variant_product).await?;
+            }
+
+            // Variants show the images and tags of their parent and have none
+            // to update
+            if DbProductVariant::find_conn(conn, updated_product.id).await?.is_none() {
+                DbProductImage::set_urls_conn(conn, updated_product.id, &product.image_urls()).await?;
+                DbTag::set_for_product_conn(conn, updated_product.id, &product.tag_titles()).await?;
+            }
+
+            let mut products = load_products_conn(conn, &[updated_product]).await?;
+            Ok(products.remove(0))
+        }).await
     }
 
+    /// Removes a product together with all of its variants.
     pub async fn remove(&self, product_id: i64) -> Result<bool, ShopsterError> {
-        let result = DbProduct::delete(self.tenant_id, product_id).await?;
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            for db_variant in DbProductVariant::get_for_parent_conn(conn, product_id).await? {
+                DbProduct::delete_conn(conn, db_variant.product_id).await?;
+            }
+            let result = DbProduct::delete_conn(conn, product_id).await?;
+            Ok(result > 0)
+        }).await
+    }
+
+    /// Returns the images of a product in order. Variants have the images of
+    /// their parent.
+    pub async fn get_images(&self, product_id: i64) -> Result<Vec<ProductImage>, ShopsterError> {
+        DbProduct::find(self.tenant_id, product_id).await?;
+        let mut db_images = DbProductImage::get_for_products(self.tenant_id, &[product_id]).await?;
+        let db_images = db_images.remove(&product_id).unwrap_or_default();
+        Ok(db_images.iter().map(ProductImage::from).collect())
+    }
+
+    /// Adds an image after the existing images of `image.product_id`; its
+    /// `position` is ignored. Images are added to the parent of a variant.
+    pub async fn add_image(&self, image: &ProductImage) -> Result<ProductImage, ShopsterError> {
+        Self::validate_image(image)?;
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            if DbProductVariant::find_conn(conn, image.product_id).await?.is_some() {
+                return Err(ShopsterError::InvalidOperationError(
+                    "Variants have the images of their parent".to_string(),
+                ));
+            }
+            let created_image = DbProductImage::create_conn(conn, DbProductImage::from(image)).await?;
+            Ok(ProductImage::from(&created_image))
+        }).await
+    }
+
+    /// Updates the URL and metadata of an image. Use `reorder_images` to move
+    /// it.
+    pub async fn update_image(&self, image: &ProductImage) -> Result<ProductImage, ShopsterError> {
+        Self::validate_image(image)?;
+        let updated_image = DbProductImage::update(self.tenant_id, image.id, DbProductImage::from(image)).await?;
+        Ok(ProductImage::from(&updated_image))
+    }
+
+    /// Puts the images of a product into the order of `image_ids`, which must
+    /// list each of its images exactly once.
+    pub async fn reorder_images(&self, product_id: i64, image_ids: &[i64]) -> Result<Vec<ProductImage>, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let db_images = DbProductImage::reorder_conn(conn, product_id, image_ids).await?;
+            Ok(db_images.iter().map(ProductImage::from).collect())
+        }).await
+    }
+
+    /// Removes an image; the images after it move up by one.
+    pub async fn remove_image(&self, image_id: i64) -> Result<bool, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let result = DbProductImage::delete_conn(conn, image_id).await?;
+            Ok(result > 0)
+        }).await
+    }
+
+    /// Returns the translations of a product ordered by language.
+    pub async fn get_translations(&self, product_id: i64) -> Result<Vec<ProductTranslation>, ShopsterError> {
+        let db_translations = DbProductTranslation::get_for_product(self.tenant_id, product_id).await?;
+        Ok(db_translations.iter().map(ProductTranslation::from).collect())
+    }
+
+    /// Creates or replaces the translation of a product into
+    /// `translation.language`. Variants are translated through their parent,
+    /// and texts in the default language are those of the product itself.
+    pub async fn set_translation(&self, translation: &ProductTranslation) -> Result<ProductTranslation, ShopsterError> {
+        validate_language(&translation.language)?;
+        if translation.title.as_ref().is_some_and(|title| title.trim().is_empty()) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Product title cannot be empty".to_string(),
+            ));
+        }
+        if default_language(self.tenant_id).await?.as_ref() == Some(&translation.language) {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Texts in the default language '{}' are set on the product itself",
+                translation.language
+            )));
+        }
+        if DbProductVariant::is_variant(self.tenant_id, translation.product_id).await? {
+            return Err(ShopsterError::InvalidOperationError(
+                "Variants have the translations of their parent".to_string(),
+            ));
+        }
+        DbProduct::find(self.tenant_id, translation.product_id).await?;
+
+        let db_translation = DbProductTranslation::upsert(self.tenant_id, DbProductTranslation::from(translation)).await?;
+        Ok(ProductTranslation::from(&db_translation))
+    }
+
+    pub async fn remove_translation(&self, product_id: i64, language: &str) -> Result<bool, ShopsterError> {
+        let result = DbProductTranslation::delete(self.tenant_id, product_id, language).await?;
+        Ok(result > 0)
+    }
+
+    /// Returns the variants of a product, oldest first.
+    pub async fn get_variants(&self, product_id: i64) -> Result<Vec<ProductVariant>, ShopsterError> {
+        let db_variants = DbProductVariant::get_for_parent(self.tenant_id, product_id).await?;
+        let mut variants = Vec::new();
+        for db_variant in &db_variants {
+            let db_product = DbProduct::find(self.tenant_id, db_variant.product_id).await?;
+            variants.push(ProductVariant::from((db_variant, &db_product)));
+        }
+        Ok(variants)
+    }
+
+    pub async fn get_variant(&self, variant_id: i64) -> Result<ProductVariant, ShopsterError> {
+        let db_variant = DbProductVariant::find(self.tenant_id, variant_id).await?;
+        let db_product = DbProduct::find(self.tenant_id, variant_id).await?;
+        Ok(ProductVariant::from((&db_variant, &db_product)))
+    }
+
+    /// Finds the variant of a product with exactly the given options, in any
+    /// order.
+    pub async fn find_variant(&self, product_id: i64, options: &[VariantOption]) -> Result<Option<ProductVariant>, ShopsterError> {
+        let options = DbVariantOptions::new(options.iter().map(DbVariantOption::from).collect());
+        match DbProductVariant::find_by_options(self.tenant_id, product_id, &options).await? {
+            Some(db_variant) => {
+                let db_product = DbProduct::find(self.tenant_id, db_variant.product_id).await?;
+                Ok(Some(ProductVariant::from((&db_variant, &db_product))))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Adds a variant to the product `variant.parent_id`. Variants cannot have
+    /// variants of their own, and the options must differ from those of every
+    /// other variant of the product.
+    pub async fn insert_variant(&self, variant: &ProductVariant) -> Result<ProductVariant, ShopsterError> {
+        Self::validate_variant(variant)?;
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let parent = Self::find_parent_conn(conn, variant.parent_id).await?;
+            let db_variant = DbProductVariant::from(variant);
+            let variant_product = Self::variant_product(&parent, &variant.article_number, &variant.gtin, &db_variant, Utc::now().naive_utc());
+            let created_product = DbProduct::create_conn(conn, variant_product).await?;
+
+            let db_variant = DbProductVariant { product_id: created_product.id, updated_at: None, ..db_variant };
+            let created_variant = DbProductVariant::create_conn(conn, db_variant).await?;
+            Ok(ProductVariant::from((&created_variant, &created_product)))
+        }).await
+    }
+
+    /// Updates SKU, GTIN, options and overrides of a variant. A variant cannot
+    /// be moved to another parent.
+    pub async fn update_variant(&self, variant: &ProductVariant) -> Result<ProductVariant, ShopsterError> {
+        Self::validate_variant(variant)?;
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let existing = DbProductVariant::find_conn(conn, variant.id).await?
+                .ok_or_else(|| ShopsterError::InvalidOperationError(format!("Product {} is not a variant", variant.id)))?;
+            if existing.parent_id != variant.parent_id {
+                return Err(ShopsterError::InvalidOperationError(
+                    "The parent of a variant cannot be changed".to_string(),
+                ));
+            }
+
+            let parent = DbProduct::find_conn(conn, existing.parent_id).await?;
+            let existing_product = DbProduct::find_conn(conn, variant.id).await?;
+            let db_variant = DbProductVariant { created_at: existing.created_at, ..DbProductVariant::from(variant) };
+            let variant_product = Self::variant_product(&parent, &variant.article_number, &variant.gtin, &db_variant, existing_product.created_at);
+            let updated_product = DbProduct::update_conn(conn, variant.id, variant_product).await?;
+            let updated_variant = DbProductVariant::update_conn(conn, variant.id, db_variant).await?;
+            Ok(ProductVariant::from((&updated_variant, &updated_product)))
+        }).await
+    }
+
+    pub async fn remove_variant(&self, variant_id: i64) -> Result<bool, ShopsterError> {
+        DbProductVariant::find(self.tenant_id, variant_id).await?;
+        let result = DbProduct::delete(self.tenant_id, variant_id).await?;
         Ok(result > 0)
     }
+
+    /// Replaces the texts of the products by their translations for `locale`.
+    async fn localize(&self, mut products: Vec<Product>, locale: &str) -> Result<Vec<Product>, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let default_language = default_language_conn(&mut conn).await?;
+        let languages = fallback_languages(locale, default_language.as_deref());
+        let product_ids: Vec<i64> = products.iter().map(|product| product.id).collect();
+        let translations = DbProductTranslation::get_for_products_conn(&mut conn, &product_ids, &languages).await?;
+        for product in &mut products {
+            if let Some(db_translations) = translations.get(&product.id) {
+                product.localize(db_translations, &languages);
+            }
+        }
+        Ok(products)
+    }
+
+    async fn find_parent_conn(conn: &mut AsyncPgConnection, parent_id: i64) -> Result<DbProduct, ShopsterError> {
+        let parent = DbProduct::find_conn(conn, parent_id).await?;
+        if DbProductVariant::find_conn(conn, parent_id).await?.is_some() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Variants cannot have variants".to_string(),
+            ));
+        }
+        Ok(parent)
+    }
+
+    /// Builds the product row of a variant from its parent and its overrides.
+    /// `created_at` is that of the variant's own row, not of its parent.
+    fn variant_product(parent: &DbProduct, article_number: &str, gtin: &str, db_variant: &DbProductVariant, created_at: NaiveDateTime) -> DbProduct {
+        DbProduct {
+            id: db_variant.product_id,
+            article_number: article_number.to_string(),
+            gtin: gtin.to_string(),
+            price: db_variant.price.unwrap_or(parent.price),
+            currency: db_variant.currency.clone().unwrap_or_else(|| parent.currency.clone()),
+            weight: db_variant.weight.unwrap_or(parent.weight),
+            created_at,
+            updated_at: Some(Utc::now().naive_utc()),
+            ..parent.clone()
+        }
+    }
+
+    fn validate_image(image: &ProductImage) -> Result<(), ShopsterError> {
+        if image.url.trim().is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Image URL cannot be empty".to_string(),
+            ));
+        }
+        if image.width.is_some_and(|width| width <= 0) || image.height.is_some_and(|height| height <= 0) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Image width and height must be positive".to_string(),
+            ));
+        }
+        if image.mime_type.as_ref().is_some_and(|mime_type| !mime_type.starts_with("image/")) {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Invalid image MIME type '{}'",
+                image.mime_type.as_deref().unwrap_or_default()
+            )));
+        }
+        if image.alt_text.keys().any(|language| language.trim().is_empty()) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Alternative texts need a language".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_variant(variant: &ProductVariant) -> Result<(), ShopsterError> {
+        if variant.options.is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "A variant needs at least one option".to_string(),
+            ));
+        }
+        let mut names = HashSet::new();
+        for option in &variant.options {
+            if option.name.trim().is_empty() || option.value.trim().is_empty() {
+                return Err(ShopsterError::InvalidOperationError(
+                    "Variant option names and values cannot be empty".to_string(),
+                ));
+            }
+            if !names.insert(option.name.as_str()) {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Variant option {} is given more than once",
+                    option.name
+                )));
+            }
+        }
+        if variant.price.as_ref().is_some_and(|price| price.amount < 0) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Product price cannot be negative".to_string(),
+            ));
+        }
+        if variant.weight.is_some_and(|weight| weight < 0) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Product weight cannot be negative".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,365 @@
+//! Purchase orders and goods receipt.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use uuid::Uuid;
+use chrono::{NaiveDateTime, Utc};
+
+use diesel_async::AsyncConnection;
+
+use crate::aquire_pool;
+use crate::error::ShopsterError;
+use crate::postgresql::dbpurchaseorder::{DbPurchaseOrder, DbPurchaseOrderItem, DbPurchaseOrderStatus};
+use crate::postgresql::dbstockmovement::{DbMovementReference, DbStockMovementKind};
+use crate::postgresql::dbwarehouse::DbWarehouse;
+
+/// The lifecycle status of a purchase order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PurchaseOrderStatus {
+    /// Being prepared; items can still be changed.
+    Draft,
+    /// Sent to the supplier.
+    Ordered,
+    /// Some of the ordered goods have arrived.
+    PartiallyReceived,
+    /// All ordered goods have arrived.
+    Received,
+}
+
+impl fmt::Display for PurchaseOrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<DbPurchaseOrderStatus> for PurchaseOrderStatus {
+    fn from(status: DbPurchaseOrderStatus) -> Self {
+        match status {
+            DbPurchaseOrderStatus::Draft => PurchaseOrderStatus::Draft,
+            DbPurchaseOrderStatus::Ordered => PurchaseOrderStatus::Ordered,
+            DbPurchaseOrderStatus::PartiallyReceived => PurchaseOrderStatus::PartiallyReceived,
+            DbPurchaseOrderStatus::Received => PurchaseOrderStatus::Received,
+        }
+    }
+}
+
+impl From<PurchaseOrderStatus> for DbPurchaseOrderStatus {
+    fn from(status: PurchaseOrderStatus) -> Self {
+        match status {
+            PurchaseOrderStatus::Draft => DbPurchaseOrderStatus::Draft,
+            PurchaseOrderStatus::Ordered => DbPurchaseOrderStatus::Ordered,
+            PurchaseOrderStatus::PartiallyReceived => DbPurchaseOrderStatus::PartiallyReceived,
+            PurchaseOrderStatus::Received => DbPurchaseOrderStatus::Received,
+        }
+    }
+}
+
+/// A product line of a purchase order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurchaseOrderItem {
+    pub id: i64,
+    pub product_id: i64,
+    pub quantity: i64,
+    /// Units that have arrived so far. Only advanced by `PurchaseOrders::receive`.
+    pub received_quantity: i64,
+}
+
+impl PurchaseOrderItem {
+    /// Units still expected from the supplier.
+    pub fn open_quantity(&self) -> i64 {
+        self.quantity - self.received_quantity
+    }
+}
+
+impl From<&DbPurchaseOrderItem> for PurchaseOrderItem {
+    fn from(db_item: &DbPurchaseOrderItem) -> Self {
+        PurchaseOrderItem {
+            id: db_item.id,
+            product_id: db_item.product_id,
+            quantity: db_item.quantity,
+            received_quantity: db_item.received_quantity,
+        }
+    }
+}
+
+impl From<&PurchaseOrderItem> for DbPurchaseOrderItem {
+    fn from(item: &PurchaseOrderItem) -> Self {
+        DbPurchaseOrderItem {
+            id: item.id,
+            purchase_order_id: 0,
+            product_id: item.product_id,
+            quantity: item.quantity,
+            received_quantity: 0,
+        }
+    }
+}
+
+/// An order for goods placed with a supplier.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PurchaseOrder {
+    pub id: i64,
+    pub supplier_id: i64,
+    /// Where the goods are received; the default location if `None`.
+    pub location_id: Option<i64>,
+    pub status: PurchaseOrderStatus,
+    pub items: Vec<PurchaseOrderItem>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    pub ordered_at: Option<NaiveDateTime>,
+    pub received_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbPurchaseOrder> for PurchaseOrder {
+    fn from(db_purchase_order: &DbPurchaseOrder) -> Self {
+        PurchaseOrder {
+            id: db_purchase_order.id,
+            supplier_id: db_purchase_order.supplier_id,
+            location_id: db_purchase_order.location_id,
+            status: db_purchase_order.status.into(),
+            items: Vec::new(),
+            created_at: db_purchase_order.created_at,
+            updated_at: db_purchase_order.updated_at,
+            ordered_at: db_purchase_order.ordered_at,
+            received_at: db_purchase_order.received_at,
+        }
+    }
+}
+
+/// Goods that arrived for a line of a purchase order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoodsReceiptLine {
+    pub purchase_order_item_id: i64,
+    pub quantity: i64,
+}
+
+/// Handler for purchase orders.
+///
+/// A purchase order is prepared as a `Draft`, placed with the supplier
+/// (`Ordered`) and then received in one or more deliveries, moving to
+/// `PartiallyReceived` and finally `Received`. Receiving goods adds them to
+/// `warehouse.in_stock` and records `GoodsReceipt` movements referencing the
+/// purchase order in the same transaction.
+pub struct PurchaseOrders {
+    tenant_id: Uuid
+}
+
+impl PurchaseOrders {
+    pub fn new(tenant_id: Uuid) -> Self {
+        PurchaseOrders { tenant_id }
+    }
+
+    pub async fn get_all(&self) -> Result<Vec<PurchaseOrder>, ShopsterError> {
+        let db_purchase_orders = DbPurchaseOrder::get_all(self.tenant_id).await?;
+        self.with_items(db_purchase_orders).await
+    }
+
+    pub async fn get(&self, purchase_order_id: i64) -> Result<PurchaseOrder, ShopsterError> {
+        let db_purchase_order = DbPurchaseOrder::find(self.tenant_id, purchase_order_id).await?;
+        Ok(self.with_items(vec![db_purchase_order]).await?.remove(0))
+    }
+
+    pub async fn get_for_supplier(&self, supplier_id: i64) -> Result<Vec<PurchaseOrder>, ShopsterError> {
+        let db_purchase_orders = DbPurchaseOrder::get_for_supplier(self.tenant_id, supplier_id).await?;
+        self.with_items(db_purchase_orders).await
+    }
+
+    async fn with_items(&self, db_purchase_orders: Vec<DbPurchaseOrder>) -> Result<Vec<PurchaseOrder>, ShopsterError> {
+        let mut purchase_orders = Vec::new();
+        for db_purchase_order in db_purchase_orders {
+            let db_items = DbPurchaseOrderItem::get_for_purchase_order(self.tenant_id, db_purchase_order.id).await?;
+            let mut purchase_order = PurchaseOrder::from(&db_purchase_order);
+            purchase_order.items = db_items.iter().map(PurchaseOrderItem::from).collect();
+            purchase_orders.push(purchase_order);
+        }
+        Ok(purchase_orders)
+    }
+
+    fn validate_items(items: &[PurchaseOrderItem]) -> Result<(), ShopsterError> {
+        if items.is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Purchase order must contain at least one item".to_string(),
+            ));
+        }
+        if items.iter().any(|item| item.quantity <= 0) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Quantity must be positive".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates a purchase order as a `Draft`, whatever status is passed in.
+    pub async fn insert(&self, purchase_order: &PurchaseOrder) -> Result<PurchaseOrder, ShopsterError> {
+        Self::validate_items(&purchase_order.items)?;
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_items_input: Vec<DbPurchaseOrderItem> = purchase_order.items.iter().map(DbPurchaseOrderItem::from).collect();
+
+        conn.transaction(async |conn| {
+            let now = Utc::now().naive_utc();
+            let created = DbPurchaseOrder::create_conn(conn, DbPurchaseOrder {
+                id: 0,
+                supplier_id: purchase_order.supplier_id,
+                location_id: purchase_order.location_id,
+                status: DbPurchaseOrderStatus::Draft,
+                created_at: now,
+                updated_at: Some(now),
+                ordered_at: None,
+                received_at: None,
+            }).await?;
+
+            let db_items: Vec<DbPurchaseOrderItem> = db_items_input.into_iter()
+                .map(|item| DbPurchaseOrderItem { purchase_order_id: created.id, ..item })
+                .collect();
+            let created_items = DbPurchaseOrderItem::create_for_purchase_order_conn(conn, db_items).await?;
+
+            let mut reply = PurchaseOrder::from(&created);
+            reply.items = created_items.iter().map(PurchaseOrderItem::from).collect();
+            Ok(reply)
+        }).await
+    }
+
+    /// Replaces the supplier, location and items of a `Draft` purchase order.
+    pub async fn update(&self, purchase_order: &PurchaseOrder) -> Result<PurchaseOrder, ShopsterError> {
+        Self::validate_items(&purchase_order.items)?;
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_items_input: Vec<DbPurchaseOrderItem> = purchase_order.items.iter().map(DbPurchaseOrderItem::from).collect();
+
+        conn.transaction(async |conn| {
+            let existing = DbPurchaseOrder::find_for_update_conn(conn, purchase_order.id).await?;
+            Self::ensure_draft(&existing)?;
+
+            let updated = DbPurchaseOrder::update_conn(conn, existing.id, purchase_order.supplier_id, purchase_order.location_id).await?;
+            DbPurchaseOrderItem::delete_for_purchase_order_conn(conn, existing.id).await?;
+            let db_items: Vec<DbPurchaseOrderItem> = db_items_input.into_iter()
+                .map(|item| DbPurchaseOrderItem { purchase_order_id: existing.id, ..item })
+                .collect();
+            let created_items = DbPurchaseOrderItem::create_for_purchase_order_conn(conn, db_items).await?;
+
+            let mut reply = PurchaseOrder::from(&updated);
+            reply.items = created_items.iter().map(PurchaseOrderItem::from).collect();
+            Ok(reply)
+        }).await
+    }
+
+    /// Places a `Draft` purchase order with its supplier.
+    pub async fn place(&self, purchase_order_id: i64) -> Result<PurchaseOrder, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let existing = DbPurchaseOrder::find_for_update_conn(conn, purchase_order_id).await?;
+            Self::ensure_draft(&existing)?;
+
+            let updated = DbPurchaseOrder::update_status_conn(conn, existing.id, DbPurchaseOrderStatus::Ordered).await?;
+            let db_items = DbPurchaseOrderItem::get_for_purchase_order_conn(conn, existing.id).await?;
+
+            let mut reply = PurchaseOrder::from(&updated);
+            reply.items = db_items.iter().map(PurchaseOrderItem::from).collect();
+            Ok(reply)
+        }).await
+    }
+
+    /// Books goods that arrived for a purchase order that is `Ordered` or
+    /// `PartiallyReceived`.
+    ///
+    /// Every line must reference an item of the purchase order, and no more
+    /// than its open quantity can be received. The goods are added to the
+    /// stock at the purchase order's location, allocated to open backorders
+    /// and recorded as `GoodsReceipt` movements, all in one transaction. The
+    /// purchase order moves to `Received` once every item has fully arrived.
+    pub async fn receive(&self, purchase_order_id: i64, lines: &[GoodsReceiptLine]) -> Result<PurchaseOrder, ShopsterError> {
+        if lines.is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Goods receipt must contain at least one line".to_string(),
+            ));
+        }
+        if lines.iter().any(|line| line.quantity <= 0) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Quantity must be positive".to_string(),
+            ));
+        }
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let existing = DbPurchaseOrder::find_for_update_conn(conn, purchase_order_id).await?;
+            let status: PurchaseOrderStatus = existing.status.into();
+            if !matches!(status, PurchaseOrderStatus::Ordered | PurchaseOrderStatus::PartiallyReceived) {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Cannot receive goods for a purchase order in status {}",
+                    status
+                )));
+            }
+
+            let db_items = DbPurchaseOrderItem::get_for_purchase_order_conn(conn, existing.id).await?;
+
+            // Keyed by product so warehouse rows are locked in a stable order
+            let mut received: BTreeMap<(i64, i64), i64> = BTreeMap::new();
+            for line in lines {
+                let item = db_items.iter()
+                    .find(|item| item.id == line.purchase_order_item_id)
+                    .ok_or_else(|| ShopsterError::InvalidOperationError(format!(
+                        "Item {} does not belong to purchase order {}",
+                        line.purchase_order_item_id, existing.id
+                    )))?;
+
+                let total = received.entry((item.product_id, item.id)).or_insert(0);
+                *total += line.quantity;
+                if *total > item.quantity - item.received_quantity {
+                    return Err(ShopsterError::InvalidOperationError(format!(
+                        "Received quantity for item {} exceeds its open quantity",
+                        item.id
+                    )));
+                }
+            }
+
+            let reference = DbMovementReference::purchase_order(existing.id);
+            for ((product_id, item_id), quantity) in received {
+                DbPurchaseOrderItem::apply_received_delta_conn(conn, item_id, quantity).await?;
+                DbWarehouse::apply_in_stock_delta_conn(conn, product_id, existing.location_id, quantity, DbStockMovementKind::GoodsReceipt, &reference).await?;
+            }
+
+            let db_items = DbPurchaseOrderItem::get_for_purchase_order_conn(conn, existing.id).await?;
+            let next_status = if db_items.iter().all(|item| item.received_quantity == item.quantity) {
+                DbPurchaseOrderStatus::Received
+            } else {
+                DbPurchaseOrderStatus::PartiallyReceived
+            };
+            let updated = DbPurchaseOrder::update_status_conn(conn, existing.id, next_status).await?;
+
+            let mut reply = PurchaseOrder::from(&updated);
+            reply.items = db_items.iter().map(PurchaseOrderItem::from).collect();
+            Ok(reply)
+        }).await
+    }
+
+    /// Deletes a `Draft` purchase order.
+    pub async fn remove(&self, purchase_order_id: i64) -> Result<bool, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let existing = DbPurchaseOrder::find_for_update_conn(conn, purchase_order_id).await?;
+            Self::ensure_draft(&existing)?;
+            let result = DbPurchaseOrder::delete_conn(conn, existing.id).await?;
+            Ok(result > 0)
+        }).await
+    }
+
+    fn ensure_draft(purchase_order: &DbPurchaseOrder) -> Result<(), ShopsterError> {
+        if purchase_order.status != DbPurchaseOrderStatus::Draft {
+            return Err(ShopsterError::InvalidOperationError(format!(
+                "Purchase order {} has already been placed",
+                purchase_order.id
+            )));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+//! Customer returns (RMA) and restocking.
+
+use std::collections::HashMap;
+use std::fmt;
+use uuid::Uuid;
+use chrono::{NaiveDateTime, Utc};
+
+use diesel_async::AsyncConnection;
+
+use crate::aquire_pool;
+use crate::error::ShopsterError;
+use crate::orders::{OrderStatus, Orders};
+use crate::postgresql::dborder::DbOrderItem;
+use crate::postgresql::dbreturn::{DbReturn, DbReturnItem, DbReturnStatus};
+use crate::postgresql::dbstockmovement::{DbMovementReference, DbStockMovementKind};
+use crate::postgresql::dbwarehouse::DbWarehouse;
+
+/// The lifecycle status of a return request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReturnStatus {
+    Requested,
+    Approved,
+    Received,
+    Refunded,
+    Rejected,
+}
+
+impl fmt::Display for ReturnStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<DbReturnStatus> for ReturnStatus {
+    fn from(status: DbReturnStatus) -> Self {
+        match status {
+            DbReturnStatus::Requested => ReturnStatus::Requested,
+            DbReturnStatus::Approved => ReturnStatus::Approved,
+            DbReturnStatus::Received => ReturnStatus::Received,
+            DbReturnStatus::Refunded => ReturnStatus::Refunded,
+            DbReturnStatus::Rejected => ReturnStatus::Rejected,
+        }
+    }
+}
+
+impl From<ReturnStatus> for DbReturnStatus {
+    fn from(status: ReturnStatus) -> Self {
+        match status {
+            ReturnStatus::Requested => DbReturnStatus::Requested,
+            ReturnStatus::Approved => DbReturnStatus::Approved,
+            ReturnStatus::Received => DbReturnStatus::Received,
+            ReturnStatus::Refunded => DbReturnStatus::Refunded,
+            ReturnStatus::Rejected => DbReturnStatus::Rejected,
+        }
+    }
+}
+
+/// An order line being sent back, referencing an `OrderItemSnapshot` by id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReturnItem {
+    pub id: i64,
+    pub order_item_id: i64,
+    pub quantity: i64,
+}
+
+impl From<&DbReturnItem> for ReturnItem {
+    fn from(db_item: &DbReturnItem) -> Self {
+        ReturnItem {
+            id: db_item.id,
+            order_item_id: db_item.order_item_id,
+            quantity: db_item.quantity,
+        }
+    }
+}
+
+impl From<&ReturnItem> for DbReturnItem {
+    fn from(item: &ReturnItem) -> Self {
+        DbReturnItem {
+            id: item.id,
+            return_id: 0,
+            order_item_id: item.order_item_id,
+            quantity: item.quantity,
+        }
+    }
+}
+
+/// A customer's request to send back goods from a completed order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReturnRequest {
+    pub id: i64,
+    pub order_id: i64,
+    pub status: ReturnStatus,
+    pub reason: String,
+    pub items: Vec<ReturnItem>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbReturn> for ReturnRequest {
+    fn from(db_return: &DbReturn) -> Self {
+        ReturnRequest {
+            id: db_return.id,
+            order_id: db_return.order_id,
+            status: db_return.status.into(),
+            reason: db_return.reason.clone(),
+            items: Vec::new(),
+            created_at: db_return.created_at,
+            updated_at: db_return.updated_at,
+        }
+    }
+}
+
+/// Handler for customer returns.
+///
+/// A return is opened against specific lines of a `Done` order and moves
+/// through `Requested -> Approved -> Received -> Refunded`; it may be
+/// `Rejected` until the goods have arrived. Marking a return as `Received`
+/// puts the returned units back into `warehouse.in_stock` in the same
+/// transaction as the status change.
+pub struct Returns {
+    tenant_id: Uuid
+}
+
+impl Returns {
+    pub fn new(tenant_id: Uuid) -> Self {
+        Returns { tenant_id }
+    }
+
+    fn is_valid_transition(from: ReturnStatus, to: ReturnStatus) -> bool {
+        matches!(
+            (from, to),
+            (ReturnStatus::Requested, ReturnStatus::Approved)
+                | (ReturnStatus::Approved, ReturnStatus::Received)
+                | (ReturnStatus::Received, ReturnStatus::Refunded)
+                | (ReturnStatus::Requested, ReturnStatus::Rejected)
+                | (ReturnStatus::Approved, ReturnStatus::Rejected)
+        )
+    }
+
+    pub async fn get_all(&self) -> Result<Vec<ReturnRequest>, ShopsterError> {
+        let db_returns = DbReturn::get_all(self.tenant_id).await?;
+        let mut returns = Vec::new();
+
+        for db_return in db_returns {
+            let db_items = DbReturnItem::get_for_return(self.tenant_id, db_return.id).await?;
+            let mut return_request = ReturnRequest::from(&db_return);
+            return_request.items = db_items.iter().map(ReturnItem::from).collect();
+            returns.push(return_request);
+        }
+
+        Ok(returns)
+    }
+
+    pub async fn get(&self, return_id: i64) -> Result<ReturnRequest, ShopsterError> {
+        let db_return = DbReturn::find(self.tenant_id, return_id).await?;
+        let db_items = DbReturnItem::get_for_return(self.tenant_id, db_return.id).await?;
+
+        let mut return_request = ReturnRequest::from(&db_return);
+        return_request.items = db_items.iter().map(ReturnItem::from).collect();
+        Ok(return_request)
+    }
+
+    pub async fn get_for_order(&self, order_id: i64) -> Result<Vec<ReturnRequest>, ShopsterError> {
+        let db_returns = DbReturn::get_for_order(self.tenant_id, order_id).await?;
+        let mut returns = Vec::new();
+
+        for db_return in db_returns {
+            let db_items = DbReturnItem::get_for_return(self.tenant_id, db_return.id).await?;
+            let mut return_request = ReturnRequest::from(&db_return);
+            return_request.items = db_items.iter().map(ReturnItem::from).collect();
+            returns.push(return_request);
+        }
+
+        Ok(returns)
+    }
+
+    /// Opens a return request for lines of a `Done` order.
+    ///
+    /// The quantity returned per line, summed over all returns that were not
+    /// rejected, may not exceed the ordered quantity of that line.
+    pub async fn open(&self, order_id: i64, items: &[ReturnItem], reason: String) -> Result<ReturnRequest, ShopsterError> {
+        if items.is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Return must contain at least one item".to_string(),
+            ));
+        }
+        if items.iter().any(|item| item.quantity <= 0) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Quantity must be positive".to_string(),
+            ));
+        }
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_items_input: Vec<DbReturnItem> = items.iter().map(DbReturnItem::from).collect();
+
+        conn.transaction(async |conn| {
+            let order = Orders::get_by_id_conn(conn, order_id).await?;
+
+            if order.status != OrderStatus::Done {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Cannot open a return for an order in status {}",
+                    order.status
+                )));
+            }
+
+            let mut returned: HashMap<i64, i64> = HashMap::new();
+            for item in DbReturnItem::get_open_for_order_conn(conn, order.id).await? {
+                *returned.entry(item.order_item_id).or_insert(0) += item.quantity;
+            }
+            for item in &db_items_input {
+                let order_item = order.items.iter()
+                    .find(|order_item| order_item.id == item.order_item_id)
+                    .ok_or_else(|| ShopsterError::InvalidOperationError(format!(
+                        "Order item {} does not belong to order {}",
+                        item.order_item_id, order.id
+                    )))?;
+
+                let total = returned.entry(item.order_item_id).or_insert(0);
+                *total += item.quantity;
+                if *total > order_item.quantity {
+                    return Err(ShopsterError::InvalidOperationError(format!(
+                        "Returned quantity for order item {} exceeds ordered quantity",
+                        item.order_item_id
+                    )));
+                }
+            }
+
+            let now = Utc::now().naive_utc();
+            let created_return = DbReturn::create_conn(conn, DbReturn {
+                id: 0,
+                order_id: order.id,
+                status: DbReturnStatus::Requested,
+                reason,
+                created_at: now,
+                updated_at: Some(now),
+            }).await?;
+
+            let db_items: Vec<DbReturnItem> = db_items_input.into_iter()
+                .map(|item| DbReturnItem { return_id: created_return.id, ..item })
+                .collect();
+            let created_items = DbReturnItem::create_for_return_conn(conn, db_items).await?;
+
+            let mut reply = ReturnRequest::from(&created_return);
+            reply.items = created_items.iter().map(ReturnItem::from).collect();
+            Ok(reply)
+        }).await
+    }
+
+    /// Moves a return to the next status. Transitioning to `Received` restocks
+    /// every returned unit within the same transaction.
+    pub async fn update_status(&self, return_id: i64, status: ReturnStatus) -> Result<ReturnRequest, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            let existing_return = DbReturn::find_for_update_conn(conn, return_id).await?;
+            let previous_status: ReturnStatus = existing_return.status.into();
+
+            if !Self::is_valid_transition(previous_status, status) {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Invalid return status transition: {} -> {}",
+                    previous_status, status
+                )));
+            }
+
+            let db_items = DbReturnItem::get_for_return_conn(conn, return_id).await?;
+
+            if status == ReturnStatus::Received {
+                let order_items = DbOrderItem::get_for_order_conn(conn, existing_return.order_id).await?;
+                for item in &db_items {
+                    let order_item = order_items.iter()
+                        .find(|order_item| order_item.id == item.order_item_id)
+                        .ok_or_else(|| ShopsterError::InvalidOperationError(format!(
+                            "Order item {} no longer exists",
+                            item.order_item_id
+                        )))?;
+                    DbWarehouse::apply_in_stock_delta_conn(
+                        conn,
+                        order_item.product_id,
+                        None,
+                        item.quantity,
+                        DbStockMovementKind::Return,
+                        &DbMovementReference::return_request(return_id, existing_return.order_id),
+                    ).await?;
+                }
+            }
+
+            let updated_return = DbReturn::update_status_conn(conn, return_id, status.into()).await?;
+
+            let mut reply = ReturnRequest::from(&updated_return);
+            reply.items = db_items.iter().map(ReturnItem::from).collect();
+            Ok(reply)
+        }).await
+    }
+}
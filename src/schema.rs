@@ -2,12 +2,32 @@
 
 pub mod sql_types {
     #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "dbinvoicekind"))]
+    pub struct DbInvoiceKind;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "dborderstatus"))]
     pub struct DbOrderStatus;
 
-    #[derive(diesel::sql_types::SqlType)]
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "dbpaymentstatus"))]
     pub struct DbPaymentStatus;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "dbpurchaseorderstatus"))]
+    pub struct DbPurchaseOrderStatus;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "dbreturnstatus"))]
+    pub struct DbReturnStatus;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "dbstockpolicy"))]
+    pub struct DbStockPolicy;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "dbstockmovementkind"))]
+    pub struct DbStockMovementKind;
 }
 
 diesel::table! {
@@ -16,6 +36,8 @@ diesel::table! {
         product_id -> Int8,
         quantity -> Int8,
         basket_id -> Uuid,
+        reserved_quantity -> Int8,
+        reserved_until -> Nullable<Timestamp>,
     }
 }
 
@@ -24,6 +46,41 @@ diesel::table! {
         id -> Uuid,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        customer_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    categories (id) {
+        id -> Int8,
+        parent_id -> Nullable<Int8>,
+        title -> Text,
+        slug -> Text,
+        position -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    category_translations (category_id, language) {
+        category_id -> Int8,
+        language -> Text,
+        title -> Text,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    customer_addresses (id) {
+        id -> Int8,
+        customer_id -> Uuid,
+        address -> Jsonb,
+        is_default_billing -> Bool,
+        is_default_shipping -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
     }
 }
 
@@ -40,6 +97,62 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    invoice_lines (id) {
+        id -> Int8,
+        invoice_id -> Int8,
+        order_item_id -> Nullable<Int8>,
+        article_number -> Text,
+        title -> Text,
+        quantity -> Int8,
+        unit_price -> Int8,
+        net_amount -> Int8,
+        vat_amount -> Int8,
+        gross_amount -> Int8,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::DbInvoiceKind;
+
+    invoices (id) {
+        id -> Int8,
+        order_id -> Int8,
+        invoice_number -> Text,
+        kind -> DbInvoiceKind,
+        corrected_invoice_id -> Nullable<Int8>,
+        refund_id -> Nullable<Int8>,
+        billing_address -> Jsonb,
+        delivery_address -> Jsonb,
+        currency -> Text,
+        vat_rate -> Float8,
+        net_total -> Int8,
+        vat_total -> Int8,
+        gross_total -> Int8,
+        issued_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    locations (id) {
+        id -> Int8,
+        name -> Text,
+        address -> Nullable<Jsonb>,
+        priority -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    number_sequences (name, period) {
+        name -> Text,
+        period -> Text,
+        value -> Int8,
+    }
+}
+
 diesel::table! {
     order_items (id) {
         id -> Int8,
@@ -58,6 +171,26 @@ diesel::table! {
         currency -> Text,
         weight -> Int4,
         created_at -> Timestamp,
+        fulfilled_quantity -> Int8,
+        variant_options -> Jsonb,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::DbOrderStatus;
+    use super::sql_types::DbPaymentStatus;
+
+    order_status_history (id) {
+        id -> Int8,
+        order_id -> Int8,
+        old_status -> DbOrderStatus,
+        new_status -> DbOrderStatus,
+        old_payment_status -> DbPaymentStatus,
+        new_payment_status -> DbPaymentStatus,
+        actor -> Nullable<Text>,
+        comment -> Nullable<Text>,
+        created_at -> Timestamp,
     }
 }
 
@@ -70,12 +203,68 @@ diesel::table! {
         id -> Int8,
         customer_id -> Nullable<Uuid>,
         status -> DbOrderStatus,
-        delivery_address -> Text,
-        billing_address -> Text,
+        delivery_address -> Jsonb,
+        billing_address -> Jsonb,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
         payment_reference -> Nullable<Text>,
         payment_status -> DbPaymentStatus,
+        order_number -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    product_categories (product_id, category_id) {
+        product_id -> Int8,
+        category_id -> Int8,
+    }
+}
+
+diesel::table! {
+    product_images (id) {
+        id -> Int8,
+        product_id -> Int8,
+        url -> Text,
+        position -> Int4,
+        alt_text -> Jsonb,
+        width -> Nullable<Int4>,
+        height -> Nullable<Int4>,
+        mime_type -> Nullable<Text>,
+        checksum -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    product_tags (product_id, tag_id) {
+        product_id -> Int8,
+        tag_id -> Int4,
+    }
+}
+
+diesel::table! {
+    product_translations (product_id, language) {
+        product_id -> Int8,
+        language -> Text,
+        title -> Nullable<Text>,
+        short_description -> Nullable<Text>,
+        description -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    product_variants (product_id) {
+        product_id -> Int8,
+        parent_id -> Int8,
+        options -> Jsonb,
+        price -> Nullable<Int8>,
+        currency -> Nullable<Text>,
+        weight -> Nullable<Int4>,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
     }
 }
 
@@ -87,18 +276,164 @@ diesel::table! {
         title -> Text,
         short_description -> Text,
         description -> Text,
-        tags -> Text,
-        title_image -> Text,
-        additional_images -> Text,
         price -> Int8,
         currency -> Text,
         weight -> Int4,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        search_tags -> Text,
+    }
+}
+
+diesel::table! {
+    purchase_order_items (id) {
+        id -> Int8,
+        purchase_order_id -> Int8,
+        product_id -> Int8,
+        quantity -> Int8,
+        received_quantity -> Int8,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::DbPurchaseOrderStatus;
+
+    purchase_orders (id) {
+        id -> Int8,
+        supplier_id -> Int8,
+        location_id -> Nullable<Int8>,
+        status -> DbPurchaseOrderStatus,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+        ordered_at -> Nullable<Timestamp>,
+        received_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    reorder_points (id) {
+        id -> Int8,
+        product_id -> Int8,
+        reorder_point -> Int8,
+        reorder_quantity -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    refund_items (id) {
+        id -> Int8,
+        refund_id -> Int8,
+        order_item_id -> Int8,
+        quantity -> Int8,
+    }
+}
+
+diesel::table! {
+    refunds (id) {
+        id -> Int8,
+        order_id -> Int8,
+        amount -> Int8,
+        currency -> Text,
+        reason -> Text,
+        payment_reference -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    return_items (id) {
+        id -> Int8,
+        return_id -> Int8,
+        order_item_id -> Int8,
+        quantity -> Int8,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::DbReturnStatus;
+
+    returns (id) {
+        id -> Int8,
+        order_id -> Int8,
+        status -> DbReturnStatus,
+        reason -> Text,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    shipment_items (id) {
+        id -> Int8,
+        shipment_id -> Int8,
+        order_item_id -> Int8,
+        quantity -> Int8,
+    }
+}
+
+diesel::table! {
+    shipments (id) {
+        id -> Int8,
+        order_id -> Int8,
+        carrier -> Text,
+        tracking_code -> Text,
+        shipped_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    stock_alerts (id) {
+        id -> Int8,
+        product_id -> Int8,
+        available -> Int8,
+        reorder_point -> Int8,
+        reorder_quantity -> Int8,
+        created_at -> Timestamp,
+        acknowledged_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::DbStockMovementKind;
+
+    stock_movements (id) {
+        id -> Int8,
+        product_id -> Int8,
+        kind -> DbStockMovementKind,
+        quantity -> Int8,
+        reason -> Nullable<Text>,
+        order_id -> Nullable<Int8>,
+        return_id -> Nullable<Int8>,
+        created_at -> Timestamp,
+        location_id -> Nullable<Int8>,
+        purchase_order_id -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    suppliers (id) {
+        id -> Int8,
+        name -> Text,
+        email -> Nullable<Text>,
+        phone -> Nullable<Text>,
+        address -> Nullable<Jsonb>,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::DbStockPolicy;
+
     warehouse (id) {
         id -> Int8,
         product_id -> Int8,
@@ -106,6 +441,9 @@ diesel::table! {
         reserved -> Int8,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        stock_policy -> DbStockPolicy,
+        backordered -> Int8,
+        location_id -> Int8,
     }
 }
 
@@ -118,6 +456,15 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    tags (id) {
+        id -> Int4,
+        title -> Text,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Uuid,
@@ -132,17 +479,70 @@ diesel::table! {
 }
 
 diesel::joinable!(basketproducts -> baskets (basket_id));
+diesel::joinable!(baskets -> customers (customer_id));
+diesel::joinable!(category_translations -> categories (category_id));
+diesel::joinable!(customer_addresses -> customers (customer_id));
+diesel::joinable!(invoice_lines -> invoices (invoice_id));
+diesel::joinable!(invoice_lines -> order_items (order_item_id));
+diesel::joinable!(invoices -> orders (order_id));
+diesel::joinable!(invoices -> refunds (refund_id));
 diesel::joinable!(order_items -> orders (order_id));
+diesel::joinable!(order_status_history -> orders (order_id));
 diesel::joinable!(orders -> customers (customer_id));
+diesel::joinable!(product_categories -> categories (category_id));
+diesel::joinable!(product_categories -> products (product_id));
+diesel::joinable!(product_images -> products (product_id));
+diesel::joinable!(product_tags -> products (product_id));
+diesel::joinable!(product_tags -> tags (tag_id));
+diesel::joinable!(product_translations -> products (product_id));
+diesel::joinable!(purchase_order_items -> purchase_orders (purchase_order_id));
+diesel::joinable!(purchase_orders -> locations (location_id));
+diesel::joinable!(purchase_orders -> suppliers (supplier_id));
+diesel::joinable!(refund_items -> order_items (order_item_id));
+diesel::joinable!(refund_items -> refunds (refund_id));
+diesel::joinable!(refunds -> orders (order_id));
+diesel::joinable!(return_items -> order_items (order_item_id));
+diesel::joinable!(return_items -> returns (return_id));
+diesel::joinable!(returns -> orders (order_id));
+diesel::joinable!(shipment_items -> order_items (order_item_id));
+diesel::joinable!(shipment_items -> shipments (shipment_id));
+diesel::joinable!(shipments -> orders (order_id));
+diesel::joinable!(warehouse -> locations (location_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     basketproducts,
     baskets,
+    categories,
+    category_translations,
+    customer_addresses,
     customers,
+    invoice_lines,
+    invoices,
+    locations,
+    number_sequences,
     order_items,
+    order_status_history,
     orders,
+    product_categories,
+    product_images,
+    product_tags,
+    product_translations,
+    product_variants,
     products,
+    purchase_order_items,
+    purchase_orders,
+    reorder_points,
+    refund_items,
+    refunds,
+    return_items,
+    returns,
     settings,
+    shipment_items,
+    shipments,
+    stock_alerts,
+    stock_movements,
+    suppliers,
+    tags,
     warehouse,
     users,
 );
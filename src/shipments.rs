@@ -0,0 +1,288 @@
+//! Shipment tracking for orders.
+
+use std::collections::HashMap;
+use uuid::Uuid;
+use chrono::{NaiveDateTime, Utc};
+
+use diesel_async::{AsyncConnection, AsyncPgConnection};
+
+use crate::aquire_pool;
+use crate::error::ShopsterError;
+use crate::orders::{Order, OrderStatus, Orders};
+use crate::postgresql::dbaddress::DbAddress;
+use crate::postgresql::dborder::{DbOrder, DbOrderItem};
+use crate::postgresql::dbshipment::{DbShipment, DbShipmentItem};
+use crate::postgresql::dbstockmovement::DbMovementReference;
+use crate::postgresql::dbwarehouse::DbWarehouse;
+
+/// An order line contained in a shipment, referencing an `OrderItemSnapshot` by id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShipmentItem {
+    pub id: i64,
+    pub order_item_id: i64,
+    pub quantity: i64,
+}
+
+impl From<&DbShipmentItem> for ShipmentItem {
+    fn from(db_item: &DbShipmentItem) -> Self {
+        ShipmentItem {
+            id: db_item.id,
+            order_item_id: db_item.order_item_id,
+            quantity: db_item.quantity,
+        }
+    }
+}
+
+impl From<&ShipmentItem> for DbShipmentItem {
+    fn from(item: &ShipmentItem) -> Self {
+        DbShipmentItem {
+            id: item.id,
+            shipment_id: 0,
+            order_item_id: item.order_item_id,
+            quantity: item.quantity,
+        }
+    }
+}
+
+/// A parcel handed over to a carrier for an order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shipment {
+    pub id: i64,
+    pub order_id: i64,
+    pub carrier: String,
+    pub tracking_code: String,
+    pub items: Vec<ShipmentItem>,
+    pub shipped_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbShipment> for Shipment {
+    fn from(db_shipment: &DbShipment) -> Self {
+        Shipment {
+            id: db_shipment.id,
+            order_id: db_shipment.order_id,
+            carrier: db_shipment.carrier.clone(),
+            tracking_code: db_shipment.tracking_code.clone(),
+            items: Vec::new(),
+            shipped_at: db_shipment.shipped_at,
+            delivered_at: db_shipment.delivered_at,
+            created_at: db_shipment.created_at,
+            updated_at: db_shipment.updated_at,
+        }
+    }
+}
+
+impl From<&Shipment> for DbShipment {
+    fn from(shipment: &Shipment) -> Self {
+        DbShipment {
+            id: shipment.id,
+            order_id: shipment.order_id,
+            carrier: shipment.carrier.clone(),
+            tracking_code: shipment.tracking_code.clone(),
+            shipped_at: shipment.shipped_at,
+            delivered_at: shipment.delivered_at,
+            created_at: Utc::now().naive_utc(),
+            updated_at: Some(Utc::now().naive_utc()),
+        }
+    }
+}
+
+/// Handler for shipment tracking operations.
+///
+/// Recording shipments drives the order lifecycle: an order moves to
+/// `PartiallyShipped` while some of its lines are still open and to `Shipping`
+/// once every line is fulfilled; when every shipment has also been delivered
+/// the order moves to `Done`. These transitions go through `Orders::update`, so
+/// status validation and reservation handling stay in one place.
+///
+/// Each shipped unit consumes its warehouse reservation and is taken out of
+/// `in_stock`, so stock only decreases for quantities that actually left.
+pub struct Shipments {
+    tenant_id: Uuid
+}
+
+impl Shipments {
+    pub fn new(tenant_id: Uuid) -> Self {
+        Shipments { tenant_id }
+    }
+
+    pub async fn get_for_order(&self, order_id: i64) -> Result<Vec<Shipment>, ShopsterError> {
+        let db_shipments = DbShipment::get_for_order(self.tenant_id, order_id).await?;
+        let mut shipments = Vec::new();
+
+        for db_shipment in db_shipments {
+            let db_items = DbShipmentItem::get_for_shipment(self.tenant_id, db_shipment.id).await?;
+            let mut shipment = Shipment::from(&db_shipment);
+            shipment.items = db_items.iter().map(ShipmentItem::from).collect();
+            shipments.push(shipment);
+        }
+
+        Ok(shipments)
+    }
+
+    pub async fn get(&self, shipment_id: i64) -> Result<Shipment, ShopsterError> {
+        let db_shipment = DbShipment::find(self.tenant_id, shipment_id).await?;
+        let db_items = DbShipmentItem::get_for_shipment(self.tenant_id, db_shipment.id).await?;
+
+        let mut shipment = Shipment::from(&db_shipment);
+        shipment.items = db_items.iter().map(ShipmentItem::from).collect();
+        Ok(shipment)
+    }
+
+    /// Records a shipment for an order that is `ReadyToShip` or `PartiallyShipped`.
+    ///
+    /// Every item must reference a line of the order, and the quantity shipped
+    /// may not exceed what is still unfulfilled on that line.
+    pub async fn insert(&self, shipment: &Shipment) -> Result<Shipment, ShopsterError> {
+        if shipment.carrier.trim().is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Carrier cannot be empty".to_string(),
+            ));
+        }
+        if shipment.tracking_code.trim().is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Tracking code cannot be empty".to_string(),
+            ));
+        }
+        if shipment.items.is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Shipment must contain at least one item".to_string(),
+            ));
+        }
+        if shipment.items.iter().any(|item| item.quantity <= 0) {
+            return Err(ShopsterError::InvalidOperationError(
+                "Quantity must be positive".to_string(),
+            ));
+        }
+
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        let db_shipment = DbShipment::from(shipment);
+        let db_items_input: Vec<DbShipmentItem> = shipment.items.iter().map(DbShipmentItem::from).collect();
+
+        conn.transaction(async |conn| {
+            let order = Orders::get_by_id_conn(conn, db_shipment.order_id).await?;
+
+            if !matches!(order.status, OrderStatus::ReadyToShip | OrderStatus::PartiallyShipped) {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Cannot record a shipment for an order in status {}",
+                    order.status
+                )));
+            }
+
+            let mut requested: HashMap<i64, i64> = HashMap::new();
+            for item in &db_items_input {
+                *requested.entry(item.order_item_id).or_insert(0) += item.quantity;
+            }
+            for (order_item_id, quantity) in &requested {
+                let order_item = order.items.iter()
+                    .find(|order_item| order_item.id == *order_item_id)
+                    .ok_or_else(|| ShopsterError::InvalidOperationError(format!(
+                        "Order item {} does not belong to order {}",
+                        order_item_id, order.id
+                    )))?;
+
+                if *quantity > order_item.unfulfilled_quantity() {
+                    return Err(ShopsterError::InvalidOperationError(format!(
+                        "Shipped quantity for order item {} exceeds ordered quantity",
+                        order_item_id
+                    )));
+                }
+            }
+
+            let created_shipment = DbShipment::create_conn(conn, db_shipment).await?;
+            let db_items: Vec<DbShipmentItem> = db_items_input.into_iter()
+                .map(|item| DbShipmentItem { shipment_id: created_shipment.id, ..item })
+                .collect();
+            let created_items = DbShipmentItem::create_for_shipment_conn(conn, db_items).await?;
+
+            let destination = DbAddress::from(&order.delivery_address);
+            for order_item in &order.items {
+                if let Some(quantity) = requested.get(&order_item.id) {
+                    DbOrderItem::apply_fulfilled_delta_conn(conn, order_item.id, *quantity).await?;
+                    DbWarehouse::apply_shipped_quantity_conn(conn, order_item.product_id, *quantity, Some(&destination), &DbMovementReference::order(order.id)).await?;
+                }
+            }
+
+            let mut order = Orders::get_by_id_conn(conn, order.id).await?;
+            let next_status = if order.items.iter().all(|item| item.unfulfilled_quantity() == 0) {
+                OrderStatus::Shipping
+            } else {
+                OrderStatus::PartiallyShipped
+            };
+            if order.status != next_status {
+                order.status = next_status;
+                let comment = format!("Shipment {} recorded", created_shipment.id);
+                order = Orders::update_conn(conn, &order, None, Some(&comment)).await?;
+            }
+            Self::complete_if_delivered_conn(conn, order).await?;
+
+            let mut reply = Shipment::from(&created_shipment);
+            reply.items = created_items.iter().map(ShipmentItem::from).collect();
+            Ok(reply)
+        }).await
+    }
+
+    pub async fn update_tracking(&self, shipment_id: i64, carrier: &str, tracking_code: &str) -> Result<Shipment, ShopsterError> {
+        if carrier.trim().is_empty() || tracking_code.trim().is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Carrier and tracking code cannot be empty".to_string(),
+            ));
+        }
+
+        DbShipment::update_tracking(self.tenant_id, shipment_id, carrier, tracking_code).await?;
+        self.get(shipment_id).await
+    }
+
+    /// Marks a shipment as delivered. Moves the order to `Done` once all of its
+    /// lines have been shipped and every shipment has been delivered. A
+    /// shipment can only be delivered once.
+    pub async fn mark_delivered(&self, shipment_id: i64, delivered_at: NaiveDateTime) -> Result<Shipment, ShopsterError> {
+        let pool = aquire_pool(self.tenant_id).await?;
+        let mut conn = pool.get().await.map_err(|e| ShopsterError::DatabaseConnectionError(e.to_string()))?;
+
+        conn.transaction(async |conn| {
+            // Deliveries of the same order are serialized, so that the last one
+            // sees all others and completes the order
+            let order_id = DbShipment::find_conn(conn, shipment_id).await?.order_id;
+            DbOrder::find_for_update_conn(conn, order_id).await?;
+            let existing_shipment = DbShipment::find_conn(conn, shipment_id).await?;
+            if let Some(delivered_at) = existing_shipment.delivered_at {
+                return Err(ShopsterError::InvalidOperationError(format!(
+                    "Shipment {} was already delivered at {}",
+                    shipment_id, delivered_at
+                )));
+            }
+            let order = Orders::get_by_id_conn(conn, order_id).await?;
+
+            let updated_shipment = DbShipment::mark_delivered_conn(conn, shipment_id, delivered_at).await?;
+            Self::complete_if_delivered_conn(conn, order).await?;
+
+            let db_items = DbShipmentItem::get_for_shipment_conn(conn, shipment_id).await?;
+            let mut reply = Shipment::from(&updated_shipment);
+            reply.items = db_items.iter().map(ShipmentItem::from).collect();
+            Ok(reply)
+        }).await
+    }
+
+    async fn complete_if_delivered_conn(conn: &mut AsyncPgConnection, mut order: Order) -> Result<Order, ShopsterError> {
+        if order.status != OrderStatus::Shipping {
+            return Ok(order);
+        }
+
+        if order.items.iter().any(|item| item.unfulfilled_quantity() > 0) {
+            return Ok(order);
+        }
+
+        let shipments = DbShipment::get_for_order_conn(conn, order.id).await?;
+        if shipments.iter().any(|shipment| shipment.delivered_at.is_none()) {
+            return Ok(order);
+        }
+
+        order.status = OrderStatus::Done;
+        Orders::update_conn(conn, &order, None, Some("All shipments delivered")).await
+    }
+}
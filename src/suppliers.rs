@@ -0,0 +1,102 @@
+//! Suppliers that goods are purchased from.
+
+use chrono::{NaiveDateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::addresses::Address;
+use crate::error::ShopsterError;
+use crate::postgresql::dbaddress::DbAddress;
+use crate::postgresql::dbsupplier::DbSupplier;
+
+/// A company goods are purchased from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Supplier {
+    pub id: i64,
+    pub name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub address: Option<Address>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbSupplier> for Supplier {
+    fn from(db_supplier: &DbSupplier) -> Self {
+        Supplier {
+            id: db_supplier.id,
+            name: db_supplier.name.clone(),
+            email: db_supplier.email.clone(),
+            phone: db_supplier.phone.clone(),
+            address: db_supplier.address.as_ref().map(Address::from),
+            created_at: db_supplier.created_at,
+            updated_at: db_supplier.updated_at,
+        }
+    }
+}
+
+impl From<&Supplier> for DbSupplier {
+    fn from(supplier: &Supplier) -> Self {
+        DbSupplier {
+            id: supplier.id,
+            name: supplier.name.clone(),
+            email: supplier.email.clone(),
+            phone: supplier.phone.clone(),
+            address: supplier.address.as_ref().map(DbAddress::from),
+            created_at: Utc::now().naive_utc(),
+            updated_at: Some(Utc::now().naive_utc()),
+        }
+    }
+}
+
+/// Handler for suppliers.
+pub struct Suppliers {
+    tenant_id: Uuid,
+}
+
+impl Suppliers {
+    pub fn new(tenant_id: Uuid) -> Self {
+        Suppliers { tenant_id }
+    }
+
+    /// Returns all suppliers ordered by name.
+    pub async fn get_all(&self) -> Result<Vec<Supplier>, ShopsterError> {
+        let db_suppliers = DbSupplier::get_all(self.tenant_id).await?;
+        Ok(db_suppliers.iter().map(Supplier::from).collect())
+    }
+
+    pub async fn get(&self, supplier_id: i64) -> Result<Supplier, ShopsterError> {
+        let db_supplier = DbSupplier::find(self.tenant_id, supplier_id).await?;
+        Ok(Supplier::from(&db_supplier))
+    }
+
+    pub async fn insert(&self, supplier: &Supplier) -> Result<Supplier, ShopsterError> {
+        Self::validate(supplier)?;
+        let created_supplier = DbSupplier::create(self.tenant_id, DbSupplier::from(supplier)).await?;
+        Ok(Supplier::from(&created_supplier))
+    }
+
+    pub async fn update(&self, supplier: &Supplier) -> Result<Supplier, ShopsterError> {
+        Self::validate(supplier)?;
+        let updated_supplier = DbSupplier::update(self.tenant_id, supplier.id, DbSupplier::from(supplier)).await?;
+        Ok(Supplier::from(&updated_supplier))
+    }
+
+    /// Removes a supplier. Fails while purchase orders still reference it.
+    pub async fn remove(&self, supplier_id: i64) -> Result<bool, ShopsterError> {
+        let result = DbSupplier::delete(self.tenant_id, supplier_id).await?;
+        Ok(result > 0)
+    }
+
+    fn validate(supplier: &Supplier) -> Result<(), ShopsterError> {
+        if supplier.name.trim().is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Supplier name cannot be empty".to_string(),
+            ));
+        }
+        if let Some(address) = &supplier.address {
+            address.validate()?;
+        }
+        Ok(())
+    }
+}
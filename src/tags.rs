@@ -0,0 +1,120 @@
+//! Tags that products are labelled with.
+
+use chrono::{NaiveDateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::ShopsterError;
+use crate::postgresql::dbproduct::DbProduct;
+use crate::postgresql::dbtag::DbTag;
+use crate::products::{load_products, Product};
+
+/// A tag with a unique title, e.g. `summer`. Products refer to their tags by
+/// title in `Product::tags`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: i32,
+    pub title: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbTag> for Tag {
+    fn from(db_tag: &DbTag) -> Self {
+        Tag {
+            id: db_tag.id,
+            title: db_tag.title.clone(),
+            created_at: db_tag.created_at,
+            updated_at: db_tag.updated_at,
+        }
+    }
+}
+
+impl From<&Tag> for DbTag {
+    fn from(tag: &Tag) -> Self {
+        DbTag {
+            id: tag.id,
+            title: tag.title.trim().to_string(),
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }
+    }
+}
+
+/// Handler for the tags of the product catalog.
+pub struct Tags {
+    tenant_id: Uuid,
+}
+
+impl Tags {
+    pub fn new(tenant_id: Uuid) -> Self {
+        Tags { tenant_id }
+    }
+
+    /// Returns all tags ordered by title.
+    pub async fn get_all(&self) -> Result<Vec<Tag>, ShopsterError> {
+        let db_tags = DbTag::get_all(self.tenant_id).await?;
+        Ok(db_tags.iter().map(Tag::from).collect())
+    }
+
+    pub async fn get(&self, tag_id: i32) -> Result<Tag, ShopsterError> {
+        let db_tag = DbTag::find(self.tenant_id, tag_id).await?;
+        Ok(Tag::from(&db_tag))
+    }
+
+    pub async fn get_by_title(&self, title: &str) -> Result<Option<Tag>, ShopsterError> {
+        let db_tag = DbTag::find_by_title(self.tenant_id, title.trim()).await?;
+        Ok(db_tag.as_ref().map(Tag::from))
+    }
+
+    /// Creates a tag without products. Leading and trailing whitespace is
+    /// removed from the title.
+    pub async fn insert(&self, tag: &Tag) -> Result<Tag, ShopsterError> {
+        Self::validate_title(&tag.title)?;
+        let created_tag = DbTag::create(self.tenant_id, DbTag::from(tag)).await?;
+        Ok(Tag::from(&created_tag))
+    }
+
+    /// Renames a tag on all of its products. Renaming to the title of another
+    /// tag is rejected; merge the two tags instead.
+    pub async fn rename(&self, tag_id: i32, title: &str) -> Result<Tag, ShopsterError> {
+        Self::validate_title(title)?;
+        let renamed_tag = DbTag::rename(self.tenant_id, tag_id, title.trim()).await?;
+        Ok(Tag::from(&renamed_tag))
+    }
+
+    /// Adds the tag `target_id` to all products of the tag `source_id` and
+    /// removes the source tag. Returns the target tag.
+    pub async fn merge(&self, source_id: i32, target_id: i32) -> Result<Tag, ShopsterError> {
+        if source_id == target_id {
+            return Err(ShopsterError::InvalidOperationError(
+                "A tag cannot be merged into itself".to_string(),
+            ));
+        }
+        let merged_tag = DbTag::merge(self.tenant_id, source_id, target_id).await?;
+        Ok(Tag::from(&merged_tag))
+    }
+
+    /// Removes a tag from all products and deletes it.
+    pub async fn remove(&self, tag_id: i32) -> Result<bool, ShopsterError> {
+        let result = DbTag::delete(self.tenant_id, tag_id).await?;
+        Ok(result > 0)
+    }
+
+    /// Returns the products carrying a tag. Variants are tagged through their
+    /// parent and are not included; see `Products::query` for a filtered and
+    /// paginated listing by tag title.
+    pub async fn get_products(&self, tag_id: i32) -> Result<Vec<Product>, ShopsterError> {
+        let db_products: Vec<DbProduct> = DbTag::get_products(self.tenant_id, tag_id).await?;
+        load_products(self.tenant_id, &db_products).await
+    }
+
+    fn validate_title(title: &str) -> Result<(), ShopsterError> {
+        if title.trim().is_empty() {
+            return Err(ShopsterError::InvalidOperationError(
+                "Tag title cannot be empty".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
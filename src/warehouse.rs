@@ -1,13 +1,54 @@
 //! Inventory and warehouse management.
 
+use std::ops::Range;
+
 use chrono::{NaiveDateTime, Utc};
 use serde_derive::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::error::ShopsterError;
-use crate::postgresql::dbwarehouse::DbWarehouse;
+use crate::postgresql::dblocation::DbLocation;
+use crate::postgresql::dbreorderpoint::{DbLowStock, DbReorderPoint, DbStockAlert};
+use crate::postgresql::dbstockmovement::{DbMovementReference, DbStockMovement, DbStockMovementKind};
+use crate::postgresql::dbwarehouse::{DbStockPolicy, DbWarehouse};
+
+/// How reservations of a product are handled when its stock runs out.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StockPolicy {
+    /// Orders can only reserve stock that is available.
+    Deny,
+    /// Orders beyond the available stock are accepted and the missing units
+    /// are tracked as backordered until stock arrives.
+    Backorder,
+    /// Stock is never checked, e.g. for digital goods.
+    Unlimited,
+}
+
+impl From<DbStockPolicy> for StockPolicy {
+    fn from(policy: DbStockPolicy) -> Self {
+        match policy {
+            DbStockPolicy::Deny => StockPolicy::Deny,
+            DbStockPolicy::Backorder => StockPolicy::Backorder,
+            DbStockPolicy::Unlimited => StockPolicy::Unlimited,
+        }
+    }
+}
+
+impl From<StockPolicy> for DbStockPolicy {
+    fn from(policy: StockPolicy) -> Self {
+        match policy {
+            StockPolicy::Deny => DbStockPolicy::Deny,
+            StockPolicy::Backorder => DbStockPolicy::Backorder,
+            StockPolicy::Unlimited => DbStockPolicy::Unlimited,
+        }
+    }
+}
 
 /// A unit of inventory for a product.
+///
+/// Items read per product sum up the stock of all locations and have no
+/// `location_id`. When inserting or updating, `None` refers to the default
+/// location.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WarehouseItem {
     pub id: i64,
@@ -16,6 +57,10 @@ pub struct WarehouseItem {
     pub reserved: i64,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    pub stock_policy: StockPolicy,
+    /// Ordered units that could not be reserved yet for lack of stock.
+    pub backordered: i64,
+    pub location_id: Option<i64>,
 }
 
 impl WarehouseItem {
@@ -24,6 +69,148 @@ impl WarehouseItem {
     }
 }
 
+/// The cause of a stock movement.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StockMovementKind {
+    /// Goods arrived from a supplier.
+    GoodsReceipt,
+    /// Goods left the warehouse in a shipment.
+    Sale,
+    /// Goods came back from a customer return.
+    Return,
+    /// Stock was set manually, e.g. after an inventory count.
+    Correction,
+    /// Units were reserved for an order.
+    Reservation,
+    /// A reservation was given back.
+    Release,
+    /// Goods were moved between locations.
+    Transfer,
+}
+
+impl From<DbStockMovementKind> for StockMovementKind {
+    fn from(kind: DbStockMovementKind) -> Self {
+        match kind {
+            DbStockMovementKind::GoodsReceipt => StockMovementKind::GoodsReceipt,
+            DbStockMovementKind::Sale => StockMovementKind::Sale,
+            DbStockMovementKind::Return => StockMovementKind::Return,
+            DbStockMovementKind::Correction => StockMovementKind::Correction,
+            DbStockMovementKind::Reservation => StockMovementKind::Reservation,
+            DbStockMovementKind::Release => StockMovementKind::Release,
+            DbStockMovementKind::Transfer => StockMovementKind::Transfer,
+        }
+    }
+}
+
+/// An entry of the stock ledger.
+///
+/// For goods receipts, sales, returns and corrections `quantity` is the change
+/// of `in_stock`; for reservations and releases it is the change of the units
+/// claimed by orders (`reserved` plus `backordered`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockMovement {
+    pub id: i64,
+    pub product_id: i64,
+    pub kind: StockMovementKind,
+    pub quantity: i64,
+    pub reason: Option<String>,
+    pub order_id: Option<i64>,
+    pub return_id: Option<i64>,
+    pub created_at: NaiveDateTime,
+    pub location_id: Option<i64>,
+    pub purchase_order_id: Option<i64>,
+}
+
+impl From<&DbStockMovement> for StockMovement {
+    fn from(db_movement: &DbStockMovement) -> Self {
+        StockMovement {
+            id: db_movement.id,
+            product_id: db_movement.product_id,
+            kind: db_movement.kind.into(),
+            quantity: db_movement.quantity,
+            reason: db_movement.reason.clone(),
+            order_id: db_movement.order_id,
+            return_id: db_movement.return_id,
+            created_at: db_movement.created_at,
+            location_id: db_movement.location_id,
+            purchase_order_id: db_movement.purchase_order_id,
+        }
+    }
+}
+
+/// When to restock a product: once its free stock (`in_stock - reserved`
+/// over all locations) drops to `reorder_point`, `reorder_quantity` units
+/// should be ordered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReorderPoint {
+    pub product_id: i64,
+    pub reorder_point: i64,
+    pub reorder_quantity: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbReorderPoint> for ReorderPoint {
+    fn from(db_reorder_point: &DbReorderPoint) -> Self {
+        ReorderPoint {
+            product_id: db_reorder_point.product_id,
+            reorder_point: db_reorder_point.reorder_point,
+            reorder_quantity: db_reorder_point.reorder_quantity,
+            created_at: db_reorder_point.created_at,
+            updated_at: db_reorder_point.updated_at,
+        }
+    }
+}
+
+/// A product that is at or below its reorder point.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LowStockItem {
+    pub product_id: i64,
+    pub available: i64,
+    pub reorder_point: i64,
+    pub reorder_quantity: i64,
+}
+
+impl From<&DbLowStock> for LowStockItem {
+    fn from(db_low_stock: &DbLowStock) -> Self {
+        LowStockItem {
+            product_id: db_low_stock.product_id,
+            available: db_low_stock.available,
+            reorder_point: db_low_stock.reorder_point,
+            reorder_quantity: db_low_stock.reorder_quantity,
+        }
+    }
+}
+
+/// Raised when a reservation or stock movement lets the free stock of a
+/// product drop to or below its reorder point. Alerts stay open until they
+/// are acknowledged, e.g. once purchasing has been notified.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StockAlert {
+    pub id: i64,
+    pub product_id: i64,
+    /// Free stock right after the change that raised the alert.
+    pub available: i64,
+    pub reorder_point: i64,
+    pub reorder_quantity: i64,
+    pub created_at: NaiveDateTime,
+    pub acknowledged_at: Option<NaiveDateTime>,
+}
+
+impl From<&DbStockAlert> for StockAlert {
+    fn from(db_alert: &DbStockAlert) -> Self {
+        StockAlert {
+            id: db_alert.id,
+            product_id: db_alert.product_id,
+            available: db_alert.available,
+            reorder_point: db_alert.reorder_point,
+            reorder_quantity: db_alert.reorder_quantity,
+            created_at: db_alert.created_at,
+            acknowledged_at: db_alert.acknowledged_at,
+        }
+    }
+}
+
 /// Warehouse item with full product details.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WarehouseItemDetails {
@@ -34,6 +221,8 @@ pub struct WarehouseItemDetails {
     pub in_stock: i64,
     pub reserved: i64,
     pub available: i64,
+    pub stock_policy: StockPolicy,
+    pub backordered: i64,
 }
 
 impl From<&DbWarehouse> for WarehouseItem {
@@ -45,6 +234,28 @@ impl From<&DbWarehouse> for WarehouseItem {
             reserved: db_item.reserved,
             created_at: db_item.created_at,
             updated_at: db_item.updated_at,
+            stock_policy: db_item.stock_policy.into(),
+            backordered: db_item.backordered,
+            location_id: Some(db_item.location_id),
+        }
+    }
+}
+
+impl WarehouseItem {
+    /// Sums up the rows of one product at all locations. The lowest row id
+    /// identifies the product's stock; the policy is the same on all rows.
+    fn aggregate(db_items: &[DbWarehouse]) -> Self {
+        let first = &db_items[0];
+        WarehouseItem {
+            id: db_items.iter().map(|item| item.id).min().unwrap_or(first.id),
+            product_id: first.product_id,
+            in_stock: db_items.iter().map(|item| item.in_stock).sum(),
+            reserved: db_items.iter().map(|item| item.reserved).sum(),
+            created_at: db_items.iter().map(|item| item.created_at).min().unwrap_or(first.created_at),
+            updated_at: db_items.iter().filter_map(|item| item.updated_at).max(),
+            stock_policy: first.stock_policy.into(),
+            backordered: db_items.iter().map(|item| item.backordered).sum(),
+            location_id: None,
         }
     }
 }
@@ -58,6 +269,9 @@ impl From<&WarehouseItem> for DbWarehouse {
             reserved: item.reserved,
             created_at: Utc::now().naive_utc(),
             updated_at: Some(Utc::now().naive_utc()),
+            stock_policy: item.stock_policy.into(),
+            backordered: item.backordered,
+            location_id: item.location_id.unwrap_or_default(),
         }
     }
 }
@@ -72,9 +286,13 @@ impl Warehouse {
         Warehouse { tenant_id }
     }
 
+    /// Returns the stock of every product, summed up over all locations.
     pub async fn get_all(&self) -> Result<Vec<WarehouseItem>, ShopsterError> {
         let db_items = DbWarehouse::get_all(self.tenant_id).await?;
-        Ok(db_items.iter().map(WarehouseItem::from).collect())
+        Ok(db_items
+            .chunk_by(|a, b| a.product_id == b.product_id)
+            .map(WarehouseItem::aggregate)
+            .collect())
     }
 
     pub async fn get_all_with_details(&self) -> Result<Vec<WarehouseItemDetails>, ShopsterError> {
@@ -92,36 +310,160 @@ impl Warehouse {
                 in_stock: item.in_stock,
                 reserved: item.reserved,
                 available: item.available(),
+                stock_policy: item.stock_policy,
+                backordered: item.backordered,
             });
         }
 
         Ok(result)
     }
 
+    /// Returns the stock of a product summed up over all locations.
     pub async fn get_by_product_id(&self, product_id: i64) -> Result<WarehouseItem, ShopsterError> {
-        let db_item = DbWarehouse::find_by_product_id(self.tenant_id, product_id).await?;
-        Ok(WarehouseItem::from(&db_item))
+        let db_items = DbWarehouse::find_by_product_id(self.tenant_id, product_id).await?;
+        Ok(WarehouseItem::aggregate(&db_items))
     }
 
+    /// Returns the stock of a product at each location it is kept at.
+    pub async fn get_stock_by_location(&self, product_id: i64) -> Result<Vec<WarehouseItem>, ShopsterError> {
+        let db_items = DbWarehouse::find_by_product_id(self.tenant_id, product_id).await?;
+        Ok(db_items.iter().map(WarehouseItem::from).collect())
+    }
+
+    /// Returns the stock of all products kept at a location.
+    pub async fn get_by_location(&self, location_id: i64) -> Result<Vec<WarehouseItem>, ShopsterError> {
+        let db_items = DbWarehouse::get_for_location(self.tenant_id, location_id).await?;
+        Ok(db_items.iter().map(WarehouseItem::from).collect())
+    }
+
+    /// Stocks a product at `item.location_id`, or at the default location.
     pub async fn insert(&self, item: &WarehouseItem) -> Result<WarehouseItem, ShopsterError> {
-        let db_item = DbWarehouse::from(item);
+        let location_id = self.resolve_location(item.location_id).await?;
+        let db_item = DbWarehouse { location_id, ..DbWarehouse::from(item) };
         let created_item = DbWarehouse::create(self.tenant_id, db_item).await?;
         Ok(WarehouseItem::from(&created_item))
     }
 
+    /// Overwrites the stock of a product at `item.location_id`. Without a
+    /// location the product must be stocked at a single location, whose row is
+    /// updated; items read with `get_by_product_id` can only be written back
+    /// this way. A changed stock policy applies to all locations.
     pub async fn update_by_product_id(&self, product_id: i64, item: &WarehouseItem) -> Result<WarehouseItem, ShopsterError> {
-        let db_item = DbWarehouse::from(item);
-        let updated_item = DbWarehouse::update_by_product_id(self.tenant_id, product_id, db_item).await?;
+        self.update_by_product_id_with_reason(product_id, item, None).await
+    }
+
+    /// Like `update_by_product_id`, but records why the stock was changed in
+    /// the resulting movements.
+    pub async fn update_by_product_id_with_reason(&self, product_id: i64, item: &WarehouseItem, reason: Option<&str>) -> Result<WarehouseItem, ShopsterError> {
+        let updated_item = DbWarehouse::update_by_product_id(self.tenant_id, product_id, item.location_id, DbWarehouse::from(item), &DbMovementReference::reason(reason)).await?;
         Ok(WarehouseItem::from(&updated_item))
     }
 
+    /// Removes the stock of a product at all locations.
     pub async fn remove_by_product_id(&self, product_id: i64) -> Result<bool, ShopsterError> {
-        let result = DbWarehouse::delete_by_product_id(self.tenant_id, product_id).await?;
+        let result = DbWarehouse::delete_by_product_id(self.tenant_id, product_id, &DbMovementReference::default()).await?;
         Ok(result > 0)
     }
 
+    /// Adds goods to the stock of a product at the default location. Open
+    /// backorders are served first.
+    pub async fn receive_stock(&self, product_id: i64, quantity: i64) -> Result<WarehouseItem, ShopsterError> {
+        self.receive(product_id, None, quantity).await
+    }
+
+    /// Like `receive_stock`, but for a specific location.
+    pub async fn receive_stock_at(&self, location_id: i64, product_id: i64, quantity: i64) -> Result<WarehouseItem, ShopsterError> {
+        self.receive(product_id, Some(location_id), quantity).await
+    }
+
+    async fn receive(&self, product_id: i64, location_id: Option<i64>, quantity: i64) -> Result<WarehouseItem, ShopsterError> {
+        if quantity <= 0 {
+            return Err(ShopsterError::InvalidOperationError(
+                "Quantity must be positive".to_string(),
+            ));
+        }
+        let db_items = DbWarehouse::receive_stock(self.tenant_id, product_id, location_id, quantity, &DbMovementReference::default()).await?;
+        Ok(WarehouseItem::aggregate(&db_items))
+    }
+
+    /// Moves `quantity` unreserved units of a product from one location to
+    /// another. Returns the stock at the source and at the target location.
+    pub async fn transfer(&self, product_id: i64, from_location_id: i64, to_location_id: i64, quantity: i64) -> Result<(WarehouseItem, WarehouseItem), ShopsterError> {
+        if quantity <= 0 {
+            return Err(ShopsterError::InvalidOperationError(
+                "Quantity must be positive".to_string(),
+            ));
+        }
+        if from_location_id == to_location_id {
+            return Err(ShopsterError::InvalidOperationError(
+                "Source and target location must differ".to_string(),
+            ));
+        }
+        DbLocation::find(self.tenant_id, to_location_id).await?;
+        let (source, target) = DbWarehouse::transfer(self.tenant_id, product_id, from_location_id, to_location_id, quantity, &DbMovementReference::default()).await?;
+        Ok((WarehouseItem::from(&source), WarehouseItem::from(&target)))
+    }
+
+    /// Reserves `delta` units of a product (or releases them if negative)
+    /// according to its stock policy. Returns the stock over all locations.
     pub async fn apply_reserved_delta(&self, product_id: i64, delta: i64) -> Result<WarehouseItem, ShopsterError> {
-        let db_item = DbWarehouse::apply_reserved_delta(self.tenant_id, product_id, delta).await?;
-        Ok(WarehouseItem::from(&db_item))
+        let db_items = DbWarehouse::apply_reserved_delta(self.tenant_id, product_id, delta, &DbMovementReference::default()).await?;
+        Ok(WarehouseItem::aggregate(&db_items))
+    }
+
+    /// Returns the stock movements of a product recorded within `range`, oldest first.
+    pub async fn movements(&self, product_id: i64, range: Range<NaiveDateTime>) -> Result<Vec<StockMovement>, ShopsterError> {
+        let db_movements = DbStockMovement::get_for_product(self.tenant_id, product_id, range.start, range.end).await?;
+        Ok(db_movements.iter().map(StockMovement::from).collect())
+    }
+
+    pub async fn get_reorder_point(&self, product_id: i64) -> Result<Option<ReorderPoint>, ShopsterError> {
+        let db_reorder_point = DbReorderPoint::find_by_product_id(self.tenant_id, product_id).await?;
+        Ok(db_reorder_point.as_ref().map(ReorderPoint::from))
+    }
+
+    /// Sets the reorder point and quantity of a product, replacing earlier values.
+    pub async fn set_reorder_point(&self, product_id: i64, reorder_point: i64, reorder_quantity: i64) -> Result<ReorderPoint, ShopsterError> {
+        if reorder_point < 0 {
+            return Err(ShopsterError::InvalidOperationError(
+                "Reorder point cannot be negative".to_string(),
+            ));
+        }
+        if reorder_quantity <= 0 {
+            return Err(ShopsterError::InvalidOperationError(
+                "Reorder quantity must be positive".to_string(),
+            ));
+        }
+        let db_reorder_point = DbReorderPoint::upsert(self.tenant_id, product_id, reorder_point, reorder_quantity).await?;
+        Ok(ReorderPoint::from(&db_reorder_point))
+    }
+
+    pub async fn remove_reorder_point(&self, product_id: i64) -> Result<bool, ShopsterError> {
+        let result = DbReorderPoint::delete_by_product_id(self.tenant_id, product_id).await?;
+        Ok(result > 0)
+    }
+
+    /// Returns the products at or below their reorder point, lowest free stock first.
+    pub async fn get_low_stock(&self) -> Result<Vec<LowStockItem>, ShopsterError> {
+        let db_low_stock = DbReorderPoint::get_low_stock(self.tenant_id).await?;
+        Ok(db_low_stock.iter().map(LowStockItem::from).collect())
+    }
+
+    /// Returns the stock alerts that have not been acknowledged, oldest first.
+    pub async fn get_stock_alerts(&self) -> Result<Vec<StockAlert>, ShopsterError> {
+        let db_alerts = DbStockAlert::get_open(self.tenant_id).await?;
+        Ok(db_alerts.iter().map(StockAlert::from).collect())
+    }
+
+    pub async fn acknowledge_stock_alert(&self, alert_id: i64) -> Result<StockAlert, ShopsterError> {
+        let db_alert = DbStockAlert::acknowledge(self.tenant_id, alert_id).await?;
+        Ok(StockAlert::from(&db_alert))
+    }
+
+    async fn resolve_location(&self, location_id: Option<i64>) -> Result<i64, ShopsterError> {
+        match location_id {
+            Some(location_id) => Ok(location_id),
+            None => Ok(DbLocation::find_default(self.tenant_id).await?.id),
+        }
     }
 }
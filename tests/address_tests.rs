@@ -0,0 +1,61 @@
+use stec_shopster::addresses::{is_valid_postal_code, Address};
+
+fn make_address(country: &str, postal_code: &str) -> Address {
+    Address {
+        name: "Test Customer".to_string(),
+        company: Some("Test Company".to_string()),
+        street: "Test Street".to_string(),
+        house_number: "1a".to_string(),
+        postal_code: postal_code.to_string(),
+        city: "Testcity".to_string(),
+        country: country.to_string(),
+        phone: None,
+    }
+}
+
+#[test]
+fn address_postal_code_formats_test() {
+    assert!(is_valid_postal_code("DE", "12345"));
+    assert!(!is_valid_postal_code("DE", "1234"));
+    assert!(!is_valid_postal_code("DE", "1234A"));
+    assert!(is_valid_postal_code("AT", "1010"));
+    assert!(!is_valid_postal_code("AT", "10100"));
+    assert!(is_valid_postal_code("NL", "1234 AB"));
+    assert!(is_valid_postal_code("NL", "1234ab"));
+    assert!(!is_valid_postal_code("NL", "12345"));
+    assert!(is_valid_postal_code("PL", "00-950"));
+    assert!(!is_valid_postal_code("PL", "00950"));
+    assert!(is_valid_postal_code("US", "90210"));
+    assert!(is_valid_postal_code("US", "90210-1234"));
+    assert!(is_valid_postal_code("GB", "SW1A 1AA"));
+    assert!(is_valid_postal_code("GB", "M1 1AE"));
+    assert!(!is_valid_postal_code("GB", "12345"));
+
+    // Countries without a known format only need a plausible code
+    assert!(is_valid_postal_code("BR", "01310-100"));
+    assert!(!is_valid_postal_code("BR", ""));
+    assert!(!is_valid_postal_code("BR", "01310/100"));
+}
+
+#[test]
+fn address_validate_test() {
+    assert!(make_address("DE", "12345").validate().is_ok());
+    assert!(make_address("DE", "1234").validate().is_err());
+    assert!(make_address("de", "12345").validate().is_err());
+    assert!(make_address("DEU", "12345").validate().is_err());
+
+    let missing_city = Address { city: " ".to_string(), ..make_address("DE", "12345") };
+    assert!(missing_city.validate().is_err());
+}
+
+#[test]
+fn address_display_test() {
+    let address = make_address("DE", "12345");
+    assert_eq!(
+        "Test Customer\nTest Company\nTest Street 1a\n12345 Testcity\nDE",
+        address.to_string()
+    );
+
+    let without_company = Address { company: None, ..address };
+    assert_eq!("Test Customer\nTest Street 1a\n12345 Testcity\nDE", without_company.to_string());
+}
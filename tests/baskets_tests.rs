@@ -4,8 +4,23 @@ use chrono::Utc;
 use stec_tenet::{Storage, Tenet};
 use uuid::Uuid;
 use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::customers::Customer;
 use stec_shopster::products::{Price, Product};
-use crate::common::test_harness;
+use stec_tenet::encryption_modes::EncryptionModes;
+use crate::common::{make_product, test_harness};
+
+fn make_customer(email: &str) -> Customer {
+    Customer {
+        id: Uuid::new_v4(),
+        email: email.to_string(),
+        email_verified: true,
+        encryption_mode: EncryptionModes::Argon2,
+        password: "password123".to_string(),
+        full_name: "Basket Customer".to_string(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
 
 #[tokio::test]
 async fn basket_test() {
@@ -694,3 +709,87 @@ async fn insert_product_without_price_is_rejected_test() {
         assert!(result.is_err(), "Inserting a product without a price should be rejected");
     }).await;
 }
+
+#[tokio::test]
+async fn customer_basket_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+
+        let tenant = tenet.create_tenant("customer_basket_test".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let customers = shopster.customers(tenant.id).unwrap();
+        let customer = customers.insert(&make_customer("basket@stecug.de")).await.unwrap();
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        assert!(baskets.get_for_customer(customer.id).await.unwrap().is_none());
+
+        let basket_id = baskets.add_basket_for_customer(customer.id).await.unwrap();
+        let basket = baskets.get_for_customer(customer.id).await.unwrap().unwrap();
+        assert_eq!(basket_id, basket.id);
+        assert_eq!(Some(customer.id), basket.customer_id);
+        assert!(baskets.add_basket_for_customer(customer.id).await.is_err(), "A customer has only one basket");
+
+        let guest_basket_id = baskets.add_basket().await.unwrap();
+        assert_eq!(None, baskets.get_basket(guest_basket_id).await.unwrap().customer_id);
+
+        // Deleting the customer turns the basket into a guest basket
+        customers.remove(customer.id).await.unwrap();
+        assert_eq!(None, baskets.get_basket(basket_id).await.unwrap().customer_id);
+    }).await;
+}
+
+#[tokio::test]
+async fn merge_guest_basket_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+
+        let tenant = tenet.create_tenant("merge_guest_basket_test".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-GUEST-1", "9000000000001")).await.unwrap();
+        let socks = products.insert(&make_product("ART-GUEST-2", "9000000000002")).await.unwrap();
+
+        let customers = shopster.customers(tenant.id).unwrap();
+        let alice = customers.insert(&make_customer("alice-basket@stecug.de")).await.unwrap();
+        let bob = customers.insert(&make_customer("bob-basket@stecug.de")).await.unwrap();
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+
+        // Without a persistent basket the guest basket is adopted
+        let guest_basket_id = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(guest_basket_id, shirt.id, 1).await.unwrap();
+        let alice_basket_id = baskets.merge_guest_basket(guest_basket_id, alice.id).await.unwrap();
+        assert_eq!(guest_basket_id, alice_basket_id);
+        assert_eq!(Some(alice.id), baskets.get_basket(alice_basket_id).await.unwrap().customer_id);
+
+        // Logging in again with the own basket is a no-op
+        assert_eq!(alice_basket_id, baskets.merge_guest_basket(alice_basket_id, alice.id).await.unwrap());
+
+        // A second guest basket is folded into the persistent one
+        let second_guest_id = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(second_guest_id, shirt.id, 2).await.unwrap();
+        baskets.add_product_to_basket(second_guest_id, socks.id, 3).await.unwrap();
+        assert_eq!(alice_basket_id, baskets.merge_guest_basket(second_guest_id, alice.id).await.unwrap());
+        assert!(baskets.get_basket(second_guest_id).await.is_err());
+
+        let mut items = baskets.get_products_from_basket(alice_basket_id).await.unwrap();
+        items.sort_by_key(|item| item.product_id);
+        assert_eq!(2, items.len());
+        assert_eq!(3, items[0].quantity);
+        assert_eq!(3, items[1].quantity);
+
+        // Another customer's basket cannot be taken over
+        assert!(baskets.merge_guest_basket(alice_basket_id, bob.id).await.is_err());
+        assert!(baskets.get_for_customer(bob.id).await.unwrap().is_none());
+    }).await;
+}
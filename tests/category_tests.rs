@@ -0,0 +1,130 @@
+mod common;
+
+use chrono::Utc;
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::categories::Category;
+use stec_shopster::products::{Product, ProductVariant, VariantOption};
+use crate::common::{make_product, test_harness};
+
+fn make_category(parent_id: Option<i64>, title: &str, slug: &str, position: i32) -> Category {
+    Category {
+        id: 0,
+        parent_id,
+        title: title.to_string(),
+        slug: slug.to_string(),
+        position,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+#[tokio::test]
+async fn category_tree_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("category_tree".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let categories = shopster.categories(tenant.id).unwrap();
+        let clothing = categories.insert(&make_category(None, "Clothing", "clothing", 1)).await.unwrap();
+        let toys = categories.insert(&make_category(None, "Toys", "toys", 0)).await.unwrap();
+        let shirts = categories.insert(&make_category(Some(clothing.id), "Shirts", "shirts", 0)).await.unwrap();
+        let polos = categories.insert(&make_category(Some(shirts.id), "Polo Shirts", "polo-shirts", 0)).await.unwrap();
+
+        // Invalid slugs, duplicate slugs and unknown parents are rejected
+        assert!(categories.insert(&make_category(None, "Shoes", "Shoes!", 0)).await.is_err());
+        assert!(categories.insert(&make_category(None, "More Toys", "toys", 0)).await.is_err());
+        assert!(categories.insert(&make_category(Some(-1), "Orphan", "orphan", 0)).await.is_err());
+
+        let roots = categories.get_children(None).await.unwrap();
+        assert_eq!(vec![toys.id, clothing.id], roots.iter().map(|c| c.id).collect::<Vec<_>>());
+        assert_eq!(vec![shirts.id], categories.get_children(Some(clothing.id)).await.unwrap().iter().map(|c| c.id).collect::<Vec<_>>());
+        assert_eq!(Some(polos.id), categories.get_by_slug("polo-shirts").await.unwrap().map(|c| c.id));
+
+        let breadcrumbs = categories.get_breadcrumbs(polos.id).await.unwrap();
+        assert_eq!(vec!["clothing", "shirts", "polo-shirts"], breadcrumbs.iter().map(|c| c.slug.as_str()).collect::<Vec<_>>());
+        assert!(categories.get_breadcrumbs(-1).await.is_err());
+
+        // A category cannot be moved below itself or its descendants
+        let mut moved = clothing.clone();
+        moved.parent_id = Some(polos.id);
+        assert!(categories.update(&moved).await.is_err());
+        moved.parent_id = Some(clothing.id);
+        assert!(categories.update(&moved).await.is_err());
+
+        // Moving a subtree changes the breadcrumbs of everything below it
+        let mut shirts = shirts;
+        shirts.parent_id = Some(toys.id);
+        categories.update(&shirts).await.unwrap();
+        let breadcrumbs = categories.get_breadcrumbs(polos.id).await.unwrap();
+        assert_eq!(vec![toys.id, shirts.id, polos.id], breadcrumbs.iter().map(|c| c.id).collect::<Vec<_>>());
+
+        // Only leaves can be removed
+        assert!(categories.remove(toys.id).await.is_err());
+        assert!(categories.remove(polos.id).await.unwrap());
+        assert_eq!(3, categories.get_all().await.unwrap().len());
+    }).await;
+}
+
+#[tokio::test]
+async fn category_products_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("category_products".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let categories = shopster.categories(tenant.id).unwrap();
+        let clothing = categories.insert(&make_category(None, "Clothing", "clothing", 0)).await.unwrap();
+        let shirts = categories.insert(&make_category(Some(clothing.id), "Shirts", "shirts", 0)).await.unwrap();
+        let polos = categories.insert(&make_category(Some(shirts.id), "Polo Shirts", "polo-shirts", 0)).await.unwrap();
+
+        let products = shopster.products(tenant.id).unwrap();
+        let jacket = products.insert(&make_product("ART-CAT-1", "7300000000001")).await.unwrap();
+        let shirt = products.insert(&make_product("ART-CAT-2", "7300000000002")).await.unwrap();
+        let polo = products.insert(&make_product("ART-CAT-3", "7300000000003")).await.unwrap();
+
+        categories.add_product(clothing.id, jacket.id).await.unwrap();
+        categories.add_product(shirts.id, shirt.id).await.unwrap();
+        categories.add_product(polos.id, polo.id).await.unwrap();
+        categories.add_product(shirts.id, polo.id).await.unwrap();
+        // Assigning twice has no effect
+        categories.add_product(polos.id, polo.id).await.unwrap();
+
+        let ids = |products: Vec<Product>| products.iter().map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(vec![jacket.id], ids(categories.get_products(clothing.id, false).await.unwrap()));
+        assert_eq!(vec![jacket.id, shirt.id, polo.id], ids(categories.get_products(clothing.id, true).await.unwrap()));
+        assert_eq!(vec![shirt.id, polo.id], ids(categories.get_products(shirts.id, true).await.unwrap()));
+        assert_eq!(2, categories.get_for_product(polo.id).await.unwrap().len());
+
+        // Variants are categorised through their parent
+        let variant = products.insert_variant(&ProductVariant {
+            id: 0,
+            parent_id: polo.id,
+            article_number: "ART-CAT-3-M".to_string(),
+            gtin: "7300000000004".to_string(),
+            options: vec![VariantOption { name: "Size".to_string(), value: "M".to_string() }],
+            price: None,
+            weight: None,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }).await.unwrap();
+        assert!(categories.add_product(polos.id, variant.id).await.is_err());
+
+        assert!(categories.remove_product(shirts.id, polo.id).await.unwrap());
+        assert!(!categories.remove_product(shirts.id, polo.id).await.unwrap());
+        assert_eq!(vec![polos.id], categories.get_for_product(polo.id).await.unwrap().iter().map(|c| c.id).collect::<Vec<_>>());
+
+        // Removing a product removes its assignments
+        products.remove(shirt.id).await.unwrap();
+        assert_eq!(vec![polo.id], ids(categories.get_products(shirts.id, true).await.unwrap()));
+    }).await;
+}
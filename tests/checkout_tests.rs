@@ -0,0 +1,310 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use stec_tenet::{Storage, Tenet};
+use stec_tenet::encryption_modes::EncryptionModes;
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::customers::Customer;
+use stec_shopster::error::{ShopsterError, StockShortage};
+use stec_shopster::warehouse::{StockPolicy, Warehouse, WarehouseItem};
+use crate::common::{make_address, make_product, test_harness};
+
+async fn stock(warehouse: &Warehouse, product_id: i64, in_stock: i64) {
+    stock_with_policy(warehouse, product_id, in_stock, StockPolicy::Deny).await;
+}
+
+async fn stock_with_policy(warehouse: &Warehouse, product_id: i64, in_stock: i64, stock_policy: StockPolicy) {
+    warehouse.insert(&WarehouseItem {
+        id: 0,
+        product_id,
+        in_stock,
+        reserved: 0,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        stock_policy,
+        backordered: 0,
+        location_id: None,
+    }).await.unwrap();
+}
+
+#[tokio::test]
+async fn checkout_reserves_stock_and_deletes_guest_basket_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("checkout_reserves_stock".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-CHK-1", "7100000000001")).await.unwrap();
+        let socks = products.insert(&make_product("ART-CHK-2", "7100000000002")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        stock(&warehouse, shirt.id, 5).await;
+        stock(&warehouse, socks.id, 2).await;
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        let basket_id = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(basket_id, shirt.id, 3).await.unwrap();
+        baskets.add_product_to_basket(basket_id, socks.id, 2).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let order = orders.create_from_basket(basket_id, make_address(), make_address(), None).await.unwrap();
+        assert_eq!(2, order.items.len());
+
+        assert_eq!(3, warehouse.get_by_product_id(shirt.id).await.unwrap().reserved);
+        assert_eq!(2, warehouse.get_by_product_id(socks.id).await.unwrap().reserved);
+        assert!(baskets.get_basket(basket_id).await.is_err(), "Guest baskets are deleted on checkout");
+
+        // An empty basket cannot be checked out
+        let empty_basket_id = baskets.add_basket().await.unwrap();
+        assert!(orders.create_from_basket(empty_basket_id, make_address(), make_address(), None).await.is_err());
+    }).await;
+}
+
+#[tokio::test]
+async fn checkout_rejects_insufficient_stock_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("checkout_insufficient_stock".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-CHK-3", "7100000000003")).await.unwrap();
+        let socks = products.insert(&make_product("ART-CHK-4", "7100000000004")).await.unwrap();
+        let hat = products.insert(&make_product("ART-CHK-5", "7100000000005")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        stock(&warehouse, shirt.id, 5).await;
+        stock(&warehouse, socks.id, 4).await;
+        warehouse.apply_reserved_delta(socks.id, 3).await.unwrap();
+        // The hat has no warehouse row at all
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        let basket_id = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(basket_id, shirt.id, 2).await.unwrap();
+        let socks_line = baskets.add_product_to_basket(basket_id, socks.id, 2).await.unwrap();
+        let hat_line = baskets.add_product_to_basket(basket_id, hat.id, 1).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let result = orders.create_from_basket(basket_id, make_address(), make_address(), None).await;
+
+        match result {
+            Err(ShopsterError::InsufficientStockError(mut shortages)) => {
+                shortages.sort_by_key(|s| s.basket_product_id);
+                assert_eq!(vec![
+                    StockShortage { basket_product_id: socks_line, product_id: socks.id, requested: 2, available: 1 },
+                    StockShortage { basket_product_id: hat_line, product_id: hat.id, requested: 1, available: 0 },
+                ], shortages);
+            },
+            Err(e) => panic!("Unexpected error: {:?}", e),
+            Ok(_) => panic!("Checkout must fail"),
+        }
+
+        // Nothing was changed by the failed checkout
+        assert_eq!(0, warehouse.get_by_product_id(shirt.id).await.unwrap().reserved);
+        assert_eq!(3, warehouse.get_by_product_id(socks.id).await.unwrap().reserved);
+        assert_eq!(3, baskets.get_basket(basket_id).await.unwrap().products.len());
+        assert!(orders.get_all().await.unwrap().is_empty());
+    }).await;
+}
+
+#[tokio::test]
+async fn checkout_empties_customer_basket_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("checkout_customer_basket".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-CHK-6", "7100000000006")).await.unwrap();
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        stock(&warehouse, shirt.id, 5).await;
+
+        let customers = shopster.customers(tenant.id).unwrap();
+        let customer = customers.insert(&Customer {
+            id: Uuid::new_v4(),
+            email: "checkout@example.com".to_string(),
+            email_verified: true,
+            encryption_mode: EncryptionModes::Argon2,
+            password: "password123".to_string(),
+            full_name: "Checkout Customer".to_string(),
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }).await.unwrap();
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        let basket_id = baskets.add_basket_for_customer(customer.id).await.unwrap();
+        baskets.add_product_to_basket(basket_id, shirt.id, 1).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        orders.create_from_basket(basket_id, make_address(), make_address(), None).await.unwrap();
+
+        let basket = baskets.get_for_customer(customer.id).await.unwrap().unwrap();
+        assert_eq!(basket_id, basket.id, "Persistent baskets are kept");
+        assert!(basket.products.is_empty());
+    }).await;
+}
+
+#[tokio::test]
+async fn concurrent_checkout_of_last_unit_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("checkout_concurrent".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-CHK-7", "7100000000007")).await.unwrap();
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        stock(&warehouse, shirt.id, 1).await;
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        let first_basket = baskets.add_basket().await.unwrap();
+        let second_basket = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(first_basket, shirt.id, 1).await.unwrap();
+        baskets.add_product_to_basket(second_basket, shirt.id, 1).await.unwrap();
+
+        let first_orders = shopster.orders(tenant.id).unwrap();
+        let second_orders = shopster.orders(tenant.id).unwrap();
+        let (first, second) = tokio::join!(
+            first_orders.create_from_basket(first_basket, make_address(), make_address(), None),
+            second_orders.create_from_basket(second_basket, make_address(), make_address(), None),
+        );
+
+        assert_eq!(1, [first.is_ok(), second.is_ok()].iter().filter(|ok| **ok).count());
+        assert_eq!(1, warehouse.get_by_product_id(shirt.id).await.unwrap().reserved);
+    }).await;
+}
+
+#[tokio::test]
+async fn checkout_backorders_and_unlimited_products_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("checkout_backorder".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-CHK-8", "7100000000008")).await.unwrap();
+        let ebook = products.insert(&make_product("ART-CHK-9", "7100000000009")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        stock_with_policy(&warehouse, shirt.id, 1, StockPolicy::Backorder).await;
+        stock_with_policy(&warehouse, ebook.id, 0, StockPolicy::Unlimited).await;
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        let basket_id = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(basket_id, shirt.id, 3).await.unwrap();
+        baskets.add_product_to_basket(basket_id, ebook.id, 2).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        orders.create_from_basket(basket_id, make_address(), make_address(), None).await.unwrap();
+
+        let shirt_stock = warehouse.get_by_product_id(shirt.id).await.unwrap();
+        assert_eq!((1, 2), (shirt_stock.reserved, shirt_stock.backordered));
+        let ebook_stock = warehouse.get_by_product_id(ebook.id).await.unwrap();
+        assert_eq!((2, 0), (ebook_stock.reserved, ebook_stock.backordered));
+    }).await;
+}
+
+#[tokio::test]
+async fn basket_reservations_hold_stock_until_expiry_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("basket_reservations".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-CHK-20", "7100000000020")).await.unwrap();
+        let poster = products.insert(&make_product("ART-CHK-21", "7100000000021")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        stock(&warehouse, shirt.id, 5).await;
+        stock_with_policy(&warehouse, poster.id, 1, StockPolicy::Backorder).await;
+
+        // Disabled by default: baskets do not touch the warehouse
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        assert_eq!(None, baskets.reservation_ttl().await.unwrap());
+        let first = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(first, shirt.id, 1).await.unwrap();
+        assert_eq!(0, warehouse.get_by_product_id(shirt.id).await.unwrap().reserved);
+
+        assert!(baskets.set_reservation_ttl(Some(Duration::seconds(10))).await.is_err());
+        baskets.set_reservation_ttl(Some(Duration::minutes(15))).await.unwrap();
+        assert_eq!(Some(Duration::minutes(15)), baskets.reservation_ttl().await.unwrap());
+
+        let first_line = baskets.add_product_to_basket(first, shirt.id, 4).await.unwrap();
+        assert_eq!(4, warehouse.get_by_product_id(shirt.id).await.unwrap().reserved);
+
+        // Another basket cannot take the held units
+        let second = baskets.add_basket().await.unwrap();
+        match baskets.add_product_to_basket(second, shirt.id, 2).await {
+            Err(ShopsterError::InsufficientStockError(shortages)) => {
+                assert_eq!((shirt.id, 2, 1), (shortages[0].product_id, shortages[0].requested, shortages[0].available));
+            },
+            _ => panic!("Expected InsufficientStockError"),
+        }
+        assert!(baskets.get_products_from_basket(second).await.unwrap().is_empty());
+        let second_line = baskets.add_product_to_basket(second, shirt.id, 1).await.unwrap();
+
+        // Holds follow the quantity
+        baskets.update_product_quantity(first, first_line, 2).await.unwrap();
+        baskets.update_product_quantity(second, second_line, 3).await.unwrap();
+        assert_eq!(5, warehouse.get_by_product_id(shirt.id).await.unwrap().reserved);
+        baskets.remove_product_from_basket(first, first_line).await.unwrap();
+        assert_eq!(3, warehouse.get_by_product_id(shirt.id).await.unwrap().reserved);
+
+        // Holds never backorder
+        baskets.add_product_to_basket(second, poster.id, 3).await.unwrap();
+        let poster_stock = warehouse.get_by_product_id(poster.id).await.unwrap();
+        assert_eq!((1, 0), (poster_stock.reserved, poster_stock.backordered));
+
+        // Merging moves the holds along with the lines
+        baskets.merge_baskets(second, first).await.unwrap();
+        let lines = baskets.get_products_from_basket(first).await.unwrap();
+        let held: i64 = lines.iter().map(|line| line.reserved_quantity).sum();
+        assert_eq!(4, held);
+        assert!(lines.iter().all(|line| line.reserved_until.is_some()));
+        assert_eq!(3, warehouse.get_by_product_id(shirt.id).await.unwrap().reserved);
+
+        // The sweep releases expired holds and keeps the lines
+        assert_eq!(0, baskets.release_expired_reservations(Utc::now().naive_utc()).await.unwrap());
+        let later = (Utc::now() + Duration::minutes(16)).naive_utc();
+        assert_eq!(2, baskets.release_expired_reservations(later).await.unwrap());
+        assert_eq!(0, baskets.release_expired_reservations(later).await.unwrap());
+        assert_eq!(0, warehouse.get_by_product_id(shirt.id).await.unwrap().reserved);
+        assert_eq!(0, warehouse.get_by_product_id(poster.id).await.unwrap().reserved);
+        assert_eq!(2, baskets.get_products_from_basket(first).await.unwrap().len());
+
+        // Checkout turns the basket's hold into the order's reservation
+        let third = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(third, shirt.id, 2).await.unwrap();
+        baskets.clear_basket(first).await.unwrap();
+        let orders = shopster.orders(tenant.id).unwrap();
+        orders.create_from_basket(third, make_address(), make_address(), None).await.unwrap();
+        assert_eq!(2, warehouse.get_by_product_id(shirt.id).await.unwrap().reserved);
+    }).await;
+}
@@ -1,8 +1,14 @@
+// Not every test binary uses every helper.
+#![allow(dead_code)]
+
+use chrono::Utc;
 use tokio::sync::OnceCell;
 use uuid::Uuid;
 use testcontainers_modules::postgres::Postgres;
 use testcontainers_modules::testcontainers::ContainerAsync;
 use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use stec_shopster::addresses::Address;
+use stec_shopster::products::{Price, Product};
 
 /// Holds the two Postgres containers (tenet + shopster) shared by every test
 /// in this binary. Started once on first use and kept alive for the lifetime
@@ -60,3 +66,36 @@ where
 
     test_code(shared.tenet_connection_string.clone(), shopster_connection_string1, shopster_connection_string2).await;
 }
+
+/// An address that passes `Address::validate`.
+pub fn make_address() -> Address {
+    Address {
+        name: "Test Customer".to_string(),
+        company: None,
+        street: "Test Street".to_string(),
+        house_number: "1".to_string(),
+        postal_code: "12345".to_string(),
+        city: "Testcity".to_string(),
+        country: "DE".to_string(),
+        phone: None,
+    }
+}
+
+/// A plain product priced at 5.00 EUR, without tags.
+pub fn make_product(article_number: &str, gtin: &str) -> Product {
+    Product {
+        id: 0,
+        article_number: article_number.to_string(),
+        title: "Test Product".to_string(),
+        gtin: gtin.to_string(),
+        short_description: "Short".to_string(),
+        description: "Description".to_string(),
+        image_url: "/images/test.png".to_string(),
+        additional_images: Vec::new(),
+        price: Some(Price { amount: 500, currency: "EUR".to_string() }),
+        weight: 100,
+        tags: Vec::new(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
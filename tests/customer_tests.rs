@@ -3,11 +3,38 @@ mod common;
 use stec_tenet::{Storage, Tenet};
 use stec_tenet::encryption_modes::EncryptionModes;
 use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::addresses::Address;
 use stec_shopster::customers::{Customer, CustomerProfile};
 use uuid::Uuid;
 
 use crate::common::{test_harness, test_harness_two_tenants};
 
+fn make_customer(email: &str) -> Customer {
+    Customer {
+        id: Default::default(),
+        email: email.to_string(),
+        email_verified: true,
+        encryption_mode: EncryptionModes::Argon2,
+        password: "1234567890".to_string(),
+        full_name: "Dummy Testuser".to_string(),
+        created_at: Default::default(),
+        updated_at: None,
+    }
+}
+
+fn make_address(street: &str) -> Address {
+    Address {
+        name: "Dummy Testuser".to_string(),
+        company: None,
+        street: street.to_string(),
+        house_number: "1".to_string(),
+        postal_code: "12345".to_string(),
+        city: "Testcity".to_string(),
+        country: "DE".to_string(),
+        phone: None,
+    }
+}
+
 #[tokio::test]
 async fn customer_test() {
     test_harness(|tenet_connection_string, shopster_connection_string| async move {
@@ -832,3 +859,86 @@ async fn tenant_isolation_test() {
         customers2.remove(tenant2_customers[0].id).await.unwrap();
     }).await;
 }
+
+#[tokio::test]
+async fn customer_address_book_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+
+        let tenant = tenet.create_tenant("customer_address_book".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let customers = shopster.customers(tenant.id).unwrap();
+        let customer = customers.insert(&make_customer("addresses@stecug.de")).await.unwrap();
+        assert!(customers.get_addresses(customer.id).await.unwrap().is_empty());
+        assert!(customers.get_default_billing_address(customer.id).await.unwrap().is_none());
+
+        // The first address becomes the default for both billing and shipping
+        let home = customers.add_address(customer.id, &make_address("Home Street")).await.unwrap();
+        assert!(home.is_default_billing);
+        assert!(home.is_default_shipping);
+
+        let office = customers.add_address(customer.id, &make_address("Office Street")).await.unwrap();
+        assert!(!office.is_default_billing);
+        assert!(!office.is_default_shipping);
+        assert_eq!(2, customers.get_addresses(customer.id).await.unwrap().len());
+
+        customers.set_default_shipping_address(customer.id, office.id).await.unwrap();
+        assert_eq!(office.id, customers.get_default_shipping_address(customer.id).await.unwrap().unwrap().id);
+        assert_eq!(home.id, customers.get_default_billing_address(customer.id).await.unwrap().unwrap().id);
+        assert!(!customers.get_address(customer.id, home.id).await.unwrap().unwrap().is_default_shipping);
+
+        let updated = customers.update_address(customer.id, office.id, &make_address("New Office Street")).await.unwrap();
+        assert_eq!("New Office Street", updated.address.street);
+        assert!(updated.is_default_shipping);
+        assert!(updated.updated_at.is_some());
+
+        assert!(customers.add_address(customer.id, &Address { postal_code: "1234".to_string(), ..make_address("Bad Street") }).await.is_err());
+        assert!(customers.update_address(customer.id, office.id, &Address { country: "Germany".to_string(), ..make_address("Bad Street") }).await.is_err());
+
+        assert!(customers.remove_address(customer.id, office.id).await.unwrap());
+        assert!(!customers.remove_address(customer.id, office.id).await.unwrap());
+        assert!(customers.get_default_shipping_address(customer.id).await.unwrap().is_none());
+        assert_eq!(1, customers.get_addresses(customer.id).await.unwrap().len());
+    }).await;
+}
+
+#[tokio::test]
+async fn customer_address_ownership_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+
+        let tenant = tenet.create_tenant("customer_address_ownership".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let customers = shopster.customers(tenant.id).unwrap();
+        let alice = customers.insert(&make_customer("alice@stecug.de")).await.unwrap();
+        let bob = customers.insert(&make_customer("bob@stecug.de")).await.unwrap();
+
+        let alice_home = customers.add_address(alice.id, &make_address("Alice Street")).await.unwrap();
+        let bob_home = customers.add_address(bob.id, &make_address("Bob Street")).await.unwrap();
+        assert!(bob_home.is_default_billing, "Defaults are tracked per customer");
+
+        // Addresses of other customers can neither be read nor changed
+        assert!(customers.get_address(bob.id, alice_home.id).await.unwrap().is_none());
+        assert!(customers.update_address(bob.id, alice_home.id, &make_address("Stolen Street")).await.is_err());
+        assert!(customers.set_default_billing_address(bob.id, alice_home.id).await.is_err());
+        assert!(!customers.remove_address(bob.id, alice_home.id).await.unwrap());
+
+        // A failed default change keeps the previous default
+        assert_eq!(bob_home.id, customers.get_default_billing_address(bob.id).await.unwrap().unwrap().id);
+        assert_eq!("Alice Street", customers.get_address(alice.id, alice_home.id).await.unwrap().unwrap().address.street);
+
+        // Deleting the customer deletes the address book
+        customers.remove(alice.id).await.unwrap();
+        assert!(customers.get_addresses(alice.id).await.unwrap().is_empty());
+    }).await;
+}
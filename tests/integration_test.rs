@@ -4,7 +4,7 @@
 //! `docker ps` to verify Docker is available
 //! `cargo test --test integration_test` to run these tests
 
-use stec_shopster::{Shopster, DatabaseSelector, orders::Order, orders::OrderStatus, orders::PaymentStatus};
+use stec_shopster::{Shopster, DatabaseSelector, addresses::Address, orders::Order, orders::OrderStatus, orders::PaymentStatus};
 use stec_tenet::{Tenet, Storage};
 use uuid::Uuid;
 
@@ -53,7 +53,7 @@ async fn settings_get_all() {
         let settings = shopster.settings(tenant.id).unwrap().get_all().await;
 
         assert!(settings.is_ok());
-        assert_eq!(14, settings.unwrap().len());
+        assert_eq!(20, settings.unwrap().len());
     }).await;
 }
 
@@ -74,13 +74,32 @@ async fn order_test() {
             id: 0,
             customer_id: None,
             status: OrderStatus::New,
-            delivery_address: "Duffy Duck, Duck road 22, 44444 Duckhousen".to_string(),
-            billing_address: "Duffy Duck, Duck road 22, 44444 Duckhousen".to_string(),
+            delivery_address: Address {
+                name: "Duffy Duck".to_string(),
+                company: None,
+                street: "Duck road".to_string(),
+                house_number: "22".to_string(),
+                postal_code: "44444".to_string(),
+                city: "Duckhousen".to_string(),
+                country: "DE".to_string(),
+                phone: None,
+            },
+            billing_address: Address {
+                name: "Duffy Duck".to_string(),
+                company: None,
+                street: "Duck road".to_string(),
+                house_number: "22".to_string(),
+                postal_code: "44444".to_string(),
+                city: "Duckhousen".to_string(),
+                country: "DE".to_string(),
+                phone: None,
+            },
             items: Vec::new(),
             created_at: Default::default(),
             updated_at: None,
             payment_reference: None,
             payment_status: PaymentStatus::Pending,
+            order_number: None,
         };
 
         let _ = orders.insert(&new_order).await.unwrap();
@@ -95,7 +114,16 @@ async fn order_test() {
 
         let updated_order = all_orders.get_mut(0).unwrap();
         updated_order.status = OrderStatus::InProgress;
-        updated_order.delivery_address = "Bugs Bunny, Bunny road 44, 55555 Bunnycity".to_string();
+        updated_order.delivery_address = Address {
+            name: "Bugs Bunny".to_string(),
+            company: None,
+            street: "Bunny road".to_string(),
+            house_number: "44".to_string(),
+            postal_code: "55555".to_string(),
+            city: "Bunnycity".to_string(),
+            country: "DE".to_string(),
+            phone: None,
+        };
 
         orders.update(updated_order).await.unwrap();
 
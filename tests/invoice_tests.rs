@@ -0,0 +1,245 @@
+mod common;
+
+use std::convert::TryFrom;
+use chrono::Utc;
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, DbInvoiceKind, Shopster};
+use stec_shopster::addresses::Address;
+use stec_shopster::invoices::InvoiceKind;
+use stec_shopster::orders::{Order, OrderItemPrice, OrderItemSnapshot, OrderStatus, Orders, PaymentStatus, RefundLine};
+use stec_shopster::products::{Price, Product};
+use stec_shopster::warehouse::{StockPolicy, WarehouseItem};
+use crate::common::{make_address, make_product, test_harness};
+
+fn make_priced_product(article_number: &str, gtin: &str, amount: i64) -> Product {
+    Product {
+        title: "Invoice Test Product".to_string(),
+        price: Some(Price { amount, currency: "EUR".to_string() }),
+        ..make_product(article_number, gtin)
+    }
+}
+
+fn make_item(product: &Product, quantity: i64) -> OrderItemSnapshot {
+    OrderItemSnapshot {
+        id: 0,
+        product_id: product.id,
+        quantity,
+        article_number: product.article_number.clone(),
+        gtin: product.gtin.clone(),
+        title: product.title.clone(),
+        short_description: product.short_description.clone(),
+        description: product.description.clone(),
+        tags: vec![],
+        title_image: product.image_url.clone(),
+        additional_images: vec![],
+        price: OrderItemPrice {
+            amount: product.price.as_ref().unwrap().amount,
+            currency: "EUR".to_string(),
+        },
+        weight: product.weight,
+        fulfilled_quantity: 0,
+        variant_options: Vec::new(),
+    }
+}
+
+/// Creates an order with 2 x 11.90 EUR and 1 x 5.95 EUR (29.75 EUR gross).
+async fn make_order(shopster: &Shopster, tenant_id: uuid::Uuid, gtin_prefix: &str) -> (Orders, Order) {
+    let products = shopster.products(tenant_id).unwrap();
+    let shirt = products.insert(&make_priced_product(&format!("ART-{}1", gtin_prefix), &format!("{}1", gtin_prefix), 1190)).await.unwrap();
+    let socks = products.insert(&make_priced_product(&format!("ART-{}2", gtin_prefix), &format!("{}2", gtin_prefix), 595)).await.unwrap();
+
+    let warehouse = shopster.warehouse(tenant_id).unwrap();
+    for product_id in [shirt.id, socks.id] {
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id,
+            in_stock: 10,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+    }
+
+    let order = Order {
+        id: 0,
+        customer_id: None,
+        status: OrderStatus::New,
+        delivery_address: make_address(),
+        billing_address: Address {
+            street: "Billing <Street>".to_string(),
+            house_number: "2".to_string(),
+            ..make_address()
+        },
+        items: vec![make_item(&shirt, 2), make_item(&socks, 1)],
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        payment_reference: None,
+        payment_status: PaymentStatus::Pending,
+        order_number: None,
+    };
+
+    let orders = shopster.orders(tenant_id).unwrap();
+    let order = orders.insert(&order).await.unwrap();
+    (orders, order)
+}
+
+#[tokio::test]
+async fn invoice_issue_with_vat_breakdown_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("invoice_issue_vat".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let (_, order) = make_order(&shopster, tenant.id, "740000000000").await;
+
+        let invoices = shopster.invoices(tenant.id).unwrap();
+        let invoice = invoices.issue(order.id).await.unwrap();
+
+        let year = Utc::now().format("%Y").to_string();
+        assert_eq!(format!("RE-{}-000001", year), invoice.invoice_number);
+        assert_eq!(InvoiceKind::Invoice, invoice.kind);
+        assert_eq!("EUR", invoice.currency);
+        assert_eq!(19.0, invoice.vat_rate);
+        assert_eq!(2, invoice.lines.len());
+
+        assert_eq!(2380, invoice.lines[0].gross_amount);
+        assert_eq!(2000, invoice.lines[0].net_amount);
+        assert_eq!(380, invoice.lines[0].vat_amount);
+        assert_eq!(595, invoice.lines[1].gross_amount);
+        assert_eq!(500, invoice.lines[1].net_amount);
+
+        assert_eq!(2975, invoice.gross_total);
+        assert_eq!(2500, invoice.net_total);
+        assert_eq!(475, invoice.vat_total);
+
+        let fetched = invoices.get_by_number(&invoice.invoice_number).await.unwrap().unwrap();
+        assert_eq!(invoice, fetched);
+        assert!(invoices.issue(order.id).await.is_err(), "An order can only be invoiced once");
+        assert_eq!(1, invoices.get_for_order(order.id).await.unwrap().len());
+    }).await;
+}
+
+#[tokio::test]
+async fn invoice_rejected_for_cancelled_order_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("invoice_cancelled_order".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let (orders, mut order) = make_order(&shopster, tenant.id, "740000000001").await;
+        order.status = OrderStatus::Cancelled;
+        orders.update(&order).await.unwrap();
+
+        let invoices = shopster.invoices(tenant.id).unwrap();
+        assert!(invoices.issue(order.id).await.is_err());
+        assert!(invoices.get_for_order(order.id).await.unwrap().is_empty());
+    }).await;
+}
+
+#[tokio::test]
+async fn invoice_credit_note_for_refund_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("invoice_credit_note".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let (orders, order) = make_order(&shopster, tenant.id, "740000000002").await;
+        orders.update_payment_status(order.id, PaymentStatus::Paid).await.unwrap();
+
+        let refund = orders.refund(
+            order.id,
+            &[RefundLine { id: 0, order_item_id: order.items[0].id, quantity: 1 }],
+            1190,
+            "Damaged".to_string(),
+            None,
+        ).await.unwrap();
+
+        let invoices = shopster.invoices(tenant.id).unwrap();
+        assert!(invoices.issue_credit_note(refund.id).await.is_err(), "Credit notes need an invoice to correct");
+
+        let invoice = invoices.issue(order.id).await.unwrap();
+        let credit_note = invoices.issue_credit_note(refund.id).await.unwrap();
+
+        let year = Utc::now().format("%Y").to_string();
+        assert_eq!(format!("GS-{}-000001", year), credit_note.invoice_number);
+        assert_eq!(InvoiceKind::CreditNote, credit_note.kind);
+        assert_eq!(Some(invoice.id), credit_note.corrected_invoice_id);
+        assert_eq!(Some(refund.id), credit_note.refund_id);
+        assert_eq!(1, credit_note.lines.len());
+        assert_eq!(-1190, credit_note.gross_total);
+        assert_eq!(-1000, credit_note.net_total);
+        assert_eq!(-190, credit_note.vat_total);
+        assert!(invoices.issue_credit_note(refund.id).await.is_err(), "Each refund is credited once");
+
+        // A goodwill refund exceeding the value of its lines gets an adjustment line
+        let goodwill = orders.refund(order.id, &[], 500, "Goodwill".to_string(), None).await.unwrap();
+        let adjustment = invoices.issue_credit_note(goodwill.id).await.unwrap();
+        assert_eq!(format!("GS-{}-000002", year), adjustment.invoice_number);
+        assert_eq!(1, adjustment.lines.len());
+        assert_eq!(None, adjustment.lines[0].order_item_id);
+        assert_eq!("Refund: Goodwill", adjustment.lines[0].title);
+        assert_eq!(-500, adjustment.gross_total);
+
+        assert_eq!(3, invoices.get_for_order(order.id).await.unwrap().len());
+    }).await;
+}
+
+#[tokio::test]
+async fn invoice_rendering_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("invoice_rendering".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let (_, order) = make_order(&shopster, tenant.id, "740000000003").await;
+        let invoices = shopster.invoices(tenant.id).unwrap();
+        let invoice = invoices.issue(order.id).await.unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&invoice.to_json().unwrap()).unwrap();
+        assert_eq!(invoice.invoice_number, json["invoice_number"]);
+        assert_eq!(2975, json["gross_total"]);
+        assert_eq!(2, json["lines"].as_array().unwrap().len());
+
+        let text = invoice.render_text();
+        assert!(text.contains(&format!("Invoice {}", invoice.invoice_number)));
+        assert!(text.contains("Net: 25.00 EUR"));
+        assert!(text.contains("VAT 19%: 4.75 EUR"));
+        assert!(text.contains("Total: 29.75 EUR"));
+
+        let html = invoice.render_html();
+        assert!(html.contains("Billing &lt;Street&gt; 2"), "HTML output must be escaped");
+        assert!(html.contains("<strong>Total: 29.75 EUR</strong>"));
+    }).await;
+}
+
+/// Test conversions between i32 values and DbInvoiceKind
+#[test]
+fn test_invoice_kind_conversions() {
+    assert_eq!(DbInvoiceKind::try_from(0).unwrap(), DbInvoiceKind::Invoice);
+    assert_eq!(DbInvoiceKind::try_from(1).unwrap(), DbInvoiceKind::CreditNote);
+    assert!(DbInvoiceKind::try_from(2).is_err());
+    assert!(DbInvoiceKind::try_from(-1).is_err());
+
+    for kind in [DbInvoiceKind::Invoice, DbInvoiceKind::CreditNote] {
+        assert_eq!(kind, DbInvoiceKind::try_from(i32::from(&kind)).unwrap());
+    }
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::addresses::Address;
+use stec_shopster::locations::{AllocationStrategy, Location};
+use stec_shopster::warehouse::{StockMovementKind, StockPolicy, WarehouseItem};
+use crate::common::{make_product, test_harness};
+
+fn make_address(postal_code: &str, city: &str) -> Address {
+    Address {
+        name: "Test Recipient".to_string(),
+        company: None,
+        street: "Test Street".to_string(),
+        house_number: "1".to_string(),
+        postal_code: postal_code.to_string(),
+        city: city.to_string(),
+        country: "DE".to_string(),
+        phone: None,
+    }
+}
+
+fn make_location(name: &str, address: Option<Address>, priority: i32) -> Location {
+    Location {
+        id: 0,
+        name: name.to_string(),
+        address,
+        priority,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+fn make_stock(product_id: i64, in_stock: i64, location_id: Option<i64>) -> WarehouseItem {
+    WarehouseItem {
+        id: 0,
+        product_id,
+        in_stock,
+        reserved: 0,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        stock_policy: StockPolicy::Deny,
+        backordered: 0,
+        location_id,
+    }
+}
+
+#[tokio::test]
+async fn locations_transfer_and_aggregate_stock_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("locations_transfer".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let locations = shopster.locations(tenant.id).unwrap();
+        let default_location = locations.get_all().await.unwrap().remove(0);
+        assert_eq!("Default", default_location.name);
+        assert!(locations.insert(&make_location(" ", None, 1)).await.is_err());
+        let store = locations.insert(&make_location("Store", Some(make_address("10115", "Berlin")), 10)).await.unwrap();
+        assert_eq!(AllocationStrategy::Priority, locations.allocation_strategy().await.unwrap());
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-LOC-001", "3000000000001")).await.unwrap();
+
+        // Stock without a location is kept at the default location
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        let created = warehouse.insert(&make_stock(product.id, 10, None)).await.unwrap();
+        assert_eq!(Some(default_location.id), created.location_id);
+
+        let item = warehouse.receive_stock_at(store.id, product.id, 4).await.unwrap();
+        assert_eq!((14, None), (item.in_stock, item.location_id));
+
+        let (source, target) = warehouse.transfer(product.id, default_location.id, store.id, 3).await.unwrap();
+        assert_eq!((7, 7), (source.in_stock, target.in_stock));
+        assert!(warehouse.transfer(product.id, default_location.id, store.id, 8).await.is_err());
+        assert!(warehouse.transfer(product.id, store.id, store.id, 1).await.is_err());
+
+        // Reservations fill the locations by priority
+        let item = warehouse.apply_reserved_delta(product.id, 9).await.unwrap();
+        assert_eq!((14, 9), (item.in_stock, item.reserved));
+        let by_location: Vec<_> = warehouse.get_stock_by_location(product.id).await.unwrap().iter()
+            .map(|item| (item.location_id.unwrap(), item.in_stock, item.reserved))
+            .collect();
+        assert_eq!(vec![(default_location.id, 7, 7), (store.id, 7, 2)], by_location);
+        assert!(warehouse.apply_reserved_delta(product.id, 6).await.is_err());
+
+        // Reserved stock cannot be transferred
+        assert!(warehouse.transfer(product.id, store.id, default_location.id, 6).await.is_err());
+        assert_eq!(1, warehouse.get_by_location(store.id).await.unwrap().len());
+        assert_eq!(1, warehouse.get_all().await.unwrap().len());
+
+        // The summed up stock cannot be written back, only the row of a location
+        let total = warehouse.get_by_product_id(product.id).await.unwrap();
+        assert!(warehouse.update_by_product_id(product.id, &WarehouseItem { in_stock: 20, ..total }).await.is_err());
+        let store_stock = warehouse.get_stock_by_location(product.id).await.unwrap().remove(1);
+        let updated = warehouse.update_by_product_id(product.id, &WarehouseItem { in_stock: 8, ..store_stock.clone() }).await.unwrap();
+        assert_eq!((store_stock.id, Some(store.id), 8, 2), (updated.id, updated.location_id, updated.in_stock, updated.reserved));
+        assert_eq!(store_stock.created_at, updated.created_at);
+        let total = warehouse.get_by_product_id(product.id).await.unwrap();
+        assert_eq!((15, 9), (total.in_stock, total.reserved));
+
+        let before = (Utc::now() - Duration::hours(1)).naive_utc();
+        let after = (Utc::now() + Duration::hours(1)).naive_utc();
+        let transfers: Vec<_> = warehouse.movements(product.id, before..after).await.unwrap().iter()
+            .filter(|m| m.kind == StockMovementKind::Transfer)
+            .map(|m| (m.location_id, m.quantity))
+            .collect();
+        assert_eq!(vec![(Some(default_location.id), -3), (Some(store.id), 3)], transfers);
+
+        // A location can only be removed once it holds no stock
+        assert!(locations.remove(store.id).await.is_err());
+        let empty = locations.insert(&make_location("Empty", None, 20)).await.unwrap();
+        assert!(locations.remove(empty.id).await.unwrap());
+    }).await;
+}
+
+#[tokio::test]
+async fn nearest_allocation_strategy_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("locations_nearest".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let locations = shopster.locations(tenant.id).unwrap();
+        let berlin = locations.insert(&make_location("Berlin", Some(make_address("10115", "Berlin")), 1)).await.unwrap();
+        let munich = locations.insert(&make_location("Munich", Some(make_address("80331", "Munich")), 2)).await.unwrap();
+        locations.set_allocation_strategy(AllocationStrategy::Nearest).await.unwrap();
+        assert_eq!(AllocationStrategy::Nearest, locations.allocation_strategy().await.unwrap());
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-LOC-002", "3000000000002")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&make_stock(product.id, 5, Some(berlin.id))).await.unwrap();
+        warehouse.insert(&make_stock(product.id, 5, Some(munich.id))).await.unwrap();
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        let basket_id = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(basket_id, product.id, 7).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let delivery_address = make_address("80333", "Munich");
+        orders.create_from_basket(basket_id, delivery_address.clone(), delivery_address, None).await.unwrap();
+
+        // Munich is closest to the delivery address and is used up first
+        let reserved: Vec<_> = warehouse.get_stock_by_location(product.id).await.unwrap().iter()
+            .map(|item| (item.location_id.unwrap(), item.reserved))
+            .collect();
+        assert_eq!(vec![(berlin.id, 2), (munich.id, 5)], reserved);
+    }).await;
+}
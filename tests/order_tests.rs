@@ -1,47 +1,39 @@
 mod common;
 
 use std::convert::TryFrom;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use uuid::Uuid;
 use stec_tenet::{Storage, Tenet};
 use stec_shopster::{DatabaseSelector, DbOrderStatus, DbPaymentStatus, Shopster};
+use stec_shopster::addresses::Address;
 use stec_shopster::customers::Customer;
 use stec_shopster::orders::{Order, OrderItemSnapshot, OrderItemPrice, OrderStatus, PaymentStatus};
 use stec_shopster::products::{Price, Product};
-use stec_shopster::warehouse::WarehouseItem;
+use stec_shopster::warehouse::{StockPolicy, WarehouseItem};
 use stec_tenet::encryption_modes::EncryptionModes;
-use crate::common::test_harness;
+use crate::common::{make_address, make_product, test_harness};
 
 fn make_order(status: OrderStatus) -> Order {
     Order {
         id: 0,
         customer_id: None,
         status,
-        delivery_address: "Test Street 1, 12345 Testcity".to_string(),
-        billing_address: "Test Street 1, 12345 Testcity".to_string(),
+        delivery_address: make_address(),
+        billing_address: make_address(),
         items: Vec::new(),
         created_at: Utc::now().naive_utc(),
         updated_at: None,
         payment_reference: None,
         payment_status: PaymentStatus::Pending,
+        order_number: None,
     }
 }
 
 fn make_product_with_price(article: &str, gtin: &str, price: i64) -> Product {
     Product {
-        id: 0,
-        article_number: article.to_string(),
         title: "Order Test Product".to_string(),
-        gtin: gtin.to_string(),
-        short_description: "Short".to_string(),
-        description: "Desc".to_string(),
-        image_url: "/images/test.png".to_string(),
-        additional_images: Vec::new(),
         price: Some(Price { amount: price, currency: "EUR".to_string() }),
-        weight: 100,
-        tags: Vec::new(),
-        created_at: Utc::now().naive_utc(),
-        updated_at: None,
+        ..make_product(article, gtin)
     }
 }
 
@@ -60,7 +52,7 @@ async fn order_insert_and_get_test() {
         let inserted = orders.insert(&make_order(OrderStatus::New)).await.unwrap();
 
         assert_eq!(OrderStatus::New, inserted.status);
-        assert_eq!("Test Street 1, 12345 Testcity", inserted.delivery_address);
+        assert_eq!(make_address(), inserted.delivery_address);
 
         let fetched = orders.get_by_id(inserted.id).await.unwrap();
         assert_eq!(inserted.id, fetched.id);
@@ -145,6 +137,9 @@ async fn order_inventory_reservation_on_insert_test() {
             reserved: 0,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         }).await.unwrap();
 
         // Insert a New order with items — New is a reserving status
@@ -152,8 +147,8 @@ async fn order_inventory_reservation_on_insert_test() {
             id: 0,
             customer_id: None,
             status: OrderStatus::New,
-            delivery_address: "Test Street 1, 12345 Testcity".to_string(),
-            billing_address: "Test Street 1, 12345 Testcity".to_string(),
+            delivery_address: make_address(),
+            billing_address: make_address(),
             items: vec![OrderItemSnapshot {
                 id: 0,
                 product_id: product.id,
@@ -168,11 +163,14 @@ async fn order_inventory_reservation_on_insert_test() {
                 additional_images: vec![],
                 price: OrderItemPrice { amount: 500, currency: "EUR".to_string() },
                 weight: product.weight,
+                fulfilled_quantity: 0,
+                variant_options: Vec::new(),
             }],
             created_at: Utc::now().naive_utc(),
             updated_at: None,
             payment_reference: None,
             payment_status: PaymentStatus::Pending,
+            order_number: None,
         };
 
         let orders = shopster.orders(tenant.id).unwrap();
@@ -206,14 +204,17 @@ async fn order_reservation_released_on_shipping_test() {
             reserved: 0,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         }).await.unwrap();
 
         let order_with_item = Order {
             id: 0,
             customer_id: None,
             status: OrderStatus::New,
-            delivery_address: "Test Street 1, 12345 Testcity".to_string(),
-            billing_address: "Test Street 1, 12345 Testcity".to_string(),
+            delivery_address: make_address(),
+            billing_address: make_address(),
             items: vec![OrderItemSnapshot {
                 id: 0,
                 product_id: product.id,
@@ -228,11 +229,14 @@ async fn order_reservation_released_on_shipping_test() {
                 additional_images: vec![],
                 price: OrderItemPrice { amount: 200, currency: "EUR".to_string() },
                 weight: product.weight,
+                fulfilled_quantity: 0,
+                variant_options: Vec::new(),
             }],
             created_at: Utc::now().naive_utc(),
             updated_at: None,
             payment_reference: None,
             payment_status: PaymentStatus::Pending,
+            order_number: None,
         };
 
         let orders = shopster.orders(tenant.id).unwrap();
@@ -242,16 +246,24 @@ async fn order_reservation_released_on_shipping_test() {
         let wh_after_new = warehouse.get_by_product_id(product.id).await.unwrap();
         assert_eq!(5, wh_after_new.reserved);
 
-        // Transition to Shipping (non-reserving) — reservation should be released
+        // Moving to Shipping without shipments ships the open quantities
         order.status = OrderStatus::InProgress;
         order = orders.update(&order).await.unwrap();
         order.status = OrderStatus::ReadyToShip;
         order = orders.update(&order).await.unwrap();
         order.status = OrderStatus::Shipping;
-        orders.update(&order).await.unwrap();
+        order = orders.update(&order).await.unwrap();
+        assert_eq!(5, order.items[0].fulfilled_quantity);
 
         let wh_after_shipping = warehouse.get_by_product_id(product.id).await.unwrap();
-        assert_eq!(0, wh_after_shipping.reserved, "Reservation should be released when order reaches Shipping");
+        assert_eq!(0, wh_after_shipping.reserved, "Reservation should be consumed when order reaches Shipping");
+        assert_eq!(45, wh_after_shipping.in_stock, "Shipped units should leave the warehouse");
+
+        order.status = OrderStatus::Done;
+        let order = orders.update(&order).await.unwrap();
+        assert_eq!(0, order.items[0].unfulfilled_quantity());
+        let wh_after_done = warehouse.get_by_product_id(product.id).await.unwrap();
+        assert_eq!((45, 0), (wh_after_done.in_stock, wh_after_done.reserved));
     }).await;
 }
 
@@ -277,6 +289,9 @@ async fn order_create_from_basket_test() {
             reserved: 0,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         }).await.unwrap();
 
         let baskets = shopster.baskets(tenant.id).unwrap();
@@ -286,8 +301,8 @@ async fn order_create_from_basket_test() {
         let orders = shopster.orders(tenant.id).unwrap();
         let order = orders.create_from_basket(
             basket_id,
-            "Delivery Street 5, 99999 Deliverytown".to_string(),
-            "Billing Street 5, 99999 Billingtown".to_string(),
+            Address { street: "Delivery Street".to_string(), city: "Deliverytown".to_string(), ..make_address() },
+            Address { street: "Billing Street".to_string(), city: "Billingtown".to_string(), ..make_address() },
             None,
         ).await.unwrap();
 
@@ -299,6 +314,132 @@ async fn order_create_from_basket_test() {
     }).await;
 }
 
+#[tokio::test]
+async fn order_create_from_basket_with_address_ids_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("order_from_basket_address_ids".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product_with_price("ART-ORD-ADDR", "3333333333334", 999)).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 10,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let customers = shopster.customers(tenant.id).unwrap();
+        let customer = customers.insert(&Customer {
+            id: Uuid::new_v4(),
+            email: "addressbook@example.com".to_string(),
+            email_verified: true,
+            encryption_mode: EncryptionModes::Argon2,
+            password: "password123".to_string(),
+            full_name: "Address Book".to_string(),
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }).await.unwrap();
+        let home = customers.add_address(customer.id, &Address { street: "Home Street".to_string(), ..make_address() }).await.unwrap();
+        let office = customers.add_address(customer.id, &Address { street: "Office Street".to_string(), ..make_address() }).await.unwrap();
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        let basket_id = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(basket_id, product.id, 1).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+
+        // Addresses must belong to the ordering customer
+        assert!(orders.create_from_basket_with_address_ids(basket_id, customer.id, home.id, home.id + office.id + 1, None).await.is_err());
+        assert!(orders.create_from_basket_with_address_ids(basket_id, Uuid::new_v4(), home.id, office.id, None).await.is_err());
+
+        let order = orders.create_from_basket_with_address_ids(basket_id, customer.id, office.id, home.id, None).await.unwrap();
+        assert_eq!(Some(customer.id), order.customer_id);
+        assert_eq!("Office Street", order.delivery_address.street);
+        assert_eq!("Home Street", order.billing_address.street);
+
+        // The order keeps a snapshot of the address
+        customers.update_address(customer.id, office.id, &Address { street: "Moved Street".to_string(), ..make_address() }).await.unwrap();
+        let fetched = orders.get_by_id(order.id).await.unwrap();
+        assert_eq!("Office Street", fetched.delivery_address.street);
+    }).await;
+}
+
+#[tokio::test]
+async fn order_create_from_customer_basket_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("order_from_customer_basket".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product_with_price("ART-ORD-CUST", "3333333333335", 999)).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 10,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let customers = shopster.customers(tenant.id).unwrap();
+        let make_customer = |email: &str| Customer {
+            id: Uuid::new_v4(),
+            email: email.to_string(),
+            email_verified: true,
+            encryption_mode: EncryptionModes::Argon2,
+            password: "password123".to_string(),
+            full_name: "Basket Owner".to_string(),
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        };
+        let owner = customers.insert(&make_customer("basket-owner@example.com")).await.unwrap();
+        let other = customers.insert(&make_customer("basket-other@example.com")).await.unwrap();
+        let other_address = customers.add_address(other.id, &make_address()).await.unwrap();
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        let basket_id = baskets.add_basket_for_customer(owner.id).await.unwrap();
+        baskets.add_product_to_basket(basket_id, product.id, 1).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+
+        // Another customer cannot order from the basket
+        assert!(orders.create_from_basket_with_address_ids(basket_id, other.id, other_address.id, other_address.id, None).await.is_err());
+
+        let order = orders.create_from_basket(basket_id, make_address(), make_address(), None).await.unwrap();
+        assert_eq!(Some(owner.id), order.customer_id);
+        assert_eq!(1, orders.get_by_customer_id(owner.id).await.unwrap().len());
+
+        // Guest baskets still create guest orders
+        let guest_basket_id = baskets.add_basket().await.unwrap();
+        baskets.add_product_to_basket(guest_basket_id, product.id, 1).await.unwrap();
+        let guest_order = orders.create_from_basket(guest_basket_id, make_address(), make_address(), None).await.unwrap();
+        assert_eq!(None, guest_order.customer_id);
+    }).await;
+}
+
 #[tokio::test]
 async fn order_missing_address_test() {
     test_harness(|tenet_connection_string, shopster_connection_string| async move {
@@ -313,16 +454,22 @@ async fn order_missing_address_test() {
         let orders = shopster.orders(tenant.id).unwrap();
 
         let empty_delivery = Order {
-            delivery_address: "".to_string(),
+            delivery_address: Address { street: "".to_string(), ..make_address() },
             ..make_order(OrderStatus::New)
         };
         assert!(orders.insert(&empty_delivery).await.is_err());
 
         let empty_billing = Order {
-            billing_address: "   ".to_string(),
+            billing_address: Address { name: "   ".to_string(), ..make_address() },
             ..make_order(OrderStatus::New)
         };
         assert!(orders.insert(&empty_billing).await.is_err());
+
+        let invalid_country = Order {
+            delivery_address: Address { country: "Germany".to_string(), ..make_address() },
+            ..make_order(OrderStatus::New)
+        };
+        assert!(orders.insert(&invalid_country).await.is_err());
     }).await;
 }
 
@@ -393,14 +540,17 @@ async fn order_remove_releases_reservation_test() {
             reserved: 0,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         }).await.unwrap();
 
         let order_with_item = Order {
             id: 0,
             customer_id: None,
             status: OrderStatus::New,
-            delivery_address: "Test Street 1, 12345 Testcity".to_string(),
-            billing_address: "Test Street 1, 12345 Testcity".to_string(),
+            delivery_address: make_address(),
+            billing_address: make_address(),
             items: vec![OrderItemSnapshot {
                 id: 0,
                 product_id: product.id,
@@ -415,11 +565,14 @@ async fn order_remove_releases_reservation_test() {
                 additional_images: vec![],
                 price: OrderItemPrice { amount: 100, currency: "EUR".to_string() },
                 weight: product.weight,
+                fulfilled_quantity: 0,
+                variant_options: Vec::new(),
             }],
             created_at: Utc::now().naive_utc(),
             updated_at: None,
             payment_reference: None,
             payment_status: PaymentStatus::Pending,
+            order_number: None,
         };
 
         let orders = shopster.orders(tenant.id).unwrap();
@@ -447,6 +600,7 @@ fn test_valid_order_status_conversions() {
     assert_eq!(DbOrderStatus::try_from(3).unwrap(), DbOrderStatus::Shipping);
     assert_eq!(DbOrderStatus::try_from(4).unwrap(), DbOrderStatus::Done);
     assert_eq!(DbOrderStatus::try_from(5).unwrap(), DbOrderStatus::Cancelled);
+    assert_eq!(DbOrderStatus::try_from(6).unwrap(), DbOrderStatus::PartiallyShipped);
 }
 
 /// Test that invalid i32 values return errors instead of panicking
@@ -457,7 +611,7 @@ fn test_invalid_order_status_conversions() {
     assert!(DbOrderStatus::try_from(-100).is_err());
 
     // Test out-of-range positive values
-    assert!(DbOrderStatus::try_from(6).is_err());
+    assert!(DbOrderStatus::try_from(7).is_err());
     assert!(DbOrderStatus::try_from(10).is_err());
     assert!(DbOrderStatus::try_from(100).is_err());
     assert!(DbOrderStatus::try_from(i32::MAX).is_err());
@@ -467,7 +621,7 @@ fn test_invalid_order_status_conversions() {
 /// Test that error messages are informative
 #[test]
 fn test_order_status_error_messages() {
-    let invalid_values = vec![-1, 6, 10, 99];
+    let invalid_values = vec![-1, 7, 10, 99];
 
     for val in invalid_values {
         let result = DbOrderStatus::try_from(val);
@@ -496,6 +650,7 @@ fn test_round_trip_conversion() {
         DbOrderStatus::Shipping,
         DbOrderStatus::Done,
         DbOrderStatus::Cancelled,
+        DbOrderStatus::PartiallyShipped,
     ];
 
     for original_status in statuses {
@@ -525,7 +680,7 @@ fn test_extreme_values_dont_panic() {
         i32::MIN + 1,
         -1000000,
         -1,
-        6,
+        7,
         1000,
         i32::MAX - 1,
         i32::MAX,
@@ -552,6 +707,7 @@ fn test_status_numeric_mapping() {
         (3, DbOrderStatus::Shipping),
         (4, DbOrderStatus::Done),
         (5, DbOrderStatus::Cancelled),
+        (6, DbOrderStatus::PartiallyShipped),
     ];
 
     for (expected_num, status) in mappings {
@@ -590,14 +746,17 @@ async fn order_cancel_from_new_releases_reservation_test() {
             reserved: 0,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         }).await.unwrap();
 
         let order_with_item = Order {
             id: 0,
             customer_id: None,
             status: OrderStatus::New,
-            delivery_address: "Test Street 1, 12345 Testcity".to_string(),
-            billing_address: "Test Street 1, 12345 Testcity".to_string(),
+            delivery_address: make_address(),
+            billing_address: make_address(),
             items: vec![OrderItemSnapshot {
                 id: 0,
                 product_id: product.id,
@@ -612,11 +771,14 @@ async fn order_cancel_from_new_releases_reservation_test() {
                 additional_images: vec![],
                 price: OrderItemPrice { amount: 300, currency: "EUR".to_string() },
                 weight: product.weight,
+                fulfilled_quantity: 0,
+                variant_options: Vec::new(),
             }],
             created_at: Utc::now().naive_utc(),
             updated_at: None,
             payment_reference: None,
             payment_status: PaymentStatus::Pending,
+            order_number: None,
         };
 
         let orders = shopster.orders(tenant.id).unwrap();
@@ -757,6 +919,150 @@ async fn order_update_payment_status_independent_of_fulfillment_test() {
     }).await;
 }
 
+#[tokio::test]
+async fn order_status_history_records_transitions_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("order_status_history".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let mut order = orders.insert(&make_order(OrderStatus::New)).await.unwrap();
+        assert!(orders.get_history(order.id).await.unwrap().is_empty());
+
+        order.status = OrderStatus::InProgress;
+        order = orders.update(&order).await.unwrap();
+
+        // Updates that leave both statuses untouched are not recorded
+        order.delivery_address.city = "Othercity".to_string();
+        order = orders.update(&order).await.unwrap();
+
+        orders.update_payment_status_with_note(order.id, PaymentStatus::Paid, Some("stripe-webhook"), None).await.unwrap();
+
+        order = orders.get_by_id(order.id).await.unwrap();
+        order.status = OrderStatus::Cancelled;
+        orders.update_with_note(&order, Some("alice@example.com"), Some("Customer request")).await.unwrap();
+
+        let history = orders.get_history(order.id).await.unwrap();
+        assert_eq!(3, history.len());
+
+        assert_eq!(OrderStatus::New, history[0].old_status);
+        assert_eq!(OrderStatus::InProgress, history[0].new_status);
+        assert_eq!(None, history[0].actor);
+
+        assert_eq!(OrderStatus::InProgress, history[1].new_status);
+        assert_eq!(PaymentStatus::Pending, history[1].old_payment_status);
+        assert_eq!(PaymentStatus::Paid, history[1].new_payment_status);
+        assert_eq!(Some("stripe-webhook".to_string()), history[1].actor);
+
+        assert_eq!(OrderStatus::InProgress, history[2].old_status);
+        assert_eq!(OrderStatus::Cancelled, history[2].new_status);
+        assert_eq!(PaymentStatus::Paid, history[2].new_payment_status);
+        assert_eq!(Some("alice@example.com".to_string()), history[2].actor);
+        assert_eq!(Some("Customer request".to_string()), history[2].comment);
+        assert!(history[1].created_at <= history[2].created_at);
+    }).await;
+}
+
+#[tokio::test]
+async fn order_status_history_not_written_for_rejected_transition_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("order_status_history_rejected".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let mut order = orders.insert(&make_order(OrderStatus::New)).await.unwrap();
+
+        order.status = OrderStatus::Done;
+        assert!(orders.update_with_note(&order, Some("bob"), None).await.is_err());
+        assert!(orders.get_history(order.id).await.unwrap().is_empty());
+    }).await;
+}
+
+#[tokio::test]
+async fn order_numbers_are_sequential_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("order_numbers_sequential".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let year = Utc::now().format("%Y").to_string();
+
+        let first = orders.insert(&make_order(OrderStatus::New)).await.unwrap();
+        let second = orders.insert(&make_order(OrderStatus::New)).await.unwrap();
+        assert_eq!(Some(format!("{}-000001", year)), first.order_number);
+        assert_eq!(Some(format!("{}-000002", year)), second.order_number);
+
+        // A failed insert must not consume a number
+        let mut invalid = make_order(OrderStatus::New);
+        invalid.delivery_address.postal_code = "1234".to_string();
+        assert!(orders.insert(&invalid).await.is_err());
+        let third = orders.insert(&make_order(OrderStatus::New)).await.unwrap();
+        assert_eq!(Some(format!("{}-000003", year)), third.order_number);
+
+        let found = orders.get_by_order_number(&format!("{}-000002", year)).await.unwrap().unwrap();
+        assert_eq!(second.id, found.id);
+        assert!(orders.get_by_order_number("does-not-exist").await.unwrap().is_none());
+
+        // Updates keep the order number and the creation time
+        let mut changed = orders.get_by_id(first.id).await.unwrap();
+        changed.order_number = Some("CHANGED".to_string());
+        changed.created_at = Utc::now().naive_utc();
+        changed.status = OrderStatus::InProgress;
+        let updated = orders.update(&changed).await.unwrap();
+        assert_eq!(OrderStatus::InProgress, updated.status);
+        assert_eq!(first.order_number, updated.order_number);
+        assert_eq!(first.created_at, updated.created_at);
+        assert!(orders.get_by_order_number("CHANGED").await.unwrap().is_none());
+    }).await;
+}
+
+#[tokio::test]
+async fn order_number_format_from_settings_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("order_number_format".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let settings = shopster.settings(tenant.id).unwrap();
+        let format_setting = settings.get_by_title("order_number_format".to_string()).await.unwrap();
+        assert_eq!("{year}-{counter:6}", format_setting.value);
+
+        settings.update_by_id(format_setting.id, "SHOP-{counter:3}".to_string()).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let order = orders.insert(&make_order(OrderStatus::New)).await.unwrap();
+        assert_eq!(Some("SHOP-001".to_string()), order.order_number);
+
+        // Templates without a counter would produce duplicate numbers
+        settings.update_by_id(format_setting.id, "SHOP-{year}".to_string()).await.unwrap();
+        assert!(orders.insert(&make_order(OrderStatus::New)).await.is_err());
+
+        settings.update_by_id(format_setting.id, "SHOP-{counter:3}".to_string()).await.unwrap();
+        let next = orders.insert(&make_order(OrderStatus::New)).await.unwrap();
+        assert_eq!(Some("SHOP-002".to_string()), next.order_number);
+        assert_eq!(2, orders.get_all().await.unwrap().len());
+    }).await;
+}
+
 /// Test successful conversions from valid i32 values to DbPaymentStatus
 #[test]
 fn test_valid_payment_status_conversions() {
@@ -764,13 +1070,14 @@ fn test_valid_payment_status_conversions() {
     assert_eq!(DbPaymentStatus::try_from(1).unwrap(), DbPaymentStatus::Paid);
     assert_eq!(DbPaymentStatus::try_from(2).unwrap(), DbPaymentStatus::Failed);
     assert_eq!(DbPaymentStatus::try_from(3).unwrap(), DbPaymentStatus::Refunded);
+    assert_eq!(DbPaymentStatus::try_from(4).unwrap(), DbPaymentStatus::PartiallyRefunded);
 }
 
 /// Test that invalid i32 values return errors instead of panicking
 #[test]
 fn test_invalid_payment_status_conversions() {
     assert!(DbPaymentStatus::try_from(-1).is_err());
-    assert!(DbPaymentStatus::try_from(4).is_err());
+    assert!(DbPaymentStatus::try_from(5).is_err());
     assert!(DbPaymentStatus::try_from(100).is_err());
     assert!(DbPaymentStatus::try_from(i32::MAX).is_err());
     assert!(DbPaymentStatus::try_from(i32::MIN).is_err());
@@ -784,6 +1091,7 @@ fn test_payment_status_round_trip_conversion() {
         DbPaymentStatus::Paid,
         DbPaymentStatus::Failed,
         DbPaymentStatus::Refunded,
+        DbPaymentStatus::PartiallyRefunded,
     ];
 
     for original_status in statuses {
@@ -793,3 +1101,79 @@ fn test_payment_status_round_trip_conversion() {
         assert_eq!(converted_back, original_status, "Round-trip conversion failed for status: {:?}", original_status);
     }
 }
+
+fn make_item(product: &Product, quantity: i64) -> OrderItemSnapshot {
+    OrderItemSnapshot {
+        id: 0,
+        product_id: product.id,
+        quantity,
+        article_number: product.article_number.clone(),
+        gtin: product.gtin.clone(),
+        title: product.title.clone(),
+        short_description: product.short_description.clone(),
+        description: product.description.clone(),
+        tags: vec![],
+        title_image: product.image_url.clone(),
+        additional_images: vec![],
+        price: OrderItemPrice { amount: 100, currency: "EUR".to_string() },
+        weight: product.weight,
+        fulfilled_quantity: 0,
+        variant_options: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn order_expire_stale_reservations_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("order_expire_reservations".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product_with_price("ART-ORD-EXP", "4444444444555", 100)).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 20,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        assert_eq!(Duration::hours(24), orders.reservation_timeout().await.unwrap());
+        assert!(orders.set_reservation_timeout(Duration::seconds(30)).await.is_err());
+        assert_eq!(Duration::minutes(30), orders.set_reservation_timeout(Duration::minutes(30)).await.unwrap());
+
+        let unpaid = orders.insert(&Order { items: vec![make_item(&product, 4)], ..make_order(OrderStatus::New) }).await.unwrap();
+        orders.insert(&Order { items: vec![make_item(&product, 2)], payment_status: PaymentStatus::Paid, ..make_order(OrderStatus::New) }).await.unwrap();
+        orders.insert(&Order { items: vec![make_item(&product, 1)], ..make_order(OrderStatus::InProgress) }).await.unwrap();
+        assert_eq!(7, warehouse.get_by_product_id(product.id).await.unwrap().reserved);
+
+        // Orders younger than the timeout are kept
+        assert!(orders.expire_stale_reservations(Utc::now().naive_utc()).await.unwrap().is_empty());
+
+        // Concurrent sweeps cancel each stale order exactly once
+        let later = (Utc::now() + Duration::minutes(31)).naive_utc();
+        let (first, second) = tokio::join!(orders.expire_stale_reservations(later), orders.expire_stale_reservations(later));
+        let expired: Vec<_> = first.unwrap().into_iter().chain(second.unwrap()).collect();
+        assert_eq!(1, expired.len());
+        assert_eq!((unpaid.id, OrderStatus::Cancelled), (expired[0].id, expired[0].status));
+        assert_eq!(3, warehouse.get_by_product_id(product.id).await.unwrap().reserved);
+        assert!(orders.expire_stale_reservations(later).await.unwrap().is_empty());
+
+        let history = orders.get_history(unpaid.id).await.unwrap();
+        let last = history.last().unwrap();
+        assert_eq!((OrderStatus::New, OrderStatus::Cancelled), (last.old_status, last.new_status));
+        assert_eq!(Some("Reservation expired".to_string()), last.comment);
+    }).await;
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use chrono::Utc;
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::products::{Price, Product, ProductImage, ProductVariant, VariantOption};
+use crate::common::test_harness;
+
+fn make_product(article_number: &str, gtin: &str, image_url: &str, additional_images: &[&str]) -> Product {
+    Product {
+        id: 0,
+        article_number: article_number.to_string(),
+        title: "Image Test Product".to_string(),
+        gtin: gtin.to_string(),
+        short_description: "Short".to_string(),
+        description: "Description".to_string(),
+        image_url: image_url.to_string(),
+        additional_images: additional_images.iter().map(|url| url.to_string()).collect(),
+        price: Some(Price { amount: 500, currency: "EUR".to_string() }),
+        weight: 100,
+        tags: Vec::new(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+fn make_image(product_id: i64, url: &str) -> ProductImage {
+    ProductImage {
+        id: 0,
+        product_id,
+        url: url.to_string(),
+        position: 0,
+        alt_text: BTreeMap::from([
+            ("de".to_string(), "Vorderseite".to_string()),
+            ("en".to_string(), "Front view".to_string()),
+        ]),
+        width: Some(1200),
+        height: Some(800),
+        mime_type: Some("image/webp".to_string()),
+        checksum: Some("sha256:4f2a".to_string()),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+fn urls(images: &[ProductImage]) -> Vec<&str> {
+    images.iter().map(|image| image.url.as_str()).collect()
+}
+
+#[tokio::test]
+async fn product_image_management_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_image_management".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-IMG-1", "7600000000001", "/a.png", &["/b.png", "/c.png"])).await.unwrap();
+        assert_eq!("/a.png", product.image_url);
+        assert_eq!(vec!["/b.png", "/c.png"], product.additional_images);
+
+        let images = products.get_images(product.id).await.unwrap();
+        assert_eq!(vec!["/a.png", "/b.png", "/c.png"], urls(&images));
+        assert_eq!(vec![0, 1, 2], images.iter().map(|image| image.position).collect::<Vec<_>>());
+
+        // New images are appended with their metadata
+        let added = products.add_image(&make_image(product.id, "/d.webp")).await.unwrap();
+        assert_eq!(3, added.position);
+        assert_eq!(Some("Front view"), added.alt_text.get("en").map(String::as_str));
+        assert_eq!(Some(1200), added.width);
+        assert_eq!(vec!["/b.png", "/c.png", "/d.webp"], products.get(product.id).await.unwrap().additional_images);
+
+        let mut invalid = make_image(product.id, "/e.pdf");
+        invalid.mime_type = Some("application/pdf".to_string());
+        assert!(products.add_image(&invalid).await.is_err());
+        assert!(products.add_image(&make_image(product.id, " ")).await.is_err());
+
+        // Metadata is updated in place
+        let mut updated = added.clone();
+        updated.alt_text.insert("en".to_string(), "Back view".to_string());
+        updated.height = Some(900);
+        let updated = products.update_image(&updated).await.unwrap();
+        assert_eq!(3, updated.position);
+        assert_eq!(Some(900), updated.height);
+        assert_eq!(Some("Back view"), updated.alt_text.get("en").map(String::as_str));
+
+        // Reordering has to list every image exactly once
+        let ids: Vec<i64> = products.get_images(product.id).await.unwrap().iter().map(|image| image.id).collect();
+        let reordered = products.reorder_images(product.id, &[ids[3], ids[0], ids[2], ids[1]]).await.unwrap();
+        assert_eq!(vec!["/d.webp", "/a.png", "/c.png", "/b.png"], urls(&reordered));
+        assert_eq!("/d.webp", products.get(product.id).await.unwrap().image_url);
+        assert!(products.reorder_images(product.id, &[ids[0], ids[1], ids[2]]).await.is_err());
+        assert!(products.reorder_images(product.id, &[ids[0], ids[0], ids[1], ids[2]]).await.is_err());
+
+        // Removing closes the gap
+        assert!(products.remove_image(ids[0]).await.unwrap());
+        assert!(!products.remove_image(ids[0]).await.unwrap());
+        let images = products.get_images(product.id).await.unwrap();
+        assert_eq!(vec!["/d.webp", "/c.png", "/b.png"], urls(&images));
+        assert_eq!(vec![0, 1, 2], images.iter().map(|image| image.position).collect::<Vec<_>>());
+    }).await;
+}
+
+#[tokio::test]
+async fn product_image_lists_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_image_lists".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+
+        // Products without images have empty lists
+        let plain = products.insert(&make_product("ART-IMG-2", "7600000000002", "", &[])).await.unwrap();
+        assert_eq!("", plain.image_url);
+        assert!(plain.additional_images.is_empty());
+        assert!(products.get_images(plain.id).await.unwrap().is_empty());
+
+        let product = products.insert(&make_product("ART-IMG-3", "7600000000003", "/a.png", &[])).await.unwrap();
+        let added = products.add_image(&make_image(product.id, "/b.webp")).await.unwrap();
+
+        // Updating a product makes its images match its lists, keeping the
+        // metadata of images that stay
+        let mut product = products.get(product.id).await.unwrap();
+        product.image_url = "/b.webp".to_string();
+        product.additional_images = vec!["/c.png".to_string()];
+        let updated = products.update(&product).await.unwrap();
+        assert_eq!("/b.webp", updated.image_url);
+        assert_eq!(vec!["/c.png"], updated.additional_images);
+        let images = products.get_images(product.id).await.unwrap();
+        assert_eq!(vec!["/b.webp", "/c.png"], urls(&images));
+        assert_eq!(added.id, images[0].id);
+        assert_eq!(added.alt_text, images[0].alt_text);
+
+        // Variants show the images of their parent
+        let variant = products.insert_variant(&ProductVariant {
+            id: 0,
+            parent_id: product.id,
+            article_number: "ART-IMG-3-S".to_string(),
+            gtin: "7600000000004".to_string(),
+            options: vec![VariantOption { name: "Size".to_string(), value: "S".to_string() }],
+            price: None,
+            weight: None,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }).await.unwrap();
+        let variant_product = products.get(variant.id).await.unwrap();
+        assert_eq!("/b.webp", variant_product.image_url);
+        assert_eq!(vec!["/c.png"], variant_product.additional_images);
+        assert_eq!(2, products.get_images(variant.id).await.unwrap().len());
+        assert!(products.add_image(&make_image(variant.id, "/d.png")).await.is_err());
+
+        // Removing a product removes its images
+        products.remove(product.id).await.unwrap();
+        assert!(products.get_images(product.id).await.is_err());
+    }).await;
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use chrono::Utc;
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::products::{Price, Product, ProductCursor, ProductQuery, ProductSort, ProductVariant, VariantOption};
+use stec_shopster::warehouse::{StockPolicy, WarehouseItem};
+use crate::common::test_harness;
+
+fn make_product(article_number: &str, gtin: &str, title: &str, tags: &[&str], amount: i64, currency: &str) -> Product {
+    Product {
+        id: 0,
+        article_number: article_number.to_string(),
+        title: title.to_string(),
+        gtin: gtin.to_string(),
+        short_description: "Short".to_string(),
+        description: "Description".to_string(),
+        image_url: "/images/test.png".to_string(),
+        additional_images: Vec::new(),
+        price: Some(Price { amount, currency: currency.to_string() }),
+        weight: 100,
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+fn make_stock(product_id: i64, in_stock: i64, reserved: i64) -> WarehouseItem {
+    WarehouseItem {
+        id: 0,
+        product_id,
+        in_stock,
+        reserved,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        stock_policy: StockPolicy::Deny,
+        backordered: 0,
+        location_id: None,
+    }
+}
+
+fn ids(products: &[Product]) -> Vec<i64> {
+    products.iter().map(|product| product.id).collect()
+}
+
+#[tokio::test]
+async fn product_query_pagination_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_query_pagination".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let c = products.insert(&make_product("ART-Q-1", "7500000000001", "Cap", &[], 1500, "EUR")).await.unwrap();
+        let a = products.insert(&make_product("ART-Q-2", "7500000000002", "Apron", &[], 900, "EUR")).await.unwrap();
+        let e = products.insert(&make_product("ART-Q-3", "7500000000003", "Earmuffs", &[], 1500, "EUR")).await.unwrap();
+        let b = products.insert(&make_product("ART-Q-4", "7500000000004", "Boots", &[], 7000, "EUR")).await.unwrap();
+        let d = products.insert(&make_product("ART-Q-5", "7500000000005", "Dress", &[], 1500, "EUR")).await.unwrap();
+
+        // Paging through equal prices neither skips nor repeats products
+        let query = ProductQuery::new().sort(ProductSort::PriceAscending).limit(2);
+        let mut listed = Vec::new();
+        let mut page = products.query(&query).await.unwrap();
+        listed.extend(ids(&page.products));
+        while let Some(cursor) = page.next_cursor {
+            page = products.query(&query.clone().after(cursor)).await.unwrap();
+            listed.extend(ids(&page.products));
+        }
+        assert_eq!(vec![a.id, c.id, e.id, d.id, b.id], listed);
+
+        let page = products.query(&ProductQuery::new().sort(ProductSort::PriceDescending).limit(3)).await.unwrap();
+        assert_eq!(vec![b.id, d.id, e.id], ids(&page.products));
+        let page = products.query(&ProductQuery::new().sort(ProductSort::PriceDescending).after(page.next_cursor.unwrap())).await.unwrap();
+        assert_eq!(vec![c.id, a.id], ids(&page.products));
+        assert!(page.next_cursor.is_none());
+
+        let page = products.query(&ProductQuery::new().sort(ProductSort::Title)).await.unwrap();
+        assert_eq!(vec![a.id, b.id, c.id, d.id, e.id], ids(&page.products));
+        assert!(page.next_cursor.is_none());
+
+        // Cursors survive a round trip through a client
+        let page = products.query(&ProductQuery::new().limit(2)).await.unwrap();
+        assert_eq!(vec![d.id, b.id], ids(&page.products));
+        let json = serde_json::to_string(&page.next_cursor.unwrap()).unwrap();
+        let cursor: ProductCursor = serde_json::from_str(&json).unwrap();
+        let page = products.query(&ProductQuery::new().limit(2).after(cursor)).await.unwrap();
+        assert_eq!(vec![e.id, a.id], ids(&page.products));
+
+        // Updates keep the creation time, so paging by newest stays stable
+        let query = ProductQuery::new().sort(ProductSort::Newest).limit(2);
+        let page = products.query(&query).await.unwrap();
+        assert_eq!(vec![d.id, b.id], ids(&page.products));
+        let mut changed = products.get(b.id).await.unwrap();
+        changed.title = "Rain Boots".to_string();
+        changed.created_at = Utc::now().naive_utc();
+        let updated = products.update(&changed).await.unwrap();
+        assert_eq!(b.created_at, updated.created_at);
+        assert!(updated.updated_at.is_some());
+        let page = products.query(&query.clone().after(page.next_cursor.unwrap())).await.unwrap();
+        assert_eq!(vec![e.id, a.id], ids(&page.products));
+        let page = products.query(&ProductQuery::new().sort(ProductSort::Newest)).await.unwrap();
+        assert_eq!(vec![d.id, b.id, e.id, a.id, c.id], ids(&page.products));
+
+        assert!(products.query(&ProductQuery::new().limit(0)).await.is_err());
+        assert!(products.query(&ProductQuery::new().min_price(100).max_price(99)).await.is_err());
+    }).await;
+}
+
+#[tokio::test]
+async fn product_query_filter_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_query_filter".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-F-1", "7500000000011", "Shirt", &["clothing", "summer"], 2000, "EUR")).await.unwrap();
+        let jacket = products.insert(&make_product("ART-F-2", "7500000000012", "Jacket", &["clothing", "winter"], 9000, "EUR")).await.unwrap();
+        let scarf = products.insert(&make_product("ART-F-3", "7500000000013", "Scarf", &["winter"], 1500, "USD")).await.unwrap();
+        let created_after = Utc::now().naive_utc();
+        let hat = products.insert(&make_product("ART-F-4", "7500000000014", "Hat", &["summer"], 1200, "EUR")).await.unwrap();
+
+        let list = |query: ProductQuery| {
+            let products = &products;
+            async move {
+                let mut listed = ids(&products.query(&query.sort(ProductSort::Title)).await.unwrap().products);
+                listed.sort();
+                listed
+            }
+        };
+
+        assert_eq!(vec![shirt.id, jacket.id], list(ProductQuery::new().tag("clothing")).await);
+        assert_eq!(vec![jacket.id, scarf.id], list(ProductQuery::new().tag("winter")).await);
+        assert!(list(ProductQuery::new().tag("wint")).await.is_empty());
+        assert_eq!(vec![shirt.id, scarf.id], list(ProductQuery::new().min_price(1500).max_price(2000)).await);
+        assert_eq!(vec![shirt.id, jacket.id, hat.id], list(ProductQuery::new().currency("EUR")).await);
+        assert_eq!(vec![hat.id], list(ProductQuery::new().created_after(created_after)).await);
+        assert_eq!(vec![shirt.id], list(ProductQuery::new().tag("summer").max_price(5000).currency("EUR").min_price(1300)).await);
+
+        // In stock means free stock of the product or one of its variants
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&make_stock(shirt.id, 5, 0)).await.unwrap();
+        warehouse.insert(&make_stock(scarf.id, 2, 2)).await.unwrap();
+        let variant = products.insert_variant(&ProductVariant {
+            id: 0,
+            parent_id: jacket.id,
+            article_number: "ART-F-2-L".to_string(),
+            gtin: "7500000000015".to_string(),
+            options: vec![VariantOption { name: "Size".to_string(), value: "L".to_string() }],
+            price: None,
+            weight: None,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }).await.unwrap();
+        warehouse.insert(&make_stock(variant.id, 1, 0)).await.unwrap();
+        assert_eq!(vec![shirt.id, jacket.id], list(ProductQuery::new().in_stock_only()).await);
+
+        // Products that may be ordered without free stock count as in stock
+        warehouse.insert(&WarehouseItem { stock_policy: StockPolicy::Backorder, ..make_stock(hat.id, 0, 0) }).await.unwrap();
+        assert_eq!(vec![shirt.id, jacket.id, hat.id], list(ProductQuery::new().in_stock_only()).await);
+
+        // Variants are listed through their parent
+        assert_eq!(vec![shirt.id, jacket.id, scarf.id, hat.id], list(ProductQuery::new()).await);
+    }).await;
+}
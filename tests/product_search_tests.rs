@@ -0,0 +1,187 @@
+mod common;
+
+use chrono::Utc;
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::products::{Price, PriceRangeFacet, Product, ProductSearch, ProductVariant, TagFacet, VariantOption};
+use stec_shopster::warehouse::{StockPolicy, WarehouseItem};
+use crate::common::test_harness;
+
+fn make_product(article_number: &str, gtin: &str, title: &str, description: &str, tags: &[&str], price: i64) -> Product {
+    Product {
+        id: 0,
+        article_number: article_number.to_string(),
+        title: title.to_string(),
+        gtin: gtin.to_string(),
+        short_description: String::new(),
+        description: description.to_string(),
+        image_url: "/images/test.png".to_string(),
+        additional_images: Vec::new(),
+        price: Some(Price { amount: price, currency: "EUR".to_string() }),
+        weight: 100,
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+fn make_stock(product_id: i64, in_stock: i64) -> WarehouseItem {
+    WarehouseItem {
+        id: 0,
+        product_id,
+        in_stock,
+        reserved: 0,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        stock_policy: StockPolicy::Deny,
+        backordered: 0,
+        location_id: None,
+    }
+}
+
+#[tokio::test]
+async fn product_search_ranking_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_search_ranking".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shoes = products.insert(&make_product("RUN-100", "7400000000001", "Running Shoes", "Light shoes for daily runs", &["sport", "shoes"], 8000)).await.unwrap();
+        let socks = products.insert(&make_product("RUN-200", "7400000000002", "Socks", "Breathable socks, great with running shoes", &["sport"], 900)).await.unwrap();
+        products.insert(&make_product("MUG-100", "7400000000003", "Coffee Mug", "Holds a lot of coffee", &[], 1200)).await.unwrap();
+
+        // Matches in the title rank above matches in the description, and
+        // stemming finds "runs" and "running" alike
+        let result = products.search(&ProductSearch::new("running shoe")).await.unwrap();
+        assert_eq!(2, result.total);
+        assert_eq!(vec![shoes.id, socks.id], result.hits.iter().map(|hit| hit.product.id).collect::<Vec<_>>());
+        assert!(result.hits[0].rank > result.hits[1].rank);
+
+        // Article numbers and tags are searchable
+        let result = products.search(&ProductSearch::new("RUN-200")).await.unwrap();
+        assert_eq!(vec![socks.id], result.hits.iter().map(|hit| hit.product.id).collect::<Vec<_>>());
+        assert_eq!(2, products.search(&ProductSearch::new("sport")).await.unwrap().total);
+        let result = products.search(&ProductSearch::new("sport -daily")).await.unwrap();
+        assert_eq!(vec![socks.id], result.hits.iter().map(|hit| hit.product.id).collect::<Vec<_>>());
+
+        // Pages
+        let mut search = ProductSearch::new("sport");
+        search.per_page = 1;
+        search.page = 1;
+        let result = products.search(&search).await.unwrap();
+        assert_eq!(2, result.total);
+        assert_eq!(1, result.hits.len());
+        search.page = 2;
+        assert!(products.search(&search).await.unwrap().hits.is_empty());
+
+        // Invalid searches are rejected
+        assert!(products.search(&ProductSearch::new("  ")).await.is_err());
+        search.page = -1;
+        assert!(products.search(&search).await.is_err());
+        let mut search = ProductSearch::new("sport");
+        search.price_bounds = vec![5000, 1000];
+        assert!(products.search(&search).await.is_err());
+
+        // Variants are found through their parent
+        products.insert_variant(&ProductVariant {
+            id: 0,
+            parent_id: shoes.id,
+            article_number: "RUN-100-42".to_string(),
+            gtin: "7400000000004".to_string(),
+            options: vec![VariantOption { name: "Size".to_string(), value: "42".to_string() }],
+            price: None,
+            weight: None,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }).await.unwrap();
+        let result = products.search(&ProductSearch::new("shoes")).await.unwrap();
+        assert!(result.hits.iter().any(|hit| hit.product.id == shoes.id));
+        assert!(result.hits.iter().all(|hit| hit.product.article_number != "RUN-100-42"));
+    }).await;
+}
+
+#[tokio::test]
+async fn product_search_facets_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_search_facets".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let tent = products.insert(&make_product("CMP-1", "7400000000011", "Camping Tent", "", &["camping", "outdoor"], 15000)).await.unwrap();
+        let stove = products.insert(&make_product("CMP-2", "7400000000012", "Camping Stove", "", &["camping"], 4000)).await.unwrap();
+        products.insert(&make_product("CMP-3", "7400000000013", "Camping Mug", "", &["camping", "kitchen"], 900)).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&make_stock(tent.id, 3)).await.unwrap();
+        warehouse.insert(&make_stock(stove.id, 0)).await.unwrap();
+
+        let mut search = ProductSearch::new("camping");
+        search.per_page = 1;
+        search.price_bounds = vec![1000, 5000];
+        let result = products.search(&search).await.unwrap();
+
+        // Facets count all matches, not only the returned page
+        assert_eq!(1, result.hits.len());
+        assert_eq!(3, result.total);
+        assert_eq!(vec![
+            TagFacet { tag: "camping".to_string(), count: 3 },
+            TagFacet { tag: "kitchen".to_string(), count: 1 },
+            TagFacet { tag: "outdoor".to_string(), count: 1 },
+        ], result.facets.tags);
+        assert_eq!(vec![
+            PriceRangeFacet { min: None, max: Some(1000), count: 1 },
+            PriceRangeFacet { min: Some(1000), max: Some(5000), count: 1 },
+            PriceRangeFacet { min: Some(5000), max: None, count: 1 },
+        ], result.facets.price_ranges);
+        assert_eq!(1, result.facets.availability.available);
+        assert_eq!(2, result.facets.availability.unavailable);
+
+        // Backorderable products count as available
+        let mut stove_stock = make_stock(stove.id, 0);
+        stove_stock.stock_policy = StockPolicy::Backorder;
+        warehouse.update_by_product_id(stove.id, &stove_stock).await.unwrap();
+        let result = products.search(&search).await.unwrap();
+        assert_eq!(2, result.facets.availability.available);
+
+        // Empty ranges are reported too
+        let result = products.search(&ProductSearch::new("tent")).await.unwrap();
+        assert_eq!(5, result.facets.price_ranges.len());
+        assert_eq!(vec![0, 0, 0, 0, 1], result.facets.price_ranges.iter().map(|range| range.count).collect::<Vec<_>>());
+    }).await;
+}
+
+#[tokio::test]
+async fn product_search_language_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_search_language".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let boots = products.insert(&make_product("WND-1", "7400000000021", "Wanderschuhe", "Wasserdichte Schuhe für lange Wanderungen", &[], 9000)).await.unwrap();
+
+        // The English dictionary does not know German inflections
+        assert_eq!(0, products.search(&ProductSearch::new("Wanderung")).await.unwrap().total);
+
+        let settings = shopster.settings(tenant.id).unwrap();
+        let language = settings.get_by_title("language".to_string()).await.unwrap();
+        settings.update_by_id(language.id, "de".to_string()).await.unwrap();
+
+        let result = products.search(&ProductSearch::new("Wanderung")).await.unwrap();
+        assert_eq!(vec![boots.id], result.hits.iter().map(|hit| hit.product.id).collect::<Vec<_>>());
+    }).await;
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use chrono::Utc;
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::error::ShopsterError;
+use stec_shopster::products::{Price, Product, ProductVariant, VariantOption};
+use stec_shopster::warehouse::{StockPolicy, WarehouseItem};
+use crate::common::{make_address, test_harness};
+
+fn make_product(article_number: &str, gtin: &str) -> Product {
+    Product {
+        title: "Variant Test Shirt".to_string(),
+        image_url: "/images/shirt.png".to_string(),
+        price: Some(Price { amount: 2000, currency: "EUR".to_string() }),
+        weight: 200,
+        tags: vec!["shirts".to_string()],
+        ..common::make_product(article_number, gtin)
+    }
+}
+
+fn option(name: &str, value: &str) -> VariantOption {
+    VariantOption { name: name.to_string(), value: value.to_string() }
+}
+
+fn make_variant(parent_id: i64, article_number: &str, gtin: &str, options: Vec<VariantOption>) -> ProductVariant {
+    ProductVariant {
+        id: 0,
+        parent_id,
+        article_number: article_number.to_string(),
+        gtin: gtin.to_string(),
+        options,
+        price: None,
+        weight: None,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+#[tokio::test]
+async fn product_variants_inherit_from_parent_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_variants_inherit".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-VAR-1", "7200000000001")).await.unwrap();
+
+        let small = products.insert_variant(&make_variant(shirt.id, "ART-VAR-1-S", "7200000000002", vec![
+            option("Size", "S"),
+            option("Colour", "Red"),
+        ])).await.unwrap();
+        let mut large = make_variant(shirt.id, "ART-VAR-1-L", "7200000000003", vec![option("Size", "L")]);
+        large.price = Some(Price { amount: 2500, currency: "EUR".to_string() });
+        let large = products.insert_variant(&large).await.unwrap();
+
+        // Options are stored sorted by name
+        assert_eq!(vec![option("Colour", "Red"), option("Size", "S")], small.options);
+        assert_eq!(2, products.get_variants(shirt.id).await.unwrap().len());
+        assert_eq!(1, products.get_all().await.unwrap().len(), "Variants are not listed as products");
+
+        let small_product = products.get(small.id).await.unwrap();
+        assert_eq!("Variant Test Shirt", small_product.title);
+        assert_eq!(2000, small_product.price.as_ref().unwrap().amount);
+        let large_product = products.get(large.id).await.unwrap();
+        assert_eq!(2500, large_product.price.as_ref().unwrap().amount);
+        let (small_created_at, large_created_at) = (small_product.created_at, large_product.created_at);
+
+        let found = products.find_variant(shirt.id, &[option("Size", "S"), option("Colour", "Red")]).await.unwrap();
+        assert_eq!(Some(small.id), found.map(|variant| variant.id));
+        assert!(products.find_variant(shirt.id, &[option("Size", "S")]).await.unwrap().is_none());
+
+        // Duplicate options, empty options and variants of variants are rejected
+        assert!(products.insert_variant(&make_variant(shirt.id, "ART-VAR-1-L2", "7200000000004", vec![option("Size", "L")])).await.is_err());
+        assert!(products.insert_variant(&make_variant(shirt.id, "ART-VAR-1-X", "7200000000005", Vec::new())).await.is_err());
+        assert!(products.insert_variant(&make_variant(shirt.id, "ART-VAR-1-Y", "7200000000006", vec![option("Size", "M"), option("Size", "XL")])).await.is_err());
+        assert!(products.insert_variant(&make_variant(small.id, "ART-VAR-1-Z", "7200000000007", vec![option("Fit", "Slim")])).await.is_err());
+
+        // Changes of the parent reach the variants, except overridden fields
+        let mut shirt = shirt;
+        shirt.title = "Renamed Shirt".to_string();
+        shirt.price = Some(Price { amount: 1800, currency: "EUR".to_string() });
+        products.update(&shirt).await.unwrap();
+
+        let small_product = products.get(small.id).await.unwrap();
+        assert_eq!("Renamed Shirt", small_product.title);
+        assert_eq!(1800, small_product.price.as_ref().unwrap().amount);
+        assert_eq!(small_created_at, small_product.created_at, "Variants keep their creation time");
+        let large_product = products.get(large.id).await.unwrap();
+        assert_eq!("Renamed Shirt", large_product.title);
+        assert_eq!(2500, large_product.price.as_ref().unwrap().amount);
+
+        // Clearing the override falls back to the parent's price
+        let mut large = large;
+        large.price = None;
+        large.weight = Some(350);
+        let large = products.update_variant(&large).await.unwrap();
+        assert!(large.price.is_none());
+        let large_product = products.get(large.id).await.unwrap();
+        assert_eq!(1800, large_product.price.as_ref().unwrap().amount);
+        assert_eq!(350, large_product.weight);
+        assert_eq!(large_created_at, large_product.created_at);
+
+        assert!(products.remove_variant(large.id).await.unwrap());
+        assert!(products.get(large.id).await.is_err());
+        assert!(products.remove_variant(shirt.id).await.is_err(), "Only variants can be removed as variants");
+
+        // Removing the parent removes its variants
+        assert!(products.remove(shirt.id).await.unwrap());
+        assert!(products.get(small.id).await.is_err());
+    }).await;
+}
+
+#[tokio::test]
+async fn product_variants_checkout_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_variants_checkout".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-VAR-2", "7200000000011")).await.unwrap();
+        let small = products.insert_variant(&make_variant(shirt.id, "ART-VAR-2-S", "7200000000012", vec![option("Size", "S")])).await.unwrap();
+        let large = products.insert_variant(&make_variant(shirt.id, "ART-VAR-2-L", "7200000000013", vec![option("Size", "L")])).await.unwrap();
+
+        // Every variant has its own stock
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        for (product_id, in_stock) in [(small.id, 1), (large.id, 5)] {
+            warehouse.insert(&WarehouseItem {
+                id: 0,
+                product_id,
+                in_stock,
+                reserved: 0,
+                created_at: Utc::now().naive_utc(),
+                updated_at: None,
+                stock_policy: StockPolicy::Deny,
+                backordered: 0,
+                location_id: None,
+            }).await.unwrap();
+        }
+
+        let baskets = shopster.baskets(tenant.id).unwrap();
+        let basket_id = baskets.add_basket().await.unwrap();
+        match baskets.add_product_to_basket(basket_id, shirt.id, 1).await {
+            Err(ShopsterError::InvalidOperationError(_)) => {},
+            other => panic!("Products with variants cannot be put into a basket: {:?}", other),
+        }
+        baskets.add_product_to_basket(basket_id, large.id, 2).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let order = orders.create_from_basket(basket_id, make_address(), make_address(), None).await.unwrap();
+        assert_eq!(1, order.items.len());
+        assert_eq!(large.id, order.items[0].product_id);
+        assert_eq!("ART-VAR-2-L", order.items[0].article_number);
+        assert_eq!(vec![option("Size", "L")], order.items[0].variant_options);
+        assert_eq!(vec![option("Size", "L")], orders.get_by_id(order.id).await.unwrap().items[0].variant_options);
+
+        assert_eq!(2, warehouse.get_by_product_id(large.id).await.unwrap().reserved);
+        assert_eq!(0, warehouse.get_by_product_id(small.id).await.unwrap().reserved);
+    }).await;
+}
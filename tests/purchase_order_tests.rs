@@ -0,0 +1,164 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::purchase_orders::{GoodsReceiptLine, PurchaseOrder, PurchaseOrderItem, PurchaseOrderStatus};
+use stec_shopster::suppliers::Supplier;
+use stec_shopster::warehouse::{StockMovementKind, StockPolicy, WarehouseItem};
+use crate::common::{make_product, test_harness};
+
+fn make_supplier(name: &str) -> Supplier {
+    Supplier {
+        id: 0,
+        name: name.to_string(),
+        email: Some("orders@supplier.example".to_string()),
+        phone: None,
+        address: None,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+fn make_purchase_order(supplier_id: i64, lines: &[(i64, i64)]) -> PurchaseOrder {
+    PurchaseOrder {
+        id: 0,
+        supplier_id,
+        location_id: None,
+        status: PurchaseOrderStatus::Draft,
+        items: lines.iter().map(|(product_id, quantity)| PurchaseOrderItem {
+            id: 0,
+            product_id: *product_id,
+            quantity: *quantity,
+            received_quantity: 0,
+        }).collect(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        ordered_at: None,
+        received_at: None,
+    }
+}
+
+#[tokio::test]
+async fn suppliers_crud_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("suppliers_crud".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let suppliers = shopster.suppliers(tenant.id).unwrap();
+        assert!(suppliers.insert(&make_supplier("  ")).await.is_err());
+
+        let supplier = suppliers.insert(&make_supplier("Textile Wholesale")).await.unwrap();
+        let updated = suppliers.update(&Supplier { phone: Some("+49 30 123456".to_string()), ..supplier.clone() }).await.unwrap();
+        assert_eq!(Some("+49 30 123456".to_string()), updated.phone);
+        assert_eq!(1, suppliers.get_all().await.unwrap().len());
+
+        // Suppliers with purchase orders cannot be removed
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-PO-001", "4000000000001")).await.unwrap();
+        let purchase_orders = shopster.purchase_orders(tenant.id).unwrap();
+        let purchase_order = purchase_orders.insert(&make_purchase_order(supplier.id, &[(product.id, 5)])).await.unwrap();
+        assert!(suppliers.remove(supplier.id).await.is_err());
+
+        assert!(purchase_orders.remove(purchase_order.id).await.unwrap());
+        assert!(suppliers.remove(supplier.id).await.unwrap());
+        assert!(suppliers.get(supplier.id).await.is_err());
+    }).await;
+}
+
+#[tokio::test]
+async fn purchase_order_lifecycle_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("purchase_order_lifecycle".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-PO-002", "4000000000002")).await.unwrap();
+        let socks = products.insert(&make_product("ART-PO-003", "4000000000003")).await.unwrap();
+
+        // The shirt has open backorders; the socks are not stocked yet
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: shirt.id,
+            in_stock: 0,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Backorder,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+        warehouse.apply_reserved_delta(shirt.id, 3).await.unwrap();
+
+        let suppliers = shopster.suppliers(tenant.id).unwrap();
+        let supplier = suppliers.insert(&make_supplier("Textile Wholesale")).await.unwrap();
+
+        let purchase_orders = shopster.purchase_orders(tenant.id).unwrap();
+        assert!(purchase_orders.insert(&make_purchase_order(supplier.id, &[])).await.is_err());
+        assert!(purchase_orders.insert(&make_purchase_order(supplier.id, &[(shirt.id, 0)])).await.is_err());
+
+        let draft = purchase_orders.insert(&make_purchase_order(supplier.id, &[(shirt.id, 5)])).await.unwrap();
+        assert_eq!(PurchaseOrderStatus::Draft, draft.status);
+        let draft = purchase_orders.update(&PurchaseOrder { id: draft.id, ..make_purchase_order(supplier.id, &[(shirt.id, 10), (socks.id, 4)]) }).await.unwrap();
+        assert_eq!(2, draft.items.len());
+
+        // Drafts cannot be received
+        let shirt_line = draft.items.iter().find(|item| item.product_id == shirt.id).unwrap().id;
+        let socks_line = draft.items.iter().find(|item| item.product_id == socks.id).unwrap().id;
+        assert!(purchase_orders.receive(draft.id, &[GoodsReceiptLine { purchase_order_item_id: shirt_line, quantity: 1 }]).await.is_err());
+
+        let ordered = purchase_orders.place(draft.id).await.unwrap();
+        assert_eq!(PurchaseOrderStatus::Ordered, ordered.status);
+        assert!(ordered.ordered_at.is_some());
+        assert!(purchase_orders.place(draft.id).await.is_err());
+        assert!(purchase_orders.update(&PurchaseOrder { id: draft.id, ..make_purchase_order(supplier.id, &[(shirt.id, 1)]) }).await.is_err());
+        assert!(purchase_orders.remove(draft.id).await.is_err());
+
+        let partial = purchase_orders.receive(draft.id, &[
+            GoodsReceiptLine { purchase_order_item_id: shirt_line, quantity: 4 },
+            GoodsReceiptLine { purchase_order_item_id: socks_line, quantity: 4 },
+        ]).await.unwrap();
+        assert_eq!(PurchaseOrderStatus::PartiallyReceived, partial.status);
+        assert_eq!(6, partial.items.iter().find(|item| item.id == shirt_line).unwrap().open_quantity());
+
+        // Received goods serve the backorders first
+        let shirt_stock = warehouse.get_by_product_id(shirt.id).await.unwrap();
+        assert_eq!((4, 3, 0), (shirt_stock.in_stock, shirt_stock.reserved, shirt_stock.backordered));
+        assert_eq!(4, warehouse.get_by_product_id(socks.id).await.unwrap().in_stock);
+
+        // More than the open quantity cannot be received, and nothing is booked
+        assert!(purchase_orders.receive(draft.id, &[
+            GoodsReceiptLine { purchase_order_item_id: shirt_line, quantity: 5 },
+            GoodsReceiptLine { purchase_order_item_id: shirt_line, quantity: 2 },
+        ]).await.is_err());
+        assert!(purchase_orders.receive(draft.id, &[GoodsReceiptLine { purchase_order_item_id: socks_line, quantity: 1 }]).await.is_err());
+        assert_eq!(4, warehouse.get_by_product_id(shirt.id).await.unwrap().in_stock);
+
+        let received = purchase_orders.receive(draft.id, &[GoodsReceiptLine { purchase_order_item_id: shirt_line, quantity: 6 }]).await.unwrap();
+        assert_eq!(PurchaseOrderStatus::Received, received.status);
+        assert!(received.received_at.is_some());
+        assert_eq!(10, warehouse.get_by_product_id(shirt.id).await.unwrap().in_stock);
+
+        let before = (Utc::now() - Duration::hours(1)).naive_utc();
+        let after = (Utc::now() + Duration::hours(1)).naive_utc();
+        let receipts: Vec<_> = warehouse.movements(shirt.id, before..after).await.unwrap().iter()
+            .filter(|m| m.kind == StockMovementKind::GoodsReceipt)
+            .map(|m| (m.quantity, m.purchase_order_id))
+            .collect();
+        assert_eq!(vec![(4, Some(draft.id)), (6, Some(draft.id))], receipts);
+
+        assert_eq!(1, purchase_orders.get_for_supplier(supplier.id).await.unwrap().len());
+        assert_eq!(received, purchase_orders.get(draft.id).await.unwrap());
+    }).await;
+}
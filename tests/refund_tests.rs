@@ -0,0 +1,180 @@
+mod common;
+
+use chrono::Utc;
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::orders::{Order, OrderItemPrice, OrderItemSnapshot, OrderStatus, Orders, PaymentStatus, RefundLine};
+use stec_shopster::products::Product;
+use stec_shopster::warehouse::{StockPolicy, WarehouseItem};
+use crate::common::{make_address, make_product, test_harness};
+
+fn make_order(product: &Product, quantity: i64) -> Order {
+    Order {
+        id: 0,
+        customer_id: None,
+        status: OrderStatus::New,
+        delivery_address: make_address(),
+        billing_address: make_address(),
+        items: vec![OrderItemSnapshot {
+            id: 0,
+            product_id: product.id,
+            quantity,
+            article_number: product.article_number.clone(),
+            gtin: product.gtin.clone(),
+            title: product.title.clone(),
+            short_description: product.short_description.clone(),
+            description: product.description.clone(),
+            tags: vec![],
+            title_image: product.image_url.clone(),
+            additional_images: vec![],
+            price: OrderItemPrice { amount: 500, currency: "EUR".to_string() },
+            weight: product.weight,
+            fulfilled_quantity: 0,
+            variant_options: Vec::new(),
+        }],
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        payment_reference: None,
+        payment_status: PaymentStatus::Pending,
+        order_number: None,
+    }
+}
+
+/// Inserts a product with stock and a paid order for `quantity` units of it (500 cents each).
+async fn make_paid_order(shopster: &Shopster, tenant_id: uuid::Uuid, gtin: &str, quantity: i64) -> (Orders, Order) {
+    let products = shopster.products(tenant_id).unwrap();
+    let product = products.insert(&make_product(&format!("ART-{}", gtin), gtin)).await.unwrap();
+
+    let warehouse = shopster.warehouse(tenant_id).unwrap();
+    warehouse.insert(&WarehouseItem {
+        id: 0,
+        product_id: product.id,
+        in_stock: 10,
+        reserved: 0,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        stock_policy: StockPolicy::Deny,
+        backordered: 0,
+        location_id: None,
+    }).await.unwrap();
+
+    let orders = shopster.orders(tenant_id).unwrap();
+    let order = orders.insert(&make_order(&product, quantity)).await.unwrap();
+    let order = orders.update_payment_status(order.id, PaymentStatus::Paid).await.unwrap();
+    (orders, order)
+}
+
+#[tokio::test]
+async fn refund_partial_then_full_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("refund_partial_then_full".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let (orders, order) = make_paid_order(&shopster, tenant.id, "7300000000001", 3).await;
+        let order_item_id = order.items[0].id;
+
+        let first = orders.refund(
+            order.id,
+            &[RefundLine { id: 0, order_item_id, quantity: 1 }],
+            500,
+            "Damaged item".to_string(),
+            Some("re_001".to_string()),
+        ).await.unwrap();
+        assert_eq!(500, first.amount);
+        assert_eq!("EUR", first.currency);
+        assert_eq!(Some("re_001".to_string()), first.payment_reference);
+        assert_eq!(1, first.lines.len());
+        assert_eq!(PaymentStatus::PartiallyRefunded, orders.get_by_id(order.id).await.unwrap().payment_status);
+
+        // Goodwill refunds need not reference any lines
+        orders.refund(order.id, &[], 1000, "Goodwill".to_string(), None).await.unwrap();
+        assert_eq!(PaymentStatus::Refunded, orders.get_by_id(order.id).await.unwrap().payment_status);
+
+        let ledger = orders.get_refunds(order.id).await.unwrap();
+        assert_eq!(2, ledger.len());
+        assert_eq!(1500, ledger.iter().map(|refund| refund.amount).sum::<i64>());
+        assert_eq!(order_item_id, ledger[0].lines[0].order_item_id);
+        assert!(ledger[1].lines.is_empty());
+    }).await;
+}
+
+#[tokio::test]
+async fn refund_cannot_exceed_captured_total_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("refund_exceeds_total".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let (orders, order) = make_paid_order(&shopster, tenant.id, "7300000000002", 2).await;
+
+        assert!(orders.refund(order.id, &[], 1001, "Too much".to_string(), None).await.is_err());
+        assert!(orders.refund(order.id, &[], 0, "Nothing".to_string(), None).await.is_err());
+
+        orders.refund(order.id, &[], 600, "First".to_string(), None).await.unwrap();
+        assert!(
+            orders.refund(order.id, &[], 401, "Second".to_string(), None).await.is_err(),
+            "Earlier refunds count against the captured total"
+        );
+
+        assert_eq!(1, orders.get_refunds(order.id).await.unwrap().len());
+        assert_eq!(PaymentStatus::PartiallyRefunded, orders.get_by_id(order.id).await.unwrap().payment_status);
+    }).await;
+}
+
+#[tokio::test]
+async fn refund_line_validation_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("refund_line_validation".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let (orders, order) = make_paid_order(&shopster, tenant.id, "7300000000003", 2).await;
+        let order_item_id = order.items[0].id;
+
+        let too_many = [RefundLine { id: 0, order_item_id, quantity: 3 }];
+        assert!(orders.refund(order.id, &too_many, 100, "Too many".to_string(), None).await.is_err());
+
+        let foreign = [RefundLine { id: 0, order_item_id: order_item_id + 1000, quantity: 1 }];
+        assert!(orders.refund(order.id, &foreign, 100, "Foreign".to_string(), None).await.is_err());
+
+        let both = [RefundLine { id: 0, order_item_id, quantity: 2 }];
+        orders.refund(order.id, &both, 200, "Both".to_string(), None).await.unwrap();
+        let again = [RefundLine { id: 0, order_item_id, quantity: 1 }];
+        assert!(
+            orders.refund(order.id, &again, 100, "Again".to_string(), None).await.is_err(),
+            "Each unit can only be refunded once"
+        );
+    }).await;
+}
+
+#[tokio::test]
+async fn refund_rejected_for_unpaid_order_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("refund_unpaid_order".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let (orders, order) = make_paid_order(&shopster, tenant.id, "7300000000004", 1).await;
+        orders.update_payment_status(order.id, PaymentStatus::Pending).await.unwrap();
+
+        assert!(orders.refund(order.id, &[], 100, "Not paid".to_string(), None).await.is_err());
+        assert!(orders.get_refunds(order.id).await.unwrap().is_empty());
+    }).await;
+}
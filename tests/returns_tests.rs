@@ -0,0 +1,270 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::orders::{Order, OrderItemPrice, OrderItemSnapshot, OrderStatus, PaymentStatus};
+use stec_shopster::products::Product;
+use stec_shopster::returns::{ReturnItem, ReturnStatus};
+use stec_shopster::shipments::{Shipment, ShipmentItem};
+use stec_shopster::warehouse::{StockMovementKind, StockPolicy, WarehouseItem};
+use crate::common::{make_address, make_product, test_harness};
+
+fn make_order(product: &Product, quantity: i64) -> Order {
+    Order {
+        id: 0,
+        customer_id: None,
+        status: OrderStatus::New,
+        delivery_address: make_address(),
+        billing_address: make_address(),
+        items: vec![OrderItemSnapshot {
+            id: 0,
+            product_id: product.id,
+            quantity,
+            article_number: product.article_number.clone(),
+            gtin: product.gtin.clone(),
+            title: product.title.clone(),
+            short_description: product.short_description.clone(),
+            description: product.description.clone(),
+            tags: vec![],
+            title_image: product.image_url.clone(),
+            additional_images: vec![],
+            price: OrderItemPrice { amount: 400, currency: "EUR".to_string() },
+            weight: product.weight,
+            fulfilled_quantity: 0,
+            variant_options: Vec::new(),
+        }],
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        payment_reference: None,
+        payment_status: PaymentStatus::Pending,
+        order_number: None,
+    }
+}
+
+/// Creates an order for `quantity` units and walks it through shipping and delivery to `Done`.
+async fn make_done_order(shopster: &Shopster, tenant_id: uuid::Uuid, product: &Product, quantity: i64) -> Order {
+    let orders = shopster.orders(tenant_id).unwrap();
+    let mut order = orders.insert(&make_order(product, quantity)).await.unwrap();
+    order.status = OrderStatus::InProgress;
+    order = orders.update(&order).await.unwrap();
+    order.status = OrderStatus::ReadyToShip;
+    order = orders.update(&order).await.unwrap();
+
+    let shipments = shopster.shipments(tenant_id).unwrap();
+    let shipment = shipments.insert(&Shipment {
+        id: 0,
+        order_id: order.id,
+        carrier: "DHL".to_string(),
+        tracking_code: "TRACK-RET".to_string(),
+        items: vec![ShipmentItem { id: 0, order_item_id: order.items[0].id, quantity }],
+        shipped_at: Utc::now().naive_utc(),
+        delivered_at: None,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }).await.unwrap();
+    shipments.mark_delivered(shipment.id, Utc::now().naive_utc()).await.unwrap();
+
+    let order = orders.get_by_id(order.id).await.unwrap();
+    assert_eq!(OrderStatus::Done, order.status);
+    order
+}
+
+fn return_item(order_item_id: i64, quantity: i64) -> ReturnItem {
+    ReturnItem { id: 0, order_item_id, quantity }
+}
+
+#[tokio::test]
+async fn return_received_restocks_warehouse_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("return_received_restocks".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-RET-001", "7200000000001")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 10,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let order = make_done_order(&shopster, tenant.id, &product, 3).await;
+        assert_eq!(7, warehouse.get_by_product_id(product.id).await.unwrap().in_stock);
+
+        let returns = shopster.returns(tenant.id).unwrap();
+        let opened = returns.open(order.id, &[return_item(order.items[0].id, 2)], "Wrong size".to_string()).await.unwrap();
+        assert_eq!(ReturnStatus::Requested, opened.status);
+        assert_eq!("Wrong size", opened.reason);
+        assert_eq!(1, opened.items.len());
+        assert_eq!(2, opened.items[0].quantity);
+
+        returns.update_status(opened.id, ReturnStatus::Approved).await.unwrap();
+        assert_eq!(7, warehouse.get_by_product_id(product.id).await.unwrap().in_stock, "Approval must not restock");
+
+        let received = returns.update_status(opened.id, ReturnStatus::Received).await.unwrap();
+        assert_eq!(ReturnStatus::Received, received.status);
+        assert_eq!(9, warehouse.get_by_product_id(product.id).await.unwrap().in_stock);
+
+        let refunded = returns.update_status(opened.id, ReturnStatus::Refunded).await.unwrap();
+        assert_eq!(ReturnStatus::Refunded, refunded.status);
+        assert_eq!(9, warehouse.get_by_product_id(product.id).await.unwrap().in_stock);
+
+        let for_order = returns.get_for_order(order.id).await.unwrap();
+        assert_eq!(1, for_order.len());
+        assert_eq!(ReturnStatus::Refunded, for_order[0].status);
+        assert_eq!(1, returns.get_all().await.unwrap().len());
+
+        let range = (Utc::now() - Duration::hours(1)).naive_utc()..(Utc::now() + Duration::hours(1)).naive_utc();
+        let movements: Vec<_> = warehouse.movements(product.id, range).await.unwrap().into_iter()
+            .map(|m| (m.kind, m.quantity, m.order_id, m.return_id))
+            .collect();
+        assert_eq!(vec![
+            (StockMovementKind::Correction, 10, None, None),
+            (StockMovementKind::Reservation, 3, Some(order.id), None),
+            (StockMovementKind::Sale, -3, Some(order.id), None),
+            (StockMovementKind::Release, -3, Some(order.id), None),
+            (StockMovementKind::Return, 2, Some(order.id), Some(opened.id)),
+        ], movements);
+    }).await;
+}
+
+#[tokio::test]
+async fn return_rejected_for_order_not_done_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("return_order_not_done".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-RET-002", "7200000000002")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 10,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let order = orders.insert(&make_order(&product, 1)).await.unwrap();
+
+        let returns = shopster.returns(tenant.id).unwrap();
+        let result = returns.open(order.id, &[return_item(order.items[0].id, 1)], "Changed my mind".to_string()).await;
+        assert!(result.is_err(), "Returns must only be opened for Done orders");
+        assert_eq!(0, returns.get_for_order(order.id).await.unwrap().len());
+    }).await;
+}
+
+#[tokio::test]
+async fn return_quantity_limited_to_ordered_quantity_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("return_quantity_limit".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-RET-003", "7200000000003")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 5,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let order = make_done_order(&shopster, tenant.id, &product, 2).await;
+        let order_item_id = order.items[0].id;
+
+        let returns = shopster.returns(tenant.id).unwrap();
+        assert!(returns.open(order.id, &[return_item(order_item_id, 3)], "Too many".to_string()).await.is_err());
+        assert!(returns.open(order.id, &[return_item(order_item_id, 0)], "Zero".to_string()).await.is_err());
+        assert!(returns.open(order.id, &[return_item(order_item_id + 1000, 1)], "Foreign".to_string()).await.is_err());
+
+        let first = returns.open(order.id, &[return_item(order_item_id, 2)], "Damaged".to_string()).await.unwrap();
+        assert!(
+            returns.open(order.id, &[return_item(order_item_id, 1)], "Again".to_string()).await.is_err(),
+            "Open returns count against the ordered quantity"
+        );
+
+        // A rejected return frees its quantity again
+        returns.update_status(first.id, ReturnStatus::Rejected).await.unwrap();
+        returns.open(order.id, &[return_item(order_item_id, 1)], "Again".to_string()).await.unwrap();
+    }).await;
+}
+
+#[tokio::test]
+async fn return_invalid_status_transition_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("return_invalid_transition".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-RET-004", "7200000000004")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 5,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let order = make_done_order(&shopster, tenant.id, &product, 1).await;
+
+        let returns = shopster.returns(tenant.id).unwrap();
+        let opened = returns.open(order.id, &[return_item(order.items[0].id, 1)], "Broken".to_string()).await.unwrap();
+
+        assert!(returns.update_status(opened.id, ReturnStatus::Received).await.is_err(), "Goods must be approved before receipt");
+        assert!(returns.update_status(opened.id, ReturnStatus::Refunded).await.is_err());
+        assert_eq!(4, warehouse.get_by_product_id(product.id).await.unwrap().in_stock);
+
+        returns.update_status(opened.id, ReturnStatus::Approved).await.unwrap();
+        returns.update_status(opened.id, ReturnStatus::Received).await.unwrap();
+        assert!(returns.update_status(opened.id, ReturnStatus::Rejected).await.is_err(), "Received returns cannot be rejected");
+        assert_eq!(ReturnStatus::Received, returns.get(opened.id).await.unwrap().status);
+        assert_eq!(5, warehouse.get_by_product_id(product.id).await.unwrap().in_stock);
+    }).await;
+}
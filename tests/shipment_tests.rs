@@ -0,0 +1,376 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::orders::{Order, OrderItemPrice, OrderItemSnapshot, OrderStatus, Orders, PaymentStatus};
+use stec_shopster::products::Product;
+use stec_shopster::shipments::{Shipment, ShipmentItem};
+use stec_shopster::warehouse::{StockMovementKind, StockPolicy, WarehouseItem};
+use crate::common::{make_address, make_product, test_harness};
+
+fn make_order(product: &Product, quantity: i64) -> Order {
+    Order {
+        id: 0,
+        customer_id: None,
+        status: OrderStatus::New,
+        delivery_address: make_address(),
+        billing_address: make_address(),
+        items: vec![OrderItemSnapshot {
+            id: 0,
+            product_id: product.id,
+            quantity,
+            article_number: product.article_number.clone(),
+            gtin: product.gtin.clone(),
+            title: product.title.clone(),
+            short_description: product.short_description.clone(),
+            description: product.description.clone(),
+            tags: vec![],
+            title_image: product.image_url.clone(),
+            additional_images: vec![],
+            price: OrderItemPrice { amount: 250, currency: "EUR".to_string() },
+            weight: product.weight,
+            fulfilled_quantity: 0,
+            variant_options: Vec::new(),
+        }],
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        payment_reference: None,
+        payment_status: PaymentStatus::Pending,
+        order_number: None,
+    }
+}
+
+fn make_shipment(order_id: i64, order_item_id: i64, quantity: i64, tracking_code: &str) -> Shipment {
+    Shipment {
+        id: 0,
+        order_id,
+        carrier: "DHL".to_string(),
+        tracking_code: tracking_code.to_string(),
+        items: vec![ShipmentItem { id: 0, order_item_id, quantity }],
+        shipped_at: Utc::now().naive_utc(),
+        delivered_at: None,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+async fn make_ready_to_ship(orders: &Orders, mut order: Order) -> Order {
+    order.status = OrderStatus::InProgress;
+    order = orders.update(&order).await.unwrap();
+    order.status = OrderStatus::ReadyToShip;
+    orders.update(&order).await.unwrap()
+}
+
+#[tokio::test]
+async fn shipment_insert_moves_order_to_shipping_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("shipment_insert_shipping".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-SHP-001", "7100000000001")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 10,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let order = orders.insert(&make_order(&product, 3)).await.unwrap();
+        let order = make_ready_to_ship(&orders, order).await;
+
+        let shipments = shopster.shipments(tenant.id).unwrap();
+        let shipment = shipments.insert(&make_shipment(order.id, order.items[0].id, 3, "TRACK-001")).await.unwrap();
+
+        assert_eq!("DHL", shipment.carrier);
+        assert_eq!("TRACK-001", shipment.tracking_code);
+        assert_eq!(1, shipment.items.len());
+        assert_eq!(3, shipment.items[0].quantity);
+
+        let fetched = orders.get_by_id(order.id).await.unwrap();
+        assert_eq!(OrderStatus::Shipping, fetched.status);
+
+        let wh_item = warehouse.get_by_product_id(product.id).await.unwrap();
+        assert_eq!(0, wh_item.reserved, "Reservation should be consumed by the shipment");
+        assert_eq!(7, wh_item.in_stock, "Shipped units should leave the warehouse");
+
+        let for_order = shipments.get_for_order(order.id).await.unwrap();
+        assert_eq!(1, for_order.len());
+        assert_eq!(shipment.id, for_order[0].id);
+    }).await;
+}
+
+#[tokio::test]
+async fn shipment_last_delivery_moves_order_to_done_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("shipment_delivery_done".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-SHP-002", "7100000000002")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 5,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let order = orders.insert(&make_order(&product, 3)).await.unwrap();
+        let order = make_ready_to_ship(&orders, order).await;
+        let order_item_id = order.items[0].id;
+
+        let shipments = shopster.shipments(tenant.id).unwrap();
+        let first = shipments.insert(&make_shipment(order.id, order_item_id, 2, "TRACK-A")).await.unwrap();
+
+        // Delivering the only shipment so far must not complete a partially shipped order
+        shipments.mark_delivered(first.id, Utc::now().naive_utc()).await.unwrap();
+        let fetched = orders.get_by_id(order.id).await.unwrap();
+        assert_eq!(OrderStatus::PartiallyShipped, fetched.status);
+        assert_eq!(2, fetched.items[0].fulfilled_quantity);
+
+        let second = shipments.insert(&make_shipment(order.id, order_item_id, 1, "TRACK-B")).await.unwrap();
+        assert_eq!(OrderStatus::Shipping, orders.get_by_id(order.id).await.unwrap().status);
+
+        let delivered = shipments.mark_delivered(second.id, Utc::now().naive_utc()).await.unwrap();
+        assert!(delivered.delivered_at.is_some());
+        assert_eq!(OrderStatus::Done, orders.get_by_id(order.id).await.unwrap().status);
+
+        // A shipment is delivered only once
+        assert!(shipments.mark_delivered(second.id, Utc::now().naive_utc()).await.is_err());
+        assert_eq!(delivered.delivered_at, shipments.get(second.id).await.unwrap().delivered_at);
+
+        let wh_item = warehouse.get_by_product_id(product.id).await.unwrap();
+        assert_eq!(2, wh_item.in_stock);
+        assert_eq!(0, wh_item.reserved);
+    }).await;
+}
+
+#[tokio::test]
+async fn shipment_split_fulfillment_keeps_open_lines_reserved_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("shipment_split_fulfillment".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-SHP-005", "7100000000005")).await.unwrap();
+        let socks = products.insert(&make_product("ART-SHP-006", "7100000000006")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        for product_id in [shirt.id, socks.id] {
+            warehouse.insert(&WarehouseItem {
+                id: 0,
+                product_id,
+                in_stock: 10,
+                reserved: 0,
+                created_at: Utc::now().naive_utc(),
+                updated_at: None,
+                stock_policy: StockPolicy::Deny,
+                backordered: 0,
+                location_id: None,
+            }).await.unwrap();
+        }
+
+        let mut order = make_order(&shirt, 2);
+        order.items.extend(make_order(&socks, 4).items);
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let order = orders.insert(&order).await.unwrap();
+        let mut order = make_ready_to_ship(&orders, order).await;
+        let shirt_line = order.items.iter().find(|item| item.product_id == shirt.id).unwrap().id;
+
+        let shipments = shopster.shipments(tenant.id).unwrap();
+        shipments.insert(&make_shipment(order.id, shirt_line, 2, "TRACK-SPLIT")).await.unwrap();
+
+        order = orders.get_by_id(order.id).await.unwrap();
+        assert_eq!(OrderStatus::PartiallyShipped, order.status);
+
+        let shirt_stock = warehouse.get_by_product_id(shirt.id).await.unwrap();
+        assert_eq!(8, shirt_stock.in_stock);
+        assert_eq!(0, shirt_stock.reserved);
+
+        let socks_stock = warehouse.get_by_product_id(socks.id).await.unwrap();
+        assert_eq!(10, socks_stock.in_stock, "Unshipped lines must not touch in_stock");
+        assert_eq!(4, socks_stock.reserved, "Unshipped lines must stay reserved");
+
+        // Cancelling the rest of a partially shipped order releases only the open quantities
+        order.status = OrderStatus::Cancelled;
+        orders.update(&order).await.unwrap();
+
+        assert_eq!(0, warehouse.get_by_product_id(socks.id).await.unwrap().reserved);
+        assert_eq!(8, warehouse.get_by_product_id(shirt.id).await.unwrap().in_stock);
+    }).await;
+}
+
+#[tokio::test]
+async fn shipment_rejected_for_order_not_ready_to_ship_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("shipment_not_ready".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-SHP-003", "7100000000003")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 10,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let order = orders.insert(&make_order(&product, 1)).await.unwrap();
+
+        let shipments = shopster.shipments(tenant.id).unwrap();
+        let result = shipments.insert(&make_shipment(order.id, order.items[0].id, 1, "TRACK-X")).await;
+        assert!(result.is_err(), "Shipments must not be recorded for New orders");
+
+        assert_eq!(0, shipments.get_for_order(order.id).await.unwrap().len());
+        assert_eq!(OrderStatus::New, orders.get_by_id(order.id).await.unwrap().status);
+    }).await;
+}
+
+#[tokio::test]
+async fn shipment_invalid_items_rejected_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("shipment_invalid_items".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-SHP-004", "7100000000004")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 10,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let orders = shopster.orders(tenant.id).unwrap();
+        let order = orders.insert(&make_order(&product, 2)).await.unwrap();
+        let order = make_ready_to_ship(&orders, order).await;
+        let other_order = orders.insert(&make_order(&product, 1)).await.unwrap();
+
+        let shipments = shopster.shipments(tenant.id).unwrap();
+
+        let too_many = make_shipment(order.id, order.items[0].id, 3, "TRACK-1");
+        assert!(shipments.insert(&too_many).await.is_err(), "Shipping more than ordered must fail");
+
+        let foreign_item = make_shipment(order.id, other_order.items[0].id, 1, "TRACK-2");
+        assert!(shipments.insert(&foreign_item).await.is_err(), "Items of another order must be rejected");
+
+        let empty_tracking = make_shipment(order.id, order.items[0].id, 1, "  ");
+        assert!(shipments.insert(&empty_tracking).await.is_err());
+
+        assert_eq!(0, shipments.get_for_order(order.id).await.unwrap().len());
+        assert_eq!(OrderStatus::ReadyToShip, orders.get_by_id(order.id).await.unwrap().status);
+    }).await;
+}
+
+#[tokio::test]
+async fn shipment_movements_match_stock_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("shipment_movements".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        let orders = shopster.orders(tenant.id).unwrap();
+        let shipments = shopster.shipments(tenant.id).unwrap();
+        let before = (Utc::now() - Duration::hours(1)).naive_utc();
+
+        for (article_number, gtin, stock_policy) in [
+            ("ART-SHP-005", "7100000000005", StockPolicy::Deny),
+            ("ART-SHP-006", "7100000000006", StockPolicy::Unlimited),
+        ] {
+            let product = products.insert(&make_product(article_number, gtin)).await.unwrap();
+            warehouse.insert(&WarehouseItem {
+                id: 0,
+                product_id: product.id,
+                in_stock: 10,
+                reserved: 0,
+                created_at: Utc::now().naive_utc(),
+                updated_at: None,
+                stock_policy,
+                backordered: 0,
+                location_id: None,
+            }).await.unwrap();
+
+            let order = orders.insert(&make_order(&product, 3)).await.unwrap();
+            let order = make_ready_to_ship(&orders, order).await;
+            shipments.insert(&make_shipment(order.id, order.items[0].id, 3, "TRACK-5")).await.unwrap();
+
+            // Sales follow `in_stock`, reservations and releases follow `reserved`
+            let item = warehouse.get_by_product_id(product.id).await.unwrap();
+            let after = (Utc::now() + Duration::hours(1)).naive_utc();
+            let movements = warehouse.movements(product.id, before..after).await.unwrap();
+            let sum = |kinds: &[StockMovementKind]| movements.iter()
+                .filter(|m| kinds.contains(&m.kind))
+                .map(|m| m.quantity)
+                .sum::<i64>();
+            let stock_kinds = [StockMovementKind::GoodsReceipt, StockMovementKind::Sale, StockMovementKind::Return, StockMovementKind::Correction, StockMovementKind::Transfer];
+            assert_eq!(item.in_stock, sum(&stock_kinds));
+            assert_eq!(item.reserved, sum(&[StockMovementKind::Reservation, StockMovementKind::Release]));
+            let expected_stock = if stock_policy == StockPolicy::Unlimited { 10 } else { 7 };
+            assert_eq!((expected_stock, 0), (item.in_stock, item.reserved));
+        }
+    }).await;
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use chrono::Utc;
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::products::{Price, Product, ProductQuery, ProductSearch, ProductVariant, VariantOption};
+use stec_shopster::tags::Tag;
+use crate::common::test_harness;
+
+fn make_product(article_number: &str, gtin: &str, title: &str, tags: &[&str]) -> Product {
+    Product {
+        id: 0,
+        article_number: article_number.to_string(),
+        title: title.to_string(),
+        gtin: gtin.to_string(),
+        short_description: "Short".to_string(),
+        description: "Description".to_string(),
+        image_url: String::new(),
+        additional_images: Vec::new(),
+        price: Some(Price { amount: 1000, currency: "EUR".to_string() }),
+        weight: 100,
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+fn make_tag(title: &str) -> Tag {
+    Tag {
+        id: 0,
+        title: title.to_string(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+fn titles(tags: &[Tag]) -> Vec<&str> {
+    tags.iter().map(|tag| tag.title.as_str()).collect()
+}
+
+#[tokio::test]
+async fn product_tags_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_tags".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let tags = shopster.tags(tenant.id).unwrap();
+
+        // Products without tags have an empty list, blank and repeated titles
+        // are dropped, and titles may contain the old separator
+        let plain = products.insert(&make_product("ART-T-1", "7700000000001", "Plain", &[])).await.unwrap();
+        assert!(plain.tags.is_empty());
+        assert!(products.get(plain.id).await.unwrap().tags.is_empty());
+        let legacy = products.insert(&make_product("ART-T-2", "7700000000002", "Legacy", &[""])).await.unwrap();
+        assert!(legacy.tags.is_empty());
+        let shirt = products.insert(&make_product("ART-T-3", "7700000000003", "Shirt", &["summer", " cotton ", "summer", "black|white"])).await.unwrap();
+        assert_eq!(vec!["black|white", "cotton", "summer"], shirt.tags);
+        assert_eq!(vec!["black|white", "cotton", "summer"], titles(&tags.get_all().await.unwrap()));
+
+        // Products share tags by title
+        let dress = products.insert(&make_product("ART-T-4", "7700000000004", "Dress", &["summer"])).await.unwrap();
+        let summer = tags.get_by_title("summer").await.unwrap().unwrap();
+        let mut tagged: Vec<i64> = tags.get_products(summer.id).await.unwrap().iter().map(|product| product.id).collect();
+        tagged.sort();
+        assert_eq!(vec![shirt.id, dress.id], tagged);
+        assert_eq!(3, tags.get_all().await.unwrap().len());
+
+        // Updating a product replaces its tags
+        let mut dress = products.get(dress.id).await.unwrap();
+        dress.tags = vec!["evening".to_string()];
+        assert_eq!(vec!["evening"], products.update(&dress).await.unwrap().tags);
+        assert_eq!(vec![shirt.id], tags.get_products(summer.id).await.unwrap().iter().map(|product| product.id).collect::<Vec<_>>());
+
+        // Variants show the tags of their parent
+        let variant = products.insert_variant(&ProductVariant {
+            id: 0,
+            parent_id: shirt.id,
+            article_number: "ART-T-3-M".to_string(),
+            gtin: "7700000000005".to_string(),
+            options: vec![VariantOption { name: "Size".to_string(), value: "M".to_string() }],
+            price: None,
+            weight: None,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }).await.unwrap();
+        assert_eq!(vec!["black|white", "cotton", "summer"], products.get(variant.id).await.unwrap().tags);
+
+        // The listing filters by title, the search finds tags
+        let page = products.query(&ProductQuery::new().tag("black|white")).await.unwrap();
+        assert_eq!(vec![shirt.id], page.products.iter().map(|product| product.id).collect::<Vec<_>>());
+        assert_eq!(1, products.search(&ProductSearch::new("cotton")).await.unwrap().total);
+    }).await;
+}
+
+#[tokio::test]
+async fn tag_management_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("tag_management".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let tags = shopster.tags(tenant.id).unwrap();
+
+        let boots = products.insert(&make_product("ART-M-1", "7700000000011", "Boots", &["Shoes", "leather"])).await.unwrap();
+        let sneakers = products.insert(&make_product("ART-M-2", "7700000000012", "Sneakers", &["shoes"])).await.unwrap();
+
+        let sale = tags.insert(&make_tag(" sale ")).await.unwrap();
+        assert_eq!("sale", sale.title);
+        assert!(tags.insert(&make_tag("sale")).await.is_err());
+        assert!(tags.insert(&make_tag("  ")).await.is_err());
+        assert!(tags.get_products(sale.id).await.unwrap().is_empty());
+
+        // Renaming changes the title on every product and in the search
+        let leather = tags.get_by_title("leather").await.unwrap().unwrap();
+        let renamed = tags.rename(leather.id, "suede").await.unwrap();
+        assert_eq!("suede", renamed.title);
+        assert!(renamed.updated_at.is_some());
+        assert_eq!(vec!["Shoes", "suede"], products.get(boots.id).await.unwrap().tags);
+        assert_eq!(1, products.search(&ProductSearch::new("suede")).await.unwrap().total);
+        assert_eq!(0, products.search(&ProductSearch::new("leather")).await.unwrap().total);
+        assert!(tags.rename(leather.id, "sale").await.is_err());
+        assert!(tags.rename(leather.id, "").await.is_err());
+
+        // Merging moves all products to the target and removes the source
+        let upper = tags.get_by_title("Shoes").await.unwrap().unwrap();
+        let lower = tags.get_by_title("shoes").await.unwrap().unwrap();
+        let merged = tags.merge(upper.id, lower.id).await.unwrap();
+        assert_eq!(lower.id, merged.id);
+        assert!(tags.get_by_title("Shoes").await.unwrap().is_none());
+        assert_eq!(vec!["shoes", "suede"], products.get(boots.id).await.unwrap().tags);
+        assert_eq!(vec!["shoes"], products.get(sneakers.id).await.unwrap().tags);
+        assert_eq!(2, tags.get_products(lower.id).await.unwrap().len());
+        assert!(tags.merge(lower.id, lower.id).await.is_err());
+        assert!(tags.merge(upper.id, lower.id).await.is_err());
+
+        // Removing a tag removes it from its products
+        assert!(tags.remove(lower.id).await.unwrap());
+        assert!(!tags.remove(lower.id).await.unwrap());
+        assert_eq!(vec!["suede"], products.get(boots.id).await.unwrap().tags);
+        assert!(products.get(sneakers.id).await.unwrap().tags.is_empty());
+        assert_eq!(vec!["sale", "suede"], titles(&tags.get_all().await.unwrap()));
+    }).await;
+}
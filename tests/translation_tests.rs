@@ -0,0 +1,183 @@
+mod common;
+
+use chrono::Utc;
+use stec_tenet::{Storage, Tenet};
+use stec_shopster::{DatabaseSelector, Shopster};
+use stec_shopster::categories::{Category, CategoryTranslation};
+use stec_shopster::products::{Price, Product, ProductTranslation, ProductVariant, VariantOption};
+use crate::common::test_harness;
+
+fn make_product(article_number: &str, gtin: &str, title: &str, description: &str) -> Product {
+    Product {
+        id: 0,
+        article_number: article_number.to_string(),
+        title: title.to_string(),
+        gtin: gtin.to_string(),
+        short_description: "Short".to_string(),
+        description: description.to_string(),
+        image_url: String::new(),
+        additional_images: Vec::new(),
+        price: Some(Price { amount: 1000, currency: "EUR".to_string() }),
+        weight: 100,
+        tags: Vec::new(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+fn make_translation(product_id: i64, language: &str, title: Option<&str>, description: Option<&str>) -> ProductTranslation {
+    ProductTranslation {
+        product_id,
+        language: language.to_string(),
+        title: title.map(String::from),
+        short_description: None,
+        description: description.map(String::from),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+fn make_category_translation(category_id: i64, language: &str, title: &str) -> CategoryTranslation {
+    CategoryTranslation {
+        category_id,
+        language: language.to_string(),
+        title: title.to_string(),
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+    }
+}
+
+#[tokio::test]
+async fn product_translation_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("product_translation".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let boots = products.insert(&make_product("ART-L-1", "7800000000001", "Hiking Boots", "Waterproof boots")).await.unwrap();
+        let mug = products.insert(&make_product("ART-L-2", "7800000000002", "Mug", "A mug")).await.unwrap();
+
+        products.set_translation(&make_translation(boots.id, "de", Some("Wanderschuhe"), Some("Wasserdichte Schuhe"))).await.unwrap();
+        products.set_translation(&make_translation(boots.id, "de-CH", None, Some("Wasserdichte Wanderschuhe"))).await.unwrap();
+
+        // Each text falls back from the locale to its language and then to
+        // the default language
+        let localized = products.get_localized(boots.id, "de").await.unwrap();
+        assert_eq!("Wanderschuhe", localized.title);
+        assert_eq!("Wasserdichte Schuhe", localized.description);
+        assert_eq!("Short", localized.short_description);
+        let localized = products.get_localized(boots.id, "de-CH").await.unwrap();
+        assert_eq!("Wanderschuhe", localized.title);
+        assert_eq!("Wasserdichte Wanderschuhe", localized.description);
+        let localized = products.get_localized(boots.id, "fr").await.unwrap();
+        assert_eq!("Hiking Boots", localized.title);
+        assert_eq!("Hiking Boots", products.get_localized(boots.id, "en").await.unwrap().title);
+        assert_eq!("Hiking Boots", products.get(boots.id).await.unwrap().title);
+
+        let all = products.get_all_localized("de-AT").await.unwrap();
+        let titles: Vec<&str> = all.iter().map(|product| product.title.as_str()).collect();
+        assert!(titles.contains(&"Wanderschuhe"));
+        assert!(titles.contains(&"Mug"));
+
+        // Setting a translation again replaces it
+        let updated = products.set_translation(&make_translation(boots.id, "de", Some("Bergschuhe"), None)).await.unwrap();
+        assert!(updated.updated_at.is_some());
+        let localized = products.get_localized(boots.id, "de").await.unwrap();
+        assert_eq!("Bergschuhe", localized.title);
+        assert_eq!("Waterproof boots", localized.description);
+        assert_eq!(vec!["de", "de-CH"], products.get_translations(boots.id).await.unwrap().iter().map(|translation| translation.language.as_str()).collect::<Vec<_>>());
+
+        // Invalid translations are rejected
+        assert!(products.set_translation(&make_translation(mug.id, "en", Some("Cup"), None)).await.is_err());
+        assert!(products.set_translation(&make_translation(mug.id, "German", Some("Tasse"), None)).await.is_err());
+        assert!(products.set_translation(&make_translation(mug.id, "de", Some(" "), None)).await.is_err());
+        assert!(products.get_localized(mug.id, "").await.is_err());
+
+        // Variants are translated through their parent
+        let variant = products.insert_variant(&ProductVariant {
+            id: 0,
+            parent_id: boots.id,
+            article_number: "ART-L-1-42".to_string(),
+            gtin: "7800000000003".to_string(),
+            options: vec![VariantOption { name: "Size".to_string(), value: "42".to_string() }],
+            price: None,
+            weight: None,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }).await.unwrap();
+        assert_eq!("Bergschuhe", products.get_localized(variant.id, "de").await.unwrap().title);
+        assert!(products.set_translation(&make_translation(variant.id, "fr", Some("Chaussures"), None)).await.is_err());
+
+        // Once the default language changes, its texts are the product's own
+        let settings = shopster.settings(tenant.id).unwrap();
+        let language = settings.get_by_title("language".to_string()).await.unwrap();
+        settings.update_by_id(language.id, "de".to_string()).await.unwrap();
+        let localized = products.get_localized(boots.id, "de-CH").await.unwrap();
+        assert_eq!("Hiking Boots", localized.title);
+        assert_eq!("Wasserdichte Wanderschuhe", localized.description);
+
+        assert!(products.remove_translation(boots.id, "de-CH").await.unwrap());
+        assert!(!products.remove_translation(boots.id, "de-CH").await.unwrap());
+        assert_eq!("Waterproof boots", products.get_localized(boots.id, "de-CH").await.unwrap().description);
+    }).await;
+}
+
+#[tokio::test]
+async fn category_translation_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("category_translation".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let categories = shopster.categories(tenant.id).unwrap();
+        let clothing = categories.insert(&Category {
+            id: 0,
+            parent_id: None,
+            title: "Clothing".to_string(),
+            slug: "clothing".to_string(),
+            position: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }).await.unwrap();
+        let shoes = categories.insert(&Category {
+            id: 0,
+            parent_id: Some(clothing.id),
+            title: "Shoes".to_string(),
+            slug: "shoes".to_string(),
+            position: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+        }).await.unwrap();
+
+        categories.set_translation(&make_category_translation(clothing.id, "de", "Kleidung")).await.unwrap();
+        categories.set_translation(&make_category_translation(shoes.id, "de", "Schuhe")).await.unwrap();
+        categories.set_translation(&make_category_translation(shoes.id, "de-CH", "Schue")).await.unwrap();
+
+        let breadcrumbs = categories.get_breadcrumbs_localized(shoes.id, "de-CH").await.unwrap();
+        assert_eq!(vec!["Kleidung", "Schue"], breadcrumbs.iter().map(|category| category.title.as_str()).collect::<Vec<_>>());
+        assert_eq!("Schuhe", categories.get_localized(shoes.id, "de").await.unwrap().title);
+        assert_eq!("Shoes", categories.get_localized(shoes.id, "fr").await.unwrap().title);
+        let all = categories.get_all_localized("de").await.unwrap();
+        assert_eq!(vec!["Kleidung", "Schuhe"], all.iter().map(|category| category.title.as_str()).collect::<Vec<_>>());
+        assert_eq!("shoes", all[1].slug);
+
+        assert!(categories.set_translation(&make_category_translation(shoes.id, "en", "Footwear")).await.is_err());
+        assert!(categories.set_translation(&make_category_translation(shoes.id, "fr", "")).await.is_err());
+        assert_eq!(2, categories.get_translations(shoes.id).await.unwrap().len());
+
+        // Removing a category removes its translations
+        assert!(categories.remove(shoes.id).await.unwrap());
+        assert!(categories.get_translations(shoes.id).await.unwrap().is_empty());
+        assert!(categories.remove_translation(clothing.id, "de").await.unwrap());
+        assert_eq!("Clothing", categories.get_localized(clothing.id, "de").await.unwrap().title);
+    }).await;
+}
@@ -1,29 +1,10 @@
 mod common;
 
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use stec_tenet::{Storage, Tenet};
 use stec_shopster::{DatabaseSelector, Shopster};
-use stec_shopster::products::{Price, Product};
-use stec_shopster::warehouse::WarehouseItem;
-use crate::common::test_harness;
-
-fn make_product(article_number: &str, gtin: &str) -> Product {
-    Product {
-        id: 0,
-        article_number: article_number.to_string(),
-        title: "Warehouse Test Product".to_string(),
-        gtin: gtin.to_string(),
-        short_description: "Short".to_string(),
-        description: "Description".to_string(),
-        image_url: "/images/test.png".to_string(),
-        additional_images: Vec::new(),
-        price: Some(Price { amount: 100, currency: "EUR".to_string() }),
-        weight: 500,
-        tags: Vec::new(),
-        created_at: Utc::now().naive_utc(),
-        updated_at: None,
-    }
-}
+use stec_shopster::warehouse::{StockMovementKind, StockPolicy, WarehouseItem};
+use crate::common::{make_product, test_harness};
 
 #[tokio::test]
 async fn warehouse_get_all_empty_test() {
@@ -65,6 +46,9 @@ async fn warehouse_insert_and_get_test() {
             reserved: 0,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         };
         let created = warehouse.insert(&new_item).await.unwrap();
 
@@ -100,6 +84,9 @@ async fn warehouse_get_by_product_id_test() {
             reserved: 5,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         }).await.unwrap();
 
         let item = warehouse.get_by_product_id(product.id).await.unwrap();
@@ -132,6 +119,9 @@ async fn warehouse_apply_reserved_delta_test() {
             reserved: 0,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         }).await.unwrap();
 
         // Reserve 10 units
@@ -173,6 +163,9 @@ async fn warehouse_update_test() {
             reserved: 0,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         }).await.unwrap();
 
         let updated_item = WarehouseItem {
@@ -208,6 +201,9 @@ async fn warehouse_remove_test() {
             reserved: 0,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         }).await.unwrap();
 
         let removed = warehouse.remove_by_product_id(product.id).await.unwrap();
@@ -240,6 +236,9 @@ async fn warehouse_get_all_with_details_test() {
             reserved: 5,
             created_at: Utc::now().naive_utc(),
             updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
         }).await.unwrap();
 
         let details = warehouse.get_all_with_details().await.unwrap();
@@ -248,9 +247,243 @@ async fn warehouse_get_all_with_details_test() {
         let detail = &details[0];
         assert_eq!(product.id, detail.product_id);
         assert_eq!("ART-WH-006", detail.article_number);
-        assert_eq!("Warehouse Test Product", detail.title);
+        assert_eq!("Test Product", detail.title);
         assert_eq!(25, detail.in_stock);
         assert_eq!(5, detail.reserved);
         assert_eq!(20, detail.available);
     }).await;
 }
+
+#[tokio::test]
+async fn warehouse_stock_policy_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("warehouse_stock_policy".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let denied = products.insert(&make_product("ART-WH-010", "1000000000010")).await.unwrap();
+        let backordered = products.insert(&make_product("ART-WH-011", "1000000000011")).await.unwrap();
+        let digital = products.insert(&make_product("ART-WH-012", "1000000000012")).await.unwrap();
+        let missing = products.insert(&make_product("ART-WH-013", "1000000000013")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        for (product_id, stock_policy) in [
+            (denied.id, StockPolicy::Deny),
+            (backordered.id, StockPolicy::Backorder),
+            (digital.id, StockPolicy::Unlimited),
+        ] {
+            warehouse.insert(&WarehouseItem {
+                id: 0,
+                product_id,
+                in_stock: 5,
+                reserved: 0,
+                created_at: Utc::now().naive_utc(),
+                updated_at: None,
+                stock_policy,
+                backordered: 0,
+                location_id: None,
+            }).await.unwrap();
+        }
+
+        // Deny never reserves more than is in stock
+        assert!(warehouse.apply_reserved_delta(denied.id, 6).await.is_err());
+        assert_eq!(5, warehouse.apply_reserved_delta(denied.id, 5).await.unwrap().reserved);
+        assert!(warehouse.apply_reserved_delta(denied.id, 1).await.is_err());
+
+        // Backorder books the excess separately
+        let item = warehouse.apply_reserved_delta(backordered.id, 8).await.unwrap();
+        assert_eq!((5, 3), (item.reserved, item.backordered));
+        // Releases take back backordered units first
+        let item = warehouse.apply_reserved_delta(backordered.id, -4).await.unwrap();
+        assert_eq!((4, 0), (item.reserved, item.backordered));
+
+        // Unlimited never checks stock
+        let item = warehouse.apply_reserved_delta(digital.id, 50).await.unwrap();
+        assert_eq!((50, 0), (item.reserved, item.backordered));
+
+        // Products without a warehouse row cannot be reserved
+        assert!(warehouse.apply_reserved_delta(missing.id, 1).await.is_err());
+        assert!(warehouse.get_by_product_id(missing.id).await.is_err());
+    }).await;
+}
+
+#[tokio::test]
+async fn warehouse_receive_stock_fulfils_backorders_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("warehouse_receive_stock".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-WH-014", "1000000000014")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 2,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Backorder,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        let item = warehouse.apply_reserved_delta(product.id, 10).await.unwrap();
+        assert_eq!((2, 8), (item.reserved, item.backordered));
+
+        let item = warehouse.receive_stock(product.id, 5).await.unwrap();
+        assert_eq!((7, 7, 3), (item.in_stock, item.reserved, item.backordered));
+        assert_eq!(0, item.available());
+
+        let item = warehouse.receive_stock(product.id, 10).await.unwrap();
+        assert_eq!((17, 10, 0), (item.in_stock, item.reserved, item.backordered));
+        assert_eq!(7, item.available());
+
+        assert!(warehouse.receive_stock(product.id, 0).await.is_err());
+    }).await;
+}
+
+#[tokio::test]
+async fn warehouse_movements_ledger_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("warehouse_movements".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let product = products.insert(&make_product("ART-WH-015", "1000000000015")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        let before = (Utc::now() - Duration::hours(1)).naive_utc();
+        let created = warehouse.insert(&WarehouseItem {
+            id: 0,
+            product_id: product.id,
+            in_stock: 10,
+            reserved: 0,
+            created_at: Utc::now().naive_utc(),
+            updated_at: None,
+            stock_policy: StockPolicy::Deny,
+            backordered: 0,
+            location_id: None,
+        }).await.unwrap();
+
+        warehouse.receive_stock(product.id, 5).await.unwrap();
+        warehouse.apply_reserved_delta(product.id, 4).await.unwrap();
+        warehouse.apply_reserved_delta(product.id, -1).await.unwrap();
+        warehouse.update_by_product_id_with_reason(product.id, &WarehouseItem {
+            in_stock: 12,
+            reserved: 3,
+            ..created
+        }, Some("Inventory count")).await.unwrap();
+
+        let after = (Utc::now() + Duration::hours(1)).naive_utc();
+        let movements = warehouse.movements(product.id, before..after).await.unwrap();
+        let entries: Vec<_> = movements.iter()
+            .map(|m| (m.kind, m.quantity, m.reason.as_deref()))
+            .collect();
+        assert_eq!(vec![
+            (StockMovementKind::Correction, 10, None),
+            (StockMovementKind::GoodsReceipt, 5, None),
+            (StockMovementKind::Reservation, 4, None),
+            (StockMovementKind::Release, -1, None),
+            (StockMovementKind::Correction, -3, Some("Inventory count")),
+        ], entries);
+        assert!(movements.iter().all(|m| m.product_id == product.id));
+
+        // The range is half-open and filters by creation time
+        assert!(warehouse.movements(product.id, before..before).await.unwrap().is_empty());
+        assert!(warehouse.movements(product.id, after..(after + Duration::hours(1))).await.unwrap().is_empty());
+
+        // Removing the warehouse row writes off the remaining stock
+        warehouse.remove_by_product_id(product.id).await.unwrap();
+        let movements = warehouse.movements(product.id, before..after).await.unwrap();
+        let written_off: Vec<_> = movements[5..].iter().map(|m| (m.kind, m.quantity)).collect();
+        assert_eq!(vec![(StockMovementKind::Correction, -12), (StockMovementKind::Release, -3)], written_off);
+    }).await;
+}
+
+#[tokio::test]
+async fn warehouse_reorder_points_and_alerts_test() {
+    test_harness(|tenet_connection_string, shopster_connection_string| async move {
+        let tenet = Tenet::new(tenet_connection_string);
+        let tenant = tenet.create_tenant("warehouse_reorder_points".to_string()).unwrap();
+        let storage = Storage::new_postgresql_database(shopster_connection_string, tenant.id);
+        tenant.add_storage(&storage).unwrap();
+
+        let database_selector = DatabaseSelector::new(tenet);
+        let shopster = Shopster::new(database_selector);
+
+        let products = shopster.products(tenant.id).unwrap();
+        let shirt = products.insert(&make_product("ART-WH-016", "1000000000016")).await.unwrap();
+        let socks = products.insert(&make_product("ART-WH-017", "1000000000017")).await.unwrap();
+        let hat = products.insert(&make_product("ART-WH-018", "1000000000018")).await.unwrap();
+
+        let warehouse = shopster.warehouse(tenant.id).unwrap();
+        for (product_id, in_stock) in [(shirt.id, 10), (socks.id, 3)] {
+            warehouse.insert(&WarehouseItem {
+                id: 0,
+                product_id,
+                in_stock,
+                reserved: 0,
+                created_at: Utc::now().naive_utc(),
+                updated_at: None,
+                stock_policy: StockPolicy::Deny,
+                backordered: 0,
+                location_id: None,
+            }).await.unwrap();
+        }
+
+        assert!(warehouse.set_reorder_point(shirt.id, -1, 10).await.is_err());
+        assert!(warehouse.set_reorder_point(shirt.id, 5, 0).await.is_err());
+        warehouse.set_reorder_point(shirt.id, 5, 20).await.unwrap();
+        warehouse.set_reorder_point(socks.id, 5, 10).await.unwrap();
+        // The hat has a reorder point but is not stocked at all
+        warehouse.set_reorder_point(hat.id, 1, 5).await.unwrap();
+        assert_eq!(5, warehouse.get_reorder_point(shirt.id).await.unwrap().unwrap().reorder_point);
+
+        let low_stock: Vec<_> = warehouse.get_low_stock().await.unwrap().iter()
+            .map(|item| (item.product_id, item.available))
+            .collect();
+        assert_eq!(vec![(hat.id, 0), (socks.id, 3)], low_stock);
+
+        // Staying above the reorder point raises no alert
+        warehouse.apply_reserved_delta(shirt.id, 4).await.unwrap();
+        assert!(warehouse.get_stock_alerts().await.unwrap().is_empty());
+
+        // Crossing it does, once
+        warehouse.apply_reserved_delta(shirt.id, 2).await.unwrap();
+        warehouse.apply_reserved_delta(shirt.id, 1).await.unwrap();
+        let alerts = warehouse.get_stock_alerts().await.unwrap();
+        assert_eq!(1, alerts.len());
+        assert_eq!((shirt.id, 4, 5, 20), (alerts[0].product_id, alerts[0].available, alerts[0].reorder_point, alerts[0].reorder_quantity));
+        assert_eq!(3, warehouse.get_low_stock().await.unwrap().len());
+
+        // Restocking clears the low stock state; the next drop alerts again
+        warehouse.receive_stock(shirt.id, 10).await.unwrap();
+        assert_eq!(2, warehouse.get_low_stock().await.unwrap().len());
+        warehouse.acknowledge_stock_alert(alerts[0].id).await.unwrap();
+        assert!(warehouse.get_stock_alerts().await.unwrap().is_empty());
+
+        warehouse.apply_reserved_delta(shirt.id, 8).await.unwrap();
+        let alerts = warehouse.get_stock_alerts().await.unwrap();
+        assert_eq!(vec![(shirt.id, 5)], alerts.iter().map(|a| (a.product_id, a.available)).collect::<Vec<_>>());
+
+        assert!(warehouse.remove_reorder_point(hat.id).await.unwrap());
+        assert!(warehouse.get_reorder_point(hat.id).await.unwrap().is_none());
+    }).await;
+}